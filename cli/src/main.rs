@@ -0,0 +1,363 @@
+//! `redactr`: apply a redaction plan to image files.
+//!
+//! ```text
+//! redactr --plan plan.json [--out-dir DIR] [--suffix SUFFIX] INPUT...
+//! ```
+//!
+//! Each input is a file or a glob pattern (`"shots/**/*.png"`), expanded
+//! here so quoting it works the same on every shell. The plan is the
+//! `RedactionPlan` JSON the app exports, replayed on each image scaled to
+//! its size. PNG, JPEG and WebP are read, turned upright per their EXIF
+//! orientation first so the plan lands where it was drawn; the output keeps
+//! the input's format and carries only pixels, so no metadata survives. Without
+//! `--out-dir` it is written next to the input as `NAME.redacted.EXT`.
+//! Files already carrying the suffix are skipped, so a glob can be rerun
+//! over a directory that holds earlier outputs.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, RgbaImage};
+use redactr_wasm::RedactionPlan;
+
+const USAGE: &str = "usage: redactr --plan PLAN.json [--out-dir DIR] [--suffix SUFFIX] INPUT...";
+const DEFAULT_SUFFIX: &str = ".redacted";
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    plan: PathBuf,
+    out_dir: Option<PathBuf>,
+    suffix: String,
+    inputs: Vec<String>,
+}
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Io(PathBuf, std::io::Error),
+    Image(PathBuf, image::ImageError),
+    Redact(PathBuf, redactr_wasm::RedactError),
+    Pattern(String, String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(detail) => write!(f, "{}\n{}", detail, USAGE),
+            CliError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            CliError::Image(path, error) => write!(f, "{}: {}", path.display(), error),
+            CliError::Redact(path, error) => write!(f, "{}: {}", path.display(), error),
+            CliError::Pattern(pattern, detail) => write!(f, "bad pattern {}: {}", pattern, detail),
+        }
+    }
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, CliError> {
+    let mut args = args.into_iter();
+    let (mut plan, mut out_dir, mut suffix) = (None, None, DEFAULT_SUFFIX.to_string());
+    let mut inputs = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| CliError::Usage(format!("{} needs a value", flag)))
+        };
+        match arg.as_str() {
+            "--plan" | "-p" => plan = Some(PathBuf::from(value(&arg)?)),
+            "--out-dir" | "-o" => out_dir = Some(PathBuf::from(value(&arg)?)),
+            "--suffix" => suffix = value(&arg)?,
+            "--help" | "-h" => {
+                return Err(CliError::Usage("redact image files with a plan".into()))
+            }
+            flag if flag.starts_with('-') => {
+                return Err(CliError::Usage(format!("unknown option {}", flag)))
+            }
+            _ => inputs.push(arg),
+        }
+    }
+    let plan = plan.ok_or_else(|| CliError::Usage("--plan is required".into()))?;
+    if inputs.is_empty() {
+        return Err(CliError::Usage("no input files".into()));
+    }
+    if suffix.is_empty() && out_dir.is_none() {
+        return Err(CliError::Usage(
+            "an empty --suffix would overwrite the inputs; use --out-dir".into(),
+        ));
+    }
+    Ok(Args {
+        plan,
+        out_dir,
+        suffix,
+        inputs,
+    })
+}
+
+/// Files named by `inputs`, expanding glob patterns, in order and without
+/// repeats
+fn expand(inputs: &[String]) -> Result<Vec<PathBuf>, CliError> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if !input.contains(['*', '?', '[']) {
+            files.push(PathBuf::from(input));
+            continue;
+        }
+        let pattern_error = |detail: String| CliError::Pattern(input.clone(), detail);
+        let matches = glob::glob(input).map_err(|e| pattern_error(e.to_string()))?;
+        let before = files.len();
+        for entry in matches {
+            let path = entry.map_err(|e| pattern_error(e.to_string()))?;
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+        if files.len() == before {
+            return Err(pattern_error("matches no files".into()));
+        }
+    }
+    let mut seen = std::collections::HashSet::new();
+    files.retain(|path| seen.insert(path.clone()));
+    Ok(files)
+}
+
+fn is_output(path: &Path, suffix: &str) -> bool {
+    !suffix.is_empty()
+        && path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .is_some_and(|stem| stem.ends_with(suffix))
+}
+
+/// Where the redacted copy of `input` goes
+fn output_path(input: &Path, out_dir: Option<&Path>, suffix: &str) -> PathBuf {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}{}", stem, suffix);
+    if let Some(ext) = input.extension() {
+        name = format!("{}.{}", name, ext.to_string_lossy());
+    }
+    match out_dir {
+        Some(dir) => dir.join(name),
+        None => input.with_file_name(name),
+    }
+}
+
+/// Decode `input` as it is displayed: cameras store photos sideways with
+/// an EXIF orientation tag, and the plan's coordinates are upright
+fn open_upright(input: &Path) -> Result<DynamicImage, CliError> {
+    let image_error = |e| CliError::Image(input.into(), e);
+    let mut decoder = ImageReader::open(input)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| CliError::Io(input.into(), e))?
+        .into_decoder()
+        .map_err(image_error)?;
+    let orientation = decoder.orientation().map_err(image_error)?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(image_error)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+fn redact(input: &Path, output: &Path, plan: &RedactionPlan) -> Result<(), CliError> {
+    let mut pixels = open_upright(input)?.to_rgba8();
+    let (width, height) = pixels.dimensions();
+    plan.apply_to(&mut pixels, width, height)
+        .map_err(|e| CliError::Redact(input.into(), e))?;
+    save(pixels, output)
+}
+
+fn save(pixels: RgbaImage, output: &Path) -> Result<(), CliError> {
+    let image = DynamicImage::ImageRgba8(pixels);
+    // The JPEG encoder has no alpha channel
+    let image = match ImageFormat::from_path(output) {
+        Ok(ImageFormat::Jpeg) => DynamicImage::ImageRgb8(image.to_rgb8()),
+        _ => image,
+    };
+    image
+        .save(output)
+        .map_err(|e| CliError::Image(output.into(), e))
+}
+
+fn run(args: Args) -> Result<usize, CliError> {
+    let text =
+        std::fs::read_to_string(&args.plan).map_err(|e| CliError::Io(args.plan.clone(), e))?;
+    let plan = RedactionPlan::parse(&text).map_err(|e| CliError::Redact(args.plan.clone(), e))?;
+    if let Some(dir) = &args.out_dir {
+        std::fs::create_dir_all(dir).map_err(|e| CliError::Io(dir.clone(), e))?;
+    }
+
+    let files = expand(&args.inputs)?;
+    let mut jobs: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut claimed: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for input in files.into_iter().filter(|f| !is_output(f, &args.suffix)) {
+        let output = output_path(&input, args.out_dir.as_deref(), &args.suffix);
+        if output == input {
+            return Err(CliError::Usage(format!(
+                "{} would be overwritten; use a --suffix or another --out-dir",
+                input.display()
+            )));
+        }
+        if let Some(other) = claimed.insert(output.clone(), input.clone()) {
+            return Err(CliError::Usage(format!(
+                "{} and {} would both be written to {}",
+                other.display(),
+                input.display(),
+                output.display()
+            )));
+        }
+        jobs.push((input, output));
+    }
+
+    let mut failed = 0;
+    for (input, output) in &jobs {
+        match redact(input, output, &plan) {
+            Ok(()) => println!("{} -> {}", input.display(), output.display()),
+            Err(error) => {
+                eprintln!("redactr: {}", error);
+                failed += 1;
+            }
+        }
+    }
+    Ok(failed)
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(std::env::args().skip(1)).and_then(run);
+    match result {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(failed) => {
+            eprintln!("redactr: {} file(s) failed", failed);
+            ExitCode::FAILURE
+        }
+        Err(error) => {
+            eprintln!("redactr: {}", error);
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<Args, CliError> {
+        parse_args(list.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = args(&["--plan", "p.json", "a.png", "-o", "out", "shots/*.jpg"]).unwrap();
+        assert_eq!(
+            parsed,
+            Args {
+                plan: "p.json".into(),
+                out_dir: Some("out".into()),
+                suffix: DEFAULT_SUFFIX.into(),
+                inputs: vec!["a.png".into(), "shots/*.jpg".into()],
+            }
+        );
+        assert!(args(&["a.png"]).is_err());
+        assert!(args(&["--plan", "p.json"]).is_err());
+        assert!(args(&["--plan", "p.json", "--suffix", "", "a.png"]).is_err());
+        assert!(args(&["--plan", "p.json", "--bogus", "a.png"]).is_err());
+    }
+
+    #[test]
+    fn test_output_paths() {
+        let input = Path::new("shots/login.png");
+        assert_eq!(
+            output_path(input, None, ".redacted"),
+            Path::new("shots/login.redacted.png")
+        );
+        assert_eq!(
+            output_path(input, Some(Path::new("out")), ""),
+            Path::new("out/login.png")
+        );
+        assert!(is_output(
+            Path::new("shots/login.redacted.png"),
+            ".redacted"
+        ));
+        assert!(!is_output(input, ".redacted"));
+    }
+
+    #[test]
+    fn test_batch_redacts_a_glob() {
+        let dir = std::env::temp_dir().join(format!("redactr-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let gray = RgbaImage::from_pixel(20, 10, image::Rgba([128, 128, 128, 255]));
+        for name in ["a.png", "b.png"] {
+            gray.save(dir.join(name)).unwrap();
+        }
+        let plan = dir.join("plan.json");
+        std::fs::write(
+            &plan,
+            r##"{"version": 1, "width": 40, "height": 20, "regions": [
+                {"rect": {"x": 0, "y": 0, "w": 20, "h": 20}, "effect": "solid_fill",
+                 "params": {"color": "#000000"}}]}"##,
+        )
+        .unwrap();
+        let pattern = format!("{}/*.png", dir.display());
+        let failed = run(args(&["--plan", plan.to_str().unwrap(), &pattern]).unwrap()).unwrap();
+        assert_eq!(failed, 0);
+
+        // The plan is scaled to the image: its left half is now black
+        let out = image::open(dir.join("a.redacted.png")).unwrap().to_rgba8();
+        assert_eq!(out.get_pixel(9, 5).0, [0, 0, 0, 255]);
+        assert_eq!(out.get_pixel(10, 5).0, [128, 128, 128, 255]);
+        assert!(dir.join("b.redacted.png").exists());
+
+        // A rerun skips the outputs instead of redacting them again
+        let jobs = expand(&[pattern]).unwrap();
+        assert_eq!(
+            jobs.iter().filter(|f| !is_output(f, ".redacted")).count(),
+            2
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// APP1 segment holding just an EXIF orientation tag
+    fn exif_orientation(value: u8) -> Vec<u8> {
+        let mut tiff = b"MM\0*\0\0\0\x08\0\x01".to_vec();
+        // Tag 0x0112, SHORT, count 1, then no further IFD
+        tiff.extend_from_slice(&[1, 0x12, 0, 3, 0, 0, 0, 1, 0, value, 0, 0, 0, 0, 0, 0]);
+        let mut segment = vec![0xFF, 0xE1, 0, (2 + 6 + tiff.len()) as u8];
+        segment.extend_from_slice(b"Exif\0\0");
+        segment.extend_from_slice(&tiff);
+        segment
+    }
+
+    #[test]
+    fn test_exif_rotated_jpeg_is_redacted_upright() {
+        let dir = std::env::temp_dir().join(format!("redactr-exif-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Stored 32x16 with a white left half; orientation 6 displays it
+        // rotated 90° clockwise, 16x32 with the white half on top
+        let stored = RgbaImage::from_fn(32, 16, |x, _| {
+            let v = if x < 16 { 255 } else { 128 };
+            image::Rgba([v, v, v, 255])
+        });
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgba8(stored)
+            .to_rgb8()
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+            .unwrap();
+        jpeg.splice(2..2, exif_orientation(6));
+        let input = dir.join("photo.jpg");
+        std::fs::write(&input, jpeg).unwrap();
+
+        // Black out the displayed top half
+        let plan = RedactionPlan::parse(
+            r##"{"version": 1, "width": 16, "height": 32, "regions": [
+                {"rect": {"x": 0, "y": 0, "w": 16, "h": 16}, "effect": "solid_fill",
+                 "params": {"color": "#000000"}}]}"##,
+        )
+        .unwrap();
+        let output = dir.join("photo.redacted.jpg");
+        redact(&input, &output, &plan).unwrap();
+
+        let out = image::open(&output).unwrap().to_rgba8();
+        assert_eq!(out.dimensions(), (16, 32));
+        // The white half is the one blacked out
+        assert!(out.get_pixel(8, 8).0[0] < 16);
+        assert!(out.get_pixel(8, 24).0[0].abs_diff(128) < 16);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,248 @@
+//! Separable gaussian blur confined to a region.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::math::{expf, roundf, sqrtf};
+use crate::scratch::Scratch;
+use crate::simd;
+
+/// Gaussian-blur a region; pixels outside it are neither changed nor
+/// sampled
+pub fn gaussian_blur(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    radius: u32,
+) {
+    if let Some(mut pass) = BlurPass::new(data, width, height, x, y, w, h, radius) {
+        #[cfg(feature = "rayon")]
+        if crate::par::worth_splitting(pass.region_w, pass.region_h) {
+            pass.par_blur(data);
+            return;
+        }
+        let rows = pass.region_h;
+        pass.horizontal_rows(0, rows);
+        pass.vertical_rows(data, 0, rows);
+    }
+}
+
+/// Blur radius in pixels for a radius given as a fraction of the image
+/// width; any positive fraction blurs by at least one pixel, and none by
+/// more than the width
+pub fn relative_radius(width: u32, fraction: f32) -> u32 {
+    if fraction.is_nan() || fraction <= 0.0 {
+        return 0;
+    }
+    (roundf(width as f32 * fraction) as u32).clamp(1, width.max(1))
+}
+
+/// Scratch state for a separable gaussian blur over one region, split into
+/// row ranges so the passes can also be driven incrementally.
+pub struct BlurPass {
+    pub(crate) width: u32,
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) region_w: usize,
+    pub(crate) region_h: usize,
+    pub(crate) kernel: Vec<f32>,
+    pub(crate) temp: Scratch,
+    pub(crate) h_pass: Scratch,
+}
+
+impl BlurPass {
+    /// Copy the region out of `data`; returns `None` when there is nothing to blur.
+    pub fn new(
+        data: &[u8],
+        width: u32,
+        height: u32,
+        x: u32,
+        y: u32,
+        w: u32,
+        h: u32,
+        radius: u32,
+    ) -> Option<Self> {
+        if radius == 0 {
+            return None;
+        }
+
+        let x_end = (x + w).min(width);
+        let y_end = (y + h).min(height);
+
+        // Create a copy of the region for reading
+        let region_w = x_end.saturating_sub(x) as usize;
+        let region_h = y_end.saturating_sub(y) as usize;
+        if region_w == 0 || region_h == 0 {
+            return None;
+        }
+        let mut temp = Scratch::zeroed(region_w * region_h * 4);
+
+        // Copy region to temp buffer
+        for py in y..y_end {
+            for px in x..x_end {
+                let src_idx = ((py * width + px) * 4) as usize;
+                let dst_idx = ((py - y) as usize * region_w + (px - x) as usize) * 4;
+                if src_idx + 3 < data.len() && dst_idx + 3 < temp.len() {
+                    temp[dst_idx] = data[src_idx];
+                    temp[dst_idx + 1] = data[src_idx + 1];
+                    temp[dst_idx + 2] = data[src_idx + 2];
+                    temp[dst_idx + 3] = data[src_idx + 3];
+                }
+            }
+        }
+
+        Some(Self {
+            width,
+            x,
+            y,
+            region_w,
+            region_h,
+            kernel: gaussian_kernel(radius),
+            h_pass: Scratch::zeroed(temp.len()),
+            temp,
+        })
+    }
+
+    /// Rows in the region, the end of the range each pass runs over
+    pub fn rows(&self) -> usize {
+        self.region_h
+    }
+
+    /// Horizontal pass over region rows `start..end`
+    pub fn horizontal_rows(&mut self, start: usize, end: usize) {
+        let row = self.region_w * 4;
+        for py in start..end.min(self.region_h) {
+            let out = &mut self.h_pass[py * row..(py + 1) * row];
+            Self::horizontal_row(&self.temp, &self.kernel, self.region_w, py, out);
+        }
+    }
+
+    /// Horizontal pass over region row `py` of `temp` into `out`
+    pub(crate) fn horizontal_row(
+        temp: &[u8],
+        kernel: &[f32],
+        region_w: usize,
+        py: usize,
+        out: &mut [u8],
+    ) {
+        let half_kernel = (kernel.len() / 2) as i32;
+        for px in 0..region_w {
+            // Kernel taps that land inside the region
+            let lo = (half_kernel - px as i32).max(0) as usize;
+            let hi = (region_w as i32 + half_kernel - px as i32).min(kernel.len() as i32);
+            let weights = &kernel[lo..hi as usize];
+            let first = (py * region_w + px + lo - half_kernel as usize) * 4;
+            let [sum_r, sum_g, sum_b] = simd::weighted_rgb(temp, first, 4, weights);
+            let sum_weight: f32 = weights.iter().sum();
+
+            let idx = px * 4;
+            out[idx] = (sum_r / sum_weight) as u8;
+            out[idx + 1] = (sum_g / sum_weight) as u8;
+            out[idx + 2] = (sum_b / sum_weight) as u8;
+            out[idx + 3] = temp[(py * region_w + px) * 4 + 3];
+        }
+    }
+
+    /// Vertical pass over region rows `start..end`, writing back into `data`.
+    /// Every row of the horizontal pass must be complete first.
+    pub fn vertical_rows(&self, data: &mut [u8], start: usize, end: usize) {
+        let row_bytes = self.width as usize * 4;
+        for py in start..end.min(self.region_h) {
+            let row_start = (py + self.y as usize) * row_bytes;
+            if row_start >= data.len() {
+                break;
+            }
+            let row_end = (row_start + row_bytes).min(data.len());
+            self.vertical_row(py, &mut data[row_start..row_end]);
+        }
+    }
+
+    /// Vertical pass over region row `py` into `row`, the image row it
+    /// lies on (cut short if the buffer is)
+    pub(crate) fn vertical_row(&self, py: usize, row: &mut [u8]) {
+        let region_w = self.region_w;
+        let half_kernel = (self.kernel.len() / 2) as i32;
+        let lo = (half_kernel - py as i32).max(0) as usize;
+        let hi = (self.region_h as i32 + half_kernel - py as i32).min(self.kernel.len() as i32);
+        let weights = &self.kernel[lo..hi as usize];
+        let sum_weight: f32 = weights.iter().sum();
+        let first_row = py + lo - half_kernel as usize;
+        for px in 0..region_w {
+            let first = (first_row * region_w + px) * 4;
+            let [sum_r, sum_g, sum_b] =
+                simd::weighted_rgb(&self.h_pass, first, region_w * 4, weights);
+
+            let dst_idx = (px + self.x as usize) * 4;
+            if dst_idx + 2 < row.len() {
+                row[dst_idx] = (sum_r / sum_weight) as u8;
+                row[dst_idx + 1] = (sum_g / sum_weight) as u8;
+                row[dst_idx + 2] = (sum_b / sum_weight) as u8;
+            }
+        }
+    }
+}
+
+/// The `2 * radius + 1` taps of a gaussian with sigma `radius / 2`
+pub fn gaussian_kernel(radius: u32) -> Vec<f32> {
+    let size = (radius * 2 + 1) as usize;
+    let sigma = radius as f32 / 2.0;
+    let mut kernel = vec![0.0f32; size];
+
+    let two_sigma_sq = 2.0 * sigma * sigma;
+    let norm = 1.0 / sqrtf(core::f32::consts::PI * two_sigma_sq);
+
+    for (i, k) in kernel.iter_mut().enumerate() {
+        let x = i as f32 - radius as f32;
+        *k = norm * expf(-x * x / two_sigma_sq);
+    }
+
+    kernel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_gaussian_kernel() {
+        let kernel = gaussian_kernel(2);
+
+        // Kernel size should be 2*radius + 1 = 5
+        assert_eq!(kernel.len(), 5);
+
+        // Center should be the largest value
+        let center_idx = 2;
+        for (i, &val) in kernel.iter().enumerate() {
+            if i != center_idx {
+                assert!(kernel[center_idx] >= val);
+            }
+        }
+
+        // Kernel should be symmetric
+        assert!((kernel[0] - kernel[4]).abs() < 0.0001);
+        assert!((kernel[1] - kernel[3]).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_incremental_passes_match_one_call() {
+        let (w, h) = (30, 20);
+        let image: Vec<u8> = (0..w * h * 4).map(|i| (i * 37 % 251) as u8).collect();
+        let mut whole = image.clone();
+        gaussian_blur(&mut whole, w, h, 3, 2, 20, 15, 3);
+
+        let mut stepped = image.clone();
+        let mut pass = BlurPass::new(&stepped, w, h, 3, 2, 20, 15, 3).unwrap();
+        for start in (0..pass.rows()).step_by(4) {
+            pass.horizontal_rows(start, start + 4);
+        }
+        for start in (0..pass.rows()).step_by(7) {
+            pass.vertical_rows(&mut stepped, start, start + 7);
+        }
+        assert_eq!(stepped, whole);
+        assert_ne!(whole, image);
+    }
+}
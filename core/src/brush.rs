@@ -0,0 +1,228 @@
+//! Freehand brush strokes: the pixels within half the brush size of a
+//! polyline.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Visit every pixel within `brush_size / 2` of the stroke through `points`
+/// (flat `[x1, y1, x2, y2, ...]`). Consecutive points are joined by the
+/// segment between them, so the stroke stays continuous however sparsely
+/// the pointer was sampled; pixels where segments overlap are visited more
+/// than once.
+pub fn for_each_brush_pixel(
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    mut visit: impl FnMut(u32, u32),
+) {
+    let radius = (brush_size / 2) as i32;
+    let r = radius as f32;
+    let radius_sq = r * r;
+    let centers: Vec<(f32, f32)> = points
+        .chunks_exact(2)
+        .map(|p| ((p[0] as i32) as f32, (p[1] as i32) as f32))
+        .collect();
+    let segments = centers.windows(2).map(|w| (w[0], w[1])).chain(
+        centers
+            .first()
+            .filter(|_| centers.len() == 1)
+            .map(|&c| (c, c)),
+    );
+
+    for ((ax, ay), (bx, by)) in segments {
+        let (dx, dy) = (bx - ax, by - ay);
+        let len_sq = dx * dx + dy * dy;
+        // Saturating, so far-off points and huge brushes clamp to the image
+        let x0 = (ax.min(bx) as i32).saturating_sub(radius).max(0);
+        let y0 = (ay.min(by) as i32).saturating_sub(radius).max(0);
+        let x1 = (ax.max(bx) as i32)
+            .saturating_add(radius)
+            .min(width as i32 - 1);
+        let y1 = (ay.max(by) as i32)
+            .saturating_add(radius)
+            .min(height as i32 - 1);
+        for py in y0..=y1 {
+            for px in x0..=x1 {
+                let (ux, uy) = (px as f32 - ax, py as f32 - ay);
+                // Closest point on the segment
+                let t = if len_sq > 0.0 {
+                    ((ux * dx + uy * dy) / len_sq).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let (ex, ey) = (ux - t * dx, uy - t * dy);
+                if ex * ex + ey * ey <= radius_sq {
+                    visit(px as u32, py as u32);
+                }
+            }
+        }
+    }
+}
+
+/// Fill the pixels under a brush stroke with one color
+pub fn brush_solid_fill(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32], // Flat array: [x1, y1, x2, y2, ...]
+    brush_size: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+) {
+    for_each_brush_pixel(width, height, points, brush_size, |px, py| {
+        let idx = ((py * width + px) * 4) as usize;
+        if idx + 2 < data.len() {
+            data[idx] = r;
+            data[idx + 1] = g;
+            data[idx + 2] = b;
+        }
+    });
+}
+
+/// Pixelate the pixels under a brush stroke; blocks are anchored at the
+/// stroke's bounding box and average all of their pixels
+pub fn brush_pixelate(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    block_size: u32,
+) {
+    // Create a mask of affected pixels
+    let mut mask = vec![false; (width * height) as usize];
+    for_each_brush_pixel(width, height, points, brush_size, |px, py| {
+        mask[(py * width + px) as usize] = true;
+    });
+
+    // Find bounding box
+    let mut min_x = width;
+    let mut min_y = height;
+    let mut max_x = 0u32;
+    let mut max_y = 0u32;
+
+    for y in 0..height {
+        for x in 0..width {
+            if mask[(y * width + x) as usize] {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        }
+    }
+
+    if min_x > max_x {
+        return;
+    }
+
+    // Pixelate with mask
+    let block_size = block_size.max(1);
+    let mut by = min_y;
+    while by <= max_y {
+        let mut bx = min_x;
+        while bx <= max_x {
+            let block_w = block_size.min(max_x + 1 - bx);
+            let block_h = block_size.min(max_y + 1 - by);
+
+            // Check if any pixel in block is masked
+            let mut has_masked = false;
+            for py in by..(by + block_h) {
+                for px in bx..(bx + block_w) {
+                    if mask[(py * width + px) as usize] {
+                        has_masked = true;
+                        break;
+                    }
+                }
+                if has_masked {
+                    break;
+                }
+            }
+
+            if has_masked {
+                // Calculate average and apply only to masked pixels
+                let mut sum_r: u32 = 0;
+                let mut sum_g: u32 = 0;
+                let mut sum_b: u32 = 0;
+                let mut count: u32 = 0;
+
+                for py in by..(by + block_h) {
+                    for px in bx..(bx + block_w) {
+                        let idx = ((py * width + px) * 4) as usize;
+                        sum_r += data[idx] as u32;
+                        sum_g += data[idx + 1] as u32;
+                        sum_b += data[idx + 2] as u32;
+                        count += 1;
+                    }
+                }
+
+                if let Some(avg_r) = sum_r.checked_div(count) {
+                    let avg_r = avg_r as u8;
+                    let avg_g = (sum_g / count) as u8;
+                    let avg_b = (sum_b / count) as u8;
+
+                    for py in by..(by + block_h) {
+                        for px in bx..(bx + block_w) {
+                            if mask[(py * width + px) as usize] {
+                                let idx = ((py * width + px) * 4) as usize;
+                                data[idx] = avg_r;
+                                data[idx + 1] = avg_g;
+                                data[idx + 2] = avg_b;
+                            }
+                        }
+                    }
+                }
+            }
+
+            bx += block_size;
+        }
+        by += block_size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stroke_covers_the_segment() {
+        let mut hits = Vec::new();
+        for_each_brush_pixel(20, 10, &[2.0, 5.0, 17.0, 5.0], 4, |x, y| hits.push((x, y)));
+        assert!(hits.contains(&(2, 5)) && hits.contains(&(10, 5)) && hits.contains(&(17, 7)));
+        assert!(!hits.contains(&(10, 8)) && !hits.contains(&(19, 6)));
+    }
+
+    #[test]
+    fn test_extreme_points_and_sizes_clamp_to_the_image() {
+        let mut hits = 0;
+        for_each_brush_pixel(8, 8, &[-3.0e9, 4.0, 3.0e9, 4.0], 2, |_, _| hits += 1);
+        assert_eq!(hits, 8 * 3);
+        hits = 0;
+        for_each_brush_pixel(8, 8, &[4.0, 4.0], u32::MAX, |_, _| hits += 1);
+        assert_eq!(hits, 64);
+        hits = 0;
+        for_each_brush_pixel(8, 8, &[f32::MIN, f32::MAX], 4, |_, _| hits += 1);
+        assert_eq!(hits, 0);
+    }
+
+    #[test]
+    fn test_brush_fills_and_pixelates_only_the_stroke() {
+        let image: Vec<u8> = (0..16 * 16)
+            .flat_map(|i| [(i * 9 % 256) as u8, 40, 90, 255])
+            .collect();
+        let mut filled = image.clone();
+        brush_solid_fill(&mut filled, 16, 16, &[4.0, 4.0], 4, 0, 0, 0);
+        assert_eq!(filled[(4 * 16 + 4) * 4..][..4], [0, 0, 0, 255]);
+        assert_eq!(filled[..4], image[..4]);
+
+        let mut pixelated = image.clone();
+        brush_pixelate(&mut pixelated, 16, 16, &[8.0, 8.0], 6, 4);
+        assert_ne!(
+            pixelated[(8 * 16 + 8) * 4..][..4],
+            image[(8 * 16 + 8) * 4..][..4]
+        );
+        assert_eq!(pixelated[..64], image[..64]);
+    }
+}
@@ -0,0 +1,44 @@
+//! Solid color fill.
+
+/// Fill a region with one color, keeping each pixel's alpha
+pub fn solid_fill(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+) {
+    let x_end = (x + w).min(width);
+    let y_end = (y + h).min(height);
+
+    for py in y..y_end {
+        for px in x..x_end {
+            let idx = ((py * width + px) * 4) as usize;
+            if idx + 3 < data.len() {
+                data[idx] = r;
+                data[idx + 1] = g;
+                data[idx + 2] = b;
+                // Keep alpha unchanged
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_clamps_and_keeps_alpha() {
+        let mut data = [[10, 20, 30, 40]; 16].concat();
+        solid_fill(&mut data, 4, 4, 2, 3, 10, 10, 1, 2, 3);
+        assert_eq!(&data[(3 * 4 + 3) * 4..], [1, 2, 3, 40]);
+        assert_eq!(&data[(3 * 4 + 1) * 4..][..4], [10, 20, 30, 40]);
+        assert_eq!(data.chunks(4).filter(|px| px[0] == 1).count(), 2);
+    }
+}
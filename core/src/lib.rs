@@ -0,0 +1,41 @@
+//! Redactr's redaction effects over plain RGBA buffers.
+//!
+//! Every function takes the image as a tightly packed `width * height * 4`
+//! byte slice and a region as `x, y, w, h`, clamped to the image, and
+//! edits the pixels in place. This is the code behind the `redactr-wasm`
+//! bindings, usable directly from native tools and servers:
+//!
+//! ```
+//! let (width, height) = (64, 48);
+//! let mut data = vec![200u8; (width * height * 4) as usize];
+//! redactr_core::pixelate(&mut data, width, height, 8, 8, 32, 16, 8);
+//! redactr_core::gaussian_blur(&mut data, width, height, 0, 30, 64, 18, 6);
+//! ```
+//!
+//! Without the default `std` feature the crate is `no_std` and needs only
+//! `alloc`; float math falls back to `libm`. The `rayon` feature splits
+//! large blurs and pixelations across rayon's global pool, and `simd128`
+//! selects the wasm SIMD inner loops.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+// Image geometry is passed as flat scalars, as the wasm bindings do
+#![allow(clippy::too_many_arguments)]
+
+extern crate alloc;
+
+mod blur;
+mod brush;
+mod fill;
+mod math;
+#[cfg(feature = "rayon")]
+mod par;
+mod pixelate;
+pub mod scratch;
+mod simd;
+
+pub use blur::{gaussian_blur, gaussian_kernel, relative_radius, BlurPass};
+pub use brush::{brush_pixelate, brush_solid_fill, for_each_brush_pixel};
+pub use fill::solid_fill;
+pub use pixelate::{
+    block_size_for_min_blocks, pixelate, pixelate_aligned, pixelate_aligned_at, pixelate_band,
+};
@@ -0,0 +1,32 @@
+//! The few float functions `core` lacks, from std when there is one and
+//! `libm` otherwise.
+
+#[cfg(feature = "std")]
+pub(crate) fn expf(x: f32) -> f32 {
+    x.exp()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn expf(x: f32) -> f32 {
+    libm::expf(x)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn sqrtf(x: f32) -> f32 {
+    x.sqrt()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn sqrtf(x: f32) -> f32 {
+    libm::sqrtf(x)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn roundf(x: f32) -> f32 {
+    x.round()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn roundf(x: f32) -> f32 {
+    libm::roundf(x)
+}
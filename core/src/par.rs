@@ -0,0 +1,82 @@
+//! Gaussian blur and pixelation of large regions across rayon's pool.
+//!
+//! Regions of at least `MIN_PIXELS` are split by rows (bands of whole
+//! blocks for pixelation, rows of each pass for the blur) and run on the
+//! global pool. Every row is computed exactly as the single-threaded code
+//! does, so the output is identical.
+
+use rayon::prelude::*;
+
+use crate::BlurPass;
+
+/// Regions smaller than this are done faster than the pool can be woken
+const MIN_PIXELS: usize = 256 * 256;
+
+/// Whether a `w` x `h` region is big enough to split across threads
+pub(crate) fn worth_splitting(w: usize, h: usize) -> bool {
+    w * h >= MIN_PIXELS
+}
+
+/// Run `f` on each `band_bytes` chunk of `bands` in parallel
+pub(crate) fn for_each_band(
+    bands: &mut [u8],
+    band_bytes: usize,
+    f: impl Fn(&mut [u8]) + Sync + Send,
+) {
+    bands.par_chunks_mut(band_bytes).for_each(f);
+}
+
+impl BlurPass {
+    /// Both passes with their rows spread over the pool
+    pub(crate) fn par_blur(&mut self, data: &mut [u8]) {
+        let (temp, kernel, region_w) = (&self.temp, &self.kernel, self.region_w);
+        self.h_pass
+            .par_chunks_mut(region_w * 4)
+            .enumerate()
+            .for_each(|(py, out)| Self::horizontal_row(temp, kernel, region_w, py, out));
+
+        let row_bytes = self.width as usize * 4;
+        let start = (self.y as usize * row_bytes).min(data.len());
+        let end = ((self.y as usize + self.region_h) * row_bytes).min(data.len());
+        data[start..end]
+            .par_chunks_mut(row_bytes)
+            .enumerate()
+            .for_each(|(py, row)| self.vertical_row(py, row));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height * 4)
+            .map(|i| (i * 31 % 255) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_split_blur_matches_one_thread() {
+        let (w, h) = (300, 260);
+        let mut split = pattern(w, h);
+        crate::gaussian_blur(&mut split, w, h, 5, 3, 290, 250, 6);
+        let mut single = pattern(w, h);
+        let mut pass = BlurPass::new(&single, w, h, 5, 3, 290, 250, 6).unwrap();
+        pass.horizontal_rows(0, pass.rows());
+        pass.vertical_rows(&mut single, 0, pass.rows());
+        assert_eq!(split, single);
+    }
+
+    #[test]
+    fn test_split_pixelate_matches_one_thread() {
+        let (w, h) = (300, 260);
+        let mut split = pattern(w, h);
+        crate::pixelate(&mut split, w, h, 7, 2, 280, 255, 9);
+        let mut single = pattern(w, h);
+        for band in single[2 * 300 * 4..257 * 300 * 4].chunks_mut(9 * 300 * 4) {
+            crate::pixelate_band(band, w, 7, 287, 9);
+        }
+        assert_ne!(split, pattern(w, h));
+        assert_eq!(split, single);
+    }
+}
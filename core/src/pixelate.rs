@@ -0,0 +1,255 @@
+//! Pixelation: each block of the region takes its mean color.
+
+use crate::simd;
+
+/// Pixelate a region in blocks of `block_size` pixels, anchored at its
+/// top-left corner
+pub fn pixelate(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    block_size: u32,
+) {
+    let block_size = block_size.max(1);
+    let x_end = (x + w).min(width);
+    let y_end = (y + h).min(height);
+    if x >= x_end || y >= y_end {
+        return;
+    }
+
+    // Each band of `block_size` rows is independent of the others
+    let row_bytes = width as usize * 4;
+    let start = (y as usize * row_bytes).min(data.len());
+    let end = (y_end as usize * row_bytes).min(data.len());
+    let bands = &mut data[start..end];
+    let band_bytes = block_size as usize * row_bytes;
+    #[cfg(feature = "rayon")]
+    if crate::par::worth_splitting((x_end - x) as usize, (y_end - y) as usize) {
+        crate::par::for_each_band(bands, band_bytes, |band| {
+            pixelate_band(band, width, x, x_end, block_size)
+        });
+        return;
+    }
+    for band in bands.chunks_mut(band_bytes) {
+        pixelate_band(band, width, x, x_end, block_size);
+    }
+}
+
+/// Pixelate columns `x..x_end` of `band`, whole image rows starting at a
+/// block boundary (the last may be cut short by the end of the buffer)
+pub fn pixelate_band(band: &mut [u8], width: u32, x: u32, x_end: u32, block_size: u32) {
+    let rows = band.len().div_ceil(width as usize * 4) as u32;
+    let mut bx = x;
+    while bx < x_end {
+        let block_w = block_size.min(x_end - bx);
+
+        // Calculate average color for this block
+        let mut sum_r: u32 = 0;
+        let mut sum_g: u32 = 0;
+        let mut sum_b: u32 = 0;
+        let mut count: u32 = 0;
+
+        for py in 0..rows {
+            let start = ((py * width + bx) * 4) as usize;
+            // Pixels of the row whose RGB lies inside `band`
+            let n = match band.len().checked_sub(start + 3) {
+                Some(room) => (room / 4 + 1).min(block_w as usize),
+                None => 0,
+            };
+            let end = (start + n * 4).min(band.len());
+            let [r, g, b] = simd::sum_rgb(&band[start.min(end)..end]);
+            sum_r += r;
+            sum_g += g;
+            sum_b += b;
+            count += n as u32;
+        }
+
+        if let Some(avg_r) = sum_r.checked_div(count) {
+            let avg_r = avg_r as u8;
+            let avg_g = (sum_g / count) as u8;
+            let avg_b = (sum_b / count) as u8;
+
+            // Apply average color to entire block
+            for py in 0..rows {
+                for px in bx..(bx + block_w) {
+                    let idx = ((py * width + px) * 4) as usize;
+                    if idx + 2 < band.len() {
+                        band[idx] = avg_r;
+                        band[idx + 1] = avg_g;
+                        band[idx + 2] = avg_b;
+                    }
+                }
+            }
+        }
+
+        bx += block_size;
+    }
+}
+
+/// `block_size` shrunk so a `w` x `h` region splits into at least
+/// `min_blocks` blocks along each side, since a region covered by one or
+/// two blocks is little more than a solid fill of its average color, crisp
+/// enough to leave large text readable. A `min_blocks` of 0 leaves it as is.
+pub fn block_size_for_min_blocks(w: u32, h: u32, block_size: u32, min_blocks: u32) -> u32 {
+    match w.min(h).checked_div(min_blocks) {
+        Some(largest) => block_size.min(largest).max(1),
+        None => block_size,
+    }
+}
+
+/// `pixelate` with the block grid anchored at the image origin instead of
+/// the region's corner. Each block takes the mean of its whole grid cell,
+/// including any part outside the region, so a block's color depends only
+/// on where it sits in the image: moving the region by a pixel leaves every
+/// block it still covers unchanged.
+pub fn pixelate_aligned(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    block_size: u32,
+) {
+    pixelate_aligned_at(data, width, height, x, y, w, h, block_size, (0, 0))
+}
+
+/// `pixelate_aligned` on a buffer cut out of a larger image, whose top-left
+/// pixel sits at `origin` in it: the grid stays anchored at the image
+/// origin. Cells cut by the buffer's edge average only their part inside it.
+pub fn pixelate_aligned_at(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    block_size: u32,
+    origin: (u32, u32),
+) {
+    let block_size = block_size.max(1);
+    let x_end = (x + w).min(width);
+    let y_end = (y + h).min(height);
+    // Start of the cell holding `start`, clipped to the buffer
+    let cell_start = |start: u32, offset: u32| start.saturating_sub((start + offset) % block_size);
+    let mut by = cell_start(y, origin.1);
+    while by < y_end {
+        let cell_y_end = (by + block_size - (by + origin.1) % block_size).min(height);
+        let mut bx = cell_start(x, origin.0);
+        while bx < x_end {
+            let cell_x_end = (bx + block_size - (bx + origin.0) % block_size).min(width);
+            let (mut sum, mut count) = ([0u32; 3], 0u32);
+            for py in by..cell_y_end {
+                let start = ((py * width + bx) * 4) as usize;
+                let end = ((py * width + cell_x_end) * 4) as usize;
+                let Some(row) = data.get(start..end) else {
+                    continue;
+                };
+                let [r, g, b] = simd::sum_rgb(row);
+                sum = [sum[0] + r, sum[1] + g, sum[2] + b];
+                count += cell_x_end - bx;
+            }
+            if count > 0 {
+                let avg = sum.map(|c| (c / count) as u8);
+                for py in by.max(y)..cell_y_end.min(y_end) {
+                    for px in bx.max(x)..cell_x_end.min(x_end) {
+                        let idx = ((py * width + px) * 4) as usize;
+                        if let Some(pixel) = data.get_mut(idx..idx + 3) {
+                            pixel.copy_from_slice(&avg);
+                        }
+                    }
+                }
+            }
+            bx = cell_x_end;
+        }
+        by = cell_y_end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_take_their_mean() {
+        // Two columns of 0 and 100 average to 50 in 2x2 blocks
+        let mut data: Vec<u8> = (0..16)
+            .flat_map(|i| [(i % 2) as u8 * 100, 0, 0, 255])
+            .collect();
+        pixelate(&mut data, 4, 4, 0, 0, 4, 4, 2);
+        assert!(data.chunks(4).all(|px| px == [50, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_aligned_blocks_ignore_the_region_origin() {
+        let image: Vec<u8> = (0..24 * 16)
+            .flat_map(|i| [(i * 37 % 256) as u8, (i * 11 % 256) as u8, 60, 255])
+            .collect();
+        let mut a = image.clone();
+        pixelate_aligned(&mut a, 24, 16, 3, 2, 16, 12, 4);
+        let mut b = image.clone();
+        pixelate_aligned(&mut b, 24, 16, 4, 3, 16, 12, 4);
+        // Wherever both regions cover a pixel, it got the same block color
+        for y in 3..14 {
+            for x in 4..19 {
+                let i = (y * 24 + x) * 4;
+                assert_eq!(a[i..i + 4], b[i..i + 4], "({}, {})", x, y);
+            }
+        }
+        // Outside the region nothing changes, even in a partly covered cell
+        assert_eq!(a[(2 * 24 + 2) * 4..][..4], image[(2 * 24 + 2) * 4..][..4]);
+        // The region-anchored grid does shift
+        let mut c = image.clone();
+        pixelate(&mut c, 24, 16, 4, 3, 16, 12, 4);
+        assert_ne!(c, b);
+    }
+
+    #[test]
+    fn test_origin_keeps_the_image_grid() {
+        let image: Vec<u8> = (0..24 * 16)
+            .flat_map(|i| [(i * 37 % 256) as u8, (i * 11 % 256) as u8, 60, 255])
+            .collect();
+        let mut whole = image.clone();
+        pixelate_aligned(&mut whole, 24, 16, 8, 4, 12, 8, 4);
+        // A cut-out starting on a cell boundary matches the whole image
+        let mut cut: Vec<u8> = (4..12)
+            .flat_map(|y| image[(y * 24 + 8) * 4..(y * 24 + 20) * 4].to_vec())
+            .collect();
+        pixelate_aligned_at(&mut cut, 12, 8, 0, 0, 12, 8, 4, (8, 4));
+        for (row, y) in (4..12).enumerate() {
+            assert_eq!(cut[row * 48..][..48], whole[(y * 24 + 8) * 4..][..48]);
+        }
+        // Off a boundary the grid lines stay put: rows 0 and 1 of a cut-out
+        // at y = 6 end the cell that started at image row 4
+        let mut shifted = image[6 * 24 * 4..10 * 24 * 4].to_vec();
+        pixelate_aligned_at(&mut shifted, 24, 4, 0, 0, 24, 4, 4, (0, 6));
+        assert_eq!(shifted[..4], shifted[24 * 4..][..4]);
+        assert_ne!(shifted[24 * 4..][..4], shifted[2 * 24 * 4..][..4]);
+    }
+
+    #[test]
+    fn test_min_blocks_caps_the_block_size() {
+        assert_eq!(block_size_for_min_blocks(40, 20, 16, 4), 5);
+        assert_eq!(block_size_for_min_blocks(40, 20, 4, 4), 4);
+        assert_eq!(block_size_for_min_blocks(40, 20, 16, 0), 16);
+        assert_eq!(block_size_for_min_blocks(3, 3, 16, 4), 1);
+        // The floor division never leaves fewer blocks than asked for
+        for side in 1..64u32 {
+            let block = block_size_for_min_blocks(side, 64, 64, 3);
+            assert!(side.div_ceil(block) >= 3.min(side), "{}", side);
+        }
+    }
+
+    #[test]
+    fn test_short_buffer_is_not_overrun() {
+        let mut data = vec![90u8; 4 * 4 * 4 - 6];
+        pixelate(&mut data, 4, 4, 0, 0, 4, 4, 3);
+        assert_eq!(data.len(), 58);
+    }
+}
@@ -0,0 +1,86 @@
+//! Scratch buffers that may hold unredacted pixels.
+//!
+//! Region copies and blur passes outlive the pixels they were taken from,
+//! and freed memory isn't cleared. With `set_zeroize_scratch(true)` every
+//! such buffer is overwritten with zeros when it is dropped. The setting
+//! is process-wide, so it also covers buffers dropped on pool threads.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{compiler_fence, AtomicBool, Ordering};
+
+static ZEROIZE: AtomicBool = AtomicBool::new(false);
+
+/// Zeroize scratch buffers when they're dropped (off by default)
+pub fn set_zeroize_scratch(enabled: bool) {
+    ZEROIZE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn zeroize_scratch_enabled() -> bool {
+    ZEROIZE.load(Ordering::Relaxed)
+}
+
+/// Overwrite `buf` with zeros in a way the optimizer can't drop
+pub fn zeroize(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        // SAFETY: `byte` is a valid, exclusive reference
+        unsafe { core::ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Byte buffer zeroized on drop when zeroizing is enabled
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scratch(Vec<u8>);
+
+impl Scratch {
+    pub fn zeroed(len: usize) -> Self {
+        Self(vec![0; len])
+    }
+
+    /// Zeroize now, whatever the global setting
+    pub fn wipe(&mut self) {
+        zeroize(&mut self.0);
+    }
+}
+
+impl From<Vec<u8>> for Scratch {
+    fn from(buf: Vec<u8>) -> Self {
+        Self(buf)
+    }
+}
+
+impl Deref for Scratch {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for Scratch {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        if zeroize_scratch_enabled() {
+            self.wipe();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wipe_zeroes_contents() {
+        let mut scratch = Scratch::from(vec![1, 2, 3, 4]);
+        scratch.wipe();
+        assert_eq!(*scratch, [0, 0, 0, 0]);
+    }
+}
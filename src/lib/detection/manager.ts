@@ -149,6 +149,35 @@ async function initTesseract(): Promise<Tesseract.Worker> {
   return tesseractWorker;
 }
 
+export interface OcrWord {
+  confidence: number;
+  text: string;
+  bbox: { x0: number; y0: number; x1: number; y1: number };
+  // Per-character boxes, when Tesseract reports them
+  symbols?: Array<{ text: string; bbox: { x0: number; y0: number; x1: number; y1: number } }>;
+}
+
+// Recognize the words in an image with the shared Tesseract worker
+export async function recognizeWords(imageData: ImageData): Promise<OcrWord[]> {
+  const worker = await initTesseract();
+
+  // Convert ImageData to canvas for Tesseract
+  const canvas = document.createElement("canvas");
+  canvas.width = imageData.width;
+  canvas.height = imageData.height;
+  const ctx = canvas.getContext("2d");
+  if (!ctx) throw new Error("Could not get 2d context");
+  ctx.putImageData(imageData, 0, 0);
+
+  // Get data URL for Tesseract
+  const dataUrl = canvas.toDataURL("image/png");
+
+  // Recognize text
+  const result = await worker.recognize(dataUrl);
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  return ((result.data as any).words as OcrWord[] | undefined) ?? [];
+}
+
 // Run text detection using Tesseract.js
 async function runTextDetection(): Promise<void> {
   const state = get(detectionStore);
@@ -164,34 +193,11 @@ async function runTextDetection(): Promise<void> {
   }
 
   try {
-    const worker = await initTesseract();
-
-    // Convert ImageData to canvas for Tesseract
-    const canvas = document.createElement("canvas");
-    canvas.width = imageData.width;
-    canvas.height = imageData.height;
-    const ctx = canvas.getContext("2d");
-    if (!ctx) throw new Error("Could not get 2d context");
-    ctx.putImageData(imageData, 0, 0);
-
-    // Get data URL for Tesseract
-    const dataUrl = canvas.toDataURL("image/png");
-
-    // Recognize text
-    const result = await worker.recognize(dataUrl);
+    const words = await recognizeWords(imageData);
 
     // Convert words to detections
     const textDetections: Detection[] = [];
 
-    // eslint-disable-next-line @typescript-eslint/no-explicit-any
-    const words = (result.data as any).words as
-      | Array<{
-          confidence: number;
-          text: string;
-          bbox: { x0: number; y0: number; x1: number; y1: number };
-        }>
-      | undefined;
-
     if (words) {
       for (const word of words) {
         if (word.confidence > 60 && word.text.trim().length > 0) {
@@ -0,0 +1,42 @@
+// @ts-nocheck
+import { describe, it, expect } from 'vitest';
+import { splitBands } from '../pool';
+
+describe('splitBands', () => {
+  it('should return no bands for an empty region', () => {
+    const op = { kind: 'solid_fill', x: 0, y: 50, w: 10, h: 10, r: 0, g: 0, b: 0 };
+    expect(splitBands(op, 40, 4)).toEqual([]);
+  });
+
+  it('should cover every row of the region exactly once', () => {
+    const op = { kind: 'solid_fill', x: 0, y: 3, w: 10, h: 17, r: 0, g: 0, b: 0 };
+    const bands = splitBands(op, 100, 4);
+    expect(bands[0].start).toBe(3);
+    expect(bands[bands.length - 1].end).toBe(20);
+    for (let i = 1; i < bands.length; i++) {
+      expect(bands[i].start).toBe(bands[i - 1].end);
+    }
+  });
+
+  it('should align pixelate bands to whole block rows', () => {
+    const op = { kind: 'pixelate', x: 0, y: 5, w: 10, h: 30, blockSize: 8 };
+    const bands = splitBands(op, 100, 3);
+    for (const band of bands.slice(0, -1)) {
+      expect((band.end - 5) % 8).toBe(0);
+    }
+  });
+
+  it('should add blur halo clipped to the region', () => {
+    const op = { kind: 'gaussian_blur', x: 0, y: 10, w: 10, h: 40, radius: 4 };
+    const bands = splitBands(op, 100, 2);
+    expect(bands).toEqual([
+      { start: 10, end: 30, readStart: 10, readEnd: 34 },
+      { start: 30, end: 50, readStart: 26, readEnd: 50 }
+    ]);
+  });
+
+  it('should not create more bands than rows', () => {
+    const op = { kind: 'solid_fill', x: 0, y: 0, w: 10, h: 2, r: 0, g: 0, b: 0 };
+    expect(splitBands(op, 10, 8)).toHaveLength(2);
+  });
+});
@@ -0,0 +1,60 @@
+// @ts-nocheck
+import { describe, it, expect, vi, beforeEach } from 'vitest';
+
+vi.mock('../redactor', () => ({ wasmReady: Promise.resolve() }));
+
+import { redactFrame } from '../stream';
+
+class FakeVideoFrame {
+  constructor(data, init) {
+    this.data = data;
+    this.init = init;
+  }
+}
+
+// Picture padded above and below, like a decoder's 1088-row buffer for 1080p
+function paddedFrame(format) {
+  return {
+    format,
+    codedWidth: 24,
+    codedHeight: 20,
+    visibleRect: { x: 0, y: 2, width: 24, height: 16 },
+    displayWidth: 24,
+    displayHeight: 16,
+    timestamp: 5000,
+    duration: null,
+    copyTo: vi.fn(async () => [])
+  };
+}
+
+describe('redactFrame', () => {
+  beforeEach(() => {
+    globalThis.VideoFrame = FakeVideoFrame;
+  });
+
+  it('should redact only the visible rect of an RGBA frame', async () => {
+    const redactor = { process_frame: vi.fn(), process_frame_i420: vi.fn() };
+    const frame = paddedFrame('RGBA');
+    const out = await redactFrame(redactor, frame, 5);
+
+    const [buffer, options] = frame.copyTo.mock.calls[0];
+    expect(options.rect).toEqual(frame.visibleRect);
+    expect(buffer.length).toBe(24 * 16 * 4);
+    expect(redactor.process_frame).toHaveBeenCalledWith(buffer, 5);
+    expect(out.init).toMatchObject({ codedWidth: 24, codedHeight: 16, displayWidth: 24, displayHeight: 16 });
+  });
+
+  it('should size the I420 planes to the visible rect', async () => {
+    const redactor = { process_frame: vi.fn(), process_frame_i420: vi.fn() };
+    const frame = paddedFrame('I420');
+    const out = await redactFrame(redactor, frame, 5);
+
+    const [buffer, options] = frame.copyTo.mock.calls[0];
+    expect(options.rect).toEqual(frame.visibleRect);
+    expect(options.layout[0]).toEqual({ offset: 0, stride: 24 });
+    expect(buffer.length).toBe(24 * 16 + 2 * 12 * 8);
+    const [y, u, v] = redactor.process_frame_i420.mock.calls[0];
+    expect([y.length, u.length, v.length]).toEqual([24 * 16, 12 * 8, 12 * 8]);
+    expect(out.init).toMatchObject({ format: 'I420', codedWidth: 24, codedHeight: 16 });
+  });
+});
@@ -0,0 +1,64 @@
+// Export-time metadata scrubbing. Canvas encoders rarely add metadata, but
+// the blob is still scrubbed (EXIF/XMP, text chunks, MPF previews, trailing
+// data) and verified before it leaves the app. `prepareExport` also runs the
+// active redaction policy's export checks, if one is set.
+
+import { wasmReady } from './redactor';
+import type { RedactionCommand } from '../stores/history';
+
+export async function scrubBlob(blob: Blob): Promise<Blob> {
+  await wasmReady;
+  const { scrub_metadata, verify_metadata_scrubbed } = await import('./pkg/redactr_wasm');
+  const scrubbed = scrub_metadata(new Uint8Array(await blob.arrayBuffer()));
+
+  const report = verify_metadata_scrubbed(scrubbed);
+  const leftover = report.unexpected();
+  report.free();
+  if (leftover.length > 0) {
+    throw new Error(`Metadata left after scrubbing: ${leftover.join(', ')}`);
+  }
+  return new Blob([scrubbed], { type: blob.type });
+}
+
+// Flat [x, y, w, h, ...] bounds of every applied command; brush strokes
+// cover their points grown by half the brush size
+export function commandRegions(commands: RedactionCommand[], width: number, height: number): number[] {
+  const flat: number[] = [];
+  for (const command of commands) {
+    if (command.region) {
+      const { x, y, width: w, height: h } = command.region;
+      flat.push(Math.round(x), Math.round(y), Math.round(w), Math.round(h));
+    } else if (command.points && command.points.length >= 2) {
+      const r = (command.brushSize ?? 0) / 2;
+      const xs = command.points.filter((_, i) => i % 2 === 0);
+      const ys = command.points.filter((_, i) => i % 2 === 1);
+      const x0 = Math.max(0, Math.floor(Math.min(...xs) - r));
+      const y0 = Math.max(0, Math.floor(Math.min(...ys) - r));
+      const x1 = Math.min(width, Math.ceil(Math.max(...xs) + r));
+      const y1 = Math.min(height, Math.ceil(Math.max(...ys) + r));
+      if (x1 > x0 && y1 > y0) flat.push(x0, y0, x1 - x0, y1 - y0);
+    }
+  }
+  return flat;
+}
+
+// Scrub an encoded export, then check it against the active policy:
+// throws if metadata is left or a redacted region is recoverable
+export async function prepareExport(
+  blob: Blob,
+  original: ImageData,
+  redacted: ImageData,
+  commands: RedactionCommand[]
+): Promise<Blob> {
+  const scrubbed = await scrubBlob(blob);
+  const { check_active_export } = await import('./pkg/redactr_wasm');
+  check_active_export(
+    new Uint8Array(await scrubbed.arrayBuffer()),
+    new Uint8Array(original.data.buffer),
+    new Uint8Array(redacted.data.buffer),
+    redacted.width,
+    redacted.height,
+    new Uint32Array(commandRegions(commands, redacted.width, redacted.height))
+  );
+  return scrubbed;
+}
@@ -0,0 +1,162 @@
+// Worker pool for running WASM redactions off the main thread.
+// Each worker holds its own module instance (see pool.worker.ts). Jobs are
+// either a whole image or horizontal bands of one rect operation; pixel
+// buffers are moved with transferable ArrayBuffers instead of being cloned.
+
+export type PoolOp =
+  | { kind: 'solid_fill'; x: number; y: number; w: number; h: number; r: number; g: number; b: number }
+  | { kind: 'pixelate'; x: number; y: number; w: number; h: number; blockSize: number }
+  | { kind: 'gaussian_blur'; x: number; y: number; w: number; h: number; radius: number };
+
+export interface PoolRequest {
+  id: number;
+  ops: PoolOp[];
+  width: number;
+  height: number;
+  buffer: ArrayBuffer;
+}
+
+export type PoolResponse =
+  | { id: number; type: 'done'; buffer: ArrayBuffer }
+  | { id: number; type: 'error'; error: string };
+
+export interface Band {
+  // Rows this band is responsible for writing back
+  start: number;
+  end: number;
+  // Rows the worker needs to read (band plus halo, clipped to the region)
+  readStart: number;
+  readEnd: number;
+}
+
+// Split the rows of a rect op into bands that produce identical output when
+// processed independently. Pixelate bands cover whole block rows and blur
+// bands carry `radius` rows of context on each side.
+export function splitBands(op: PoolOp, height: number, bandCount: number): Band[] {
+  const top = Math.max(0, Math.min(op.y, height));
+  const bottom = Math.max(top, Math.min(op.y + op.h, height));
+  const rows = bottom - top;
+  if (rows === 0) return [];
+
+  const unit = op.kind === 'pixelate' ? Math.max(1, op.blockSize) : 1;
+  const halo = op.kind === 'gaussian_blur' ? op.radius : 0;
+  const units = Math.ceil(rows / unit);
+  const perBand = Math.ceil(units / Math.max(1, Math.min(bandCount, units))) * unit;
+
+  const bands: Band[] = [];
+  for (let start = top; start < bottom; start += perBand) {
+    const end = Math.min(start + perBand, bottom);
+    bands.push({
+      start,
+      end,
+      readStart: Math.max(top, start - halo),
+      readEnd: Math.min(bottom, end + halo)
+    });
+  }
+  return bands;
+}
+
+interface PendingJob {
+  request: PoolRequest;
+  resolve: (buffer: ArrayBuffer) => void;
+  reject: (error: Error) => void;
+}
+
+export class RedactorPool {
+  private workers: Worker[] = [];
+  private idle: Worker[] = [];
+  private queue: PendingJob[] = [];
+  private running = new Map<number, PendingJob>();
+  private nextId = 0;
+
+  constructor(size: number = navigator.hardwareConcurrency || 4) {
+    for (let i = 0; i < Math.max(1, size); i++) {
+      const worker = new Worker(new URL('./pool.worker.ts', import.meta.url), {
+        type: 'module'
+      });
+      worker.onmessage = (event: MessageEvent<PoolResponse>) => this.settle(worker, event.data);
+      this.workers.push(worker);
+      this.idle.push(worker);
+    }
+  }
+
+  get size(): number {
+    return this.workers.length;
+  }
+
+  // Run a list of operations over a whole image on one worker
+  async run(imageData: ImageData, ops: PoolOp[]): Promise<ImageData> {
+    const buffer = await this.submit(ops, imageData.width, imageData.height, copyRows(imageData, 0, imageData.height));
+    return new ImageData(new Uint8ClampedArray(buffer), imageData.width, imageData.height);
+  }
+
+  // Run one rect operation split into bands across all workers
+  async runTiled(imageData: ImageData, op: PoolOp): Promise<ImageData> {
+    const { width, height } = imageData;
+    const out = new Uint8ClampedArray(imageData.data);
+    const rowBytes = width * 4;
+
+    await Promise.all(
+      splitBands(op, height, this.size).map(async (band) => {
+        // The band buffer starts at `readStart` and spans the op vertically
+        const rows = band.readEnd - band.readStart;
+        const bandOp = { ...op, y: 0, h: rows };
+        const buffer = await this.submit([bandOp], width, rows, copyRows(imageData, band.readStart, band.readEnd));
+        const offset = (band.start - band.readStart) * rowBytes;
+        out.set(
+          new Uint8ClampedArray(buffer, offset, (band.end - band.start) * rowBytes),
+          band.start * rowBytes
+        );
+      })
+    );
+
+    return new ImageData(out, width, height);
+  }
+
+  terminate(): void {
+    for (const worker of this.workers) worker.terminate();
+    for (const job of [...this.queue, ...this.running.values()]) {
+      job.reject(new Error('Worker pool terminated'));
+    }
+    this.workers = [];
+    this.idle = [];
+    this.queue = [];
+    this.running.clear();
+  }
+
+  private submit(ops: PoolOp[], width: number, height: number, buffer: ArrayBuffer): Promise<ArrayBuffer> {
+    return new Promise((resolve, reject) => {
+      this.queue.push({ request: { id: this.nextId++, ops, width, height, buffer }, resolve, reject });
+      this.dispatch();
+    });
+  }
+
+  private dispatch(): void {
+    while (this.idle.length > 0 && this.queue.length > 0) {
+      const worker = this.idle.pop()!;
+      const job = this.queue.shift()!;
+      this.running.set(job.request.id, job);
+      worker.postMessage(job.request, [job.request.buffer]);
+    }
+  }
+
+  private settle(worker: Worker, response: PoolResponse): void {
+    const job = this.running.get(response.id);
+    this.running.delete(response.id);
+    this.idle.push(worker);
+    if (job) {
+      if (response.type === 'done') {
+        job.resolve(response.buffer);
+      } else {
+        job.reject(new Error(response.error));
+      }
+    }
+    this.dispatch();
+  }
+}
+
+// Copy rows `start..end` into a fresh buffer that can be transferred
+function copyRows(imageData: ImageData, start: number, end: number): ArrayBuffer {
+  const rowBytes = imageData.width * 4;
+  return imageData.data.slice(start * rowBytes, end * rowBytes).buffer;
+}
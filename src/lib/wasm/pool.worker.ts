@@ -0,0 +1,46 @@
+/// <reference lib="webworker" />
+
+// Worker side of RedactorPool: owns one WASM module instance and applies
+// each request's operations to the transferred buffer before sending it back.
+
+import type { PoolOp, PoolRequest, PoolResponse } from './pool';
+
+const ready = (async () => {
+  const wasm = await import('./pkg/redactr_wasm');
+  await wasm.default();
+  return wasm;
+})();
+
+function apply(wasm: Awaited<typeof ready>, data: Uint8Array, width: number, height: number, op: PoolOp) {
+  switch (op.kind) {
+    case 'solid_fill':
+      wasm.solid_fill(data, width, height, op.x, op.y, op.w, op.h, op.r, op.g, op.b);
+      break;
+    case 'pixelate':
+      wasm.pixelate(data, width, height, op.x, op.y, op.w, op.h, op.blockSize);
+      break;
+    case 'gaussian_blur':
+      wasm.gaussian_blur(data, width, height, op.x, op.y, op.w, op.h, op.radius);
+      break;
+  }
+}
+
+self.onmessage = async (event: MessageEvent<PoolRequest>) => {
+  const { id, ops, width, height, buffer } = event.data;
+  let response: PoolResponse;
+  try {
+    const wasm = await ready;
+    const data = new Uint8Array(buffer);
+    for (const op of ops) {
+      apply(wasm, data, width, height, op);
+    }
+    response = { id, type: 'done', buffer };
+  } catch (e) {
+    response = { id, type: 'error', error: e instanceof Error ? e.message : String(e) };
+  }
+  if (response.type === 'done') {
+    self.postMessage(response, [response.buffer]);
+  } else {
+    self.postMessage(response);
+  }
+};
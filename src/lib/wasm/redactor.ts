@@ -29,9 +29,21 @@ export function isWasmReady(): boolean {
 }
 
 export interface RedactionOptions {
-  style: 'solid' | 'pixelate' | 'blur';
+  // 'noise' overwrites with random color, leaving nothing to reverse
+  style: 'solid' | 'pixelate' | 'blur' | 'noise';
   intensity: number; // 1-100
-  color?: string; // For solid fill (hex)
+  color?: string; // For solid fill (hex), or 'auto' to match the surrounding background
+  // For solid fill: color `index` (default 0) of a named, contrast-checked
+  // palette ('high_contrast', 'colorblind_safe', 'dark_mode' or a registered
+  // brand palette). Takes precedence over `color`.
+  palette?: { name: string; index?: number };
+  // Region is a detected face: pixelate/blur at least as hard as the face's
+  // size calls for, or harder if intensity asks for more. interpupillary is
+  // the eye distance in px, if known.
+  face?: { interpupillary?: number };
+  // For pixelate: anchor blocks to the image grid, so nudging the region
+  // doesn't reshuffle the blocks it still covers
+  gridAligned?: boolean;
 }
 
 function hexToRgb(hex: string): { r: number; g: number; b: number } {
@@ -45,6 +57,11 @@ function hexToRgb(hex: string): { r: number; g: number; b: number } {
     : { r: 0, g: 0, b: 0 };
 }
 
+// Fresh secret seed for each noise fill
+function noiseSeed(): Uint8Array {
+  return crypto.getRandomValues(new Uint8Array(32));
+}
+
 function intensityToBlockSize(intensity: number): number {
   // Map 1-100 to 4-32 block size
   return Math.round(4 + (intensity / 100) * 28);
@@ -77,7 +94,11 @@ export function applyRectRedaction(
 
   switch (options.style) {
     case 'solid': {
-      const rgb = hexToRgb(options.color || '#000000');
+      const rgb = options.palette
+        ? wasmModule.palette(options.palette.name).fill(options.palette.index ?? 0)
+        : options.color === 'auto'
+          ? wasmModule.background_color(data, imageData.width, imageData.height, ix, iy, iw, ih)
+          : hexToRgb(options.color || '#000000');
       wasmModule.solid_fill(
         data,
         imageData.width,
@@ -93,20 +114,107 @@ export function applyRectRedaction(
       break;
     }
     case 'pixelate': {
-      const blockSize = intensityToBlockSize(options.intensity);
-      wasmModule.pixelate(data, imageData.width, imageData.height, ix, iy, iw, ih, blockSize);
+      // The face calibration is a floor: it only ever coarsens the user's choice
+      const chosen = intensityToBlockSize(options.intensity);
+      const blockSize = options.face
+        ? Math.max(chosen, wasmModule.face_block_size(iw, ih, options.face.interpupillary ?? 0))
+        : chosen;
+      const pixelate = options.gridAligned ? wasmModule.pixelate_grid_aligned : wasmModule.pixelate;
+      pixelate(data, imageData.width, imageData.height, ix, iy, iw, ih, blockSize);
       break;
     }
     case 'blur': {
-      const radius = intensityToBlurRadius(options.intensity);
+      const chosen = intensityToBlurRadius(options.intensity);
+      const radius = options.face
+        ? Math.max(chosen, wasmModule.face_blur_radius(iw, ih, options.face.interpupillary ?? 0))
+        : chosen;
       wasmModule.gaussian_blur(data, imageData.width, imageData.height, ix, iy, iw, ih, radius);
       break;
     }
+    case 'noise': {
+      wasmModule.noise_fill(data, imageData.width, imageData.height, ix, iy, iw, ih, noiseSeed());
+      break;
+    }
+  }
+
+  return new ImageData(new Uint8ClampedArray(data.buffer), imageData.width, imageData.height);
+}
+
+// Fill a region and draw a readable pseudonym ("PERSON A", "[REDACTED-3]")
+// over it, sized to the region. Without `text`, the label is black or white,
+// whichever reads better on the fill.
+export function applyPseudonym(
+  imageData: ImageData,
+  x: number,
+  y: number,
+  width: number,
+  height: number,
+  label: string,
+  colors: { fill?: string; text?: string } = {}
+): ImageData {
+  if (!wasmModule) {
+    throw new Error('WASM module not initialized');
+  }
+
+  const data = new Uint8Array(imageData.data.buffer.slice(0));
+  const fill = hexToRgb(colors.fill || '#000000');
+  const fillColor = new wasmModule.Color(fill.r, fill.g, fill.b);
+  let textColor;
+  if (colors.text) {
+    const text = hexToRgb(colors.text);
+    textColor = new wasmModule.Color(text.r, text.g, text.b);
+  } else {
+    textColor = wasmModule.label_ink(fillColor);
+  }
+  try {
+    wasmModule.pseudonymize(
+      data,
+      imageData.width,
+      imageData.height,
+      Math.floor(x),
+      Math.floor(y),
+      Math.floor(width),
+      Math.floor(height),
+      label,
+      fillColor,
+      textColor
+    );
+  } finally {
+    fillColor.free();
+    textColor.free();
   }
 
   return new ImageData(new Uint8ClampedArray(data.buffer), imageData.width, imageData.height);
 }
 
+// Copy a region out of the (redacted) image, each pixel scaled up `zoom`
+// times, for the magnifier loupe. Parts past the image edge are transparent.
+export function cropRegion(
+  imageData: ImageData,
+  x: number,
+  y: number,
+  width: number,
+  height: number,
+  zoom = 1
+): ImageData {
+  if (!wasmModule) {
+    throw new Error('WASM module not initialized');
+  }
+
+  const [cw, ch] = [Math.floor(width), Math.floor(height)];
+  const out = wasmModule.crop(
+    new Uint8Array(imageData.data.buffer),
+    imageData.width,
+    imageData.height,
+    Math.max(0, Math.floor(x)),
+    Math.max(0, Math.floor(y)),
+    cw,
+    ch,
+    zoom
+  );
+  return new ImageData(new Uint8ClampedArray(out.buffer), cw * zoom, ch * zoom);
+}
+
 export function applyBrushRedaction(
   imageData: ImageData,
   points: number[],
@@ -149,15 +257,25 @@ export function applyBrushRedaction(
       break;
     }
     case 'blur': {
-      // For brush blur, we fall back to pixelate since blur is expensive per-stroke
-      const blockSize = intensityToBlockSize(options.intensity);
-      wasmModule.brush_pixelate(
+      const radius = intensityToBlurRadius(options.intensity);
+      wasmModule.brush_gaussian_blur(
         data,
         imageData.width,
         imageData.height,
         pointsF32,
         brushSize,
-        blockSize
+        radius
+      );
+      break;
+    }
+    case 'noise': {
+      wasmModule.brush_noise_fill(
+        data,
+        imageData.width,
+        imageData.height,
+        pointsF32,
+        brushSize,
+        noiseSeed()
       );
       break;
     }
@@ -165,3 +283,38 @@ export function applyBrushRedaction(
 
   return new ImageData(new Uint8ClampedArray(data.buffer), imageData.width, imageData.height);
 }
+
+// Returns a warning when pixelating the rect at this intensity could leave
+// text recoverable, or null if it looks safe. Pass the detected text height
+// when text detection ran; otherwise it is estimated from the pixels.
+export function pixelationWarning(
+  imageData: ImageData,
+  x: number,
+  y: number,
+  width: number,
+  height: number,
+  intensity: number,
+  glyphHeight = 0
+): string | null {
+  if (!wasmModule) {
+    throw new Error('WASM module not initialized');
+  }
+
+  const data = new Uint8Array(imageData.data.buffer, imageData.data.byteOffset, imageData.data.byteLength);
+  try {
+    wasmModule.check_pixelation_block_size(
+      data,
+      imageData.width,
+      imageData.height,
+      Math.floor(x),
+      Math.floor(y),
+      Math.floor(width),
+      Math.floor(height),
+      intensityToBlockSize(intensity),
+      Math.round(glyphHeight)
+    );
+    return null;
+  } catch (e) {
+    return e instanceof Error ? e.message : String(e);
+  }
+}
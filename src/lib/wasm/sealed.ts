@@ -0,0 +1,101 @@
+// Reversible redaction: region pixels are encrypted with AES-GCM under a
+// caller-supplied key and kept in a sidecar blob, while the visible region
+// is filled. unredact() decrypts and restores them for authorized recovery.
+
+import { wasmReady } from './redactor';
+
+export interface SealedRegion {
+  // Rect clipped to the image, as sealed
+  x: number;
+  y: number;
+  w: number;
+  h: number;
+  // Base64 AES-GCM nonce and ciphertext (with tag)
+  iv: string;
+  ciphertext: string;
+}
+
+export interface SealedSidecar {
+  version: 1;
+  width: number;
+  height: number;
+  regions: SealedRegion[];
+}
+
+function toBase64(bytes: Uint8Array): string {
+  let text = '';
+  for (let i = 0; i < bytes.length; i += 0x8000) {
+    text += String.fromCharCode(...bytes.subarray(i, i + 0x8000));
+  }
+  return btoa(text);
+}
+
+function fromBase64(text: string): Uint8Array {
+  return Uint8Array.from(atob(text), (c) => c.charCodeAt(0));
+}
+
+// Binds a ciphertext to its image size and rect so it can't be replayed elsewhere
+function additionalData(width: number, height: number, region: { x: number; y: number; w: number; h: number }) {
+  return new TextEncoder().encode(`redactr-sealed-v1:${width}x${height}:${region.x},${region.y},${region.w},${region.h}`);
+}
+
+async function wasm() {
+  await wasmReady;
+  return import('./pkg/redactr_wasm');
+}
+
+function pixelsOf(imageData: ImageData): Uint8Array {
+  return new Uint8Array(imageData.data.buffer, imageData.data.byteOffset, imageData.data.byteLength);
+}
+
+// Fill each rect in place and return the encrypted originals
+export async function sealRegions(
+  imageData: ImageData,
+  rects: { x: number; y: number; w: number; h: number }[],
+  key: CryptoKey,
+  fill: { r: number; g: number; b: number } = { r: 0, g: 0, b: 0 }
+): Promise<SealedSidecar> {
+  const { seal_region } = await wasm();
+  const { width, height } = imageData;
+  const regions: SealedRegion[] = [];
+  for (const rect of rects) {
+    const sealed = seal_region(pixelsOf(imageData), width, height, rect.x, rect.y, rect.w, rect.h, fill.r, fill.g, fill.b);
+    const region = { x: sealed.x, y: sealed.y, w: sealed.w, h: sealed.h };
+    const plain = sealed.pixels();
+    sealed.free();
+
+    const iv = crypto.getRandomValues(new Uint8Array(12));
+    const ciphertext = await crypto.subtle.encrypt(
+      { name: 'AES-GCM', iv, additionalData: additionalData(width, height, region) },
+      key,
+      plain
+    );
+    plain.fill(0);
+    regions.push({ ...region, iv: toBase64(iv), ciphertext: toBase64(new Uint8Array(ciphertext)) });
+  }
+  return { version: 1, width, height, regions };
+}
+
+// Decrypt a sidecar and restore its regions in place; rejects on a wrong key
+// or tampered sidecar without modifying the image
+export async function unredact(imageData: ImageData, sidecar: SealedSidecar, key: CryptoKey): Promise<void> {
+  const { unseal_region } = await wasm();
+  const { width, height } = imageData;
+  if (sidecar.width !== width || sidecar.height !== height) {
+    throw new Error(`Sidecar is for a ${sidecar.width}x${sidecar.height} image`);
+  }
+  const restored: { region: SealedRegion; pixels: Uint8Array }[] = [];
+  for (const region of sidecar.regions) {
+    const plain = await crypto.subtle.decrypt(
+      { name: 'AES-GCM', iv: fromBase64(region.iv), additionalData: additionalData(width, height, region) },
+      key,
+      fromBase64(region.ciphertext)
+    );
+    restored.push({ region, pixels: new Uint8Array(plain) });
+  }
+  // Later seals may overlap earlier ones, so restore in reverse order
+  for (const { region, pixels } of restored.reverse()) {
+    unseal_region(pixelsOf(imageData), width, height, region.x, region.y, region.w, region.h, pixels);
+    pixels.fill(0);
+  }
+}
@@ -0,0 +1,45 @@
+// Detached Ed25519 signatures over redacted output.
+// The WASM module hashes the pixels and metadata (signing_digest); the key
+// stays in WebCrypto, so it is never copied into the module's memory.
+
+import { wasmReady } from './redactor';
+
+export interface SignedRedaction {
+  // Base64 Ed25519 signature over signing_digest(pixels, metadata)
+  signature: string;
+  metadata: string;
+}
+
+async function digest(imageData: ImageData, metadata: string): Promise<Uint8Array> {
+  await wasmReady;
+  const { signing_digest } = await import('./pkg/redactr_wasm');
+  const pixels = new Uint8Array(imageData.data.buffer, imageData.data.byteOffset, imageData.data.byteLength);
+  return signing_digest(pixels, imageData.width, imageData.height, metadata);
+}
+
+function toBase64(bytes: Uint8Array): string {
+  return btoa(String.fromCharCode(...bytes));
+}
+
+function fromBase64(text: string): Uint8Array {
+  return Uint8Array.from(atob(text), (c) => c.charCodeAt(0));
+}
+
+// Sign a redacted image with a supplied Ed25519 private key
+export async function signRedaction(
+  imageData: ImageData,
+  metadata: string,
+  privateKey: CryptoKey
+): Promise<SignedRedaction> {
+  const signature = await crypto.subtle.sign('Ed25519', privateKey, await digest(imageData, metadata));
+  return { signature: toBase64(new Uint8Array(signature)), metadata };
+}
+
+// Check that an image and its metadata are exactly what was signed
+export async function verifyRedaction(
+  imageData: ImageData,
+  signed: SignedRedaction,
+  publicKey: CryptoKey
+): Promise<boolean> {
+  return crypto.subtle.verify('Ed25519', publicKey, fromBase64(signed.signature), await digest(imageData, signed.metadata));
+}
@@ -0,0 +1,130 @@
+// Real-time redaction for WebCodecs pipelines: a TransformStream of
+// VideoFrames that runs every frame through a VideoRedactor.
+//
+//   processor.readable.pipeThrough(createRedactionTransform(redactor)).pipeTo(generator.writable)
+//
+// Streams already apply backpressure (the transform waits for the reader);
+// live sources can instead drop frames that arrive while the consumer is
+// behind, so latency doesn't grow without bound.
+
+import { wasmReady } from './redactor';
+import type { VideoRedactor } from './pkg/redactr_wasm';
+
+export interface RedactionTransformOptions {
+  // Drop incoming frames while the readable side is full instead of
+  // queueing them. Suits live capture; leave off for file transcoding.
+  dropWhenBehind?: boolean;
+  // Timestamp passed to the redactor, in the unit its keyframes use.
+  // Defaults to milliseconds (VideoFrame timestamps are microseconds).
+  timestampOf?: (frame: VideoFrame) => number;
+}
+
+export interface RedactionTransformStats {
+  processed: number;
+  dropped: number;
+}
+
+// Not yet in TypeScript's DOM lib
+declare class MediaStreamTrackProcessor {
+  constructor(init: { track: MediaStreamTrack });
+  readonly readable: ReadableStream<VideoFrame>;
+}
+declare class MediaStreamTrackGenerator extends MediaStreamTrack {
+  constructor(init: { kind: 'video' });
+  readonly writable: WritableStream<VideoFrame>;
+}
+
+// Only the visible rect is redacted and passed on: decoders often pad the
+// coded size (1088 rows for 1080p), and the redactor is sized to the picture
+export async function redactFrame(redactor: VideoRedactor, frame: VideoFrame, timestamp: number): Promise<VideoFrame> {
+  const rect = frame.visibleRect ?? { x: 0, y: 0, width: frame.codedWidth, height: frame.codedHeight };
+  const { width, height } = rect;
+  const init = {
+    timestamp: frame.timestamp,
+    duration: frame.duration ?? undefined,
+    displayWidth: frame.displayWidth,
+    displayHeight: frame.displayHeight
+  };
+
+  if (frame.format === 'I420') {
+    const lumaSize = width * height;
+    const chromaSize = Math.ceil(width / 2) * Math.ceil(height / 2);
+    const buffer = new Uint8Array(lumaSize + 2 * chromaSize);
+    const layout = [
+      { offset: 0, stride: width },
+      { offset: lumaSize, stride: Math.ceil(width / 2) },
+      { offset: lumaSize + chromaSize, stride: Math.ceil(width / 2) }
+    ];
+    await frame.copyTo(buffer, { rect, layout });
+    const y = buffer.subarray(0, lumaSize);
+    const u = buffer.subarray(lumaSize, lumaSize + chromaSize);
+    const v = buffer.subarray(lumaSize + chromaSize);
+    redactor.process_frame_i420(y, u, v, timestamp);
+    return new VideoFrame(buffer, { ...init, format: 'I420', codedWidth: width, codedHeight: height, layout });
+  }
+
+  // Everything else (NV12, BGRA, ...) goes through RGBA
+  const rgba = new Uint8Array(width * height * 4);
+  try {
+    await frame.copyTo(rgba, { rect, format: 'RGBA' } as VideoFrameCopyToOptions);
+  } catch {
+    // Older browsers can't convert in copyTo
+    const canvas = new OffscreenCanvas(width, height);
+    const ctx = canvas.getContext('2d')!;
+    // A frame draws as its visible rect, scaled to the display size
+    ctx.drawImage(frame, 0, 0, width, height);
+    rgba.set(ctx.getImageData(0, 0, width, height).data);
+  }
+  redactor.process_frame(rgba, timestamp);
+  return new VideoFrame(rgba, { ...init, format: 'RGBA', codedWidth: width, codedHeight: height });
+}
+
+// TransformStream that redacts each VideoFrame with `redactor`. Input frames
+// are closed once processed; output frames belong to the consumer.
+export function createRedactionTransform(
+  redactor: VideoRedactor,
+  options: RedactionTransformOptions = {}
+): { transform: TransformStream<VideoFrame, VideoFrame>; stats: RedactionTransformStats } {
+  const stats: RedactionTransformStats = { processed: 0, dropped: 0 };
+  const timestampOf = options.timestampOf ?? ((frame: VideoFrame) => frame.timestamp / 1000);
+
+  const transform = new TransformStream<VideoFrame, VideoFrame>(
+    {
+      async start() {
+        await wasmReady;
+      },
+      async transform(frame, controller) {
+        try {
+          if (options.dropWhenBehind && (controller.desiredSize ?? 1) <= 0) {
+            stats.dropped++;
+            return;
+          }
+          controller.enqueue(await redactFrame(redactor, frame, timestampOf(frame)));
+          stats.processed++;
+        } finally {
+          frame.close();
+        }
+      }
+    },
+    { highWaterMark: 1 },
+    { highWaterMark: 1 }
+  );
+  return { transform, stats };
+}
+
+// Redacted copy of a camera or screen-share track (Chromium only: needs
+// MediaStreamTrackProcessor). Stopping the returned track stops the pipeline.
+export function redactVideoTrack(
+  track: MediaStreamTrack,
+  redactor: VideoRedactor,
+  options: RedactionTransformOptions = { dropWhenBehind: true }
+): MediaStreamTrack {
+  const processor = new MediaStreamTrackProcessor({ track });
+  const generator = new MediaStreamTrackGenerator({ kind: 'video' });
+  const { transform } = createRedactionTransform(redactor, options);
+  processor.readable
+    .pipeThrough(transform)
+    .pipeTo(generator.writable)
+    .catch(() => track.stop());
+  return generator;
+}
@@ -0,0 +1,224 @@
+// Redact by text: OCR the image, then let the WASM matcher find every
+// occurrence of the given strings or regular expressions and redact it in
+// one pass.
+//
+//   const { imageData: redacted, matches } = await redactTextMatches(image, ['Jane Doe']);
+//   const accounts = await redactTextPatterns(image, ['\\b\\d{10}\\b']);
+//   const terms = await redactWordlist(image, caseTerms, { maxEdits: 1 });
+
+import { wasmReady } from './redactor';
+import { recognizeWords } from '../detection/manager';
+import type { EffectStack } from './pkg/redactr_wasm';
+
+export interface TextMatchOptions {
+  // Words below this OCR confidence (0-100) are ignored
+  minConfidence?: number;
+  // Fill color for the matches (hex); defaults to black
+  color?: string;
+  // Fit bars to each line's baseline and x-height and merge adjacent words
+  // into one line bar, instead of filling the raw OCR boxes. `padding` is in
+  // px (default 2), `mergeGap` in x-heights (default 1).
+  fitBars?: boolean | { padding?: number; mergeGap?: number };
+}
+
+export interface WordlistOptions extends TextMatchOptions {
+  // Misread characters tolerated per word of five or more letters (0-2)
+  maxEdits?: number;
+}
+
+export interface TextMatch {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export interface TextRedaction {
+  imageData: ImageData;
+  matches: TextMatch[];
+}
+
+// OCR once, then run `redact` over a copy of the pixels with the words and
+// their flat boxes. `cover` redacts found word boxes as the options ask
+// (raw boxes or fitted bars) and returns what it covered.
+async function redactWords(
+  imageData: ImageData,
+  options: TextMatchOptions,
+  redact: (
+    data: Uint8Array,
+    words: string[],
+    boxes: Uint32Array,
+    stack: EffectStack,
+    cover: (found: Uint32Array) => Uint32Array
+  ) => Uint32Array
+): Promise<TextRedaction> {
+  await wasmReady;
+  const { EffectStack, Color, redact_text_boxes, redact_text_bars } = await import(
+    './pkg/redactr_wasm'
+  );
+
+  const minConfidence = options.minConfidence ?? 60;
+  const words = (await recognizeWords(imageData)).filter(
+    (word) => word.confidence >= minConfidence && word.text.trim().length > 0
+  );
+  const boxes = new Uint32Array(
+    words.flatMap((w) => [w.bbox.x0, w.bbox.y0, w.bbox.x1 - w.bbox.x0, w.bbox.y1 - w.bbox.y0])
+  );
+
+  const hex = /^#?([a-f\d]{2})([a-f\d]{2})([a-f\d]{2})$/i.exec(options.color ?? '#000000');
+  const [r, g, b] = hex ? hex.slice(1).map((c) => parseInt(c, 16)) : [0, 0, 0];
+  const color = new Color(r, g, b);
+  // `fill` consumes the empty stack, so only the one it returns needs freeing
+  const stack = new EffectStack().fill(color);
+
+  const data = new Uint8Array(imageData.data.buffer.slice(0));
+  let flat: Uint32Array;
+  try {
+    const fit = options.fitBars === true ? {} : options.fitBars || null;
+    const cover = (found: Uint32Array) => {
+      const { width, height } = imageData;
+      if (fit) {
+        return redact_text_bars(data, width, height, found, fit.padding ?? 2, fit.mergeGap ?? 1, stack);
+      }
+      redact_text_boxes(data, width, height, found, stack);
+      return found;
+    };
+    flat = redact(
+      data,
+      words.map((w) => w.text),
+      boxes,
+      stack,
+      cover
+    );
+  } finally {
+    stack.free();
+    color.free();
+  }
+
+  const matches: TextMatch[] = [];
+  for (let i = 0; i < flat.length; i += 4) {
+    matches.push({ x: flat[i], y: flat[i + 1], width: flat[i + 2], height: flat[i + 3] });
+  }
+  return {
+    imageData: new ImageData(new Uint8ClampedArray(data.buffer), imageData.width, imageData.height),
+    matches
+  };
+}
+
+// Redact every occurrence of the given strings (whole words, any case,
+// possessives included: "Doe" also covers "Doe's")
+export async function redactTextMatches(
+  imageData: ImageData,
+  queries: string[],
+  options: TextMatchOptions = {}
+): Promise<TextRedaction> {
+  const { find_text_matches } = await import('./pkg/redactr_wasm');
+  return redactWords(imageData, options, (_data, words, boxes, _stack, cover) =>
+    cover(find_text_matches(words, boxes, queries))
+  );
+}
+
+// Redact every match of the given regular expressions (see TextPattern for
+// the supported syntax). Invalid patterns throw before OCR runs.
+export async function redactTextPatterns(
+  imageData: ImageData,
+  patterns: string[],
+  options: TextMatchOptions = {}
+): Promise<TextRedaction> {
+  await wasmReady;
+  const { TextPattern } = await import('./pkg/redactr_wasm');
+  const compiled = patterns.map((p) => new TextPattern(p));
+  try {
+    return await redactWords(imageData, options, (_data, words, boxes, _stack, cover) => {
+      const found = compiled.flatMap((p) => Array.from(p.find(words, boxes)));
+      return cover(Uint32Array.from(found));
+    });
+  } finally {
+    compiled.forEach((p) => p.free());
+  }
+}
+
+// Redact every term of a wordlist, one term per entry or newline-separated
+// text as kept per case ('#' lines are comments)
+export async function redactWordlist(
+  imageData: ImageData,
+  terms: string[] | string,
+  options: WordlistOptions = {}
+): Promise<TextRedaction> {
+  await wasmReady;
+  const { Wordlist } = await import('./pkg/redactr_wasm');
+  const list = Wordlist.from_text(typeof terms === 'string' ? terms : terms.join('\n'));
+  try {
+    list.set_fuzzy(options.maxEdits ?? 0);
+    return await redactWords(imageData, options, (_data, words, boxes, _stack, cover) =>
+      cover(list.find(words, boxes))
+    );
+  } finally {
+    list.free();
+  }
+}
+
+// Redact the column whose header reads `title` ("SSN"), keeping the header
+// itself. Ruled tables are found from their lines, borderless ones from the
+// OCR layout. Returns no matches when the page has no such column.
+export async function redactTableColumn(
+  imageData: ImageData,
+  title: string,
+  options: TextMatchOptions = {}
+): Promise<TextRedaction> {
+  await wasmReady;
+  const { detect_table, TableGrid } = await import('./pkg/redactr_wasm');
+  return redactWords(imageData, options, (data, words, boxes, stack) => {
+    const grid =
+      detect_table(data, imageData.width, imageData.height) ?? TableGrid.from_ocr(words, boxes);
+    if (!grid) return new Uint32Array();
+    try {
+      const column = grid.column_titled(title, words, boxes);
+      if (column === undefined) return new Uint32Array();
+      return grid.redact_column(data, imageData.width, imageData.height, column, true, stack);
+    } finally {
+      grid.free();
+    }
+  });
+}
+
+// Redact pattern matches except their last `keep` letters or digits, e.g.
+// card numbers showing only the last 4. Uses Tesseract's character boxes
+// when every word has them, otherwise even shares of the word boxes.
+export async function redactPatternsKeepingTail(
+  imageData: ImageData,
+  patterns: string[],
+  keep: number,
+  options: TextMatchOptions = {}
+): Promise<TextRedaction> {
+  await wasmReady;
+  const { TextPattern } = await import('./pkg/redactr_wasm');
+  const minConfidence = options.minConfidence ?? 60;
+  const recognized = (await recognizeWords(imageData)).filter(
+    (word) => word.confidence >= minConfidence && word.text.trim().length > 0
+  );
+  const withChars = recognized.every(
+    (w) => w.symbols && w.symbols.length === [...w.text.trim()].length
+  );
+  const charBoxes = new Uint32Array(
+    withChars
+      ? recognized.flatMap((w) =>
+          w.symbols!.flatMap((s) => [s.bbox.x0, s.bbox.y0, s.bbox.x1 - s.bbox.x0, s.bbox.y1 - s.bbox.y0])
+        )
+      : []
+  );
+
+  const compiled = patterns.map((p) => new TextPattern(p));
+  try {
+    return await redactWords(imageData, { ...options, fitBars: false }, (data, words, boxes, stack) => {
+      const found = compiled.flatMap((p) =>
+        Array.from(
+          p.redact_partial(data, imageData.width, imageData.height, words, boxes, charBoxes, keep, stack)
+        )
+      );
+      return Uint32Array.from(found);
+    });
+  } finally {
+    compiled.forEach((p) => p.free());
+  }
+}
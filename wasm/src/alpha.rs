@@ -0,0 +1,131 @@
+//! What the fills do to the alpha channel.
+//!
+//! `solid_fill` and `brush_solid_fill` keep each pixel's alpha, so on a
+//! transparent PNG a fill over a see-through area is itself invisible and
+//! whatever the image is later composited over shows through. The `_alpha`
+//! variants here take an `AlphaMode` instead: `Opaque` makes the fill
+//! solid everywhere, `Clear` punches the region out entirely.
+
+use wasm_bindgen::prelude::*;
+
+use crate::types::Rect;
+use crate::{brush_solid_fill, for_each_brush_pixel, solid_fill};
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Keep each pixel's alpha, as the plain fills do
+    Preserve = 0,
+    /// Set alpha to 255
+    Opaque = 1,
+    /// Set alpha to 0
+    Clear = 2,
+}
+
+impl AlphaMode {
+    /// The alpha written, or `None` to keep it
+    fn value(self) -> Option<u8> {
+        match self {
+            AlphaMode::Preserve => None,
+            AlphaMode::Opaque => Some(255),
+            AlphaMode::Clear => Some(0),
+        }
+    }
+}
+
+fn set_alpha(data: &mut [u8], width: u32, x: u32, y: u32, alpha: u8) {
+    let idx = ((y * width + x) * 4) as usize;
+    if let Some(a) = data.get_mut(idx + 3) {
+        *a = alpha;
+    }
+}
+
+/// `solid_fill` that also writes alpha according to `alpha_mode`
+#[wasm_bindgen]
+pub fn solid_fill_alpha(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+    alpha_mode: AlphaMode,
+) {
+    solid_fill(data, width, height, x, y, w, h, r, g, b);
+    let (Some(alpha), Some(rect)) = (
+        alpha_mode.value(),
+        Rect::new(x, y, w, h).clip(width, height),
+    ) else {
+        return;
+    };
+    for py in rect.y..rect.bottom() {
+        for px in rect.x..rect.right() {
+            set_alpha(data, width, px, py, alpha);
+        }
+    }
+}
+
+/// `brush_solid_fill` that also writes alpha according to `alpha_mode`
+#[wasm_bindgen]
+pub fn brush_solid_fill_alpha(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+    alpha_mode: AlphaMode,
+) {
+    brush_solid_fill(data, width, height, points, brush_size, r, g, b);
+    if let Some(alpha) = alpha_mode.value() {
+        for_each_brush_pixel(width, height, points, brush_size, |px, py| {
+            set_alpha(data, width, px, py, alpha)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translucent(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height).flat_map(|_| [10, 20, 30, 40]).collect()
+    }
+
+    #[test]
+    fn test_fill_alpha_modes() {
+        for (mode, alpha) in [
+            (AlphaMode::Preserve, 40),
+            (AlphaMode::Opaque, 255),
+            (AlphaMode::Clear, 0),
+        ] {
+            let mut data = translucent(6, 6);
+            solid_fill_alpha(&mut data, 6, 6, 1, 1, 10, 2, 0, 0, 0, mode);
+            assert_eq!(&data[(6 + 1) * 4..][..4], [0, 0, 0, alpha]);
+            assert_eq!(&data[(2 * 6 + 5) * 4..][..4], [0, 0, 0, alpha]);
+            // Outside the region nothing changes
+            assert_eq!(&data[..4], [10, 20, 30, 40]);
+            assert_eq!(&data[(4 * 6 + 1) * 4..][..4], [10, 20, 30, 40]);
+        }
+    }
+
+    #[test]
+    fn test_brush_alpha_follows_the_stroke() {
+        let mut data = translucent(10, 10);
+        let points = [2.0, 5.0, 7.0, 5.0];
+        brush_solid_fill_alpha(&mut data, 10, 10, &points, 3, 0, 0, 0, AlphaMode::Opaque);
+        let mut expected = translucent(10, 10);
+        brush_solid_fill(&mut expected, 10, 10, &points, 3, 0, 0, 0);
+        for (px, want) in data.chunks_exact(4).zip(expected.chunks_exact(4)) {
+            let painted = want[..3] == [0, 0, 0];
+            assert_eq!(px[..3], want[..3]);
+            assert_eq!(px[3], if painted { 255 } else { 40 });
+        }
+    }
+}
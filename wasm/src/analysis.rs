@@ -0,0 +1,179 @@
+//! Single-image leak analysis of a redacted region.
+//!
+//! Unlike `verify_redaction` this needs no original: it measures how much
+//! detail is left (tonal entropy, edges, high-frequency energy), which
+//! catches a blur too weak for high-contrast text even when the original
+//! isn't at hand.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::json::Json;
+use crate::types::Rect;
+use crate::verify::luma;
+
+/// Sobel magnitude above which a pixel counts as an edge (a luma step of
+/// roughly 25 between neighbours)
+const EDGE_MAGNITUDE: f64 = 100.0;
+/// Edge density above which remaining strokes are likely legible
+pub const MAX_EDGE_DENSITY: f32 = 0.05;
+/// Mean absolute Laplacian above which fine detail likely survives
+pub const MAX_HIGH_FREQUENCY: f32 = 6.0;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LeakAnalysis {
+    /// Shannon entropy of the luma histogram in bits, 0..=8
+    pub entropy: f32,
+    /// Fraction of pixels on a strong edge, 0..=1
+    pub edge_density: f32,
+    /// Mean absolute 4-neighbour Laplacian of luma
+    pub high_frequency: f32,
+    /// Whether edges and high-frequency energy both exceed their limits
+    pub likely_leaking: bool,
+}
+
+#[wasm_bindgen]
+impl LeakAnalysis {
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+}
+
+impl LeakAnalysis {
+    pub(crate) fn json(&self) -> Json {
+        Json::object()
+            .with("entropy", self.entropy)
+            .with("edge_density", self.edge_density)
+            .with("high_frequency", self.high_frequency)
+            .with("likely_leaking", self.likely_leaking)
+    }
+}
+
+/// Analyse the detail left inside `rect`
+pub fn analyze_region(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+) -> Result<LeakAnalysis, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    check_region(rect, width, height)?;
+    let rect = rect.clip(width, height).unwrap_or(rect);
+    let (w, h) = (rect.w as i64, rect.h as i64);
+
+    let plane: Vec<f64> = (rect.y..rect.bottom())
+        .flat_map(|y| {
+            (rect.x..rect.right()).map(move |x| {
+                let idx = ((y * width + x) * 4) as usize;
+                luma(&data[idx..idx + 4])
+            })
+        })
+        .collect();
+    // Neighbours outside the region repeat the edge pixel
+    let at = |x: i64, y: i64| plane[(y.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize];
+
+    let mut histogram = [0usize; 256];
+    let mut edges = 0usize;
+    let mut laplacian = 0.0;
+    for y in 0..h {
+        for x in 0..w {
+            let p = at(x, y);
+            histogram[p.round().clamp(0.0, 255.0) as usize] += 1;
+
+            let gx = at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2.0 * at(x - 1, y)
+                - at(x - 1, y + 1);
+            let gy = at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2.0 * at(x, y - 1)
+                - at(x + 1, y - 1);
+            if (gx * gx + gy * gy).sqrt() > EDGE_MAGNITUDE {
+                edges += 1;
+            }
+            laplacian +=
+                (at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * p).abs();
+        }
+    }
+
+    let n = plane.len() as f64;
+    let entropy: f64 = histogram
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / n;
+            -p * p.log2()
+        })
+        .sum();
+    let edge_density = (edges as f64 / n) as f32;
+    let high_frequency = (laplacian / n) as f32;
+    Ok(LeakAnalysis {
+        entropy: entropy as f32,
+        edge_density,
+        high_frequency,
+        likely_leaking: edge_density > MAX_EDGE_DENSITY && high_frequency > MAX_HIGH_FREQUENCY,
+    })
+}
+
+/// Report entropy, edge density and high-frequency energy left in a region
+#[wasm_bindgen]
+pub fn analyze_leakage(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+) -> Result<LeakAnalysis, JsError> {
+    Ok(analyze_region(data, width, height, Rect::new(x, y, w, h))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gaussian_blur, solid_fill};
+
+    /// Black 2px strokes on white, like small high-contrast text
+    fn text(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let ink = (y / 3) % 2 == 0 && (x / 2) % 3 == 0;
+                let v = if ink { 0 } else { 255 };
+                data.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        data
+    }
+
+    fn analyze(data: &[u8]) -> LeakAnalysis {
+        analyze_region(data, 32, 32, Rect::new(0, 0, 32, 32)).unwrap()
+    }
+
+    #[test]
+    fn test_text_and_weak_blur_leak() {
+        let mut data = text(32, 32);
+        assert!(analyze(&data).likely_leaking);
+        gaussian_blur(&mut data, 32, 32, 0, 0, 32, 32, 1);
+        let r = analyze(&data);
+        assert!(r.likely_leaking, "{:?}", r);
+    }
+
+    #[test]
+    fn test_fill_and_strong_blur_are_clean() {
+        let mut data = text(32, 32);
+        solid_fill(&mut data, 32, 32, 0, 0, 32, 32, 0, 0, 0);
+        let r = analyze(&data);
+        assert_eq!(
+            (r.entropy, r.edge_density, r.high_frequency),
+            (0.0, 0.0, 0.0)
+        );
+
+        let mut data = text(32, 32);
+        gaussian_blur(&mut data, 32, 32, 0, 0, 32, 32, 12);
+        let r = analyze(&data);
+        assert!(!r.likely_leaking, "{:?}", r);
+    }
+}
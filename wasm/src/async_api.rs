@@ -0,0 +1,136 @@
+//! Promise-returning variants of the rectangle effects.
+//!
+//! The work is split into bands of rows and the event loop is given a turn
+//! (an awaited `setTimeout`) between bands, so awaiting a large blur on the main
+//! thread doesn't freeze the page. The input is copied into wasm memory and
+//! the promise resolves to a new `Uint8Array` with the redacted pixels.
+//!
+//! Each function takes an optional trailing `on_progress(fraction)` callback
+//! that is called after every band. The promise rejects up front if the
+//! active policy rejects the effect.
+
+use js_sys::{Function, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+
+use crate::callbacks::report_progress;
+use crate::effect::Effect;
+use crate::jobs::{BlurJob, Job, PixelateJob, SolidFillJob};
+use crate::policy::policed_size;
+use crate::types::{Color, Rect};
+
+/// Approximate number of pixels processed between yields
+const PIXELS_PER_TICK: u32 = 1 << 18;
+
+fn rows_per_tick(w: u32) -> u32 {
+    (PIXELS_PER_TICK / w.max(1)).max(1)
+}
+
+fn spawn(
+    mut data: Vec<u8>,
+    mut job: Box<dyn Job>,
+    rows: u32,
+    on_progress: Option<Function>,
+) -> Promise {
+    future_to_promise(async move {
+        while !job.step(&mut data, rows) {
+            report_progress(&on_progress, job.progress());
+            next_tick().await?;
+        }
+        report_progress(&on_progress, job.progress());
+        Ok(Uint8Array::from(&data[..]).into())
+    })
+}
+
+/// The size of `effect` over `x, y, w, h` as the active policy accepts or
+/// upgrades it, or the rejected promise to return instead
+fn policed_or_reject(effect: Effect, x: u32, y: u32, w: u32, h: u32) -> Result<u32, Promise> {
+    policed_size(effect, Rect::new(x, y, w, h))
+        .map_err(|error| Promise::reject(&JsError::from(error).into()))
+}
+
+/// Resolves on the next macrotask. `setTimeout` is looked up on the global
+/// object so this works in windows, workers, and embedded WebViews alike.
+async fn next_tick() -> Result<(), JsValue> {
+    let set_timeout: Function =
+        Reflect::get(&js_sys::global(), &"setTimeout".into())?.dyn_into()?;
+    let mut scheduled = Ok(JsValue::UNDEFINED);
+    let tick = Promise::new(&mut |resolve, _| {
+        scheduled = set_timeout.call2(&JsValue::UNDEFINED, &resolve, &JsValue::from(0));
+    });
+    scheduled?;
+    JsFuture::from(tick).await?;
+    Ok(())
+}
+
+/// Solid fill a region without blocking; resolves to the redacted pixels
+#[wasm_bindgen]
+pub fn solid_fill_async(
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+    on_progress: Option<Function>,
+) -> Promise {
+    let fill = Effect::SolidFill {
+        color: Color::new(r, g, b),
+    };
+    if let Err(rejected) = policed_or_reject(fill, x, y, w, h) {
+        return rejected;
+    }
+    let job = SolidFillJob::new(width, height, x, y, w, h, [r, g, b]);
+    spawn(data, Box::new(job), rows_per_tick(w), on_progress)
+}
+
+/// Pixelate a region without blocking; resolves to the redacted pixels
+#[wasm_bindgen]
+pub fn pixelate_async(
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    block_size: u32,
+    on_progress: Option<Function>,
+) -> Promise {
+    let pixelate = Effect::pixelate(block_size);
+    let block_size = match policed_or_reject(pixelate, x, y, w, h) {
+        Ok(block_size) => block_size,
+        Err(rejected) => return rejected,
+    };
+    let job = PixelateJob::new(width, height, x, y, w, h, block_size);
+    spawn(data, Box::new(job), rows_per_tick(w), on_progress)
+}
+
+/// Gaussian blur a region without blocking; resolves to the redacted pixels
+#[wasm_bindgen]
+pub fn gaussian_blur_async(
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    radius: u32,
+    on_progress: Option<Function>,
+) -> Promise {
+    let radius = match policed_or_reject(Effect::GaussianBlur { radius }, x, y, w, h) {
+        Ok(radius) => radius,
+        Err(rejected) => return rejected,
+    };
+    let job = BlurJob::new(&data, width, height, x, y, w, h, radius);
+    // Blur cost scales with the kernel width, so shrink the band to match
+    let kernel = radius.saturating_mul(2).saturating_add(1);
+    let rows = rows_per_tick(w.saturating_mul(kernel));
+    spawn(data, Box::new(job), rows, on_progress)
+}
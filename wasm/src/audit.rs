@@ -0,0 +1,216 @@
+//! Record of every operation applied to an image, for proving afterwards
+//! exactly what was redacted and how.
+//!
+//! Each entry holds the effect, its parameters and region, a timestamp and
+//! SHA-256 hashes of the whole buffer before and after the operation, so a
+//! chain of entries links the original file to the exported one.
+
+use wasm_bindgen::prelude::*;
+
+use crate::blend::apply_blended;
+use crate::classify::PiiClass;
+use crate::error::RedactError;
+use crate::hash::{hex, sha256};
+use crate::json::Json;
+use crate::pipeline::Op;
+use crate::types::Rect;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub operation: String,
+    pub params: Json,
+    /// Affected rectangle, if the operation has one
+    pub region: Option<Rect>,
+    /// PII class of the redacted content, for per-class retention rules
+    pub class: Option<PiiClass>,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: f64,
+    pub input_hash: [u8; 32],
+    pub output_hash: [u8; 32],
+}
+
+impl AuditEntry {
+    fn json(&self, index: usize) -> Json {
+        Json::object()
+            .with("index", index)
+            .with("operation", self.operation.as_str())
+            .with("params", self.params.clone())
+            .with("region", self.region.map(|r| r.json()))
+            .with("class", self.class.as_ref().map(PiiClass::name))
+            .with("timestamp", self.timestamp)
+            .with("input_sha256", hex(&self.input_hash))
+            .with("output_sha256", hex(&self.output_hash))
+    }
+}
+
+/// Append-only list of applied operations
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditLog {
+    entries: Vec<AuditEntry>,
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now() -> f64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+}
+
+/// Parameters of a rect operation, including its channels and strength
+pub(crate) fn op_params(op: &Op) -> Json {
+    op.effect
+        .params_json()
+        .with("channels", op.channels.0 as u32)
+        .with("strength", op.strength)
+}
+
+impl AuditLog {
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    /// Append an entry whose hashes were computed by the caller
+    pub(crate) fn push(
+        &mut self,
+        operation: &str,
+        params: Json,
+        region: Option<Rect>,
+        class: Option<PiiClass>,
+        input_hash: [u8; 32],
+        output_hash: [u8; 32],
+    ) {
+        self.entries.push(AuditEntry {
+            operation: operation.to_string(),
+            params,
+            region,
+            class,
+            timestamp: now(),
+            input_hash,
+            output_hash,
+        });
+    }
+
+    /// Apply `op` and record it with the buffer hashes around it
+    pub(crate) fn apply_op(
+        &mut self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        op: &Op,
+    ) -> Result<(), RedactError> {
+        let input_hash = sha256(data);
+        apply_blended(
+            &op.effect,
+            data,
+            width,
+            height,
+            op.region,
+            op.channels,
+            op.strength,
+        )?;
+        self.push(
+            op.effect.name(),
+            op_params(op),
+            Some(op.region),
+            op.class.clone(),
+            input_hash,
+            sha256(data),
+        );
+        Ok(())
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        let entries = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| entry.json(i))
+            .collect();
+        Json::object()
+            .with("version", env!("CARGO_PKG_VERSION"))
+            .with("entries", Json::Array(entries))
+    }
+}
+
+#[wasm_bindgen]
+impl AuditLog {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> AuditLog {
+        AuditLog::default()
+    }
+
+    /// Number of recorded operations
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The log as JSON: `{"version", "entries": [{operation, params, region,
+    /// class, timestamp, input_sha256, output_sha256}, ...]}`
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::types::Color;
+
+    #[test]
+    fn test_entries_chain_buffer_hashes() {
+        let mut data = vec![10u8; 8 * 8 * 4];
+        let original_hash = sha256(&data);
+        let mut log = AuditLog::new();
+        log.apply_op(
+            &mut data,
+            8,
+            8,
+            &Op::new(Rect::new(0, 0, 4, 4), Effect::GaussianBlur { radius: 2 }),
+        )
+        .unwrap();
+        log.apply_op(
+            &mut data,
+            8,
+            8,
+            &Op::new(Rect::new(4, 4, 4, 4), Effect::pixelate(2)),
+        )
+        .unwrap();
+
+        let [first, second] = log.entries() else {
+            panic!("expected two entries");
+        };
+        assert_eq!(first.input_hash, original_hash);
+        assert_eq!(first.output_hash, second.input_hash);
+        assert_eq!(second.output_hash, sha256(&data));
+        assert!(second.timestamp >= first.timestamp);
+    }
+
+    #[test]
+    fn test_json_lists_operation_details() {
+        let mut data = vec![0u8; 4 * 4 * 4];
+        let mut log = AuditLog::new();
+        let mut op = Op::new(
+            Rect::new(1, 1, 2, 2),
+            Effect::SolidFill {
+                color: Color::new(255, 0, 16),
+            },
+        );
+        op.strength = 0.5;
+        log.apply_op(&mut data, 4, 4, &op).unwrap();
+
+        let json = log.to_json();
+        assert!(json.contains(
+            r##""operation":"solid_fill","params":{"color":"#ff0010","channels":7,"strength":0.5},"region":{"x":1,"y":1,"w":2,"h":2}"##
+        ));
+        assert!(json.contains(&format!(r#""output_sha256":"{}""#, hex(&sha256(&data)))));
+    }
+}
@@ -0,0 +1,428 @@
+//! Detection of QR codes and linear barcodes, which often carry URLs with
+//! tokens in them.
+//!
+//! Nothing is decoded; the detector only needs to know where codes are.
+//! The image is binarized with Otsu's threshold, then:
+//!
+//! - QR codes are found by their three finder patterns, whose rows and
+//!   columns read dark-light-dark-light-dark in the proportions 1:1:3:1:1.
+//!   Three finders of one module size at the corners of a right isosceles
+//!   triangle make a code, at any rotation.
+//! - Linear barcodes are rows of many narrow bars that repeat, pixel for
+//!   pixel, over enough consecutive rows.
+
+use wasm_bindgen::prelude::*;
+
+use crate::effect::Effect;
+use crate::error::{check_buffer, RedactError};
+use crate::policy::policed;
+use crate::text::flatten;
+use crate::types::{Color, Rect};
+use crate::verify::luma;
+
+/// Finders seen on fewer rows than this are discarded as chance matches
+const MIN_FINDER_ROWS: u32 = 2;
+/// Dark bars a row needs to be part of a barcode
+const MIN_BARS: usize = 15;
+/// Widest bar or gap inside a barcode, in pixels
+const MAX_BAR: u32 = 16;
+/// Share of columns that must match the row above to continue a barcode
+const ROW_AGREEMENT: f32 = 0.9;
+/// Barcode heights at least this, and at least an eighth of their width
+const MIN_BAR_HEIGHT: u32 = 10;
+
+/// Dark pixels of `data` by Otsu's threshold on luma
+fn binarize(data: &[u8]) -> Vec<bool> {
+    let lumas: Vec<u8> = data.chunks_exact(4).map(|px| luma(px) as u8).collect();
+    let mut histogram = [0u32; 256];
+    for &l in &lumas {
+        histogram[l as usize] += 1;
+    }
+    let total = lumas.len() as f64;
+    let sum: f64 = (0..256).map(|i| i as f64 * histogram[i] as f64).sum();
+    let (mut best, mut threshold) = (0.0, 0u8);
+    let (mut below, mut below_sum) = (0.0, 0.0);
+    for (i, &count) in histogram.iter().enumerate() {
+        below += count as f64;
+        below_sum += i as f64 * count as f64;
+        if below == 0.0 || below == total {
+            continue;
+        }
+        let (mean_lo, mean_hi) = (below_sum / below, (sum - below_sum) / (total - below));
+        let between = below * (total - below) * (mean_lo - mean_hi).powi(2);
+        if between > best {
+            best = between;
+            threshold = i as u8;
+        }
+    }
+    lumas.iter().map(|&l| l <= threshold).collect()
+}
+
+/// Whether five runs (dark first) read 1:1:3:1:1; returns the module size
+fn finder_ratio(runs: &[u32; 5]) -> Option<f32> {
+    let total: u32 = runs.iter().sum();
+    if total < 7 {
+        return None;
+    }
+    let module = total as f32 / 7.0;
+    let slack = module / 2.0;
+    runs.iter()
+        .zip([1.0, 1.0, 3.0, 1.0, 1.0])
+        .all(|(&run, expected)| (run as f32 - module * expected).abs() < slack * expected)
+        .then_some(module)
+}
+
+/// A finder pattern's center and module size, and the rows it was seen on
+#[derive(Debug, Clone, Copy)]
+struct Finder {
+    x: f32,
+    y: f32,
+    module: f32,
+    rows: u32,
+}
+
+/// Center and total length of the finder crossing `(x, y)` vertically
+fn cross_check(
+    dark: &[bool],
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+) -> Option<(f32, u32)> {
+    let at = |y: usize| dark[y * width + x];
+    let walk = |up: bool| {
+        // Runs from the center outwards: dark (center), light, dark
+        let mut runs = [0u32; 3];
+        let mut yy = y as i64;
+        for (i, run) in runs.iter_mut().enumerate() {
+            let want = i % 2 == 0;
+            while (0..height as i64).contains(&yy) && at(yy as usize) == want {
+                *run += 1;
+                yy += if up { -1 } else { 1 };
+            }
+        }
+        runs
+    };
+    let (up, down) = (walk(true), walk(false));
+    // The center row is counted in both directions
+    let runs = [up[2], up[1], up[0] + down[0] - 1, down[1], down[2]];
+    finder_ratio(&runs)?;
+    let top = y as f32 - (up[0] - 1) as f32;
+    let center = top + runs[2] as f32 / 2.0 - 0.5;
+    Some((center, runs.iter().sum()))
+}
+
+/// Finder patterns in a binarized image
+fn finders(dark: &[bool], width: usize, height: usize) -> Vec<Finder> {
+    let mut found: Vec<Finder> = Vec::new();
+    for y in 0..height {
+        let row = &dark[y * width..(y + 1) * width];
+        // (dark, length) of the runs so far in this row
+        let mut runs: Vec<(bool, u32)> = Vec::new();
+        for x in 0..=width {
+            let pixel = row.get(x).copied().unwrap_or(false);
+            match runs.last_mut() {
+                Some((value, len)) if *value == pixel && x < width => *len += 1,
+                _ => {
+                    if let [.., a, b, c, d, e] = runs[..] {
+                        if a.0 && !b.0 && c.0 && !d.0 && e.0 {
+                            let five = [a.1, b.1, c.1, d.1, e.1];
+                            if let Some(module) = finder_ratio(&five) {
+                                let cx = x as f32 - (e.1 + d.1) as f32 - c.1 as f32 / 2.0 - 0.5;
+                                if let Some((cy, tall)) =
+                                    cross_check(dark, width, height, cx as usize, y)
+                                {
+                                    let horizontal: u32 = five.iter().sum();
+                                    if tall.abs_diff(horizontal) * 5 <= horizontal * 2 {
+                                        add_finder(&mut found, cx, cy, module);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    if x < width {
+                        runs.push((pixel, 1));
+                    }
+                }
+            }
+        }
+    }
+    found.retain(|f| f.rows >= MIN_FINDER_ROWS);
+    found
+}
+
+/// Count a sighting towards a nearby finder of the same size, or start one
+fn add_finder(found: &mut Vec<Finder>, x: f32, y: f32, module: f32) {
+    let same = found.iter_mut().find(|f| {
+        (f.x - x).abs() <= f.module * 2.0
+            && (f.y - y).abs() <= f.module * 2.0
+            && (f.module - module).abs() <= f.module.max(1.0)
+    });
+    match same {
+        Some(f) => {
+            let n = f.rows as f32;
+            f.x = (f.x * n + x) / (n + 1.0);
+            f.y = (f.y * n + y) / (n + 1.0);
+            f.module = (f.module * n + module) / (n + 1.0);
+            f.rows += 1;
+        }
+        None => found.push(Finder {
+            x,
+            y,
+            module,
+            rows: 1,
+        }),
+    }
+}
+
+/// Bounds of the code whose finders are `a` (the corner), `b` and `c`
+fn qr_bounds(a: &Finder, b: &Finder, c: &Finder, width: u32, height: u32) -> Option<Rect> {
+    let d = (b.x + c.x - a.x, b.y + c.y - a.y);
+    let points = [(a.x, a.y), (b.x, b.y), (c.x, c.y), d];
+    // Finder centers are 3.5 modules in from the corners; pad for rotation
+    let pad = (a.module + b.module + c.module) / 3.0 * 5.0;
+    let x0 = points.iter().map(|p| p.0).fold(f32::MAX, f32::min) - pad;
+    let y0 = points.iter().map(|p| p.1).fold(f32::MAX, f32::min) - pad;
+    let x1 = points.iter().map(|p| p.0).fold(f32::MIN, f32::max) + pad;
+    let y1 = points.iter().map(|p| p.1).fold(f32::MIN, f32::max) + pad;
+    let (x, y) = (x0.max(0.0) as u32, y0.max(0.0) as u32);
+    Rect::new(
+        x,
+        y,
+        (x1.max(0.0) as u32).saturating_sub(x),
+        (y1.max(0.0) as u32).saturating_sub(y),
+    )
+    .clip(width, height)
+}
+
+/// QR codes from triples of finders, each finder used at most once
+fn qr_codes(finders: &[Finder], width: u32, height: u32) -> Vec<Rect> {
+    let dist = |p: &Finder, q: &Finder| (p.x - q.x).powi(2) + (p.y - q.y).powi(2);
+    let mut used = vec![false; finders.len()];
+    let mut codes = Vec::new();
+    for i in 0..finders.len() {
+        for j in i + 1..finders.len() {
+            for k in j + 1..finders.len() {
+                if used[i] || used[j] || used[k] {
+                    continue;
+                }
+                let (p, q, r) = (&finders[i], &finders[j], &finders[k]);
+                let modules = [p.module, q.module, r.module];
+                let (lo, hi) = modules
+                    .iter()
+                    .fold((f32::MAX, 0.0f32), |(lo, hi), &m| (lo.min(m), hi.max(m)));
+                if hi > lo * 1.5 {
+                    continue;
+                }
+                // The corner is opposite the longest side
+                let mut sides = [
+                    (dist(q, r), p, q, r),
+                    (dist(p, r), q, p, r),
+                    (dist(p, q), r, p, q),
+                ];
+                sides.sort_by(|a, b| a.0.total_cmp(&b.0));
+                let (hyp, corner, b, c) = sides[2];
+                let (leg1, leg2) = (dist(corner, b), dist(corner, c));
+                let legs_equal = (leg1 - leg2).abs() <= leg1.max(leg2) * 0.3;
+                let right_angle = (hyp - leg1 - leg2).abs() <= hyp * 0.2;
+                // Version 1 codes put finders 14 modules apart
+                let far_enough = leg1.min(leg2) >= (hi * 12.0).powi(2);
+                if legs_equal && right_angle && far_enough {
+                    if let Some(rect) = qr_bounds(corner, b, c, width, height) {
+                        used[i] = true;
+                        used[j] = true;
+                        used[k] = true;
+                        codes.push(rect);
+                    }
+                }
+            }
+        }
+    }
+    codes
+}
+
+/// Runs of narrow bars in one row, as `[x0, x1)` spans
+fn bar_spans(row: &[bool]) -> Vec<(u32, u32)> {
+    let mut spans = Vec::new();
+    let (mut start, mut bars, mut run_start) = (None::<u32>, 0, 0u32);
+    for x in 0..=row.len() {
+        let edge = x == row.len() || (x > 0 && row[x] != row[x - 1]);
+        if !edge {
+            continue;
+        }
+        let (x, len) = (x as u32, x as u32 - run_start);
+        let was_dark = row[run_start as usize];
+        if len <= MAX_BAR {
+            if start.is_none() && was_dark {
+                start = Some(run_start);
+            }
+            bars += was_dark as usize;
+        } else {
+            if let Some(s) = start.filter(|_| bars >= MIN_BARS) {
+                spans.push((s, run_start));
+            }
+            (start, bars) = (None, 0);
+        }
+        run_start = x;
+    }
+    if let Some(s) = start.filter(|_| bars >= MIN_BARS) {
+        spans.push((s, row.len() as u32));
+    }
+    spans
+}
+
+/// Linear barcodes: bar spans repeated over enough consecutive rows
+fn linear_codes(dark: &[bool], width: usize, height: usize) -> Vec<Rect> {
+    // (x0, x1, first row, last row) of barcodes still growing
+    let mut open: Vec<(u32, u32, u32, u32)> = Vec::new();
+    let mut codes = Vec::new();
+    let mut close = |(x0, x1, y0, y1): (u32, u32, u32, u32)| {
+        let (w, h) = (x1 - x0, y1 - y0 + 1);
+        if h >= MIN_BAR_HEIGHT && h * 8 >= w {
+            codes.push(Rect::new(x0, y0, w, h));
+        }
+    };
+    for y in 0..height {
+        let row = &dark[y * width..(y + 1) * width];
+        let mut next = Vec::new();
+        for (x0, x1) in bar_spans(row) {
+            let continues = open.iter().position(|&(ox0, ox1, _, _)| {
+                let (lo, hi) = (x0.max(ox0), x1.min(ox1));
+                if hi <= lo || (hi - lo) * 10 < (x1 - x0).max(ox1 - ox0) * 8 {
+                    return false;
+                }
+                let above = &dark[(y - 1) * width..y * width];
+                let agree = (lo..hi)
+                    .filter(|&x| row[x as usize] == above[x as usize])
+                    .count();
+                agree as f32 >= (hi - lo) as f32 * ROW_AGREEMENT
+            });
+            match continues {
+                Some(i) => {
+                    let (ox0, ox1, y0, _) = open.swap_remove(i);
+                    next.push((ox0.min(x0), ox1.max(x1), y0, y as u32));
+                }
+                None => next.push((x0, x1, y as u32, y as u32)),
+            }
+        }
+        for code in open.drain(..) {
+            close(code);
+        }
+        open = next;
+    }
+    for code in open {
+        close(code);
+    }
+    codes
+}
+
+/// Bounds of QR codes and linear barcodes in an RGBA image
+pub(crate) fn detect(data: &[u8], width: u32, height: u32) -> Result<Vec<Rect>, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    let (w, h) = (width as usize, height as usize);
+    let dark = binarize(data);
+    let mut codes = qr_codes(&finders(&dark, w, h), width, height);
+    // A QR code's module rows can look like short barcodes
+    let linear: Vec<Rect> = linear_codes(&dark, w, h)
+        .into_iter()
+        .filter(|bar| !codes.iter().any(|qr| qr.intersects(bar)))
+        .collect();
+    codes.extend(linear);
+    Ok(codes)
+}
+
+/// Bounds (flat `[x, y, w, h, ...]`) of QR codes and linear barcodes
+#[wasm_bindgen]
+pub fn detect_barcodes(data: &[u8], width: u32, height: u32) -> Result<Vec<u32>, JsError> {
+    Ok(flatten(&detect(data, width, height)?))
+}
+
+/// Solid-fill every QR code and barcode found with `color`; returns their
+/// bounds, flat
+#[wasm_bindgen]
+pub fn redact_barcodes(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    color: &Color,
+) -> Result<Vec<u32>, JsError> {
+    let codes = detect(data, width, height)?;
+    for &rect in &codes {
+        policed(Effect::SolidFill { color: *color }, rect)?.apply_rect(data, width, height, rect);
+    }
+    Ok(flatten(&codes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white(width: u32, height: u32) -> Vec<u8> {
+        vec![255; (width * height * 4) as usize]
+    }
+
+    fn paint(data: &mut [u8], width: u32, rect: Rect, v: u8) {
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                let i = ((y * width + x) * 4) as usize;
+                data[i..i + 3].fill(v);
+            }
+        }
+    }
+
+    /// A 21-module code with three finders and scattered data modules
+    fn qr(data: &mut [u8], width: u32, (ox, oy): (u32, u32), module: u32) -> Rect {
+        let cell = |x: u32, y: u32, w: u32, h: u32| {
+            Rect::new(ox + x * module, oy + y * module, w * module, h * module)
+        };
+        for (fx, fy) in [(0, 0), (14, 0), (0, 14)] {
+            paint(data, width, cell(fx, fy, 7, 1), 0);
+            paint(data, width, cell(fx, fy + 6, 7, 1), 0);
+            paint(data, width, cell(fx, fy, 1, 7), 0);
+            paint(data, width, cell(fx + 6, fy, 1, 7), 0);
+            paint(data, width, cell(fx + 2, fy + 2, 3, 3), 0);
+        }
+        for i in 0u32..60 {
+            let (x, y) = (8 + i * 7 % 13, 8 + i * 11 % 13);
+            paint(data, width, cell(x, y, 1, 1), 0);
+        }
+        cell(0, 0, 21, 21)
+    }
+
+    #[test]
+    fn test_finds_qr_codes() {
+        let (w, h) = (200, 160);
+        let mut data = white(w, h);
+        let code = qr(&mut data, w, (30, 20), 4);
+        let found = detect(&data, w, h).unwrap();
+        assert_eq!(found.len(), 1, "{:?}", found);
+        assert!(found[0].contains(&code), "{:?} vs {:?}", found[0], code);
+        assert!(found[0].w <= code.w + 8 * 4);
+
+        // Two finders alone aren't a code
+        paint(&mut data, w, Rect::new(30, 76, 28, 28), 255);
+        assert!(detect(&data, w, h).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_redacts_linear_barcodes() {
+        let (w, h) = (220, 60);
+        let mut data = white(w, h);
+        let (mut x, mut end) = (20, 0);
+        for i in 0u32..24 {
+            let bar = 2 + i * 5 % 3 * 2;
+            paint(&mut data, w, Rect::new(x, 10, bar, 40), 0);
+            end = x + bar;
+            x = end + 2 + i % 2 * 2;
+        }
+        let black = Color::new(0, 0, 0);
+        let found = redact_barcodes(&mut data, w, h, &black).unwrap();
+        assert_eq!(found.len(), 4);
+        let bars = Rect::new(found[0], found[1], found[2], found[3]);
+        assert_eq!((bars.x, bars.y, bars.h), (20, 10, 40));
+        assert_eq!(bars.right(), end);
+        // The whole barcode is filled, gaps included
+        let i = ((30 * w + 23) * 4) as usize;
+        assert_eq!(&data[i..i + 3], &[0, 0, 0]);
+    }
+}
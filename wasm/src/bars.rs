@@ -0,0 +1,587 @@
+//! Text-line–fitted fill bars.
+//!
+//! Raw OCR boxes hug each word separately, so bars drawn from them step up
+//! and down with every ascender and leave slivers between words. Fitted
+//! bars measure each line's ink profile instead: the x-height band (where
+//! ink is densest) gives the baseline, and every bar on the line shares
+//! the same top (ascenders) and bottom (baseline plus a descender
+//! allowance). Words separated by no more than a word space merge into one
+//! bar, so a redacted phrase reads as a single clean line bar.
+//!
+//! Lines are split at column gutters, so bars never bridge two columns of a
+//! multi-column layout, and a skewed line (a scan fed in at an angle) gets
+//! a bar rotated to match rather than an axis-aligned box sitting askew.
+//!
+//! `redact_bar` draws one classic bar by hand: solid, with optional
+//! rounded corners and a 1px border.
+
+use wasm_bindgen::prelude::*;
+
+use crate::buffer::{read_region, write_region};
+use crate::effect::Effect;
+use crate::error::{check_buffer, RedactError};
+use crate::policy::{or_throw, policed};
+use crate::stack::{apply_stack, EffectStack};
+use crate::text::{flatten, same_column_line};
+use crate::types::{Color, Rect};
+use crate::verify::luma;
+
+/// Row ink, relative to the densest row, that counts as inside the
+/// x-height band
+const BAND_DENSITY: f32 = 0.4;
+/// Row ink that counts as any ink at all (ascender tips, descenders)
+const INK_DENSITY: f32 = 0.02;
+/// Descenders reach about this far below the baseline, in x-heights
+const DESCENDER: f32 = 0.4;
+
+/// Vertical metrics of one text line, in image rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineMetrics {
+    /// Topmost ink (ascenders, capitals)
+    pub top: u32,
+    /// Top of the x-height band
+    pub x_top: u32,
+    /// Last row of the x-height band
+    pub baseline: u32,
+    /// Bottommost ink (descenders)
+    pub bottom: u32,
+}
+
+impl LineMetrics {
+    pub fn x_height(&self) -> u32 {
+        self.baseline - self.x_top + 1
+    }
+}
+
+/// Measure the line of text inside `rect`, or `None` if it holds no ink
+pub fn measure_line(data: &[u8], width: u32, rect: Rect) -> Option<LineMetrics> {
+    let pixel = |x: u32, y: u32| {
+        let i = ((y * width + x) * 4) as usize;
+        luma(&data[i..i + 4])
+    };
+    // Ink is whichever extreme is rarer: split halfway between min and max,
+    // and take the minority side (dark text on light paper, or inverted)
+    let (mut lo, mut hi) = (f64::MAX, f64::MIN);
+    for y in rect.y..rect.bottom() {
+        for x in rect.x..rect.right() {
+            let l = pixel(x, y);
+            lo = lo.min(l);
+            hi = hi.max(l);
+        }
+    }
+    if hi - lo < 32.0 {
+        return None;
+    }
+    let mid = (lo + hi) / 2.0;
+    let profile: Vec<u32> = (rect.y..rect.bottom())
+        .map(|y| {
+            (rect.x..rect.right())
+                .filter(|&x| pixel(x, y) < mid)
+                .count() as u32
+        })
+        .collect();
+    let dark_total: u32 = profile.iter().sum();
+    let profile: Vec<u32> = if dark_total * 2 > rect.w * rect.h {
+        profile.iter().map(|&n| rect.w - n).collect()
+    } else {
+        profile
+    };
+
+    let peak = *profile.iter().max()? as f32;
+    let rows_over = |density: f32| {
+        let mut rows = profile
+            .iter()
+            .enumerate()
+            .filter(move |(_, &n)| n as f32 >= peak * density)
+            .map(|(i, _)| rect.y + i as u32);
+        let first = rows.next()?;
+        Some((first, rows.next_back().unwrap_or(first)))
+    };
+    let (top, bottom) = rows_over(INK_DENSITY)?;
+    let (x_top, baseline) = rows_over(BAND_DENSITY)?;
+    Some(LineMetrics {
+        top,
+        x_top,
+        baseline,
+        bottom,
+    })
+}
+
+/// Smallest line angle, in radians (about half a degree), worth drawing a
+/// rotated bar for
+const MIN_SKEW: f32 = 0.0087;
+/// Largest angle taken as skew rather than a misgrouped line (about 20°)
+const MAX_SKEW: f32 = 0.35;
+
+/// Rotated rectangle: extents along the line direction `(cos, sin)` and
+/// across it, around a center
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Oriented {
+    center: (f32, f32),
+    cos: f32,
+    sin: f32,
+    along: (f32, f32),
+    across: (f32, f32),
+}
+
+impl Oriented {
+    fn contains(&self, x: f32, y: f32) -> bool {
+        let (dx, dy) = (x - self.center.0, y - self.center.1);
+        let u = dx * self.cos + dy * self.sin;
+        let v = -dx * self.sin + dy * self.cos;
+        (self.along.0..=self.along.1).contains(&u) && (self.across.0..=self.across.1).contains(&v)
+    }
+
+    fn bounds(&self, width: u32, height: u32) -> Option<Rect> {
+        let (mut x0, mut y0, mut x1, mut y1) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+        for u in [self.along.0, self.along.1] {
+            for v in [self.across.0, self.across.1] {
+                let x = self.center.0 + u * self.cos - v * self.sin;
+                let y = self.center.1 + u * self.sin + v * self.cos;
+                (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+            }
+        }
+        let (x0, y0) = (x0.floor().max(0.0) as u32, y0.floor().max(0.0) as u32);
+        Rect::new(
+            x0,
+            y0,
+            (x1.ceil() as u32).saturating_sub(x0),
+            (y1.ceil() as u32).saturating_sub(y0),
+        )
+        .clip(width, height)
+    }
+}
+
+/// A fitted bar: axis-aligned, or rotated to the line's skew inside its
+/// bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bar {
+    pub bounds: Rect,
+    /// Line angle in radians, positive clockwise (y down); 0 when level
+    pub angle: f32,
+    shape: Option<Oriented>,
+}
+
+impl Bar {
+    fn level(bounds: Rect) -> Bar {
+        Bar {
+            bounds,
+            angle: 0.0,
+            shape: None,
+        }
+    }
+
+    /// Apply `stack` inside the bar only
+    pub fn apply(
+        &self,
+        stack: &EffectStack,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+    ) -> Result<(), RedactError> {
+        let Some(shape) = self.shape else {
+            return apply_stack(stack.effects(), data, width, height, self.bounds);
+        };
+        let rect = self.bounds;
+        let mut scratch = read_region(data, width, rect);
+        let original = scratch.to_vec();
+        apply_stack(
+            stack.effects(),
+            &mut scratch,
+            rect.w,
+            rect.h,
+            Rect::new(0, 0, rect.w, rect.h),
+        )?;
+        for y in 0..rect.h {
+            for x in 0..rect.w {
+                let (px, py) = ((rect.x + x) as f32 + 0.5, (rect.y + y) as f32 + 0.5);
+                if !shape.contains(px, py) {
+                    let i = ((y * rect.w + x) * 4) as usize;
+                    scratch[i..i + 4].copy_from_slice(&original[i..i + 4]);
+                }
+            }
+        }
+        write_region(data, width, rect, &scratch);
+        Ok(())
+    }
+}
+
+/// Least-squares angle of the line through the word centers, or 0 when
+/// the centers drift less than half a line height end to end (ragged OCR
+/// boxes rather than skew)
+fn line_angle(line: &[Rect]) -> f32 {
+    if line.len() < 2 {
+        return 0.0;
+    }
+    let centers: Vec<(f32, f32)> = line
+        .iter()
+        .map(|r| (r.x as f32 + r.w as f32 / 2.0, r.y as f32 + r.h as f32 / 2.0))
+        .collect();
+    let n = centers.len() as f32;
+    let (mx, my) = centers
+        .iter()
+        .fold((0.0, 0.0), |(a, b), c| (a + c.0 / n, b + c.1 / n));
+    let (mut cov, mut var) = (0.0, 0.0);
+    for (x, y) in &centers {
+        cov += (x - mx) * (y - my);
+        var += (x - mx) * (x - mx);
+    }
+    if var <= 0.0 {
+        return 0.0;
+    }
+    let slope = cov / var;
+    let span = centers[centers.len() - 1].0 - centers[0].0;
+    let line_height = line.iter().map(|r| r.h).max().unwrap_or(0) as f32;
+    if (slope * span).abs() < line_height / 2.0 {
+        return 0.0;
+    }
+    slope.atan()
+}
+
+/// Rotated bars for a skewed line, one per run of close words
+fn oriented_bars(line: &[Rect], angle: f32, padding: f32, merge_gap: f32) -> Vec<Oriented> {
+    let (sin, cos) = angle.sin_cos();
+    let (s, c) = (sin.abs(), cos);
+    // Recover each word's length and thickness from its axis-aligned box
+    let words: Vec<((f32, f32), f32, f32)> = line
+        .iter()
+        .map(|r| {
+            let (w, h) = (r.w as f32, r.h as f32);
+            let thick = ((h * c - w * s) / (c * c - s * s)).clamp(h * 0.3, h);
+            let length = ((w - thick * s) / c).max(1.0);
+            ((r.x as f32 + w / 2.0, r.y as f32 + h / 2.0), length, thick)
+        })
+        .collect();
+    let center = words[0].0;
+    let frame = |p: (f32, f32)| {
+        let (dx, dy) = (p.0 - center.0, p.1 - center.1);
+        (dx * cos + dy * sin, -dx * sin + dy * cos)
+    };
+    let thickness = words.iter().map(|w| w.2).fold(0.0, f32::max);
+    let gap = thickness / 2.0 * merge_gap;
+
+    let mut bars: Vec<Oriented> = Vec::new();
+    for &(p, length, thick) in &words {
+        let (u, v) = frame(p);
+        let along = (u - length / 2.0 - padding, u + length / 2.0 + padding);
+        let across = (v - thick / 2.0 - padding, v + thick / 2.0 + padding);
+        match bars.last_mut() {
+            Some(bar) if along.0 <= bar.along.1 + gap => {
+                bar.along.1 = bar.along.1.max(along.1);
+                bar.across = (bar.across.0.min(across.0), bar.across.1.max(across.1));
+            }
+            _ => bars.push(Oriented {
+                center,
+                cos,
+                sin,
+                along,
+                across,
+            }),
+        }
+    }
+    bars
+}
+
+/// Fitted bars over OCR word boxes: one per run of closely spaced words on
+/// a line, `padding` px larger than the ink on every side. Words further
+/// apart than `merge_gap` x-heights get separate bars, and words across a
+/// column gutter are never on the same line.
+///
+/// Level lines get bars measured from their ink. Skewed lines (a rotated
+/// scan) get bars rotated to the line's angle, sized from the OCR boxes;
+/// single words take the page's typical skew.
+pub fn fit_bars(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    words: &[Rect],
+    padding: u32,
+    merge_gap: f32,
+) -> Result<Vec<Bar>, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    let mut lines: Vec<Vec<Rect>> = Vec::new();
+    for word in words.iter().filter_map(|w| w.clip(width, height)) {
+        match lines.last_mut() {
+            Some(line) if same_column_line(&line[line.len() - 1], &word) => line.push(word),
+            _ => lines.push(vec![word]),
+        }
+    }
+
+    let angles: Vec<f32> = lines.iter().map(|line| line_angle(line)).collect();
+    let mut skews: Vec<f32> = lines
+        .iter()
+        .zip(&angles)
+        .filter(|(line, a)| line.len() > 1 && a.abs() < MAX_SKEW)
+        .map(|(_, &a)| a)
+        .collect();
+    skews.sort_by(f32::total_cmp);
+    let page_skew = skews.get(skews.len() / 2).copied().unwrap_or(0.0);
+
+    let mut bars = Vec::new();
+    for (line, &angle) in lines.iter().zip(&angles) {
+        let angle = if line.len() > 1 { angle } else { page_skew };
+        if angle.abs() >= MIN_SKEW && angle.abs() < MAX_SKEW {
+            for shape in oriented_bars(line, angle, padding as f32, merge_gap) {
+                bars.extend(shape.bounds(width, height).map(|bounds| Bar {
+                    bounds,
+                    angle,
+                    shape: Some(shape),
+                }));
+            }
+            continue;
+        }
+
+        let bounds = line.iter().skip(1).fold(line[0], |a, b| a.union(b));
+        let (top, bottom, x_height) = match measure_line(data, width, bounds) {
+            Some(m) => {
+                let descender = m.baseline + (m.x_height() as f32 * DESCENDER).ceil() as u32;
+                (m.top, m.bottom.max(descender), m.x_height())
+            }
+            // Nothing measurable (blank or flat): fall back to the boxes
+            None => (bounds.y, bounds.bottom() - 1, bounds.h / 2),
+        };
+        let gap = (x_height as f32 * merge_gap) as u32;
+
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for word in line {
+            match runs.last_mut() {
+                Some(run) if word.x <= run.1 + gap => run.1 = run.1.max(word.right()),
+                _ => runs.push((word.x, word.right())),
+            }
+        }
+        for (x0, x1) in runs {
+            let x = x0.saturating_sub(padding);
+            let y = top.saturating_sub(padding);
+            let bar = Rect::new(x, y, x1 + padding - x, bottom + 1 + padding - y);
+            bars.extend(bar.clip(width, height).map(Bar::level));
+        }
+    }
+    Ok(bars)
+}
+
+fn flatten_bars(bars: &[Bar]) -> Vec<u32> {
+    let bounds: Vec<Rect> = bars.iter().map(|b| b.bounds).collect();
+    flatten(&bounds)
+}
+
+/// Fitted bars over the flat OCR word `boxes` of the text to redact, as
+/// flat `[x, y, w, h, ...]` bounding boxes (a rotated bar fills only part
+/// of its box). `merge_gap` is in x-heights; about 1 joins the words of a
+/// phrase but not separate columns.
+#[wasm_bindgen]
+pub fn fit_text_bars(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    boxes: &[u32],
+    padding: u32,
+    merge_gap: f32,
+) -> Result<Vec<u32>, JsError> {
+    let words = Rect::from_flat(boxes)?;
+    Ok(flatten_bars(&fit_bars(
+        data, width, height, &words, padding, merge_gap,
+    )?))
+}
+
+/// Apply `stack` over fitted bars for the given word boxes; returns the
+/// bars' bounding boxes
+#[wasm_bindgen]
+pub fn redact_text_bars(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    boxes: &[u32],
+    padding: u32,
+    merge_gap: f32,
+    stack: &EffectStack,
+) -> Result<Vec<u32>, JsError> {
+    stack.validate()?;
+    let words = Rect::from_flat(boxes)?;
+    let bars = fit_bars(data, width, height, &words, padding, merge_gap)?;
+    for bar in &bars {
+        bar.apply(stack, data, width, height)?;
+    }
+    Ok(flatten_bars(&bars))
+}
+
+/// Whether the pixel center `(x, y)` lies in the box `x0..x1`, `y0..y1`
+/// with corners rounded to `radius`
+fn in_rounded(x: f32, y: f32, (x0, y0, x1, y1): (f32, f32, f32, f32), radius: f32) -> bool {
+    if x < x0 || x > x1 || y < y0 || y > y1 {
+        return false;
+    }
+    let dx = (x0 + radius - x).max(x - (x1 - radius)).max(0.0);
+    let dy = (y0 + radius - y).max(y - (y1 - radius)).max(0.0);
+    dx * dx + dy * dy <= radius * radius
+}
+
+/// Draw a solid bar over `x, y, w, h` (clamped to the image), its corners
+/// rounded to `corner_radius` (0 for square; at most half the shorter
+/// side) and, if `border` is given, outlined 1px in that color. Pixels
+/// outside the rounded corners keep their content; alpha is preserved.
+#[wasm_bindgen]
+pub fn redact_bar(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    color: &Color,
+    corner_radius: u32,
+    border: Option<Color>,
+) {
+    // A policy can only allow or reject a fill
+    or_throw(policed(
+        Effect::SolidFill { color: *color },
+        Rect::new(x, y, w, h),
+    ));
+    let Some(rect) = Rect::new(x, y, w, h).clip(width, height) else {
+        return;
+    };
+    // The bar's own box, not the clipped one, so a bar running off the
+    // image isn't rounded or outlined at the image edge
+    let outer = (x as f32, y as f32, (x + w) as f32, (y + h) as f32);
+    let radius = (corner_radius.min(w.min(h) / 2)) as f32;
+    let inner = (outer.0 + 1.0, outer.1 + 1.0, outer.2 - 1.0, outer.3 - 1.0);
+    for py in rect.y..rect.bottom() {
+        for px in rect.x..rect.right() {
+            let (cx, cy) = (px as f32 + 0.5, py as f32 + 0.5);
+            if !in_rounded(cx, cy, outer, radius) {
+                continue;
+            }
+            let ink = match border {
+                Some(edge) if !in_rounded(cx, cy, inner, (radius - 1.0).max(0.0)) => edge,
+                _ => *color,
+            };
+            let i = ((py * width + px) * 4) as usize;
+            if let Some(pixel) = data.get_mut(i..i + 3) {
+                pixel.copy_from_slice(&[ink.r, ink.g, ink.b]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// White page with two words of "text" on one line, 6 px gap: an
+    /// x-height band at rows 10..=17, an ascender at rows 6..=9 in the
+    /// first word and a descender at rows 18..=20 in the second
+    fn page() -> Vec<u8> {
+        let (w, h) = (80, 30);
+        (0..w * h)
+            .flat_map(|i| {
+                let (x, y) = (i % w, i / w);
+                let first = (10..30).contains(&x);
+                let second = (36..60).contains(&x);
+                let ink = ((first || second) && (10..=17).contains(&y) && x % 2 == 0)
+                    || (x == 12 && (6..=9).contains(&y))
+                    || (x == 40 && (18..=20).contains(&y));
+                let v = if ink { 0 } else { 255 };
+                [v, v, v, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_measures_line_bands() {
+        let m = measure_line(&page(), 80, Rect::new(8, 4, 60, 20)).unwrap();
+        assert_eq!(
+            m,
+            LineMetrics {
+                top: 6,
+                x_top: 10,
+                baseline: 17,
+                bottom: 20
+            }
+        );
+        assert_eq!(m.x_height(), 8);
+    }
+
+    #[test]
+    fn test_words_merge_into_one_aligned_bar() {
+        // Ragged OCR boxes for the two words
+        let words = [Rect::new(9, 5, 22, 14), Rect::new(35, 9, 26, 13)];
+        let bars = fit_bars(&page(), 80, 30, &words, 1, 1.0).unwrap();
+        assert_eq!(bars, [Bar::level(Rect::new(8, 5, 54, 18))]);
+        // A tight merge gap keeps the words apart, with the same top and bottom
+        let split = fit_bars(&page(), 80, 30, &words, 1, 0.25).unwrap();
+        assert_eq!(split.len(), 2);
+        assert!(split
+            .iter()
+            .all(|b| b.bounds.y == 5 && b.bounds.bottom() == 23));
+    }
+
+    #[test]
+    fn test_columns_are_not_bridged() {
+        let page = vec![255u8; 200 * 20 * 4];
+        // Two words 6 px apart, then a third across a 60 px gutter
+        let words = [
+            Rect::new(0, 4, 30, 10),
+            Rect::new(36, 4, 30, 10),
+            Rect::new(126, 4, 30, 10),
+        ];
+        let bars = fit_bars(&page, 200, 20, &words, 0, 100.0).unwrap();
+        let xs: Vec<(u32, u32)> = bars
+            .iter()
+            .map(|b| (b.bounds.x, b.bounds.right()))
+            .collect();
+        assert_eq!(xs, [(0, 66), (126, 156)]);
+    }
+
+    #[test]
+    fn test_bar_with_rounded_corners_and_border() {
+        let (w, h) = (40, 20);
+        let mut data = vec![255u8; (w * h * 4) as usize];
+        let black = Color::new(0, 0, 0);
+        let red = Color::new(255, 0, 0);
+        redact_bar(&mut data, w, h, 5, 4, 30, 12, &black, 4, Some(red));
+        let at = |x: u32, y: u32| {
+            let i = ((y * w + x) * 4) as usize;
+            [data[i], data[i + 1], data[i + 2]]
+        };
+        assert_eq!(at(20, 10), [0, 0, 0]);
+        // The corner pixel is outside the rounding; the edges are outlined
+        assert_eq!(at(5, 4), [255, 255, 255]);
+        for edge in [at(20, 4), at(5, 10), at(34, 10), at(20, 15)] {
+            assert_eq!(edge, [255, 0, 0]);
+        }
+        assert_eq!(at(20, 5), [0, 0, 0]);
+        assert_eq!(at(4, 10), [255, 255, 255]);
+
+        // Square corners and no border: exactly `solid_fill`
+        let mut bar = vec![255u8; (w * h * 4) as usize];
+        let mut fill = bar.clone();
+        redact_bar(&mut bar, w, h, 30, 10, 20, 20, &black, 0, None);
+        crate::solid_fill(&mut fill, w, h, 30, 10, 20, 20, 0, 0, 0);
+        assert_eq!(bar, fill);
+    }
+
+    #[test]
+    fn test_skewed_line_gets_a_rotated_bar() {
+        let (w, h) = (120, 60);
+        let mut data = vec![255u8; (w * h * 4) as usize];
+        // Three words climbing 5 px per 36 px (about 8 degrees)
+        let words = [
+            Rect::new(5, 30, 32, 14),
+            Rect::new(41, 25, 32, 14),
+            Rect::new(77, 20, 32, 14),
+        ];
+        let black = EffectStack::new().fill(&crate::types::Color::new(0, 0, 0));
+        let bars = fit_bars(&data, w, h, &words, 1, 1.0).unwrap();
+        assert_eq!(bars.len(), 1);
+        assert!((bars[0].angle + 0.1380).abs() < 0.01, "{}", bars[0].angle);
+        bars[0].apply(&black, &mut data, w, h).unwrap();
+
+        let at = |x: u32, y: u32| data[((y * w + x) * 4) as usize];
+        // Along the line's centers: covered
+        assert_eq!((at(21, 37), at(57, 32), at(93, 27)), (0, 0, 0));
+        // Bounding box corners off the line: untouched
+        let b = bars[0].bounds;
+        assert_eq!(at(b.x + 1, b.y + 1), 255);
+        assert_eq!(at(b.right() - 2, b.bottom() - 2), 255);
+    }
+}
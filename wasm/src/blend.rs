@@ -0,0 +1,402 @@
+//! Partial application of effects: per-channel masks and strength mixing.
+//!
+//! The effect runs on a copy of the region, then the result is mixed back
+//! into the image: only the selected channels are written, each blended with
+//! the original pixel by `strength`. Alpha is handled by running the effect a
+//! second time over the alpha plane, so blur/pixelate/fill all work on it too.
+
+use wasm_bindgen::prelude::*;
+
+use crate::buffer::{read_region, write_region};
+use crate::effect::Effect;
+use crate::error::RedactError;
+use crate::pipeline::Op;
+use crate::policy::{enforce_active, or_throw};
+use crate::scratch::Scratch;
+use crate::types::{Channels, Color, Rect};
+
+/// Apply `effect` to `rect`, writing only the channels in `channels`
+pub fn apply_channels(
+    effect: &Effect,
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+    channels: Channels,
+) -> Result<(), RedactError> {
+    apply_blended(effect, data, width, height, rect, channels, 1.0)
+}
+
+/// Apply `effect` to `rect` and mix it with the original pixels.
+///
+/// `strength` is clamped to 0..=1, where 1 is the plain effect and 0 leaves
+/// the image untouched. Fails if the active policy rejects the effect or
+/// the strength.
+pub fn apply_blended(
+    effect: &Effect,
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+    channels: Channels,
+    strength: f32,
+) -> Result<(), RedactError> {
+    let mut op = Op {
+        channels,
+        strength,
+        ..Op::new(rect, *effect)
+    };
+    enforce_active(&mut op)?;
+    let (effect, strength) = (&op.effect, op.strength.clamp(0.0, 1.0));
+    if strength == 0.0 {
+        return Ok(());
+    }
+    if channels == Channels::RGB && strength == 1.0 {
+        effect.apply_rect(data, width, height, rect);
+        return Ok(());
+    }
+    let Some(rect) = rect.clip(width, height) else {
+        return Ok(());
+    };
+
+    // Grid-aligned pixelation reads whole cells, which may reach past `rect`
+    let outer = effect.footprint(rect, width, height);
+    let local = Rect::new(rect.x - outer.x, rect.y - outer.y, rect.w, rect.h);
+    let origin = (outer.x, outer.y);
+    let original = read_region(data, width, outer);
+    let mut result = original.clone();
+
+    if channels.0 & Channels::RGB.0 != 0 {
+        effect.apply_rect_at(&mut result, outer.w, outer.h, local, origin);
+    }
+
+    if channels.has(3) {
+        // Spread alpha into the color channels, run the effect, and read it
+        // back from red. Fills use the color's alpha as their "color".
+        let mut plane: Scratch = original
+            .chunks_exact(4)
+            .flat_map(|px| [px[3], px[3], px[3], 255])
+            .collect::<Vec<u8>>()
+            .into();
+        let alpha_effect = match *effect {
+            Effect::SolidFill { color } => Effect::SolidFill {
+                color: Color::new(color.a, color.a, color.a),
+            },
+            Effect::Mosaic {
+                cell_size,
+                shape,
+                grout,
+                grout_width,
+            } => Effect::Mosaic {
+                cell_size,
+                shape,
+                grout: Color::new(grout.a, grout.a, grout.a),
+                grout_width,
+            },
+            other => other,
+        };
+        alpha_effect.apply_rect_at(&mut plane, outer.w, outer.h, local, origin);
+        for (px, alpha) in result.chunks_exact_mut(4).zip(plane.chunks_exact(4)) {
+            px[3] = alpha[0];
+        }
+    }
+
+    let (original, result) = (
+        read_region(&original, outer.w, local),
+        read_region(&result, outer.w, local),
+    );
+    let mut merged = original;
+    for (i, value) in merged.iter_mut().enumerate() {
+        if channels.has(i % 4) {
+            *value = mix(*value, result[i], strength);
+        }
+    }
+    write_region(data, width, rect, &merged);
+    Ok(())
+}
+
+/// Linear blend from `from` towards `to`, rounded to nearest
+pub(crate) fn mix(from: u8, to: u8, strength: f32) -> u8 {
+    (from as f32 + (to as f32 - from as f32) * strength).round() as u8
+}
+
+/// Solid fill that only writes the channels in `channels` (see `Channel`)
+#[wasm_bindgen]
+pub fn solid_fill_channels(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+    channels: u8,
+) {
+    let effect = Effect::SolidFill {
+        color: Color::rgba(r, g, b, a),
+    };
+    or_throw(apply_channels(
+        &effect,
+        data,
+        width,
+        height,
+        Rect::new(x, y, w, h),
+        Channels(channels),
+    ));
+}
+
+/// Pixelation that only writes the channels in `channels` (see `Channel`)
+#[wasm_bindgen]
+pub fn pixelate_channels(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    block_size: u32,
+    channels: u8,
+) {
+    let effect = Effect::pixelate(block_size);
+    or_throw(apply_channels(
+        &effect,
+        data,
+        width,
+        height,
+        Rect::new(x, y, w, h),
+        Channels(channels),
+    ));
+}
+
+/// Gaussian blur that only writes the channels in `channels` (see `Channel`)
+#[wasm_bindgen]
+pub fn gaussian_blur_channels(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    radius: u32,
+    channels: u8,
+) {
+    let effect = Effect::GaussianBlur { radius };
+    or_throw(apply_channels(
+        &effect,
+        data,
+        width,
+        height,
+        Rect::new(x, y, w, h),
+        Channels(channels),
+    ));
+}
+
+/// Solid fill blended with the original by `strength` (0..=1)
+#[wasm_bindgen]
+pub fn solid_fill_mix(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+    strength: f32,
+) {
+    let effect = Effect::SolidFill {
+        color: Color::new(r, g, b),
+    };
+    let rect = Rect::new(x, y, w, h);
+    or_throw(apply_blended(
+        &effect,
+        data,
+        width,
+        height,
+        rect,
+        Channels::RGB,
+        strength,
+    ));
+}
+
+/// Pixelation blended with the original by `strength` (0..=1)
+#[wasm_bindgen]
+pub fn pixelate_mix(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    block_size: u32,
+    strength: f32,
+) {
+    let effect = Effect::pixelate(block_size);
+    let rect = Rect::new(x, y, w, h);
+    or_throw(apply_blended(
+        &effect,
+        data,
+        width,
+        height,
+        rect,
+        Channels::RGB,
+        strength,
+    ));
+}
+
+/// Gaussian blur blended with the original by `strength` (0..=1)
+#[wasm_bindgen]
+pub fn gaussian_blur_mix(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    radius: u32,
+    strength: f32,
+) {
+    let effect = Effect::GaussianBlur { radius };
+    let rect = Rect::new(x, y, w, h);
+    or_throw(apply_blended(
+        &effect,
+        data,
+        width,
+        height,
+        rect,
+        Channels::RGB,
+        strength,
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Channel;
+    use crate::{gaussian_blur, pattern, pixelate};
+
+    #[test]
+    fn test_rgb_mask_matches_plain_effect() {
+        let mut expected = pattern(12, 12);
+        gaussian_blur(&mut expected, 12, 12, 1, 2, 8, 7, 2);
+
+        let mut data = pattern(12, 12);
+        gaussian_blur_channels(&mut data, 12, 12, 1, 2, 8, 7, 2, Channels::RGB.0);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_single_channel_pixelate() {
+        let original = pattern(8, 8);
+        let mut full = original.clone();
+        pixelate(&mut full, 8, 8, 0, 0, 8, 8, 4);
+
+        let mut data = original.clone();
+        pixelate_channels(&mut data, 8, 8, 0, 0, 8, 8, 4, Channel::R as u8);
+        for i in 0..data.len() {
+            let expected = if i % 4 == 0 { full[i] } else { original[i] };
+            assert_eq!(data[i], expected, "byte {}", i);
+        }
+    }
+
+    #[test]
+    fn test_channel_mask_keeps_the_image_grid() {
+        let effect = Effect::Pixelate {
+            block_size: 4,
+            grid_aligned: true,
+            min_blocks: 0,
+        };
+        let rect = Rect::new(3, 2, 7, 9);
+        let original = pattern(12, 12);
+        let mut full = original.clone();
+        effect.apply_rect(&mut full, 12, 12, rect);
+
+        let mut data = original.clone();
+        let red = Channels(Channel::R as u8);
+        apply_blended(&effect, &mut data, 12, 12, rect, red, 1.0).unwrap();
+        for i in 0..data.len() {
+            let expected = if i % 4 == 0 { full[i] } else { original[i] };
+            assert_eq!(data[i], expected, "byte {}", i);
+        }
+    }
+
+    #[test]
+    fn test_fill_only_alpha() {
+        let original = pattern(6, 6);
+        let mut data = original.clone();
+        solid_fill_channels(&mut data, 6, 6, 2, 2, 2, 2, 255, 0, 0, 0, Channel::A as u8);
+
+        for y in 0..6 {
+            for x in 0..6 {
+                let idx = (y * 6 + x) * 4;
+                let inside = (2..4).contains(&x) && (2..4).contains(&y);
+                assert_eq!(data[idx..idx + 3], original[idx..idx + 3]);
+                let alpha = if inside { 0 } else { original[idx + 3] };
+                assert_eq!(data[idx + 3], alpha);
+            }
+        }
+    }
+
+    #[test]
+    fn test_blur_alpha_plane() {
+        let mut data = vec![0u8; 10 * 10 * 4];
+        // Opaque left half, transparent right half
+        for y in 0..10 {
+            for x in 0..5 {
+                data[(y * 10 + x) * 4 + 3] = 255;
+            }
+        }
+        gaussian_blur_channels(&mut data, 10, 10, 0, 0, 10, 10, 3, Channels::A.0);
+        let edge = data[(5 * 10 + 5) * 4 + 3];
+        assert!(
+            edge > 0 && edge < 255,
+            "alpha edge should be soft, got {}",
+            edge
+        );
+        assert!(data.chunks(4).all(|px| px[..3] == [0, 0, 0]));
+    }
+
+    #[test]
+    fn test_mix_endpoints() {
+        let original = pattern(8, 8);
+        let mut full = original.clone();
+        gaussian_blur(&mut full, 8, 8, 0, 0, 8, 8, 2);
+
+        let mut data = original.clone();
+        gaussian_blur_mix(&mut data, 8, 8, 0, 0, 8, 8, 2, 1.0);
+        assert_eq!(data, full);
+
+        let mut data = original.clone();
+        gaussian_blur_mix(&mut data, 8, 8, 0, 0, 8, 8, 2, 0.0);
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn test_half_strength_fill() {
+        let mut data = vec![100u8; 4 * 4 * 4];
+        solid_fill_mix(&mut data, 4, 4, 0, 0, 4, 4, 200, 0, 100, 0.5);
+        for px in data.chunks(4) {
+            assert_eq!(px, [150, 50, 100, 100]);
+        }
+    }
+
+    #[test]
+    fn test_strength_outside_range_is_clamped() {
+        let mut expected = pattern(6, 6);
+        pixelate(&mut expected, 6, 6, 0, 0, 6, 6, 3);
+
+        let mut data = pattern(6, 6);
+        pixelate_mix(&mut data, 6, 6, 0, 0, 6, 6, 3, 4.0);
+        assert_eq!(data, expected);
+    }
+}
@@ -0,0 +1,26 @@
+//! Helpers for moving rectangular blocks of RGBA pixels in and out of an
+//! image buffer. Rects passed here must already be clipped to the image.
+
+use crate::scratch::Scratch;
+use crate::types::Rect;
+
+/// Copy the pixels of `rect` into a new tightly packed buffer
+pub(crate) fn read_region(data: &[u8], width: u32, rect: Rect) -> Scratch {
+    let row_bytes = rect.w as usize * 4;
+    let mut out = Vec::with_capacity(row_bytes * rect.h as usize);
+    for py in rect.y..rect.bottom() {
+        let start = ((py * width + rect.x) * 4) as usize;
+        out.extend_from_slice(&data[start..start + row_bytes]);
+    }
+    Scratch::from(out)
+}
+
+/// Copy a tightly packed `rect`-sized buffer back into the image
+pub(crate) fn write_region(data: &mut [u8], width: u32, rect: Rect, region: &[u8]) {
+    let row_bytes = rect.w as usize * 4;
+    for (row, py) in (rect.y..rect.bottom()).enumerate() {
+        let start = ((py * width + rect.x) * 4) as usize;
+        data[start..start + row_bytes]
+            .copy_from_slice(&region[row * row_bytes..(row + 1) * row_bytes]);
+    }
+}
@@ -0,0 +1,93 @@
+//! Effect strength scaled to the size of a detected face.
+//!
+//! Identity lives at the scale of the eyes, nose and mouth, so parameters
+//! are derived from the interpupillary distance (IPD): a blur radius of
+//! half the IPD merges the eyes into one smudge, and blocks of about 0.4
+//! IPD leave six or fewer across a face. When the detector reports no eye
+//! landmarks the IPD is estimated from the face box width.
+
+use wasm_bindgen::prelude::*;
+
+use crate::classify::PiiClass;
+use crate::effect::Effect;
+use crate::pipeline::Pipeline;
+use crate::types::Rect;
+
+/// Typical eye-center distance as a fraction of a detector's face box width
+const IPD_PER_FACE_WIDTH: f32 = 0.42;
+const BLUR_PER_IPD: f32 = 0.5;
+const BLOCK_PER_IPD: f32 = 0.4;
+/// Floors for tiny faces, where a couple of pixels still carry identity
+const MIN_RADIUS: u32 = 4;
+const MIN_BLOCK: u32 = 4;
+
+/// The IPD to calibrate against: `interpupillary` when the detector
+/// found both eyes, otherwise estimated from the face box
+fn ipd(face_w: u32, face_h: u32, interpupillary: f32) -> f32 {
+    if interpupillary > 0.0 {
+        return interpupillary;
+    }
+    // Profile and cropped boxes are narrower than tall; the larger side is
+    // the more reliable measure of scale
+    face_w.max(face_h * 4 / 5) as f32 * IPD_PER_FACE_WIDTH
+}
+
+/// Blur radius just strong enough for a face of this size. Capped at half
+/// the face so huge faces aren't blurred far beyond recognition distance.
+#[wasm_bindgen]
+pub fn face_blur_radius(face_w: u32, face_h: u32, interpupillary: f32) -> u32 {
+    let radius = (ipd(face_w, face_h, interpupillary) * BLUR_PER_IPD).ceil() as u32;
+    radius.clamp(MIN_RADIUS, (face_w.max(face_h) / 2).max(MIN_RADIUS))
+}
+
+/// Pixelation block size for a face of this size
+#[wasm_bindgen]
+pub fn face_block_size(face_w: u32, face_h: u32, interpupillary: f32) -> u32 {
+    let block = (ipd(face_w, face_h, interpupillary) * BLOCK_PER_IPD).ceil() as u32;
+    block.clamp(MIN_BLOCK, (face_w.max(face_h) / 2).max(MIN_BLOCK))
+}
+
+#[wasm_bindgen]
+impl Pipeline {
+    /// Anonymize a detected face with blocks and a smoothing blur sized to
+    /// it, tagged as `"FACE"`. Pass 0 for `interpupillary` when the
+    /// detector gave no eye landmarks.
+    pub fn face(self, region: &Rect, interpupillary: f32) -> Pipeline {
+        let (w, h) = (region.w, region.h);
+        let block_size = face_block_size(w, h, interpupillary);
+        // The blur only softens block edges, so a quarter of the block is enough
+        let radius = (block_size / 4).max(1);
+        self.push(*region, Effect::pixelate(block_size))
+            .tagged(PiiClass::Face)
+            .push(*region, Effect::GaussianBlur { radius })
+            .tagged(PiiClass::Face)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parameters_scale_with_face_size() {
+        assert_eq!(face_blur_radius(24, 24, 0.0), 6);
+        assert_eq!(face_blur_radius(400, 400, 0.0), 84);
+        assert_eq!(face_block_size(400, 400, 0.0), 68);
+        // Landmarks win over the box estimate
+        assert_eq!(face_blur_radius(400, 400, 100.0), 50);
+        // Tiny faces still get a usable minimum
+        assert_eq!(face_block_size(6, 6, 0.0), MIN_BLOCK);
+        assert_eq!(face_blur_radius(0, 0, 0.0), MIN_RADIUS);
+    }
+
+    #[test]
+    fn test_face_step_is_calibrated_and_tagged() {
+        let pipeline = Pipeline::new().face(&Rect::new(0, 0, 100, 120), 0.0);
+        let [pixelate, blur] = pipeline.ops() else {
+            panic!("expected two steps");
+        };
+        assert_eq!(pixelate.effect, Effect::pixelate(17));
+        assert_eq!(blur.effect, Effect::GaussianBlur { radius: 4 });
+        assert_eq!(blur.class, Some(PiiClass::Face));
+    }
+}
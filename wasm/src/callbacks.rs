@@ -0,0 +1,32 @@
+//! Optional JS callbacks for long-running operations.
+//!
+//! Callbacks are best-effort: an exception thrown by the host's handler is
+//! ignored rather than aborting the redaction halfway through.
+
+use js_sys::Function;
+use wasm_bindgen::JsValue;
+
+/// Call `on_progress(fraction)` if a callback was supplied
+pub(crate) fn report_progress(on_progress: &Option<Function>, fraction: f32) {
+    if let Some(callback) = on_progress {
+        let _ = callback.call1(&JsValue::UNDEFINED, &JsValue::from(fraction));
+    }
+}
+
+/// Call `on_scene_cut(timestamp, score)` if a callback was supplied
+pub(crate) fn report_scene_cut(on_scene_cut: &Option<Function>, timestamp: f64, score: f32) {
+    if let Some(callback) = on_scene_cut {
+        let _ = callback.call2(
+            &JsValue::UNDEFINED,
+            &JsValue::from(timestamp),
+            &JsValue::from(score),
+        );
+    }
+}
+
+/// Call `on_complete()` if a callback was supplied
+pub(crate) fn report_complete(on_complete: &Option<Function>) {
+    if let Some(callback) = on_complete {
+        let _ = callback.call0(&JsValue::UNDEFINED);
+    }
+}
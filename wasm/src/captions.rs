@@ -0,0 +1,245 @@
+//! Detection of burned-in captions and subtitles.
+//!
+//! Captions are rows of high-contrast glyph edges in the lower third of the
+//! frame. The detector looks for the tallest band of such rows there and
+//! returns it padded to cover outlines and descenders. It's deliberately
+//! cheap: it runs on every video frame, and a false positive only costs a
+//! redacted strip of background.
+//!
+//! Per-frame detection flickers on fades and on frames where the caption
+//! sits over busy background, so a `CaptionTracker` turns detections into
+//! spans: a band stays redacted for a hold period after it was last seen,
+//! and overlapping detections widen the current span instead of starting a
+//! new one.
+
+use wasm_bindgen::prelude::*;
+
+use crate::effect::Effect;
+use crate::error::{check_buffer, RedactError};
+use crate::json::Json;
+use crate::types::Rect;
+use crate::verify::luma;
+
+/// Luma step that counts as a glyph edge; captions are white or yellow on
+/// a dark outline, far above natural texture
+const EDGE_THRESHOLD: f32 = 60.0;
+/// Rows without edges a band may contain (gaps between caption lines)
+const MAX_GAP: u32 = 3;
+const MIN_BAND: u32 = 6;
+
+/// Caption band in a frame given as a luma lookup, or `None`
+pub(crate) fn detect_band(
+    width: u32,
+    height: u32,
+    luma_at: impl Fn(u32, u32) -> f32,
+) -> Option<Rect> {
+    let top = height * 2 / 3;
+    let min_edges = (width / 32).max(4);
+    let mut best: Option<(u32, u32, u32, u32)> = None;
+    // (start row, last text row, min x, max x) of the band being grown
+    let mut current: Option<(u32, u32, u32, u32)> = None;
+
+    for y in top..height {
+        let (mut edges, mut min_x, mut max_x) = (0, u32::MAX, 0);
+        let mut prev = luma_at(0, y);
+        for x in 1..width {
+            let l = luma_at(x, y);
+            if (l - prev).abs() > EDGE_THRESHOLD {
+                edges += 1;
+                min_x = min_x.min(x);
+                max_x = max_x.max(x);
+            }
+            prev = l;
+        }
+        if edges >= min_edges {
+            current = Some(match current {
+                Some((start, _, x0, x1)) => (start, y, x0.min(min_x), x1.max(max_x)),
+                None => (y, y, min_x, max_x),
+            });
+        }
+        let ended = current.is_some_and(|(_, last, _, _)| y - last > MAX_GAP || y + 1 == height);
+        if ended {
+            let band = current.take();
+            let height_of = |b: Option<(u32, u32, u32, u32)>| b.map_or(0, |(s, l, _, _)| l - s + 1);
+            if height_of(band) > height_of(best) {
+                best = band;
+            }
+        }
+    }
+
+    let (start, last, x0, x1) = best?;
+    let band_h = last - start + 1;
+    if band_h < MIN_BAND || band_h > height / 4 {
+        return None;
+    }
+    let pad = (band_h / 4).max(2);
+    let rect = Rect::new(
+        x0.saturating_sub(pad),
+        start.saturating_sub(pad),
+        x1 - x0 + 1 + 2 * pad,
+        band_h + 2 * pad,
+    );
+    rect.clip(width, height)
+}
+
+/// Time range over which one caption band was on screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CaptionSpan {
+    pub start: f64,
+    /// Timestamp the band was last detected at
+    pub end: f64,
+    /// Union of every detection in the span
+    pub region: Rect,
+}
+
+/// Caption redaction state of a video stream
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CaptionTracker {
+    pub effect: Effect,
+    /// How long a band stays redacted after its last detection, in frame
+    /// timestamp units
+    pub hold: f64,
+    current: Option<CaptionSpan>,
+    finished: Vec<CaptionSpan>,
+}
+
+impl CaptionTracker {
+    pub(crate) fn new(effect: Effect, hold: f64) -> CaptionTracker {
+        CaptionTracker {
+            effect,
+            hold,
+            current: None,
+            finished: Vec::new(),
+        }
+    }
+
+    /// Feed one frame's detection; returns the region to redact in it
+    pub(crate) fn update(&mut self, detected: Option<Rect>, timestamp: f64) -> Option<Rect> {
+        let held = self
+            .current
+            .filter(|span| timestamp >= span.end && timestamp - span.end <= self.hold);
+        match (detected, held) {
+            (Some(band), Some(span)) if band.intersects(&span.region) => {
+                let span = CaptionSpan {
+                    end: timestamp,
+                    region: span.region.union(&band),
+                    ..span
+                };
+                self.current = Some(span);
+                Some(span.region)
+            }
+            (Some(band), _) => {
+                self.close();
+                self.current = Some(CaptionSpan {
+                    start: timestamp,
+                    end: timestamp,
+                    region: band,
+                });
+                Some(band)
+            }
+            (None, Some(span)) => Some(span.region),
+            (None, None) => {
+                self.close();
+                None
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        self.finished.extend(self.current.take());
+    }
+
+    /// Every span so far, including the one still open
+    pub(crate) fn spans(&self) -> impl Iterator<Item = &CaptionSpan> {
+        self.finished.iter().chain(&self.current)
+    }
+
+    /// Spans as `[{start, end, region}]`
+    pub(crate) fn json(&self) -> Json {
+        Json::Array(
+            self.spans()
+                .map(|span| {
+                    Json::object()
+                        .with("start", span.start)
+                        .with("end", span.end)
+                        .with("region", span.region.json())
+                })
+                .collect(),
+        )
+    }
+}
+
+pub fn detect_caption_rgba(
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<Option<Rect>, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    Ok(detect_band(width, height, |x, y| {
+        let i = ((y * width + x) * 4) as usize;
+        luma(&data[i..i + 4]) as f32
+    }))
+}
+
+/// Burned-in caption band of an RGBA frame, or `undefined` if none is found
+#[wasm_bindgen]
+pub fn detect_caption(data: &[u8], width: u32, height: u32) -> Result<Option<Rect>, JsError> {
+    Ok(detect_caption_rgba(data, width, height)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gray frame with a two-line caption of alternating glyph strokes
+    fn captioned(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let line = (80..88).contains(&y) || (90..98).contains(&y);
+                let glyph = line && (30..130).contains(&x) && x % 4 < 2;
+                let v = if glyph {
+                    250
+                } else {
+                    90 + (x * 7 + y * 3) as u8 % 20
+                };
+                data.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_finds_caption_band() {
+        let band = detect_caption_rgba(&captioned(160, 120), 160, 120)
+            .unwrap()
+            .unwrap();
+        assert!(band.contains(&Rect::new(30, 80, 100, 18)), "{:?}", band);
+        assert!(band.y >= 75 && band.bottom() <= 103, "{:?}", band);
+    }
+
+    #[test]
+    fn test_tracker_holds_and_splits_spans() {
+        let effect = Effect::GaussianBlur { radius: 8 };
+        let mut tracker = CaptionTracker::new(effect, 100.0);
+        let band = Rect::new(20, 80, 100, 20);
+        assert_eq!(tracker.update(Some(band), 0.0), Some(band));
+        // A missed frame is covered by the hold
+        assert_eq!(tracker.update(None, 40.0), Some(band));
+        let wider = Rect::new(10, 82, 140, 20);
+        assert_eq!(tracker.update(Some(wider), 80.0), Some(band.union(&wider)));
+        assert_eq!(tracker.update(None, 300.0), None);
+        tracker.update(Some(band), 400.0);
+
+        let spans: Vec<_> = tracker.spans().map(|s| (s.start, s.end)).collect();
+        assert_eq!(spans, [(0.0, 80.0), (400.0, 400.0)]);
+    }
+
+    #[test]
+    fn test_plain_frame_has_no_caption() {
+        let frame: Vec<u8> = (0..160 * 120)
+            .flat_map(|i| [(i % 40) as u8 + 60; 4])
+            .collect();
+        assert_eq!(detect_caption_rgba(&frame, 160, 120).unwrap(), None);
+    }
+}
@@ -0,0 +1,384 @@
+//! Machine-readable description of every effect and its parameters.
+//!
+//! `Effect::validate` checks parameters against these same ranges, so the
+//! metadata a settings UI or plan validator reads can't drift from what the
+//! library actually accepts.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::RedactError;
+use crate::json::Json;
+use crate::mosaic::CellShape;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    Integer,
+    Number,
+    /// `#rrggbb` string in JSON, `Color` in the typed API
+    Color,
+    /// One of a fixed set of names
+    Choice(&'static [&'static str]),
+    Boolean,
+    /// Hex string in JSON, bytes in the typed API
+    Bytes,
+}
+
+impl ParamKind {
+    fn name(self) -> &'static str {
+        match self {
+            ParamKind::Integer => "integer",
+            ParamKind::Number => "number",
+            ParamKind::Color => "color",
+            ParamKind::Choice(_) => "choice",
+            ParamKind::Boolean => "boolean",
+            ParamKind::Bytes => "bytes",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamDefault {
+    Number(f64),
+    Text(&'static str),
+    Flag(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamInfo {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub default: ParamDefault,
+    pub description: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EffectInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Whether the output depends on the pixels being replaced
+    pub reads_pixels: bool,
+    pub params: &'static [ParamInfo],
+}
+
+const fn integer(
+    name: &'static str,
+    min: f64,
+    default: f64,
+    description: &'static str,
+) -> ParamInfo {
+    ParamInfo {
+        name,
+        kind: ParamKind::Integer,
+        min: Some(min),
+        max: None,
+        default: ParamDefault::Number(default),
+        description,
+    }
+}
+
+pub const EFFECTS: &[EffectInfo] = &[
+    EffectInfo {
+        name: "solid_fill",
+        description: "Replace the region with a single color",
+        reads_pixels: false,
+        params: &[ParamInfo {
+            name: "color",
+            kind: ParamKind::Color,
+            min: None,
+            max: None,
+            default: ParamDefault::Text("#000000"),
+            description: "Fill color",
+        }],
+    },
+    EffectInfo {
+        name: "pixelate",
+        description: "Average the region over square blocks",
+        reads_pixels: true,
+        params: &[
+            integer("block_size", 1.0, 12.0, "Block edge length in pixels"),
+            ParamInfo {
+                name: "grid_aligned",
+                kind: ParamKind::Boolean,
+                min: None,
+                max: None,
+                default: ParamDefault::Flag(false),
+                description: "Anchor blocks to the image grid instead of the region's corner",
+            },
+            integer(
+                "min_blocks",
+                0.0,
+                0.0,
+                "Fewest blocks along each side of the region; 0 for no minimum",
+            ),
+        ],
+    },
+    EffectInfo {
+        name: "gaussian_blur",
+        description: "Separable gaussian blur confined to the region",
+        reads_pixels: true,
+        params: &[integer("radius", 1.0, 8.0, "Kernel radius in pixels")],
+    },
+    EffectInfo {
+        name: "hardened_pixelate",
+        description:
+            "Pixelate with quantized, seeded-jitter blocks no smaller than the glyph height",
+        reads_pixels: true,
+        params: &[
+            integer(
+                "block_size",
+                1.0,
+                16.0,
+                "Requested block edge length in pixels",
+            ),
+            integer("seed", 0.0, 0.0, "Seed for the block jitter"),
+            integer(
+                "glyph_height",
+                0.0,
+                0.0,
+                "Text height the block size must reach; 0 estimates it",
+            ),
+        ],
+    },
+    EffectInfo {
+        name: "mosaic",
+        description: "Average the region over square, hexagonal or triangular cells",
+        reads_pixels: true,
+        params: &[
+            integer(
+                "cell_size",
+                1.0,
+                12.0,
+                "Cell width in pixels: square edge, hexagon across flats, triangle side",
+            ),
+            ParamInfo {
+                name: "shape",
+                kind: ParamKind::Choice(CellShape::NAMES),
+                min: None,
+                max: None,
+                default: ParamDefault::Text("hexagon"),
+                description: "Cell shape",
+            },
+            integer(
+                "grout_width",
+                0.0,
+                0.0,
+                "Width of the lines between cells in pixels; 0 for none",
+            ),
+            ParamInfo {
+                name: "grout_color",
+                kind: ParamKind::Color,
+                min: None,
+                max: None,
+                default: ParamDefault::Text("#000000"),
+                description: "Color of the lines between cells",
+            },
+        ],
+    },
+    EffectInfo {
+        name: "desaturate",
+        description:
+            "Convert the region to grayscale, optionally flattened; de-emphasizes without hiding",
+        reads_pixels: true,
+        params: &[ParamInfo {
+            name: "contrast",
+            kind: ParamKind::Number,
+            min: Some(0.0),
+            max: Some(1.0),
+            default: ParamDefault::Number(1.0),
+            description: "Luma contrast kept; 0 leaves flat mid-gray",
+        }],
+    },
+    EffectInfo {
+        name: "dim",
+        description: "Scale the region's RGB to darken or brighten it",
+        reads_pixels: true,
+        params: &[ParamInfo {
+            name: "factor",
+            kind: ParamKind::Number,
+            min: Some(0.0),
+            max: None,
+            default: ParamDefault::Number(0.4),
+            description: "RGB multiplier; below 1 darkens, above 1 brightens",
+        }],
+    },
+    EffectInfo {
+        name: "noise_fill",
+        description: "Replace the region with random noise that keeps nothing of the original",
+        reads_pixels: false,
+        params: &[ParamInfo {
+            name: "seed",
+            kind: ParamKind::Bytes,
+            min: None,
+            max: None,
+            default: ParamDefault::Text(""),
+            description: "At least 16 random bytes, fresh for each image; no default",
+        }],
+    },
+];
+
+/// Options every effect accepts when applied through the typed API
+pub const COMMON_PARAMS: &[ParamInfo] = &[
+    ParamInfo {
+        name: "strength",
+        kind: ParamKind::Number,
+        min: Some(0.0),
+        max: Some(1.0),
+        default: ParamDefault::Number(1.0),
+        description: "Mix of effect output with the original pixels",
+    },
+    ParamInfo {
+        name: "channels",
+        kind: ParamKind::Integer,
+        min: Some(1.0),
+        max: Some(15.0),
+        default: ParamDefault::Number(7.0),
+        description: "Bit mask of channels to write: R=1, G=2, B=4, A=8",
+    },
+];
+
+pub fn effect_info(name: &str) -> Option<&'static EffectInfo> {
+    EFFECTS.iter().find(|info| info.name == name)
+}
+
+/// Check a numeric parameter of `effect` against its catalog range
+pub(crate) fn check_param(
+    effect: &str,
+    param: &'static str,
+    value: f64,
+) -> Result<(), RedactError> {
+    let info = effect_info(effect)
+        .and_then(|info| info.params.iter().find(|p| p.name == param))
+        .or_else(|| COMMON_PARAMS.iter().find(|p| p.name == param));
+    let Some(info) = info else {
+        return Ok(());
+    };
+    let below = info.min.is_some_and(|min| value < min);
+    let above = info.max.is_some_and(|max| value > max);
+    if below || above || value.is_nan() {
+        return Err(RedactError::InvalidParameter {
+            name: param,
+            value,
+            expected: describe_range(info),
+        });
+    }
+    Ok(())
+}
+
+fn describe_range(info: &ParamInfo) -> String {
+    match (info.min, info.max) {
+        (Some(min), Some(max)) => format!("a value between {} and {}", min, max),
+        (Some(min), None) => format!("{} or more", min),
+        (None, Some(max)) => format!("{} or less", max),
+        (None, None) => "any value".to_string(),
+    }
+}
+
+fn param_json(param: &ParamInfo) -> Json {
+    let default = match param.default {
+        ParamDefault::Number(n) => Json::Number(n),
+        ParamDefault::Text(s) => Json::from(s),
+        ParamDefault::Flag(b) => Json::from(b),
+    };
+    let json = Json::object()
+        .with("name", param.name)
+        .with("type", param.kind.name())
+        .with("min", param.min)
+        .with("max", param.max)
+        .with("default", default)
+        .with("description", param.description);
+    match param.kind {
+        ParamKind::Choice(names) => json.with(
+            "choices",
+            Json::Array(names.iter().map(|&name| Json::from(name)).collect()),
+        ),
+        _ => json,
+    }
+}
+
+pub(crate) fn catalog_json() -> Json {
+    let effects = EFFECTS
+        .iter()
+        .map(|effect| {
+            Json::object()
+                .with("name", effect.name)
+                .with("description", effect.description)
+                .with("reads_pixels", effect.reads_pixels)
+                .with(
+                    "params",
+                    Json::Array(effect.params.iter().map(param_json).collect()),
+                )
+        })
+        .collect();
+    Json::object()
+        .with("version", env!("CARGO_PKG_VERSION"))
+        .with("effects", Json::Array(effects))
+        .with(
+            "common_params",
+            Json::Array(COMMON_PARAMS.iter().map(param_json).collect()),
+        )
+}
+
+/// JSON description of every effect: names, parameter types, ranges and defaults
+#[wasm_bindgen]
+pub fn describe_effects() -> String {
+    catalog_json().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::types::Color;
+
+    #[test]
+    fn test_every_effect_is_described() {
+        let effects = [
+            Effect::SolidFill {
+                color: Color::new(0, 0, 0),
+            },
+            Effect::pixelate(1),
+            Effect::GaussianBlur { radius: 1 },
+            Effect::HardenedPixelate {
+                block_size: 1,
+                seed: 0,
+                glyph_height: 0,
+            },
+            Effect::Mosaic {
+                cell_size: 1,
+                shape: CellShape::Square,
+                grout: Color::new(0, 0, 0),
+                grout_width: 0,
+            },
+            Effect::Desaturate { contrast: 1.0 },
+            Effect::Dim { factor: 0.5 },
+            Effect::NoiseFill { seed: [0; 32] },
+        ];
+        for effect in effects {
+            assert!(effect_info(effect.name()).is_some(), "{}", effect.name());
+        }
+    }
+
+    #[test]
+    fn test_json_lists_parameters() {
+        let json = describe_effects();
+        assert!(json.starts_with(r#"{"version":""#));
+        assert!(json.contains(
+            r#"{"name":"radius","type":"integer","min":1,"max":null,"default":8,"description":"Kernel radius in pixels"}"#
+        ));
+        assert!(json.contains(r#""choices":["square","hexagon","triangle"]"#));
+    }
+
+    #[test]
+    fn test_check_param_uses_catalog_ranges() {
+        assert!(check_param("pixelate", "block_size", 1.0).is_ok());
+        assert_eq!(
+            check_param("pixelate", "block_size", 0.0)
+                .unwrap_err()
+                .to_string(),
+            "invalid block_size 0: expected 1 or more"
+        );
+        assert!(check_param("gaussian_blur", "strength", 1.2).is_err());
+    }
+}
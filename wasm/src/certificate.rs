@@ -0,0 +1,214 @@
+//! Structured "redaction certificate" attached to disclosed images.
+//!
+//! Gathers what a reviewer needs in one JSON document: tool version, the
+//! plan that was run, the detections it acted on, verification metrics for
+//! every redacted region and hashes of the input and output pixels.
+
+use wasm_bindgen::prelude::*;
+
+use crate::audit::now;
+use crate::error::{check_buffer, RedactError};
+use crate::hash::{hex, sha256};
+use crate::json::Json;
+use crate::pipeline::Pipeline;
+use crate::types::Rect;
+use crate::verify::{verify_region, VerificationReport, DEFAULT_MAX_CORRELATION, DEFAULT_MAX_SSIM};
+
+/// A detector result the plan acted on
+#[derive(Debug, Clone, PartialEq)]
+struct Detection {
+    kind: String,
+    region: Rect,
+    confidence: f32,
+    label: Option<String>,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct RedactionCertificate {
+    width: u32,
+    height: u32,
+    plan: Pipeline,
+    detections: Vec<Detection>,
+    verifications: Vec<(Rect, VerificationReport)>,
+    input_hash: [u8; 32],
+    output_hash: [u8; 32],
+    issued: f64,
+}
+
+impl RedactionCertificate {
+    /// Certify that `redacted` is `original` after `plan`; every distinct
+    /// region of the plan is verified against the original
+    pub fn build(
+        plan: &Pipeline,
+        original: &[u8],
+        redacted: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<Self, RedactError> {
+        check_buffer(original.len(), width, height)?;
+        check_buffer(redacted.len(), width, height)?;
+        plan.validate(width, height)?;
+
+        let mut regions: Vec<Rect> = Vec::new();
+        for op in plan.ops() {
+            if !regions.contains(&op.region) {
+                regions.push(op.region);
+            }
+        }
+        let verifications = regions
+            .into_iter()
+            .map(|region| {
+                verify_region(
+                    original,
+                    redacted,
+                    width,
+                    height,
+                    region,
+                    DEFAULT_MAX_SSIM,
+                    DEFAULT_MAX_CORRELATION,
+                )
+                .map(|report| (region, report))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            width,
+            height,
+            plan: plan.clone(),
+            detections: Vec::new(),
+            verifications,
+            input_hash: sha256(original),
+            output_hash: sha256(redacted),
+            issued: now(),
+        })
+    }
+
+    /// Whether every redacted region passed verification
+    pub fn passed(&self) -> bool {
+        self.verifications
+            .iter()
+            .all(|(_, report)| !report.likely_recoverable)
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        let detections = self
+            .detections
+            .iter()
+            .map(|d| {
+                Json::object()
+                    .with("type", d.kind.as_str())
+                    .with("region", d.region.json())
+                    .with("confidence", d.confidence)
+                    .with("label", d.label.clone())
+            })
+            .collect();
+        let verification = self
+            .verifications
+            .iter()
+            .map(|(region, report)| report.json().with("region", region.json()))
+            .collect();
+        Json::object()
+            .with("tool", "redactr")
+            .with("version", env!("CARGO_PKG_VERSION"))
+            .with("issued", self.issued)
+            .with(
+                "image",
+                Json::object()
+                    .with("width", self.width)
+                    .with("height", self.height),
+            )
+            .with("region_count", self.verifications.len())
+            .with("plan", self.plan.json())
+            .with("detections", Json::Array(detections))
+            .with("verification", Json::Array(verification))
+            .with("passed", self.passed())
+            .with("input_sha256", hex(&self.input_hash))
+            .with("output_sha256", hex(&self.output_hash))
+    }
+}
+
+#[wasm_bindgen]
+impl RedactionCertificate {
+    /// Build a certificate for `redacted`, the result of running `plan` over
+    /// `original`
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        plan: &Pipeline,
+        original: &[u8],
+        redacted: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<RedactionCertificate, JsError> {
+        Ok(Self::build(plan, original, redacted, width, height)?)
+    }
+
+    /// Record a detector result (e.g. `"face"`, `"text"`) the plan acted on
+    pub fn add_detection(
+        &mut self,
+        kind: &str,
+        x: u32,
+        y: u32,
+        w: u32,
+        h: u32,
+        confidence: f32,
+        label: Option<String>,
+    ) {
+        self.detections.push(Detection {
+            kind: kind.to_string(),
+            region: Rect::new(x, y, w, h),
+            confidence,
+            label,
+        });
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn region_count(&self) -> usize {
+        self.verifications.len()
+    }
+
+    #[wasm_bindgen(getter, js_name = passed)]
+    pub fn passed_js(&self) -> bool {
+        self.passed()
+    }
+
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern;
+    use crate::types::Color;
+
+    #[test]
+    fn test_certificate_summarizes_plan_and_verification() {
+        let original = pattern(16, 16);
+        let plan = Pipeline::new()
+            .fill(&Rect::new(0, 0, 8, 8), &Color::new(0, 0, 0))
+            .pixelate(&Rect::new(8, 8, 8, 8), 8)
+            .blur(&Rect::new(8, 8, 8, 8), 2);
+        let mut redacted = original.clone();
+        plan.apply(&mut redacted, 16, 16).unwrap();
+
+        let mut cert = RedactionCertificate::build(&plan, &original, &redacted, 16, 16).unwrap();
+        cert.add_detection("face", 8, 8, 8, 8, 0.97, None);
+        assert_eq!(cert.region_count(), 2);
+        assert!(cert.passed());
+
+        let json = cert.to_json();
+        assert!(json.contains(r#""region_count":2,"plan":[{"effect":"solid_fill""#));
+        assert!(json.contains(r#""detections":[{"type":"face","region":{"x":8,"y":8,"w":8,"h":8}"#));
+        assert!(json.contains(&format!(r#""output_sha256":"{}""#, hex(&sha256(&redacted)))));
+    }
+
+    #[test]
+    fn test_unredacted_region_fails() {
+        let original = pattern(16, 16);
+        let plan = Pipeline::new().fill(&Rect::new(0, 0, 8, 8), &Color::new(0, 0, 0));
+        let cert = RedactionCertificate::build(&plan, &original, &original, 16, 16).unwrap();
+        assert!(!cert.passed());
+    }
+}
@@ -0,0 +1,226 @@
+//! Validated versions of the one-shot effect functions.
+//!
+//! `solid_fill`, `pixelate`, `gaussian_blur` and the brushes clamp what
+//! they're given and quietly do nothing when it makes no sense, which keeps
+//! a drag across the image edge from throwing but hides real bugs. The
+//! `try_` variants here check the buffer, region and parameters first and
+//! fail with a message naming the problem (`data length 400 does not match
+//! 20x20x4`), then do exactly what the unchecked function would. A policy
+//! rejection comes back as an error here rather than a throw.
+
+use wasm_bindgen::prelude::*;
+
+use crate::effect::Effect;
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::policy::policed;
+use crate::types::{Color, Rect};
+use crate::{policed_brush_gaussian_blur, policed_brush_pixelate, policed_brush_solid_fill};
+
+/// Check the buffer, the region and the effect before applying it
+fn checked_rect(
+    effect: Effect,
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+) -> Result<(), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    check_region(rect, width, height)?;
+    effect.validate()?;
+    policed(effect, rect)?.apply_rect(data, width, height, rect);
+    Ok(())
+}
+
+/// Check the buffer, the stroke points and the brush size
+fn check_stroke(
+    len: usize,
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+) -> Result<(), RedactError> {
+    check_buffer(len, width, height)?;
+    if points.is_empty() || !points.len().is_multiple_of(2) {
+        return Err(RedactError::InvalidParameter {
+            name: "points",
+            value: points.len() as f64,
+            expected: "a non-zero even count of coordinates".to_string(),
+        });
+    }
+    if let Some(&value) = points.iter().find(|p| !p.is_finite()) {
+        return Err(RedactError::InvalidParameter {
+            name: "points",
+            value: value as f64,
+            expected: "finite coordinates".to_string(),
+        });
+    }
+    if brush_size == 0 {
+        return Err(RedactError::InvalidParameter {
+            name: "brush_size",
+            value: 0.0,
+            expected: "1 or more".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// `solid_fill`, failing on a mismatched buffer or a region that is empty
+/// or outside the image
+#[wasm_bindgen]
+pub fn try_solid_fill(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+) -> Result<(), JsError> {
+    let effect = Effect::SolidFill {
+        color: Color::new(r, g, b),
+    };
+    let rect = Rect::new(x, y, w, h);
+    Ok(checked_rect(effect, data, width, height, rect)?)
+}
+
+/// `pixelate`, also failing on a block size outside the catalog range
+#[wasm_bindgen]
+pub fn try_pixelate(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    block_size: u32,
+) -> Result<(), JsError> {
+    let effect = Effect::pixelate(block_size);
+    let rect = Rect::new(x, y, w, h);
+    Ok(checked_rect(effect, data, width, height, rect)?)
+}
+
+/// `gaussian_blur`, also failing on a radius outside the catalog range
+#[wasm_bindgen]
+pub fn try_gaussian_blur(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    radius: u32,
+) -> Result<(), JsError> {
+    let effect = Effect::GaussianBlur { radius };
+    let rect = Rect::new(x, y, w, h);
+    Ok(checked_rect(effect, data, width, height, rect)?)
+}
+
+/// `brush_solid_fill`, failing on a mismatched buffer, malformed points or
+/// a zero brush size
+#[wasm_bindgen]
+pub fn try_brush_solid_fill(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+) -> Result<(), JsError> {
+    check_stroke(data.len(), width, height, points, brush_size)?;
+    let color = Color::new(r, g, b);
+    Ok(policed_brush_solid_fill(
+        data, width, height, points, brush_size, color,
+    )?)
+}
+
+/// `brush_pixelate` with the checks of `try_brush_solid_fill` and
+/// `try_pixelate`
+#[wasm_bindgen]
+pub fn try_brush_pixelate(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    block_size: u32,
+) -> Result<(), JsError> {
+    check_stroke(data.len(), width, height, points, brush_size)?;
+    Effect::pixelate(block_size).validate()?;
+    Ok(policed_brush_pixelate(
+        data, width, height, points, brush_size, block_size,
+    )?)
+}
+
+/// `brush_gaussian_blur` with the checks of `try_brush_solid_fill` and
+/// `try_gaussian_blur`
+#[wasm_bindgen]
+pub fn try_brush_gaussian_blur(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    radius: u32,
+) -> Result<(), JsError> {
+    check_stroke(data.len(), width, height, points, brush_size)?;
+    Effect::GaussianBlur { radius }.validate()?;
+    Ok(policed_brush_gaussian_blur(
+        data, width, height, points, brush_size, radius,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_name_the_problem() {
+        let fill = Effect::SolidFill {
+            color: Color::new(0, 0, 0),
+        };
+        let err = |data: &mut [u8], rect| {
+            checked_rect(fill, data, 20, 20, rect)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            err(&mut [0; 400], Rect::new(0, 0, 4, 4)),
+            "data length 400 does not match 20x20x4"
+        );
+        let mut data = vec![255; 20 * 20 * 4];
+        assert_eq!(
+            err(&mut data, Rect::new(2, 2, 0, 4)),
+            "region 2,2 0x4 is empty"
+        );
+        assert!(err(&mut data, Rect::new(25, 0, 4, 4)).contains("outside the 20x20 image"));
+        let zero = Effect::pixelate(0);
+        assert!(checked_rect(zero, &mut data, 20, 20, Rect::new(0, 0, 4, 4)).is_err());
+        // Nothing was written by the failed calls
+        assert!(data.iter().all(|&v| v == 255));
+
+        // A valid call matches the unchecked function
+        checked_rect(fill, &mut data, 20, 20, Rect::new(18, 18, 10, 10)).unwrap();
+        let mut expected = vec![255; 20 * 20 * 4];
+        crate::solid_fill(&mut expected, 20, 20, 18, 18, 10, 10, 0, 0, 0);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_strokes_are_checked() {
+        let len = 8 * 8 * 4;
+        assert!(check_stroke(len, 8, 8, &[1.0, 2.0, 3.0], 2).is_err());
+        assert!(check_stroke(len, 8, 8, &[], 2).is_err());
+        assert!(check_stroke(len, 8, 8, &[1.0, f32::NAN], 2).is_err());
+        assert!(check_stroke(len, 8, 8, &[1.0, 2.0], 0).is_err());
+        assert!(check_stroke(len - 4, 8, 8, &[1.0, 2.0], 2).is_err());
+        // Points off the image are fine: strokes may leave it
+        assert!(check_stroke(len, 8, 8, &[-4.0, 2.0, 30.0, 2.0], 2).is_ok());
+    }
+}
@@ -0,0 +1,152 @@
+//! PII classification labels carried by operations and regions.
+//!
+//! A class travels with its operation into plans, audit logs and
+//! certificates, so retention and review rules that differ per class can be
+//! applied downstream. Besides the built-in classes, a host can tag content
+//! with any label of its own (`"PASSPORT"`, `"MEDICAL"`), which is carried
+//! through as a custom class. Each class also has a default look, expressed
+//! as a preset name the host can override.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::RedactError;
+use crate::presets::resolve_preset;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PiiClass {
+    Face,
+    Name,
+    Ssn,
+    Financial,
+    /// A host-defined class, by its label
+    Custom(String),
+}
+
+impl PiiClass {
+    pub const BUILT_IN: [PiiClass; 4] = [
+        PiiClass::Face,
+        PiiClass::Name,
+        PiiClass::Ssn,
+        PiiClass::Financial,
+    ];
+
+    /// Label used in JSON output; a custom class's own label
+    pub fn name(&self) -> &str {
+        match self {
+            PiiClass::Face => "FACE",
+            PiiClass::Name => "NAME",
+            PiiClass::Ssn => "SSN",
+            PiiClass::Financial => "FINANCIAL",
+            PiiClass::Custom(label) => label,
+        }
+    }
+
+    /// The class labelled `name`: a built-in one, or else a custom class.
+    /// Fails only on an empty label.
+    pub fn from_name(name: &str) -> Result<PiiClass, RedactError> {
+        if name.is_empty() {
+            return Err(RedactError::InvalidField {
+                field: "class".to_string(),
+                expected: "a non-empty PII class label",
+            });
+        }
+        let built_in = PiiClass::BUILT_IN.into_iter().find(|c| c.name() == name);
+        Ok(built_in.unwrap_or_else(|| PiiClass::Custom(name.to_string())))
+    }
+
+    fn default_preset(&self) -> &'static str {
+        match self {
+            PiiClass::Face => "face_anonymize",
+            _ => "document_strict",
+        }
+    }
+}
+
+thread_local! {
+    static CLASS_PRESETS: RefCell<BTreeMap<String, String>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Preset applied to regions of `class` unless an effect is given explicitly
+pub(crate) fn preset_for(class: &PiiClass) -> String {
+    CLASS_PRESETS.with(|presets| {
+        presets
+            .borrow()
+            .get(class.name())
+            .cloned()
+            .unwrap_or_else(|| class.default_preset().to_string())
+    })
+}
+
+pub(crate) fn set_preset(class: &PiiClass, name: &str) -> Result<(), RedactError> {
+    resolve_preset(name)?;
+    CLASS_PRESETS.with(|presets| {
+        presets
+            .borrow_mut()
+            .insert(class.name().to_string(), name.to_string())
+    });
+    Ok(())
+}
+
+/// Preset applied to regions labelled `class` (e.g. `"FACE"`, or a custom
+/// label) unless an effect is given explicitly
+#[wasm_bindgen]
+pub fn class_preset(class: &str) -> Result<String, JsError> {
+    Ok(preset_for(&PiiClass::from_name(class)?))
+}
+
+/// Change the default preset for the class labelled `class`; the preset
+/// must exist
+#[wasm_bindgen]
+pub fn set_class_preset(class: &str, name: &str) -> Result<(), JsError> {
+    Ok(set_preset(&PiiClass::from_name(class)?, name)?)
+}
+
+/// Restore the built-in defaults for every class
+#[wasm_bindgen]
+pub fn reset_class_presets() {
+    CLASS_PRESETS.with(|presets| presets.borrow_mut().clear());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_round_trip() {
+        for class in PiiClass::BUILT_IN {
+            assert_eq!(PiiClass::from_name(class.name()), Ok(class));
+        }
+        let passport = PiiClass::from_name("PASSPORT").unwrap();
+        assert_eq!(passport, PiiClass::Custom("PASSPORT".to_string()));
+        assert_eq!(passport.name(), "PASSPORT");
+        // Labels are case-sensitive, so this is a custom class too
+        assert!(matches!(
+            PiiClass::from_name("face"),
+            Ok(PiiClass::Custom(_))
+        ));
+        assert!(PiiClass::from_name("").is_err());
+    }
+
+    #[test]
+    fn test_class_presets_can_be_overridden() {
+        let passport = PiiClass::Custom("PASSPORT".to_string());
+        assert_eq!(preset_for(&PiiClass::Face), "face_anonymize");
+        assert_eq!(preset_for(&PiiClass::Ssn), "document_strict");
+        assert_eq!(preset_for(&passport), "document_strict");
+        set_preset(&PiiClass::Face, "social_blur").unwrap();
+        set_preset(&passport, "face_anonymize").unwrap();
+        assert_eq!(preset_for(&PiiClass::Face), "social_blur");
+        assert_eq!(preset_for(&passport), "face_anonymize");
+        // Each custom label has its own preset
+        assert_eq!(
+            preset_for(&PiiClass::Custom("MEDICAL".to_string())),
+            "document_strict"
+        );
+        assert!(set_preset(&PiiClass::Name, "missing").is_err());
+        reset_class_presets();
+        assert_eq!(preset_for(&PiiClass::Face), "face_anonymize");
+    }
+}
@@ -0,0 +1,119 @@
+//! Before/after comparison images for review screenshots and approvals.
+//!
+//! Either the two images side by side with a divider between them, or one
+//! image split by a slider line: the original left of it, the redacted
+//! version right of it. Both come from the same two buffers the diff checks,
+//! so a screenshot can't disagree with what was verified.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, RedactError};
+
+/// Gap between the two images of a side-by-side comparison, in px
+pub const DIVIDER: u32 = 8;
+/// Width of the slider line of a split comparison, in px
+const SLIDER: u32 = 2;
+const DIVIDER_RGBA: [u8; 4] = [128, 128, 128, 255];
+const SLIDER_RGBA: [u8; 4] = [255, 255, 255, 255];
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareLayout {
+    /// `2 * width + DIVIDER` wide, original on the left
+    SideBySide = 0,
+    /// `width` wide, original left of the slider at `ratio`
+    Split = 1,
+}
+
+pub(crate) fn compose(
+    original: &[u8],
+    redacted: &[u8],
+    width: u32,
+    height: u32,
+    layout: CompareLayout,
+    ratio: f32,
+) -> Result<Vec<u8>, RedactError> {
+    check_buffer(original.len(), width, height)?;
+    check_buffer(redacted.len(), width, height)?;
+    let stride = (width * 4) as usize;
+    let rows = original
+        .chunks_exact(stride)
+        .zip(redacted.chunks_exact(stride));
+    match layout {
+        CompareLayout::SideBySide => {
+            let mut out = Vec::with_capacity(((2 * width + DIVIDER) * height * 4) as usize);
+            for (before, after) in rows {
+                out.extend_from_slice(before);
+                for _ in 0..DIVIDER {
+                    out.extend_from_slice(&DIVIDER_RGBA);
+                }
+                out.extend_from_slice(after);
+            }
+            Ok(out)
+        }
+        CompareLayout::Split => {
+            if !(0.0..=1.0).contains(&ratio) {
+                return Err(RedactError::InvalidParameter {
+                    name: "ratio",
+                    value: ratio as f64,
+                    expected: "0..=1".to_string(),
+                });
+            }
+            let split = (width as f32 * ratio).round() as u32;
+            let line = split.saturating_sub(SLIDER / 2)..(split + SLIDER / 2).min(width);
+            let mut out = Vec::with_capacity(original.len());
+            for (before, after) in rows {
+                for x in 0..width {
+                    let px = if line.contains(&x) {
+                        &SLIDER_RGBA[..]
+                    } else {
+                        let row = if x < split { before } else { after };
+                        &row[(x * 4) as usize..][..4]
+                    };
+                    out.extend_from_slice(px);
+                }
+            }
+            Ok(out)
+        }
+    }
+}
+
+/// Compose `original` and `redacted` into one comparison image. `ratio`
+/// places the slider of a split comparison (0 = all redacted, 1 = all
+/// original) and is ignored side by side.
+#[wasm_bindgen]
+pub fn compare_images(
+    original: &[u8],
+    redacted: &[u8],
+    width: u32,
+    height: u32,
+    layout: CompareLayout,
+    ratio: f32,
+) -> Result<Vec<u8>, JsError> {
+    Ok(compose(original, redacted, width, height, layout, ratio)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_side_by_side_places_both_images() {
+        let (before, after) = (vec![10u8; 3 * 2 * 4], vec![200u8; 3 * 2 * 4]);
+        let out = compose(&before, &after, 3, 2, CompareLayout::SideBySide, 0.5).unwrap();
+        let out_w = 2 * 3 + DIVIDER;
+        assert_eq!(out.len(), (out_w * 2 * 4) as usize);
+        let px = |x: u32, y: u32| out[((y * out_w + x) * 4) as usize];
+        assert_eq!((px(2, 1), px(3, 1), px(3 + DIVIDER, 1)), (10, 128, 200));
+        assert!(compose(&before, &after[..4], 3, 2, CompareLayout::SideBySide, 0.5).is_err());
+    }
+
+    #[test]
+    fn test_split_divides_at_the_ratio() {
+        let (before, after) = (vec![10u8; 10 * 4], vec![200u8; 10 * 4]);
+        let out = compose(&before, &after, 10, 1, CompareLayout::Split, 0.3).unwrap();
+        let row: Vec<u8> = out.chunks_exact(4).map(|px| px[0]).collect();
+        assert_eq!(row, [10, 10, 255, 255, 200, 200, 200, 200, 200, 200]);
+        assert!(compose(&before, &after, 10, 1, CompareLayout::Split, 1.5).is_err());
+    }
+}
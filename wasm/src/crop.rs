@@ -0,0 +1,267 @@
+//! Crops, nearest-neighbour zooms and thumbnails of an RGBA buffer.
+//!
+//! Editor views such as the magnifier loupe read from the same buffer the
+//! effects wrote, so they can never show pixels the redaction has already
+//! replaced. Parts of the crop outside the image are transparent, which
+//! keeps the output size fixed as the loupe moves over an edge.
+//! `Pipeline::run_cropped` crops and redacts in one step, copying only the
+//! kept part of the image.
+//!
+//! Thumbnails are area-averaged (every source pixel contributes by how much
+//! of it falls in the output pixel) with alpha-weighted color, so edges of
+//! transparent regions don't darken.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::image::RedactrImage;
+use crate::pipeline::Pipeline;
+use crate::types::Rect;
+
+/// Largest integer zoom factor
+pub const MAX_ZOOM: u32 = 16;
+
+/// Copy `rect` out of the image, each pixel repeated `zoom` x `zoom` times
+pub(crate) fn crop_rect(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+    zoom: u32,
+) -> Result<Vec<u8>, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    if !(1..=MAX_ZOOM).contains(&zoom) {
+        return Err(RedactError::InvalidParameter {
+            name: "zoom",
+            value: zoom as f64,
+            expected: format!("1..={}", MAX_ZOOM),
+        });
+    }
+    if rect.w == 0 || rect.h == 0 {
+        return Err(RedactError::EmptyRegion { region: rect });
+    }
+
+    let (out_w, out_h) = (rect.w * zoom, rect.h * zoom);
+    let mut out = vec![0u8; (out_w * out_h * 4) as usize];
+    for oy in 0..out_h {
+        let y = rect.y + oy / zoom;
+        if y >= height {
+            break;
+        }
+        let row = &mut out[(oy * out_w * 4) as usize..][..(out_w * 4) as usize];
+        for (ox, px) in row.chunks_exact_mut(4).enumerate() {
+            let x = rect.x + ox as u32 / zoom;
+            if x >= width {
+                break;
+            }
+            px.copy_from_slice(&data[((y * width + x) * 4) as usize..][..4]);
+        }
+    }
+    Ok(out)
+}
+
+/// New `cw * zoom` x `ch * zoom` RGBA buffer holding the region at
+/// (`x`, `y`), upsampled by the integer `zoom` (default 1)
+#[wasm_bindgen]
+pub fn crop(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    cw: u32,
+    ch: u32,
+    zoom: Option<u32>,
+) -> Result<Vec<u8>, JsError> {
+    let rect = Rect::new(x, y, cw, ch);
+    Ok(crop_rect(data, width, height, rect, zoom.unwrap_or(1))?)
+}
+
+/// Validate `plan` against the full image, then crop to `rect` (clipped to
+/// the image) and apply the steps that reach into it
+pub(crate) fn crop_and_apply(
+    plan: &Pipeline,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+) -> Result<RedactrImage, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    plan.validate(width, height)?;
+    check_region(rect, width, height)?;
+    let rect = rect.clip(width, height).unwrap_or(rect);
+    let mut image =
+        RedactrImage::from_rgba(rect.w, rect.h, crop_rect(data, width, height, rect, 1)?)?;
+    plan.cropped(rect)
+        .apply(image.pixels_mut(), rect.w, rect.h)?;
+    Ok(image)
+}
+
+/// Source pixels and their coverage for each of `dst` output pixels
+fn area_weights(src: u32, dst: u32) -> Vec<Vec<(usize, f32)>> {
+    let scale = src as f32 / dst as f32;
+    (0..dst)
+        .map(|i| {
+            let (start, end) = (i as f32 * scale, (i + 1) as f32 * scale);
+            (start.floor() as u32..(end.ceil() as u32).min(src))
+                .map(|j| {
+                    let cover = end.min(j as f32 + 1.0) - start.max(j as f32);
+                    (j as usize, cover / scale)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Downscale so the longer side is at most `max_edge`, keeping the aspect
+/// ratio; smaller images are copied unchanged
+pub(crate) fn downscale(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    max_edge: u32,
+) -> Result<(u32, u32, Vec<u8>), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    if max_edge == 0 {
+        return Err(RedactError::InvalidParameter {
+            name: "max_edge",
+            value: 0.0,
+            expected: "at least 1".to_string(),
+        });
+    }
+    let long = width.max(height);
+    if long <= max_edge {
+        return Ok((width, height, data.to_vec()));
+    }
+    let fit = |side: u32| ((side as u64 * max_edge as u64 + long as u64 / 2) / long as u64).max(1);
+    let (out_w, out_h) = (fit(width) as u32, fit(height) as u32);
+
+    // Rows first into premultiplied floats, then columns
+    let columns = area_weights(width, out_w);
+    let mut rows = vec![[0f32; 4]; (out_w * height) as usize];
+    for y in 0..height as usize {
+        let src = &data[y * width as usize * 4..][..width as usize * 4];
+        for (x, taps) in columns.iter().enumerate() {
+            let acc = &mut rows[y * out_w as usize + x];
+            for &(j, weight) in taps {
+                let px = &src[j * 4..j * 4 + 4];
+                let alpha = px[3] as f32 * weight;
+                for c in 0..3 {
+                    acc[c] += px[c] as f32 * alpha;
+                }
+                acc[3] += alpha;
+            }
+        }
+    }
+    let mut out = Vec::with_capacity((out_w * out_h * 4) as usize);
+    for taps in area_weights(height, out_h) {
+        for x in 0..out_w as usize {
+            let mut acc = [0f32; 4];
+            for &(j, weight) in &taps {
+                let px = rows[j * out_w as usize + x];
+                for (sum, v) in acc.iter_mut().zip(px) {
+                    *sum += v * weight;
+                }
+            }
+            let alpha = acc[3];
+            for sum in &acc[..3] {
+                let color = if alpha > 0.0 { sum / alpha } else { 0.0 };
+                out.push(color.round().clamp(0.0, 255.0) as u8);
+            }
+            out.push(alpha.round().clamp(0.0, 255.0) as u8);
+        }
+    }
+    Ok((out_w, out_h, out))
+}
+
+/// High-quality copy of the image with its longer side at most `max_edge`.
+/// Pass the redacted buffer; for a `RedactrImage` use its `thumbnail`.
+#[wasm_bindgen]
+pub fn thumbnail(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    max_edge: u32,
+) -> Result<RedactrImage, JsError> {
+    let (w, h, pixels) = downscale(data, width, height, max_edge)?;
+    Ok(RedactrImage::from_rgba(w, h, pixels)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern;
+    use crate::types::Color;
+
+    #[test]
+    fn test_crop_copies_the_region() {
+        let data = pattern(6, 5);
+        let out = crop_rect(&data, 6, 5, Rect::new(2, 1, 3, 2), 1).unwrap();
+        assert_eq!(out.len(), 3 * 2 * 4);
+        assert_eq!(out[..4], data[(6 + 2) * 4..][..4]);
+        assert_eq!(out[4 * 4..][..4], data[(2 * 6 + 3) * 4..][..4]);
+    }
+
+    #[test]
+    fn test_zoom_repeats_pixels_and_pads_outside() {
+        let data = pattern(4, 4);
+        let out = crop_rect(&data, 4, 4, Rect::new(3, 3, 2, 1), 3).unwrap();
+        // 6 x 3: three copies of (3, 3), then three transparent pixels
+        assert_eq!(out.len(), 6 * 3 * 4);
+        for row in out.chunks_exact(6 * 4) {
+            assert!(row[..12]
+                .chunks_exact(4)
+                .all(|px| px == &data[15 * 4..16 * 4]));
+            assert!(row[12..].iter().all(|&v| v == 0));
+        }
+        assert!(crop_rect(&data, 4, 4, Rect::new(0, 0, 2, 2), 0).is_err());
+        assert!(crop_rect(&data, 4, 4, Rect::new(0, 0, 0, 2), 1).is_err());
+    }
+
+    #[test]
+    fn test_crop_and_apply_matches_apply_then_crop() {
+        let data = pattern(12, 10);
+        let black = Color::new(0, 0, 0);
+        let plan = Pipeline::new()
+            .fill(&Rect::new(1, 1, 6, 6), &black)
+            .strength(0.5)
+            .pixelate(&Rect::new(8, 0, 4, 4), 2);
+        let mut full = data.clone();
+        plan.apply(&mut full, 12, 10).unwrap();
+
+        let crop = Rect::new(4, 2, 20, 6);
+        let image = crop_and_apply(&plan, &data, 12, 10, crop).unwrap();
+        assert_eq!((image.width(), image.height()), (8, 6));
+        // The fill is pixel-exact; the clipped pixelate differs, so compare
+        // the left part only
+        let expected = crop_rect(&full, 12, 10, Rect::new(4, 2, 4, 6), 1).unwrap();
+        let got = crop_rect(&image.to_rgba(), 8, 6, Rect::new(0, 0, 4, 6), 1).unwrap();
+        assert_eq!(got, expected);
+
+        let bad = Pipeline::new().fill(&Rect::new(20, 0, 2, 2), &black);
+        assert!(crop_and_apply(&bad, &data, 12, 10, crop).is_err());
+    }
+
+    #[test]
+    fn test_thumbnail_averages_area_and_keeps_aspect() {
+        // 6x3 columns alternating 0 and 240, halved to 3x2 (1.5 rounds up)
+        let data: Vec<u8> = (0..6 * 3)
+            .flat_map(|i| {
+                let v = if i % 2 == 0 { 0 } else { 240 };
+                [v, v, v, 255]
+            })
+            .collect();
+        let (w, h, out) = downscale(&data, 6, 3, 3).unwrap();
+        assert_eq!((w, h), (3, 2));
+        assert!(out.chunks_exact(4).all(|px| px == [120, 120, 120, 255]));
+
+        // Transparent pixels don't pull the color towards black
+        let data = [200, 0, 0, 255, 0, 0, 0, 0];
+        let (_, _, out) = downscale(&data, 2, 1, 1).unwrap();
+        assert_eq!(out, [200, 0, 0, 128]);
+
+        let (w, h, out) = downscale(&data, 2, 1, 8).unwrap();
+        assert_eq!((w, h, out.as_slice()), (2, 1, &data[..]));
+        assert!(downscale(&data, 2, 1, 0).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! Before/after comparison for QA.
+//!
+//! A diff marks every pixel whose RGB moved by more than the verification
+//! tolerance, then summarizes the change inside each approved region and
+//! everywhere else. A clean redaction changes every region substantially,
+//! leaves no recoverable structure in it, and changes nothing outside.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::json::Json;
+use crate::types::Rect;
+use crate::verify::{
+    luma, verify_region, CHANGE_TOLERANCE, DEFAULT_MAX_CORRELATION, DEFAULT_MAX_SSIM,
+};
+
+/// Change statistics for one approved region
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegionDiff {
+    pub region: Rect,
+    /// Fraction of pixels that changed, 0..=1
+    pub changed: f32,
+    /// Mean per-pixel max channel difference, 0..=255
+    pub mean_delta: f32,
+    /// Residual luma structure, as in `verify_redaction`
+    pub ssim: f32,
+    pub correlation: f32,
+    pub likely_recoverable: bool,
+}
+
+impl RegionDiff {
+    fn json(&self) -> Json {
+        Json::object()
+            .with("region", self.region.json())
+            .with("changed", self.changed)
+            .with("mean_delta", self.mean_delta)
+            .with("ssim", self.ssim)
+            .with("correlation", self.correlation)
+            .with("likely_recoverable", self.likely_recoverable)
+    }
+}
+
+/// Result of `diff`: a changed-pixel mask plus per-region statistics
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageDiff {
+    width: u32,
+    height: u32,
+    /// Largest channel difference per pixel
+    delta: Vec<u8>,
+    /// Original luma per pixel, the backdrop of the visual diff
+    backdrop: Vec<u8>,
+    regions: Vec<RegionDiff>,
+    outside_changed: usize,
+    outside_bounds: Option<Rect>,
+}
+
+pub fn diff_images(
+    original: &[u8],
+    redacted: &[u8],
+    width: u32,
+    height: u32,
+    regions: &[Rect],
+) -> Result<ImageDiff, RedactError> {
+    check_buffer(original.len(), width, height)?;
+    check_buffer(redacted.len(), width, height)?;
+    for &region in regions {
+        check_region(region, width, height)?;
+    }
+
+    let (delta, backdrop): (Vec<u8>, Vec<u8>) = original
+        .chunks_exact(4)
+        .zip(redacted.chunks_exact(4))
+        .map(|(a, b)| {
+            let d = (0..3).map(|c| a[c].abs_diff(b[c])).max().unwrap_or(0);
+            (d, luma(a).round() as u8)
+        })
+        .unzip();
+
+    let mut inside = vec![false; delta.len()];
+    let mut stats = Vec::with_capacity(regions.len());
+    for &region in regions {
+        let rect = region.clip(width, height).unwrap_or(region);
+        let (mut changed, mut total_delta) = (0usize, 0u64);
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                let i = (y * width + x) as usize;
+                inside[i] = true;
+                changed += (delta[i] > CHANGE_TOLERANCE) as usize;
+                total_delta += delta[i] as u64;
+            }
+        }
+        let area = (rect.w * rect.h) as f32;
+        let report = verify_region(
+            original,
+            redacted,
+            width,
+            height,
+            rect,
+            DEFAULT_MAX_SSIM,
+            DEFAULT_MAX_CORRELATION,
+        )?;
+        stats.push(RegionDiff {
+            region,
+            changed: changed as f32 / area,
+            mean_delta: total_delta as f32 / area,
+            ssim: report.ssim,
+            correlation: report.correlation,
+            likely_recoverable: report.likely_recoverable,
+        });
+    }
+
+    let mut outside_changed = 0;
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (i, &d) in delta.iter().enumerate() {
+        if inside[i] || d <= CHANGE_TOLERANCE {
+            continue;
+        }
+        outside_changed += 1;
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        let b = bounds.get_or_insert((x, y, x, y));
+        *b = (b.0.min(x), b.1.min(y), b.2.max(x), b.3.max(y));
+    }
+
+    Ok(ImageDiff {
+        width,
+        height,
+        delta,
+        backdrop,
+        regions: stats,
+        outside_changed,
+        outside_bounds: bounds.map(|(x0, y0, x1, y1)| Rect::new(x0, y0, x1 - x0 + 1, y1 - y0 + 1)),
+    })
+}
+
+impl ImageDiff {
+    pub fn regions(&self) -> &[RegionDiff] {
+        &self.regions
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        let regions = self.regions.iter().map(RegionDiff::json).collect();
+        Json::object()
+            .with("width", self.width)
+            .with("height", self.height)
+            .with("changed_pixels", self.changed_pixels())
+            .with("outside_changed", self.outside_changed)
+            .with("outside_bounds", self.outside_bounds.map(|r| r.json()))
+            .with("regions", Json::Array(regions))
+    }
+}
+
+#[wasm_bindgen]
+impl ImageDiff {
+    /// Pixels that changed anywhere in the image
+    #[wasm_bindgen(getter)]
+    pub fn changed_pixels(&self) -> usize {
+        self.delta.iter().filter(|&&d| d > CHANGE_TOLERANCE).count()
+    }
+
+    /// Pixels that changed outside every approved region
+    #[wasm_bindgen(getter)]
+    pub fn outside_changed(&self) -> usize {
+        self.outside_changed
+    }
+
+    /// Whether nothing outside the regions changed and every region is
+    /// at least `min_changed` changed with no recoverable structure left
+    pub fn is_clean(&self, min_changed: f32) -> bool {
+        self.outside_changed == 0
+            && self
+                .regions
+                .iter()
+                .all(|r| r.changed >= min_changed && !r.likely_recoverable)
+    }
+
+    /// One byte per pixel: 255 where the pixel changed, 0 elsewhere
+    pub fn mask(&self) -> Vec<u8> {
+        self.delta
+            .iter()
+            .map(|&d| if d > CHANGE_TOLERANCE { 255 } else { 0 })
+            .collect()
+    }
+
+    /// RGBA image of the dimmed original with changes in red (brighter for
+    /// larger differences) and changes outside the regions in yellow
+    pub fn visual(&self) -> Vec<u8> {
+        let mut inside = vec![false; self.delta.len()];
+        for r in &self.regions {
+            let rect = r.region.clip(self.width, self.height).unwrap_or(r.region);
+            for y in rect.y..rect.bottom() {
+                let start = (y * self.width + rect.x) as usize;
+                inside[start..start + rect.w as usize].fill(true);
+            }
+        }
+        let mut out = Vec::with_capacity(self.delta.len() * 4);
+        for (i, (&d, &l)) in self.delta.iter().zip(&self.backdrop).enumerate() {
+            let dim = l / 3;
+            let px = if d <= CHANGE_TOLERANCE {
+                [dim, dim, dim]
+            } else if inside[i] {
+                [128u8.saturating_add(d / 2), dim, dim]
+            } else {
+                [255, 255, 0]
+            };
+            out.extend_from_slice(&[px[0], px[1], px[2], 255]);
+        }
+        out
+    }
+
+    /// `{"width", "height", "changed_pixels", "outside_changed",
+    /// "outside_bounds", "regions": [{region, changed, mean_delta, ssim,
+    /// correlation, likely_recoverable}, ...]}`
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+}
+
+/// Compare two same-sized RGBA images against the approved regions (flat
+/// `[x, y, w, h, ...]`)
+#[wasm_bindgen]
+pub fn diff(
+    original: &[u8],
+    redacted: &[u8],
+    width: u32,
+    height: u32,
+    regions: &[u32],
+) -> Result<ImageDiff, JsError> {
+    let rects = Rect::from_flat(regions)?;
+    Ok(diff_images(original, redacted, width, height, &rects)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pattern, solid_fill};
+
+    #[test]
+    fn test_clean_redaction() {
+        let original = pattern(16, 16);
+        let mut redacted = original.clone();
+        solid_fill(&mut redacted, 16, 16, 2, 2, 6, 6, 0, 0, 0);
+        let diff = diff_images(&original, &redacted, 16, 16, &[Rect::new(2, 2, 6, 6)]).unwrap();
+        assert!(diff.is_clean(0.9));
+        assert_eq!(diff.outside_changed(), 0);
+        assert!(diff.regions()[0].changed > 0.9);
+        assert_eq!(
+            diff.mask().iter().filter(|&&m| m == 255).count(),
+            diff.changed_pixels()
+        );
+    }
+
+    #[test]
+    fn test_flags_changes_outside_regions() {
+        let original = pattern(16, 16);
+        let mut redacted = original.clone();
+        solid_fill(&mut redacted, 16, 16, 0, 0, 4, 4, 0, 0, 0);
+        solid_fill(&mut redacted, 16, 16, 10, 12, 2, 1, 0, 0, 0);
+        let diff = diff_images(&original, &redacted, 16, 16, &[Rect::new(0, 0, 4, 4)]).unwrap();
+        assert!(!diff.is_clean(0.5));
+        assert_eq!(
+            diff.json().get("outside_bounds").unwrap().to_string(),
+            r#"{"x":10,"y":12,"w":2,"h":1}"#
+        );
+        let visual = diff.visual();
+        let i = ((12 * 16 + 10) * 4) as usize;
+        assert_eq!(visual[i..i + 4], [255, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_untouched_region_is_not_clean() {
+        let original = pattern(8, 8);
+        let diff = diff_images(&original, &original, 8, 8, &[Rect::new(0, 0, 8, 8)]).unwrap();
+        assert_eq!(diff.regions()[0].changed, 0.0);
+        assert!(!diff.is_clean(0.5));
+    }
+}
@@ -0,0 +1,213 @@
+//! Dry runs: what a pipeline would change, without writing any pixels.
+//!
+//! A reviewer approves the report before the destructive pass. Each step
+//! lists the operation as it would actually run (after policy upgrades), the
+//! rectangle it would dirty, and any warnings or errors; a report with any
+//! error means `run` would fail before touching the image.
+
+use wasm_bindgen::prelude::*;
+
+use crate::json::Json;
+use crate::pipeline::{Op, Pipeline};
+use crate::policy::Policy;
+use crate::types::Rect;
+
+#[derive(Debug, Clone, PartialEq)]
+struct DryRunStep {
+    /// The step as it would run, after any policy upgrade
+    op: Op,
+    /// Part of the image that would be written, `None` if nothing would be
+    dirty: Option<Rect>,
+    /// Painted over by a later fill, so never executed
+    skipped: bool,
+    warnings: Vec<String>,
+    error: Option<String>,
+}
+
+impl DryRunStep {
+    fn json(&self, index: usize) -> Json {
+        let warnings = self
+            .warnings
+            .iter()
+            .map(|w| Json::from(w.as_str()))
+            .collect();
+        self.op
+            .json()
+            .with("index", index)
+            .with("dirty", self.dirty.map(|r| r.json()))
+            .with("skipped", self.skipped)
+            .with("warnings", Json::Array(warnings))
+            .with("error", self.error.clone())
+    }
+}
+
+/// Outcome of `Pipeline.dry_run`
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRunReport {
+    width: u32,
+    height: u32,
+    steps: Vec<DryRunStep>,
+}
+
+pub(crate) fn dry_run(
+    pipeline: &Pipeline,
+    width: u32,
+    height: u32,
+    policy: Option<&Policy>,
+) -> DryRunReport {
+    let live = pipeline.live_ops();
+    let steps = pipeline
+        .ops()
+        .iter()
+        .enumerate()
+        .map(|(index, requested)| {
+            let mut op = requested.clone();
+            let mut warnings = Vec::new();
+            let mut error = None;
+            if let Some(policy) = policy {
+                match policy.enforce(&mut op) {
+                    Ok(()) if op != *requested => warnings.push(format!(
+                        "upgraded by policy from {}",
+                        requested
+                            .json()
+                            .get("params")
+                            .cloned()
+                            .unwrap_or(Json::Null)
+                    )),
+                    Ok(()) => {}
+                    Err(e) => error = Some(e.to_string()),
+                }
+            }
+            if error.is_none() {
+                error = op.validate(width, height).err().map(|e| e.to_string());
+            }
+
+            let dirty = op.region.clip(width, height);
+            if error.is_none() && dirty != Some(op.region) {
+                warnings.push("region extends past the image and will be clipped".to_string());
+            }
+            let skipped = !live.contains(&index);
+            if skipped {
+                warnings.push("covered by a later fill and will be skipped".to_string());
+            }
+            if op.strength < 1.0 {
+                warnings.push(format!(
+                    "mixed at strength {}; original pixels stay partly visible",
+                    op.strength
+                ));
+            }
+            DryRunStep {
+                op,
+                dirty: if skipped { None } else { dirty },
+                skipped,
+                warnings,
+                error,
+            }
+        })
+        .collect();
+    DryRunReport {
+        width,
+        height,
+        steps,
+    }
+}
+
+impl DryRunReport {
+    /// Rectangles that would be written, in execution order
+    pub fn dirty_rects(&self) -> Vec<Rect> {
+        self.steps.iter().filter_map(|s| s.dirty).collect()
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        let steps = self
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| step.json(i))
+            .collect();
+        let dirty = self.dirty_rects().iter().map(Rect::json).collect();
+        Json::object()
+            .with("width", self.width)
+            .with("height", self.height)
+            .with("ok", self.ok())
+            .with("steps", Json::Array(steps))
+            .with("dirty", Json::Array(dirty))
+    }
+}
+
+#[wasm_bindgen]
+impl DryRunReport {
+    /// Whether the real run would succeed
+    #[wasm_bindgen(getter)]
+    pub fn ok(&self) -> bool {
+        self.steps.iter().all(|s| s.error.is_none())
+    }
+
+    /// Number of steps that would actually execute
+    #[wasm_bindgen(getter)]
+    pub fn executed(&self) -> usize {
+        self.steps.iter().filter(|s| !s.skipped).count()
+    }
+
+    /// Total warnings across all steps
+    #[wasm_bindgen(getter)]
+    pub fn warning_count(&self) -> usize {
+        self.steps.iter().map(|s| s.warnings.len()).sum()
+    }
+
+    /// Dirty rectangles as a flat `[x, y, w, h, ...]` list
+    pub fn dirty_flat(&self) -> Vec<u32> {
+        self.dirty_rects()
+            .iter()
+            .flat_map(|r| [r.x, r.y, r.w, r.h])
+            .collect()
+    }
+
+    /// `{"width", "height", "ok", "steps": [{effect, params, class, region,
+    /// index, dirty, skipped, warnings, error}, ...], "dirty": [...]}`
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Color;
+
+    #[test]
+    fn test_reports_dirty_rects_and_skipped_steps() {
+        let pipeline = Pipeline::new()
+            .blur(&Rect::new(0, 0, 4, 4), 3)
+            .fill(&Rect::new(0, 0, 8, 8), &Color::new(0, 0, 0))
+            .pixelate(&Rect::new(6, 6, 10, 10), 2);
+        let report = pipeline.dry_run(10, 10);
+        assert!(report.ok());
+        assert_eq!(report.executed(), 2);
+        assert_eq!(
+            report.dirty_rects(),
+            [Rect::new(0, 0, 8, 8), Rect::new(6, 6, 4, 4)]
+        );
+        assert_eq!(report.warning_count(), 2);
+    }
+
+    #[test]
+    fn test_reports_errors_and_policy_outcomes() {
+        let mut policy = Policy::new();
+        let pipeline = Pipeline::new()
+            .blur(&Rect::new(0, 0, 4, 4), 2)
+            .pixelate(&Rect::new(20, 20, 4, 4), 16);
+        let report = pipeline.dry_run_with_policy(10, 10, &policy);
+        assert!(!report.ok());
+        let json = report.to_json();
+        assert!(json.contains(r#""error":"radius 2 is below the policy minimum of 16""#));
+        assert!(json.contains(r#""dirty":null"#));
+
+        policy.auto_upgrade = true;
+        let report = pipeline.dry_run_with_policy(32, 32, &policy);
+        assert!(report.ok());
+        assert!(report.to_json().contains(r#""radius":16"#));
+        assert!(report.to_json().contains("upgraded by policy"));
+    }
+}
@@ -0,0 +1,438 @@
+use redactr_core::{gaussian_blur, pixelate, solid_fill};
+
+use crate::catalog::{check_param, effect_info, ParamDefault};
+use crate::error::RedactError;
+use crate::harden::harden_rect;
+use crate::hash::{hex, unhex};
+use crate::json::Json;
+use crate::mosaic::{mosaic_rect, CellShape};
+use crate::noise::{noise_key, paint_rect};
+use crate::tone::{desaturate_rect, dim_rect};
+use crate::types::{Color, Rect};
+
+/// A redaction effect together with its parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    SolidFill {
+        color: Color,
+    },
+    /// Block averages; with `grid_aligned` the blocks sit on the image's
+    /// grid rather than the region's, so moving the region doesn't change
+    /// the blocks it still covers. A nonzero `min_blocks` shrinks the blocks
+    /// of a small region until it has at least that many along each side.
+    Pixelate {
+        block_size: u32,
+        grid_aligned: bool,
+        min_blocks: u32,
+    },
+    GaussianBlur {
+        radius: u32,
+    },
+    /// Pixelation with quantized, seeded-jitter blocks; `glyph_height` of 0
+    /// estimates it from the region
+    HardenedPixelate {
+        block_size: u32,
+        seed: u32,
+        glyph_height: u32,
+    },
+    /// Cell averages over a square, hexagonal or triangular grid, with
+    /// `grout_width` pixel lines between cells (0 for none)
+    Mosaic {
+        cell_size: u32,
+        shape: CellShape,
+        grout: Color,
+        grout_width: u32,
+    },
+    /// Grayscale, pulled towards mid-gray when `contrast` is below 1; for
+    /// de-emphasis, not hiding
+    Desaturate {
+        contrast: f32,
+    },
+    /// RGB scaled by `factor`: below 1 darkens, above 1 brightens
+    Dim {
+        factor: f32,
+    },
+    /// Random RGB from a keystream over `seed` (see `noise_fill`); keeps
+    /// nothing of the pixels it replaces
+    NoiseFill {
+        seed: [u8; 32],
+    },
+}
+
+impl Effect {
+    /// Plain pixelation in `block_size` blocks anchored at the region
+    pub fn pixelate(block_size: u32) -> Effect {
+        Effect::Pixelate {
+            block_size,
+            grid_aligned: false,
+            min_blocks: 0,
+        }
+    }
+
+    /// Apply the effect to a rectangle (clamped to the image)
+    pub fn apply_rect(&self, data: &mut [u8], width: u32, height: u32, rect: Rect) {
+        self.apply_rect_at(data, width, height, rect, (0, 0))
+    }
+
+    /// `apply_rect` on a buffer cut out of a larger image at `origin`, so
+    /// grid-aligned pixelation keeps the image's grid. For blocks that match
+    /// the whole image the cut-out must cover `footprint`.
+    pub(crate) fn apply_rect_at(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        rect: Rect,
+        origin: (u32, u32),
+    ) {
+        let Rect { x, y, w, h } = rect;
+        match *self {
+            Effect::SolidFill { color } => {
+                solid_fill(data, width, height, x, y, w, h, color.r, color.g, color.b)
+            }
+            Effect::Pixelate {
+                block_size,
+                grid_aligned,
+                min_blocks,
+            } => {
+                let block_size = visible_block_size(rect, width, height, block_size, min_blocks);
+                if grid_aligned {
+                    redactr_core::pixelate_aligned_at(
+                        data, width, height, x, y, w, h, block_size, origin,
+                    )
+                } else {
+                    pixelate(data, width, height, x, y, w, h, block_size)
+                }
+            }
+            Effect::GaussianBlur { radius } => {
+                gaussian_blur(data, width, height, x, y, w, h, radius)
+            }
+            Effect::HardenedPixelate {
+                block_size,
+                seed,
+                glyph_height,
+            } => {
+                harden_rect(data, width, height, rect, block_size, seed, glyph_height);
+            }
+            Effect::Mosaic {
+                cell_size,
+                shape,
+                grout,
+                grout_width,
+            } => mosaic_rect(
+                data,
+                width,
+                height,
+                rect,
+                cell_size,
+                shape,
+                grout,
+                grout_width,
+            ),
+            Effect::Desaturate { contrast } => desaturate_rect(data, width, height, rect, contrast),
+            Effect::Dim { factor } => dim_rect(data, width, height, rect, factor),
+            Effect::NoiseFill { seed } => paint_rect(data, width, height, rect, seed),
+        }
+    }
+
+    /// The part of the image `apply_rect` reads for `rect`: the whole grid
+    /// cells under it for grid-aligned pixelation, the rect itself otherwise
+    pub(crate) fn footprint(&self, rect: Rect, width: u32, height: u32) -> Rect {
+        let Effect::Pixelate {
+            block_size,
+            grid_aligned: true,
+            min_blocks,
+        } = *self
+        else {
+            return rect;
+        };
+        let Some(visible) = rect.clip(width, height) else {
+            return rect;
+        };
+        let block_size = visible_block_size(rect, width, height, block_size, min_blocks).max(1);
+        let (x, y) = (
+            visible.x - visible.x % block_size,
+            visible.y - visible.y % block_size,
+        );
+        let right = visible
+            .right()
+            .div_ceil(block_size)
+            .saturating_mul(block_size);
+        let bottom = visible
+            .bottom()
+            .div_ceil(block_size)
+            .saturating_mul(block_size);
+        Rect::new(x, y, right.min(width) - x, bottom.min(height) - y)
+    }
+
+    /// The effect for an image `factor` times the size: pixel-sized
+    /// parameters scale with it, so a plan replayed on a full-resolution
+    /// original looks as it did on the preview
+    pub(crate) fn scaled(&self, factor: f32) -> Effect {
+        let scale = |n: u32| ((n as f32 * factor).round() as u32).max(1);
+        match *self {
+            Effect::SolidFill { color } => Effect::SolidFill { color },
+            Effect::Pixelate {
+                block_size,
+                grid_aligned,
+                min_blocks,
+            } => Effect::Pixelate {
+                block_size: scale(block_size),
+                grid_aligned,
+                min_blocks,
+            },
+            Effect::GaussianBlur { radius } => Effect::GaussianBlur {
+                radius: scale(radius),
+            },
+            Effect::HardenedPixelate {
+                block_size,
+                seed,
+                glyph_height,
+            } => Effect::HardenedPixelate {
+                block_size: scale(block_size),
+                seed,
+                // 0 still means estimate it from the pixels
+                glyph_height: if glyph_height == 0 {
+                    0
+                } else {
+                    scale(glyph_height)
+                },
+            },
+            Effect::Mosaic {
+                cell_size,
+                shape,
+                grout,
+                grout_width,
+            } => Effect::Mosaic {
+                cell_size: scale(cell_size),
+                shape,
+                grout,
+                grout_width: if grout_width == 0 {
+                    0
+                } else {
+                    scale(grout_width)
+                },
+            },
+            Effect::Desaturate { contrast } => Effect::Desaturate { contrast },
+            Effect::Dim { factor } => Effect::Dim { factor },
+            Effect::NoiseFill { seed } => Effect::NoiseFill { seed },
+        }
+    }
+
+    /// Name used in the effect catalog and serialized formats
+    pub fn name(&self) -> &'static str {
+        match self {
+            Effect::SolidFill { .. } => "solid_fill",
+            Effect::Pixelate { .. } => "pixelate",
+            Effect::GaussianBlur { .. } => "gaussian_blur",
+            Effect::HardenedPixelate { .. } => "hardened_pixelate",
+            Effect::Mosaic { .. } => "mosaic",
+            Effect::Desaturate { .. } => "desaturate",
+            Effect::Dim { .. } => "dim",
+            Effect::NoiseFill { .. } => "noise_fill",
+        }
+    }
+
+    /// Parameters as a JSON object keyed by catalog parameter name
+    pub(crate) fn params_json(&self) -> Json {
+        match *self {
+            Effect::SolidFill { color } => Json::object().with("color", color.hex()),
+            Effect::Pixelate {
+                block_size,
+                grid_aligned,
+                min_blocks,
+            } => Json::object()
+                .with("block_size", block_size)
+                .with("grid_aligned", grid_aligned)
+                .with("min_blocks", min_blocks),
+            Effect::GaussianBlur { radius } => Json::object().with("radius", radius),
+            Effect::HardenedPixelate {
+                block_size,
+                seed,
+                glyph_height,
+            } => Json::object()
+                .with("block_size", block_size)
+                .with("seed", seed)
+                .with("glyph_height", glyph_height),
+            Effect::Mosaic {
+                cell_size,
+                shape,
+                grout,
+                grout_width,
+            } => Json::object()
+                .with("cell_size", cell_size)
+                .with("shape", shape.name())
+                .with("grout_width", grout_width)
+                .with("grout_color", grout.hex()),
+            Effect::Desaturate { contrast } => Json::object().with("contrast", contrast),
+            Effect::Dim { factor } => Json::object().with("factor", factor),
+            Effect::NoiseFill { seed } => Json::object().with("seed", hex(&seed)),
+        }
+    }
+
+    /// Effect from a standalone JSON spec in the region format, e.g.
+    /// `{"effect": "gaussian_blur", "params": {"radius": 8}}`
+    pub(crate) fn from_spec(text: &str) -> Result<Effect, RedactError> {
+        let json = Json::parse(text)?;
+        let name = json
+            .get("effect")
+            .and_then(Json::as_str)
+            .ok_or(RedactError::InvalidField {
+                field: "effect".to_string(),
+                expected: "an effect name",
+            })?;
+        Effect::from_json(name, json.get("params"))
+    }
+
+    /// Effect named `name` with parameters from a JSON object keyed by
+    /// catalog parameter name (as written by `params_json`); missing
+    /// parameters take their catalog default
+    pub(crate) fn from_json(name: &str, params: Option<&Json>) -> Result<Effect, RedactError> {
+        let unknown = || RedactError::InvalidField {
+            field: name.to_string(),
+            expected: "a known effect name",
+        };
+        let info = effect_info(name).ok_or_else(unknown)?;
+        let param = |key: &'static str| -> Result<Json, RedactError> {
+            if let Some(value) = params.and_then(|p| p.get(key)) {
+                return Ok(value.clone());
+            }
+            let default = info
+                .params
+                .iter()
+                .find(|p| p.name == key)
+                .map(|p| p.default);
+            Ok(match default {
+                Some(ParamDefault::Number(n)) => Json::from(n),
+                Some(ParamDefault::Text(t)) => Json::from(t),
+                Some(ParamDefault::Flag(b)) => Json::from(b),
+                None => Json::Null,
+            })
+        };
+        let uint = |key: &'static str| -> Result<u32, RedactError> {
+            param(key)?
+                .as_f64()
+                .filter(|n| n.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(n))
+                .map(|n| n as u32)
+                .ok_or_else(|| RedactError::InvalidField {
+                    field: key.to_string(),
+                    expected: "a non-negative integer",
+                })
+        };
+        let number = |key: &'static str| -> Result<f32, RedactError> {
+            param(key)?
+                .as_f64()
+                .map(|n| n as f32)
+                .ok_or_else(|| RedactError::InvalidField {
+                    field: key.to_string(),
+                    expected: "a number",
+                })
+        };
+        let flag = |key: &'static str| -> Result<bool, RedactError> {
+            param(key)?
+                .as_bool()
+                .ok_or_else(|| RedactError::InvalidField {
+                    field: key.to_string(),
+                    expected: "true or false",
+                })
+        };
+        let color = |key: &'static str| -> Result<Color, RedactError> {
+            param(key)?
+                .as_str()
+                .and_then(Color::from_hex)
+                .ok_or_else(|| RedactError::InvalidField {
+                    field: key.to_string(),
+                    expected: "a #rrggbb color",
+                })
+        };
+        let effect = match info.name {
+            "solid_fill" => Effect::SolidFill {
+                color: color("color")?,
+            },
+            "pixelate" => Effect::Pixelate {
+                block_size: uint("block_size")?,
+                grid_aligned: flag("grid_aligned")?,
+                min_blocks: uint("min_blocks")?,
+            },
+            "gaussian_blur" => Effect::GaussianBlur {
+                radius: uint("radius")?,
+            },
+            "hardened_pixelate" => Effect::HardenedPixelate {
+                block_size: uint("block_size")?,
+                seed: uint("seed")?,
+                glyph_height: uint("glyph_height")?,
+            },
+            "mosaic" => Effect::Mosaic {
+                cell_size: uint("cell_size")?,
+                shape: param("shape")?
+                    .as_str()
+                    .and_then(CellShape::from_name)
+                    .ok_or_else(|| RedactError::InvalidField {
+                        field: "shape".to_string(),
+                        expected: "square, hexagon or triangle",
+                    })?,
+                grout: color("grout_color")?,
+                grout_width: uint("grout_width")?,
+            },
+            "desaturate" => Effect::Desaturate {
+                contrast: number("contrast")?,
+            },
+            "dim" => Effect::Dim {
+                factor: number("factor")?,
+            },
+            "noise_fill" => {
+                let seed = param("seed")?.as_str().and_then(unhex).ok_or_else(|| {
+                    RedactError::InvalidField {
+                        field: "seed".to_string(),
+                        expected: "a hex string",
+                    }
+                })?;
+                Effect::NoiseFill {
+                    seed: noise_key(&seed)?,
+                }
+            }
+            _ => return Err(unknown()),
+        };
+        effect.validate()?;
+        Ok(effect)
+    }
+
+    /// Reject parameters the one-shot functions would silently clamp.
+    /// Ranges come from the effect catalog (see `describe_effects`).
+    pub fn validate(&self) -> Result<(), RedactError> {
+        let name = self.name();
+        match *self {
+            Effect::SolidFill { .. } => Ok(()),
+            Effect::Pixelate { block_size, .. } => {
+                check_param(name, "block_size", block_size as f64)
+            }
+            Effect::GaussianBlur { radius } => check_param(name, "radius", radius as f64),
+            Effect::HardenedPixelate { block_size, .. } => {
+                check_param(name, "block_size", block_size as f64)
+            }
+            Effect::Mosaic { cell_size, .. } => check_param(name, "cell_size", cell_size as f64),
+            Effect::Desaturate { contrast } => check_param(name, "contrast", contrast as f64),
+            Effect::Dim { factor } => check_param(name, "factor", factor as f64),
+            Effect::NoiseFill { .. } => Ok(()),
+        }
+    }
+
+    /// Whether the result depends on the pixels being replaced
+    pub(crate) fn reads_pixels(&self) -> bool {
+        effect_info(self.name()).is_none_or(|info| info.reads_pixels)
+    }
+}
+
+/// `block_size` after `min_blocks`, counting blocks over the part of `rect`
+/// inside the image
+fn visible_block_size(
+    rect: Rect,
+    width: u32,
+    height: u32,
+    block_size: u32,
+    min_blocks: u32,
+) -> u32 {
+    let visible_w = rect.w.min(width.saturating_sub(rect.x));
+    let visible_h = rect.h.min(height.saturating_sub(rect.y));
+    redactr_core::block_size_for_min_blocks(visible_w, visible_h, block_size, min_blocks)
+}
@@ -0,0 +1,141 @@
+//! Redaction of encoded image files without a canvas round-trip.
+//!
+//! `redact_encoded` decodes a PNG, JPEG or WebP file inside wasm, applies a
+//! plan scaled to it and re-encodes it. The output is always a PNG carrying
+//! nothing but pixels, so no metadata of the input survives, and the active
+//! policy's export checks run on it before it is returned.
+
+use image::codecs::png::PngEncoder;
+use image::{ExtendedColorType, ImageEncoder, ImageFormat};
+use wasm_bindgen::prelude::*;
+
+use crate::error::RedactError;
+use crate::metadata::scan_as;
+use crate::policy::active_policy;
+use crate::types::Rect;
+use crate::RedactionPlan;
+
+/// RGBA pixels of `bytes`, a file already identified as `format`
+pub(crate) fn decode(
+    bytes: &[u8],
+    format: &'static str,
+) -> Result<(u32, u32, Vec<u8>), RedactError> {
+    let codec = match format {
+        "png" => ImageFormat::Png,
+        "jpeg" => ImageFormat::Jpeg,
+        _ => ImageFormat::WebP,
+    };
+    let image = image::load_from_memory_with_format(bytes, codec).map_err(|err| {
+        RedactError::UnsupportedEncoding {
+            format,
+            detail: err.to_string(),
+        }
+    })?;
+    let rgba = image.into_rgba8();
+    Ok((rgba.width(), rgba.height(), rgba.into_raw()))
+}
+
+/// A PNG of RGBA `data` with no chunks besides the pixels
+pub(crate) fn encode_png(width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    PngEncoder::new(&mut out)
+        .write_image(data, width, height, ExtendedColorType::Rgba8)
+        .expect("an RGBA buffer of the image's size always encodes");
+    out
+}
+
+pub(crate) fn redact_file(
+    bytes: &[u8],
+    format: &str,
+    plan_json: &str,
+) -> Result<Vec<u8>, RedactError> {
+    let report = scan_as(bytes, format)?;
+    let plan = RedactionPlan::parse(plan_json)?;
+    let (width, height, original) = decode(bytes, report.format)?;
+    let mut data = original.clone();
+    plan.apply_to(&mut data, width, height)?;
+    let encoded = encode_png(width, height, &data);
+    if let Some(policy) = active_policy() {
+        let regions: Vec<Rect> = plan
+            .regions_for(width, height)
+            .iter()
+            .filter_map(|region| region.bounds(width, height))
+            .collect();
+        policy.check_export(&encoded, &original, &data, width, height, &regions)?;
+    }
+    Ok(encoded)
+}
+
+/// Decode an encoded image in `format` (`"png"`, `"jpeg"` or `"webp"`),
+/// apply a plan (`RedactionPlan` JSON) scaled to it and return it
+/// re-encoded as a PNG. The active policy's export checks run on the
+/// result.
+#[wasm_bindgen]
+pub fn redact_encoded(bytes: &[u8], format: &str, plan_json: &str) -> Result<Vec<u8>, JsError> {
+    Ok(redact_file(bytes, format, plan_json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::codecs::webp::WebPEncoder;
+
+    const PLAN: &str = r##"{"version": 1, "width": 8, "height": 4, "regions": [
+        {"rect": {"x": 0, "y": 0, "w": 4, "h": 4}, "effect": "solid_fill",
+         "params": {"color": "#000000"}}]}"##;
+
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| [(i * 3) as u8, 100, 200, 255])
+            .collect()
+    }
+
+    #[test]
+    fn test_png_round_trip_scales_the_plan() {
+        let data = gradient(16, 8);
+        let out = redact_file(&encode_png(16, 8, &data), "PNG", PLAN).unwrap();
+        let (w, h, pixels) = decode(&out, "png").unwrap();
+        assert_eq!((w, h), (16, 8));
+        assert_eq!(pixels[7 * 4..8 * 4], [0, 0, 0, 255]);
+        assert_eq!(pixels[8 * 4..9 * 4], data[8 * 4..9 * 4]);
+    }
+
+    #[test]
+    fn test_jpeg_and_webp_come_out_as_png() {
+        let data = gradient(8, 4);
+        // JPEG has no alpha channel
+        let rgb: Vec<u8> = data
+            .chunks(4)
+            .flat_map(|px| [px[0], px[1], px[2]])
+            .collect();
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 95)
+            .write_image(&rgb, 8, 4, ExtendedColorType::Rgb8)
+            .unwrap();
+        let mut webp = Vec::new();
+        WebPEncoder::new_lossless(&mut webp)
+            .write_image(&data, 8, 4, ExtendedColorType::Rgba8)
+            .unwrap();
+        for (file, format) in [(jpeg, "jpg"), (webp, "webp")] {
+            let out = redact_file(&file, format, PLAN).unwrap();
+            assert_eq!(out[..8], *b"\x89PNG\r\n\x1a\n");
+            let (w, h, pixels) = decode(&out, "png").unwrap();
+            assert_eq!((w, h), (8, 4), "{}", format);
+            assert!(pixels[..3].iter().all(|&c| c < 8), "{}", format);
+        }
+    }
+
+    #[test]
+    fn test_broken_and_mismatched_input() {
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xD9];
+        assert!(matches!(
+            redact_file(&jpeg, "jpeg", PLAN),
+            Err(RedactError::UnsupportedEncoding { format: "jpeg", .. })
+        ));
+        assert!(redact_file(&jpeg, "png", PLAN).is_err());
+        assert!(redact_file(b"not a png", "png", PLAN).is_err());
+        let png = encode_png(1, 1, &[0; 4]);
+        assert!(redact_file(&png, "png", r#"{"version": 1}"#).is_err());
+    }
+}
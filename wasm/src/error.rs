@@ -0,0 +1,244 @@
+use std::fmt;
+
+use crate::types::Rect;
+
+/// Errors reported by the checked parts of the API.
+///
+/// Converts into a `JsError` at the wasm boundary, so exported functions can
+/// return `Result<_, JsError>` and use `?` directly.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedactError {
+    /// Pixel buffer length doesn't match `width * height * 4`
+    BufferSize { len: usize, width: u32, height: u32 },
+    /// Region has zero width or height
+    EmptyRegion { region: Rect },
+    /// Region doesn't intersect the image
+    RegionOutOfBounds {
+        region: Rect,
+        width: u32,
+        height: u32,
+    },
+    /// Effect parameter outside its accepted range
+    InvalidParameter {
+        name: &'static str,
+        value: f64,
+        expected: String,
+    },
+    /// One step of a multi-operation batch failed validation
+    InvalidOp {
+        index: usize,
+        error: Box<RedactError>,
+    },
+    /// No built-in or registered preset has this name
+    UnknownPreset { name: String },
+    /// Built-in presets can't be replaced or removed
+    ReservedPreset { name: String },
+    /// No built-in or registered fill palette has this name
+    UnknownPalette { name: String },
+    /// Built-in palettes can't be replaced or removed
+    ReservedPalette { name: String },
+    /// Two colors of a palette are too close in luminance to tell apart
+    LowContrast {
+        foreground: String,
+        background: String,
+        ratio: f32,
+        minimum: f32,
+    },
+    /// JSON input is syntactically invalid at `offset`
+    InvalidJson { offset: usize, detail: &'static str },
+    /// Text pattern is not a valid regular expression at char `offset`
+    InvalidPattern { offset: usize, detail: &'static str },
+    /// Text pattern backtracked too much to finish on a line of text
+    PatternTooComplex { pattern: String },
+    /// JSON input is well-formed but a field has the wrong shape
+    InvalidField {
+        field: String,
+        expected: &'static str,
+    },
+    /// Encoded file isn't PNG, JPEG or WebP
+    UnknownFormat,
+    /// Input is a PDF. There's no PDF support: painting over a page would
+    /// leave its text and OCR layers in place, so PDFs are refused outright
+    PdfNotSupported,
+    /// Encoded file's container structure is broken at `offset`
+    MalformedImage { detail: &'static str, offset: usize },
+    /// Encoded file's container is valid but its codec rejects the data
+    UnsupportedEncoding {
+        format: &'static str,
+        detail: String,
+    },
+    /// `detect_faces` was called before `set_face_cascade`
+    NoFaceCascade,
+    /// Segmentation model failed to load or run
+    InvalidModel { detail: String },
+    /// Pixelation blocks are smaller than the text they cover
+    BlockTooSmall {
+        block_size: u32,
+        glyph_height: u32,
+        minimum: u32,
+    },
+    /// Too few pixels of a region changed from the original
+    NotRedacted { region: Rect, changed: f32 },
+    /// Effect isn't in the policy's `allowed_effects`
+    EffectNotAllowed { effect: &'static str },
+    /// Effect parameter outside an organization policy's bounds
+    OutsidePolicyBounds {
+        param: String,
+        value: f64,
+        expected: String,
+    },
+    /// Export still carries metadata the policy requires stripping
+    MetadataRemaining { blocks: Vec<String> },
+    /// Exported region failed the policy's required verification
+    Recoverable { region: Rect },
+    /// Operation is weaker than the configured `Policy` allows
+    PolicyViolation {
+        rule: &'static str,
+        value: f64,
+        minimum: f64,
+    },
+}
+
+impl fmt::Display for RedactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedactError::BufferSize { len, width, height } => write!(
+                f,
+                "data length {} does not match {}x{}x4",
+                len, width, height
+            ),
+            RedactError::EmptyRegion { region } => write!(f, "region {} is empty", region),
+            RedactError::RegionOutOfBounds {
+                region,
+                width,
+                height,
+            } => write!(
+                f,
+                "region {} lies outside the {}x{} image",
+                region, width, height
+            ),
+            RedactError::InvalidParameter {
+                name,
+                value,
+                expected,
+            } => write!(f, "invalid {} {}: expected {}", name, value, expected),
+            RedactError::InvalidOp { index, error } => write!(f, "operation {}: {}", index, error),
+            RedactError::UnknownPreset { name } => write!(f, "unknown preset \"{}\"", name),
+            RedactError::ReservedPreset { name } => {
+                write!(f, "preset \"{}\" is built in and can't be changed", name)
+            }
+            RedactError::UnknownPalette { name } => write!(f, "unknown palette \"{}\"", name),
+            RedactError::ReservedPalette { name } => {
+                write!(f, "palette \"{}\" is built in and can't be changed", name)
+            }
+            RedactError::LowContrast {
+                foreground,
+                background,
+                ratio,
+                minimum,
+            } => write!(
+                f,
+                "{} on {} has contrast {:.2}:1, below the required {}:1",
+                foreground, background, ratio, minimum
+            ),
+            RedactError::InvalidJson { offset, detail } => {
+                write!(f, "invalid JSON at byte {}: {}", offset, detail)
+            }
+            RedactError::InvalidPattern { offset, detail } => {
+                write!(f, "invalid pattern at char {}: {}", offset, detail)
+            }
+            RedactError::PatternTooComplex { pattern } => {
+                write!(f, "pattern \"{}\" backtracks too much", pattern)
+            }
+            RedactError::InvalidField { field, expected } => {
+                write!(f, "invalid field \"{}\": expected {}", field, expected)
+            }
+            RedactError::UnknownFormat => write!(f, "unrecognized image format"),
+            RedactError::PdfNotSupported => write!(
+                f,
+                "PDF files are not supported: rasterize each page and redact the images, \
+                 since drawing over a PDF leaves its text layer under the boxes"
+            ),
+            RedactError::MalformedImage { detail, offset } => {
+                write!(f, "{} at byte {}", detail, offset)
+            }
+            RedactError::UnsupportedEncoding { format, detail } => {
+                write!(f, "cannot decode {}: {}", format, detail)
+            }
+            RedactError::NoFaceCascade => write!(
+                f,
+                "no face cascade registered: load one with set_face_cascade first"
+            ),
+            RedactError::InvalidModel { detail } => {
+                write!(f, "segmentation model failed: {}", detail)
+            }
+            RedactError::BlockTooSmall {
+                block_size,
+                glyph_height,
+                minimum,
+            } => write!(
+                f,
+                "block size {} is too small for {}px text: use at least {}",
+                block_size, glyph_height, minimum
+            ),
+            RedactError::NotRedacted { region, changed } => write!(
+                f,
+                "region {} is not redacted: only {:.1}% of pixels changed",
+                region,
+                changed * 100.0
+            ),
+            RedactError::EffectNotAllowed { effect } => {
+                write!(f, "effect {} is not allowed by the policy", effect)
+            }
+            RedactError::OutsidePolicyBounds {
+                param,
+                value,
+                expected,
+            } => write!(
+                f,
+                "{} {} is outside the policy bounds: expected {}",
+                param, value, expected
+            ),
+            RedactError::MetadataRemaining { blocks } => {
+                write!(f, "metadata left in export: {}", blocks.join(", "))
+            }
+            RedactError::Recoverable { region } => {
+                write!(f, "region {} may still be recoverable", region)
+            }
+            RedactError::PolicyViolation {
+                rule,
+                value,
+                minimum,
+            } => write!(
+                f,
+                "{} {} is below the policy minimum of {}",
+                rule, value, minimum
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RedactError {}
+
+/// Check that an RGBA buffer holds exactly `width * height` pixels
+pub(crate) fn check_buffer(len: usize, width: u32, height: u32) -> Result<(), RedactError> {
+    if len as u64 != width as u64 * height as u64 * 4 {
+        return Err(RedactError::BufferSize { len, width, height });
+    }
+    Ok(())
+}
+
+/// Check that a region is non-empty and at least partly inside the image
+pub(crate) fn check_region(region: Rect, width: u32, height: u32) -> Result<(), RedactError> {
+    if region.w == 0 || region.h == 0 {
+        return Err(RedactError::EmptyRegion { region });
+    }
+    if region.x >= width || region.y >= height {
+        return Err(RedactError::RegionOutOfBounds {
+            region,
+            width,
+            height,
+        });
+    }
+    Ok(())
+}
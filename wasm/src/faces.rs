@@ -0,0 +1,423 @@
+//! Face detection with a Viola-Jones cascade of Haar-like features.
+//!
+//! The evaluator is here; the trained weights are not. No cascade ships
+//! with the module (a frontal-face cascade is several hundred kilobytes,
+//! and the app's own face detection runs in MediaPipe on the host), so the
+//! host converts one once, e.g. OpenCV's `haarcascade_frontalface_default`,
+//! into JSON and registers it with `set_face_cascade`:
+//!
+//! ```json
+//! {"width": 24, "height": 24,
+//!  "stages": [{"threshold": -1.29,
+//!              "weak": [{"rects": [[6, 4, 12, 9, -1], [6, 7, 12, 3, 3]],
+//!                        "threshold": -0.031, "left": 2.08, "right": -2.21}]}]}
+//! ```
+//!
+//! Each weak classifier is a stump over a weighted sum of rectangles
+//! (`[x, y, w, h, weight]` in window pixels), compared against its
+//! threshold times the window's standard deviation, as in OpenCV. Windows
+//! are scanned at growing scales and overlapping hits are grouped, keeping
+//! groups with enough neighbours.
+//!
+//! Built with the `face-detect` feature, which is off by default: until a
+//! cascade is registered `detect_faces` only fails, so a default build
+//! doesn't carry an evaluator it can't use.
+
+use std::cell::RefCell;
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, RedactError};
+use crate::json::Json;
+use crate::text::flatten;
+use crate::types::Rect;
+use crate::verify::luma;
+
+/// Window growth between scan scales
+const SCALE_STEP: f64 = 1.25;
+/// Hits a group needs to count as a face; lone hits are usually noise
+const MIN_NEIGHBORS: usize = 3;
+/// Windows flatter than this (luma variance) are skipped outright
+const MIN_VARIANCE: f64 = 1.0;
+
+thread_local! {
+    static CASCADE: RefCell<Option<FaceCascade>> = const { RefCell::new(None) };
+}
+
+/// One stump: a weighted sum of rectangles against a threshold
+#[derive(Debug, Clone, PartialEq)]
+struct Weak {
+    rects: Vec<(Rect, f64)>,
+    threshold: f64,
+    left: f64,
+    right: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Stage {
+    threshold: f64,
+    weak: Vec<Weak>,
+}
+
+/// A trained cascade and the window size it was trained at
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaceCascade {
+    width: u32,
+    height: u32,
+    stages: Vec<Stage>,
+}
+
+/// Summed-area tables of luma and luma squared, one row and column larger
+/// than the image
+struct Integral {
+    stride: usize,
+    sum: Vec<f64>,
+    squares: Vec<f64>,
+}
+
+impl Integral {
+    fn new(data: &[u8], width: usize, height: usize) -> Integral {
+        let stride = width + 1;
+        let mut sum = vec![0.0; stride * (height + 1)];
+        let mut squares = sum.clone();
+        for y in 0..height {
+            let (mut row, mut row_sq) = (0.0, 0.0);
+            for x in 0..width {
+                let i = (y * width + x) * 4;
+                let l = luma(&data[i..i + 4]).round();
+                row += l;
+                row_sq += l * l;
+                sum[(y + 1) * stride + x + 1] = sum[y * stride + x + 1] + row;
+                squares[(y + 1) * stride + x + 1] = squares[y * stride + x + 1] + row_sq;
+            }
+        }
+        Integral {
+            stride,
+            sum,
+            squares,
+        }
+    }
+
+    fn area(&self, table: &[f64], x: u32, y: u32, w: u32, h: u32) -> f64 {
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = (x0 + w as usize, y0 + h as usize);
+        table[y1 * self.stride + x1] - table[y0 * self.stride + x1] - table[y1 * self.stride + x0]
+            + table[y0 * self.stride + x0]
+    }
+}
+
+/// `value[key]` as an array, or an error naming `at.key`
+fn array<'a>(value: &'a Json, at: &str, key: &str) -> Result<&'a [Json], RedactError> {
+    value
+        .get(key)
+        .and_then(Json::as_array)
+        .ok_or_else(|| RedactError::InvalidField {
+            field: format!("{}{}", at, key),
+            expected: "an array",
+        })
+}
+
+/// `value[key]` as a number, or an error naming `at.key`
+fn number(value: &Json, at: &str, key: &str) -> Result<f64, RedactError> {
+    value
+        .get(key)
+        .and_then(Json::as_f64)
+        .ok_or_else(|| RedactError::InvalidField {
+            field: format!("{}{}", at, key),
+            expected: "a number",
+        })
+}
+
+impl FaceCascade {
+    pub(crate) fn from_json_value(value: &Json) -> Result<FaceCascade, RedactError> {
+        let field = |field: String, expected| RedactError::InvalidField { field, expected };
+        let size = |key: &str| {
+            Some(number(value, "", key)?)
+                .filter(|&n| n >= 1.0 && n.fract() == 0.0)
+                .map(|n| n as u32)
+                .ok_or_else(|| field(key.to_string(), "a positive integer"))
+        };
+        let (width, height) = (size("width")?, size("height")?);
+        let mut stages = Vec::new();
+        for (s, stage) in array(value, "", "stages")?.iter().enumerate() {
+            let at = format!("stages[{}].", s);
+            let mut weak = Vec::new();
+            for (k, item) in array(stage, &at, "weak")?.iter().enumerate() {
+                let at = format!("{}weak[{}].", at, k);
+                let mut rects = Vec::new();
+                for (r, rect) in array(item, &at, "rects")?.iter().enumerate() {
+                    let at = format!("{}rects[{}]", at, r);
+                    let values = rect
+                        .as_array()
+                        .and_then(|v| v.iter().map(Json::as_f64).collect::<Option<Vec<f64>>>())
+                        .filter(|v| {
+                            v.len() == 5 && v[..4].iter().all(|&n| n >= 0.0 && n.fract() == 0.0)
+                        })
+                        .ok_or_else(|| {
+                            field(at.clone(), "[x, y, w, h, weight] with integer x, y, w, h")
+                        })?;
+                    let rect = Rect::new(
+                        values[0] as u32,
+                        values[1] as u32,
+                        values[2] as u32,
+                        values[3] as u32,
+                    );
+                    if rect.w == 0 || rect.h == 0 || rect.right() > width || rect.bottom() > height
+                    {
+                        return Err(field(at, "a rectangle inside the window"));
+                    }
+                    rects.push((rect, values[4]));
+                }
+                weak.push(Weak {
+                    rects,
+                    threshold: number(item, &at, "threshold")?,
+                    left: number(item, &at, "left")?,
+                    right: number(item, &at, "right")?,
+                });
+            }
+            let threshold = number(stage, &at, "threshold")?;
+            stages.push(Stage { threshold, weak });
+        }
+        if stages.is_empty() {
+            return Err(field("stages".into(), "at least one stage"));
+        }
+        Ok(FaceCascade {
+            width,
+            height,
+            stages,
+        })
+    }
+
+    /// Whether the window at `(x, y)`, `scale` times the training size,
+    /// passes every stage
+    fn accepts(&self, integral: &Integral, x: u32, y: u32, scale: f64) -> bool {
+        let w = (self.width as f64 * scale) as u32;
+        let h = (self.height as f64 * scale) as u32;
+        let n = (w * h) as f64;
+        let mean = integral.area(&integral.sum, x, y, w, h) / n;
+        let variance = integral.area(&integral.squares, x, y, w, h) / n - mean * mean;
+        if variance < MIN_VARIANCE {
+            return false;
+        }
+        let deviation = variance.sqrt();
+        let scaled = |v: u32| (v as f64 * scale) as u32;
+        self.stages.iter().all(|stage| {
+            let total: f64 = stage
+                .weak
+                .iter()
+                .map(|weak| {
+                    let value: f64 = weak
+                        .rects
+                        .iter()
+                        .map(|&(r, weight)| {
+                            let (rw, rh) = (scaled(r.w), scaled(r.h));
+                            let (rx, ry) = (scaled(r.x), scaled(r.y));
+                            // Weights are per training-size pixel
+                            let area = (rw * rh) as f64 / (r.w * r.h) as f64;
+                            weight * integral.area(&integral.sum, x + rx, y + ry, rw, rh) / area
+                        })
+                        .sum::<f64>()
+                        / (self.width * self.height) as f64;
+                    if value < weak.threshold * deviation {
+                        weak.left
+                    } else {
+                        weak.right
+                    }
+                })
+                .sum();
+            total >= stage.threshold
+        })
+    }
+
+    /// Faces in an RGBA image, as grouped window hits
+    pub(crate) fn detect(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<Rect>, RedactError> {
+        check_buffer(data.len(), width, height)?;
+        let integral = Integral::new(data, width as usize, height as usize);
+        let mut hits = Vec::new();
+        let mut scale = 1.0;
+        loop {
+            let w = (self.width as f64 * scale) as u32;
+            let h = (self.height as f64 * scale) as u32;
+            if w > width || h > height {
+                break;
+            }
+            let step = (scale * 2.0).round().max(1.0) as u32;
+            for y in (0..=height - h).step_by(step as usize) {
+                for x in (0..=width - w).step_by(step as usize) {
+                    if self.accepts(&integral, x, y, scale) {
+                        hits.push(Rect::new(x, y, w, h));
+                    }
+                }
+            }
+            scale *= SCALE_STEP;
+        }
+        Ok(group(&hits))
+    }
+}
+
+/// Whether two hits are the same face: corners and sizes within a fifth of
+/// their mean size, as in OpenCV's `groupRectangles`
+fn similar(a: &Rect, b: &Rect) -> bool {
+    let delta = 0.2 * (a.w.min(a.h) + b.w.min(b.h)) as f64 / 2.0;
+    let near = |p: u32, q: u32| (p as f64 - q as f64).abs() <= delta;
+    near(a.x, b.x) && near(a.y, b.y) && near(a.right(), b.right()) && near(a.bottom(), b.bottom())
+}
+
+/// Average each cluster of similar hits, keeping clusters of at least
+/// `MIN_NEIGHBORS`
+fn group(hits: &[Rect]) -> Vec<Rect> {
+    let mut cluster: Vec<usize> = (0..hits.len()).collect();
+    fn root(cluster: &mut [usize], mut i: usize) -> usize {
+        while cluster[i] != i {
+            cluster[i] = cluster[cluster[i]];
+            i = cluster[i];
+        }
+        i
+    }
+    for i in 0..hits.len() {
+        for j in i + 1..hits.len() {
+            if similar(&hits[i], &hits[j]) {
+                let (a, b) = (root(&mut cluster, i), root(&mut cluster, j));
+                cluster[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    let mut faces = Vec::new();
+    for i in 0..hits.len() {
+        if root(&mut cluster, i) != i {
+            continue;
+        }
+        let members: Vec<&Rect> = (0..hits.len())
+            .filter(|&j| root(&mut cluster, j) == i)
+            .map(|j| &hits[j])
+            .collect();
+        if members.len() < MIN_NEIGHBORS {
+            continue;
+        }
+        let n = members.len() as u32;
+        let mean = |f: fn(&Rect) -> u32| members.iter().map(|r| f(r)).sum::<u32>() / n;
+        faces.push(Rect::new(
+            mean(|r| r.x),
+            mean(|r| r.y),
+            mean(|r| r.w),
+            mean(|r| r.h),
+        ));
+    }
+    faces
+}
+
+/// Faces found with the registered cascade
+pub(crate) fn detect(data: &[u8], width: u32, height: u32) -> Result<Vec<Rect>, RedactError> {
+    CASCADE.with(|cascade| match &*cascade.borrow() {
+        Some(cascade) => cascade.detect(data, width, height),
+        None => Err(RedactError::NoFaceCascade),
+    })
+}
+
+#[wasm_bindgen]
+impl FaceCascade {
+    /// Parse a cascade from JSON (see the module docs for the format)
+    pub fn from_json(text: &str) -> Result<FaceCascade, JsError> {
+        Ok(Self::from_json_value(&Json::parse(text)?)?)
+    }
+
+    /// Training window size
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Faces in an RGBA image with this cascade, flat `[x, y, w, h, ...]`
+    pub fn detect_faces(&self, data: &[u8], width: u32, height: u32) -> Result<Vec<u32>, JsError> {
+        Ok(flatten(&self.detect(data, width, height)?))
+    }
+}
+
+/// Register the cascade `detect_faces` uses, replacing any earlier one
+#[wasm_bindgen]
+pub fn set_face_cascade(cascade: &FaceCascade) {
+    CASCADE.with(|c| *c.borrow_mut() = Some(cascade.clone()));
+}
+
+/// Faces in an RGBA image, flat `[x, y, w, h, ...]`, found with the cascade
+/// registered by `set_face_cascade`; fails if none is
+#[wasm_bindgen]
+pub fn detect_faces(data: &[u8], width: u32, height: u32) -> Result<Vec<u32>, JsError> {
+    Ok(flatten(&detect(data, width, height)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One stump firing on a dark upper half over a bright lower half
+    const CASCADE: &str = r#"{"width": 12, "height": 12, "stages": [{"threshold": 0.5,
+        "weak": [{"rects": [[0, 0, 12, 6, -1], [0, 6, 12, 6, 1]],
+                  "threshold": 0.8, "left": -1, "right": 1}]}]}"#;
+
+    fn image(width: u32, height: u32, pattern: Rect) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let inside =
+                    x >= pattern.x && x < pattern.right() && y >= pattern.y && y < pattern.bottom();
+                let v = match (inside, y < pattern.y + pattern.h / 2) {
+                    (false, _) => 128,
+                    (true, true) => 0,
+                    (true, false) => 255,
+                };
+                [v, v, v, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_finds_the_pattern_at_its_scale() {
+        let cascade = FaceCascade::from_json_value(&Json::parse(CASCADE).unwrap()).unwrap();
+        let pattern = Rect::new(20, 16, 24, 24);
+        let faces = cascade.detect(&image(72, 64, pattern), 72, 64).unwrap();
+        assert_eq!(faces.len(), 1, "{:?}", faces);
+        // A one-stump cascade also fires on windows straddling the edge
+        // inside the pattern, but never outside it
+        assert!(pattern.contains(&faces[0]), "{:?}", faces[0]);
+
+        // Flat content has nothing to find
+        assert!(cascade
+            .detect(&[128; 72 * 64 * 4], 72, 64)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_registered_cascade_and_parse_errors() {
+        assert_eq!(detect(&[0; 4], 1, 1), Err(RedactError::NoFaceCascade));
+        let cascade = FaceCascade::from_json_value(&Json::parse(CASCADE).unwrap()).unwrap();
+        set_face_cascade(&cascade);
+        assert_eq!(detect(&[0; 4], 1, 1), Ok(vec![]));
+
+        let outside = CASCADE.replace("[0, 6, 12, 6, 1]", "[0, 8, 12, 6, 1]");
+        assert_eq!(
+            FaceCascade::from_json_value(&Json::parse(&outside).unwrap()),
+            Err(RedactError::InvalidField {
+                field: "stages[0].weak[0].rects[1]".into(),
+                expected: "a rectangle inside the window",
+            })
+        );
+        let missing = CASCADE.replace("\"left\": -1, ", "");
+        assert!(matches!(
+            FaceCascade::from_json_value(&Json::parse(&missing).unwrap()),
+            Err(RedactError::InvalidField { field, .. }) if field == "stages[0].weak[0].left"
+        ));
+    }
+}
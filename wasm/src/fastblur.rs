@@ -0,0 +1,211 @@
+//! Gaussian blur approximated by three iterated box blurs.
+//!
+//! Three box blurs of suitably chosen widths converge to a gaussian of the
+//! same variance (the central limit theorem with n = 3), and each box pass
+//! is a running sum, so the cost per pixel doesn't depend on the radius.
+//! The result tracks the exact blur more closely the larger the radius,
+//! which is where the speed matters; `blur` picks between the two with a
+//! `BlurQuality`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::buffer::{read_region, write_region};
+use crate::effect::Effect;
+use crate::gaussian_blur;
+use crate::policy::{or_throw, policed_size};
+use crate::scratch::Scratch;
+use crate::types::Rect;
+
+/// Number of box passes per axis
+const PASSES: usize = 3;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlurQuality {
+    /// Separable gaussian kernel; cost grows with the radius
+    Exact = 0,
+    /// Three box blurs; constant cost per pixel
+    Fast = 1,
+}
+
+/// Box radii whose iterated blur has the variance of the gaussian that
+/// `gaussian_blur` uses for `radius` (sigma = radius / 2)
+fn box_radii(radius: u32) -> [usize; PASSES] {
+    let sigma = radius as f32 / 2.0;
+    let n = PASSES as f32;
+    let ideal = (12.0 * sigma * sigma / n + 1.0).sqrt();
+    let mut lower = ideal.floor() as i32;
+    if lower % 2 == 0 {
+        lower -= 1;
+    }
+    let l = lower as f32;
+    let smaller =
+        ((12.0 * sigma * sigma - n * l * l - 4.0 * n * l - 3.0 * n) / (-4.0 * l - 4.0)).round();
+    let mut radii = [0; PASSES];
+    for (i, r) in radii.iter_mut().enumerate() {
+        let width = if (i as f32) < smaller {
+            lower
+        } else {
+            lower + 2
+        };
+        *r = (width.max(1) as usize - 1) / 2;
+    }
+    radii
+}
+
+/// Box blur `count` lines of `len` pixels each, `step` bytes apart within a
+/// line and `line_step` bytes between lines, from `src` into `dst`. Windows
+/// are clipped at the ends of a line and averaged over what's left, like
+/// the gaussian's edge handling.
+fn box_pass(
+    src: &[u8],
+    dst: &mut [u8],
+    (len, step): (usize, usize),
+    (count, line_step): (usize, usize),
+    radius: usize,
+) {
+    for line in 0..count {
+        let base = line * line_step;
+        let at = |i: usize| base + i * step;
+        let mut sum = [0u32; 3];
+        for i in 0..radius.min(len - 1) + 1 {
+            for c in 0..3 {
+                sum[c] += src[at(i) + c] as u32;
+            }
+        }
+        for i in 0..len {
+            let lo = i.saturating_sub(radius);
+            let hi = (i + radius).min(len - 1);
+            let n = (hi - lo + 1) as u32;
+            for c in 0..3 {
+                dst[at(i) + c] = ((sum[c] + n / 2) / n) as u8;
+            }
+            dst[at(i) + 3] = src[at(i) + 3];
+            // Slide the window one pixel right
+            for c in 0..3 {
+                if i + radius + 1 < len {
+                    sum[c] += src[at(i + radius + 1) + c] as u32;
+                }
+                if i >= radius {
+                    sum[c] -= src[at(i - radius) + c] as u32;
+                }
+            }
+        }
+    }
+}
+
+/// Approximate gaussian blur of `rect` (already clipped)
+pub(crate) fn fast_blur_rect(data: &mut [u8], width: u32, rect: Rect, radius: u32) {
+    if radius == 0 {
+        return;
+    }
+    let (w, h) = (rect.w as usize, rect.h as usize);
+    let mut a = read_region(data, width, rect);
+    let mut b = Scratch::zeroed(a.len());
+    for r in box_radii(radius) {
+        box_pass(&a, &mut b, (w, 4), (h, w * 4), r);
+        box_pass(&b, &mut a, (h, w * 4), (w, 4), r);
+    }
+    write_region(data, width, rect, &a);
+}
+
+/// Blur a region with three box blurs: close to `gaussian_blur` with the
+/// same radius, at a cost that doesn't grow with it
+#[wasm_bindgen]
+pub fn fast_blur(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    radius: u32,
+) {
+    let rect = Rect::new(x, y, w, h);
+    let radius = or_throw(policed_size(Effect::GaussianBlur { radius }, rect));
+    if data.len() < (width * height * 4) as usize {
+        return;
+    }
+    if let Some(rect) = rect.clip(width, height) {
+        fast_blur_rect(data, width, rect, radius);
+    }
+}
+
+/// Gaussian blur of a region, exact or approximated per `quality`
+#[wasm_bindgen]
+pub fn blur(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    radius: u32,
+    quality: BlurQuality,
+) {
+    match quality {
+        BlurQuality::Exact => gaussian_blur(data, width, height, x, y, w, h, radius),
+        BlurQuality::Fast => fast_blur(data, width, height, x, y, w, h, radius),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stripes(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let v = if ((i % width) / 3).is_multiple_of(2) {
+                    30
+                } else {
+                    220
+                };
+                [v, v, v, 128]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_close_to_exact_blur() {
+        let (w, h) = (48, 32);
+        // High-contrast stripes are the worst case; the approximation
+        // improves with the radius, where the speed matters
+        for (radius, tolerance) in [(2, 24), (6, 20), (15, 6)] {
+            let mut exact = stripes(w, h);
+            let mut fast = exact.clone();
+            blur(&mut exact, w, h, 4, 4, 40, 24, radius, BlurQuality::Exact);
+            blur(&mut fast, w, h, 4, 4, 40, 24, radius, BlurQuality::Fast);
+            let worst = exact
+                .iter()
+                .zip(&fast)
+                .map(|(&a, &b)| (a as i32 - b as i32).abs())
+                .max()
+                .unwrap();
+            assert!(worst <= tolerance, "radius {}: off by {}", radius, worst);
+            // Alpha and everything outside the region are untouched
+            assert_eq!(fast[3], 128);
+            assert_eq!(&fast[..4 * 4], &stripes(w, h)[..4 * 4]);
+        }
+    }
+
+    #[test]
+    fn test_box_radii_match_the_variance() {
+        for radius in [1, 4, 10, 40] {
+            let sigma = radius as f32 / 2.0;
+            // Variance of a box of width 2r + 1 is ((2r + 1)^2 - 1) / 12
+            let variance: f32 = box_radii(radius)
+                .iter()
+                .map(|&r| ((2 * r + 1).pow(2) - 1) as f32 / 12.0)
+                .sum();
+            assert!((variance - sigma * sigma).abs() <= sigma * sigma * 0.35 + 0.5);
+        }
+
+        // Flat color stays flat
+        let mut flat = vec![77u8; 10 * 10 * 4];
+        fast_blur(&mut flat, 10, 10, 0, 0, 10, 10, 8);
+        assert!(flat.iter().all(|&v| v == 77));
+    }
+}
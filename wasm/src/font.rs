@@ -0,0 +1,505 @@
+//! Embedded 5x7 bitmap font for drawing labels into images.
+//!
+//! Covers uppercase letters, digits and the punctuation labels use
+//! (`[ ] ( ) - _ . , : # / * ?`); lowercase is drawn as uppercase and
+//! anything else as `?`. Glyphs are scaled to the target box and
+//! antialiased by 4x4 supersampling, so labels stay legible from a few
+//! pixels tall up to headline size.
+//!
+//! Labels drawn over a fill turn a redaction into a readable pseudonym
+//! ("PERSON A", "[REDACTED-3]"), which keeps a redacted document usable.
+
+use wasm_bindgen::prelude::*;
+
+use crate::effect::Effect;
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::policy::policed;
+use crate::types::{Color, Rect};
+
+/// Glyph rows, top first; bit 4 is the leftmost column
+const GLYPHS: &[(char, [u8; 7])] = &[
+    (
+        ' ',
+        [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000,
+        ],
+    ),
+    (
+        'A',
+        [
+            0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'B',
+        [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+        ],
+    ),
+    (
+        'C',
+        [
+            0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        'D',
+        [
+            0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110,
+        ],
+    ),
+    (
+        'E',
+        [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+        ],
+    ),
+    (
+        'F',
+        [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+    ),
+    (
+        'G',
+        [
+            0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+        ],
+    ),
+    (
+        'H',
+        [
+            0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'I',
+        [
+            0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+    ),
+    (
+        'J',
+        [
+            0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+        ],
+    ),
+    (
+        'K',
+        [
+            0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+        ],
+    ),
+    (
+        'L',
+        [
+            0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+        ],
+    ),
+    (
+        'M',
+        [
+            0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'N',
+        [
+            0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'O',
+        [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        'P',
+        [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+    ),
+    (
+        'Q',
+        [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+        ],
+    ),
+    (
+        'R',
+        [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+        ],
+    ),
+    (
+        'S',
+        [
+            0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+        ],
+    ),
+    (
+        'T',
+        [
+            0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+    ),
+    (
+        'U',
+        [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        'V',
+        [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+        ],
+    ),
+    (
+        'W',
+        [
+            0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+        ],
+    ),
+    (
+        'X',
+        [
+            0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+        ],
+    ),
+    (
+        'Y',
+        [
+            0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+    ),
+    (
+        'Z',
+        [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+        ],
+    ),
+    (
+        '0',
+        [
+            0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '1',
+        [
+            0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+    ),
+    (
+        '2',
+        [
+            0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+        ],
+    ),
+    (
+        '3',
+        [
+            0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '4',
+        [
+            0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+        ],
+    ),
+    (
+        '5',
+        [
+            0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '6',
+        [
+            0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '7',
+        [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+        ],
+    ),
+    (
+        '8',
+        [
+            0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+        ],
+    ),
+    (
+        '9',
+        [
+            0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+        ],
+    ),
+    (
+        '-',
+        [
+            0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000,
+        ],
+    ),
+    (
+        '_',
+        [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111,
+        ],
+    ),
+    (
+        '.',
+        [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100,
+        ],
+    ),
+    (
+        ',',
+        [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000,
+        ],
+    ),
+    (
+        ':',
+        [
+            0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000,
+        ],
+    ),
+    (
+        '[',
+        [
+            0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110,
+        ],
+    ),
+    (
+        ']',
+        [
+            0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110,
+        ],
+    ),
+    (
+        '(',
+        [
+            0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010,
+        ],
+    ),
+    (
+        ')',
+        [
+            0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000,
+        ],
+    ),
+    (
+        '#',
+        [
+            0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010,
+        ],
+    ),
+    (
+        '/',
+        [
+            0b00001, 0b00010, 0b00010, 0b00100, 0b01000, 0b01000, 0b10000,
+        ],
+    ),
+    (
+        '*',
+        [
+            0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000,
+        ],
+    ),
+    (
+        '?',
+        [
+            0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100,
+        ],
+    ),
+];
+
+const GLYPH_W: u32 = 5;
+const GLYPH_H: u32 = 7;
+/// Horizontal advance per character, including one column of spacing
+const ADVANCE: u32 = GLYPH_W + 1;
+/// Share of the box height the glyphs fill, leaving a margin above and below
+const FILL_HEIGHT: f32 = 0.7;
+const FILL_WIDTH: f32 = 0.9;
+const SUBSAMPLES: u32 = 4;
+
+fn glyph(c: char) -> &'static [u8; 7] {
+    let c = c.to_ascii_uppercase();
+    GLYPHS
+        .iter()
+        .find(|(g, _)| *g == c)
+        .or_else(|| GLYPHS.iter().find(|(g, _)| *g == '?'))
+        .map(|(_, rows)| rows)
+        .unwrap()
+}
+
+/// Width of a box `height` tall that fits `text` at full height
+pub(crate) fn text_width(text: &str, height: u32) -> u32 {
+    let cols = (text.chars().count() as u32 * ADVANCE).saturating_sub(1);
+    let scale = height as f32 * FILL_HEIGHT / GLYPH_H as f32;
+    (cols as f32 * scale / FILL_WIDTH).ceil() as u32
+}
+
+/// Draw `text` centered in `rect`, as large as fits, blending `ink` over
+/// the existing pixels by coverage
+pub(crate) fn draw_text(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+    text: &str,
+    ink: Color,
+) {
+    let glyphs: Vec<&[u8; 7]> = text.chars().map(glyph).collect();
+    let Some(clip) = rect.clip(width, height) else {
+        return;
+    };
+    if glyphs.is_empty() {
+        return;
+    }
+    let cols = glyphs.len() as u32 * ADVANCE - 1;
+    let scale = (rect.h as f32 * FILL_HEIGHT / GLYPH_H as f32)
+        .min(rect.w as f32 * FILL_WIDTH / cols as f32);
+    if scale <= 0.0 {
+        return;
+    }
+    let ox = rect.x as f32 + (rect.w as f32 - cols as f32 * scale) / 2.0;
+    let oy = rect.y as f32 + (rect.h as f32 - GLYPH_H as f32 * scale) / 2.0;
+
+    let lit = |sx: f32, sy: f32| {
+        let (u, v) = ((sx - ox) / scale, (sy - oy) / scale);
+        if u < 0.0 || v < 0.0 {
+            return false;
+        }
+        let (col, row) = (u as u32, v as u32);
+        let (index, within) = ((col / ADVANCE) as usize, col % ADVANCE);
+        within < GLYPH_W
+            && row < GLYPH_H
+            && glyphs
+                .get(index)
+                .is_some_and(|g| g[row as usize] & (1 << (GLYPH_W - 1 - within)) != 0)
+    };
+
+    let step = 1.0 / SUBSAMPLES as f32;
+    for y in clip.y..clip.bottom() {
+        for x in clip.x..clip.right() {
+            let mut hits = 0;
+            for j in 0..SUBSAMPLES {
+                for i in 0..SUBSAMPLES {
+                    let sx = x as f32 + (i as f32 + 0.5) * step;
+                    let sy = y as f32 + (j as f32 + 0.5) * step;
+                    hits += lit(sx, sy) as u32;
+                }
+            }
+            if hits == 0 {
+                continue;
+            }
+            let coverage = hits as f32 / (SUBSAMPLES * SUBSAMPLES) as f32;
+            let i = ((y * width + x) * 4) as usize;
+            for (c, value) in [ink.r, ink.g, ink.b].into_iter().enumerate() {
+                let old = data[i + c] as f32;
+                data[i + c] = (old + (value as f32 - old) * coverage).round() as u8;
+            }
+        }
+    }
+}
+
+/// Fill `rect` and draw `label` over it, sized to the covered text
+pub(crate) fn pseudonymize_rect(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+    label: &str,
+    fill: Color,
+    ink: Color,
+) -> Result<(), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    check_region(rect, width, height)?;
+    policed(Effect::SolidFill { color: fill }, rect)?.apply_rect(data, width, height, rect);
+    draw_text(data, width, height, rect, label, ink);
+    Ok(())
+}
+
+/// Replace a region with `label` in `ink` on a `fill` background, e.g. a
+/// black bar reading "PERSON A" in white
+#[wasm_bindgen]
+pub fn pseudonymize(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    label: &str,
+    fill: &Color,
+    ink: &Color,
+) -> Result<(), JsError> {
+    let rect = Rect::new(x, y, w, h);
+    Ok(pseudonymize_rect(
+        data, width, height, rect, label, *fill, *ink,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_is_centered_and_scaled_to_fit() {
+        let (w, h) = (80, 20);
+        let mut data = vec![0u8; (w * h * 4) as usize];
+        draw_text(
+            &mut data,
+            w,
+            h,
+            Rect::new(0, 0, w, h),
+            "AB",
+            Color::new(255, 255, 255),
+        );
+        let inked: Vec<(u32, u32)> = (0..w * h)
+            .filter(|i| data[(*i * 4) as usize] > 127)
+            .map(|i| (i % w, i / w))
+            .collect();
+        let (min_x, max_x) = inked
+            .iter()
+            .fold((w, 0), |(a, b), p| (a.min(p.0), b.max(p.0)));
+        let (min_y, max_y) = inked
+            .iter()
+            .fold((h, 0), |(a, b), p| (a.min(p.1), b.max(p.1)));
+        // 14 px tall (70% of 20), 2 px per font row, two glyphs 22 px wide
+        assert_eq!((min_y, max_y), (3, 16));
+        assert_eq!((min_x, max_x), (29, 50));
+    }
+
+    #[test]
+    fn test_pseudonym_hides_the_original() {
+        let (w, h) = (40, 12);
+        let mut data: Vec<u8> = (0..w * h * 4).map(|i| (i * 37 % 251) as u8).collect();
+        let rect = Rect::new(4, 2, 32, 8);
+        let white = Color::new(255, 255, 255);
+        pseudonymize_rect(&mut data, w, h, rect, "P A", Color::new(0, 0, 0), white).unwrap();
+        for y in 2..10 {
+            for x in 4..36 {
+                let px = &data[((y * w + x) * 4) as usize..][..3];
+                assert!(px[0] == px[1] && px[1] == px[2], "({}, {}) {:?}", x, y, px);
+            }
+        }
+        assert!(
+            pseudonymize_rect(&mut data, w, h, Rect::new(50, 0, 4, 4), "X", white, white).is_err()
+        );
+    }
+
+    #[test]
+    fn test_unknown_characters_fall_back() {
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph('é'), glyph('?'));
+    }
+}
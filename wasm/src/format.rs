@@ -0,0 +1,336 @@
+//! Effects on buffers that aren't tightly packed RGBA.
+//!
+//! Native canvases hand over BGRA, video decoders RGB, scanners grayscale,
+//! and most of them pad rows to an alignment. `apply_effect_formatted`
+//! takes such a buffer as-is, with its `PixelFormat` and row stride in
+//! bytes: only the region is unpacked into RGBA scratch, the effect runs
+//! there, and the result is packed back in place. Every effect works on
+//! its region alone, so the output is what the RGBA path would produce.
+//!
+//! For RGBA buffers with padded rows, as GPU readbacks usually are, the
+//! `_strided` variants of the core effects take just the row pitch.
+//!
+//! Formats without alpha read as opaque and drop whatever alpha the effect
+//! wrote. `Gray8` stores the luma of the result, as `verify` measures it,
+//! so a colored fill lands as its gray equivalent.
+
+use wasm_bindgen::prelude::*;
+
+use crate::effect::Effect;
+use crate::error::{check_region, RedactError};
+use crate::policy::policed;
+use crate::scratch::Scratch;
+use crate::types::{Color, Rect};
+use crate::verify::luma;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgba8 = 0,
+    /// Byte order of Windows bitmaps, Core Graphics and most video surfaces
+    Bgra8 = 1,
+    Rgb8 = 2,
+    Gray8 = 3,
+}
+
+impl PixelFormat {
+    pub(crate) fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgba8 | PixelFormat::Bgra8 => 4,
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Gray8 => 1,
+        }
+    }
+
+    fn unpack(self, px: &[u8]) -> [u8; 4] {
+        match self {
+            PixelFormat::Rgba8 => [px[0], px[1], px[2], px[3]],
+            PixelFormat::Bgra8 => [px[2], px[1], px[0], px[3]],
+            PixelFormat::Rgb8 => [px[0], px[1], px[2], 255],
+            PixelFormat::Gray8 => [px[0], px[0], px[0], 255],
+        }
+    }
+
+    fn pack(self, rgba: &[u8], px: &mut [u8]) {
+        match self {
+            PixelFormat::Rgba8 => px.copy_from_slice(rgba),
+            PixelFormat::Bgra8 => px.copy_from_slice(&[rgba[2], rgba[1], rgba[0], rgba[3]]),
+            PixelFormat::Rgb8 => px.copy_from_slice(&rgba[..3]),
+            PixelFormat::Gray8 => px[0] = luma(rgba).round() as u8,
+        }
+    }
+}
+
+/// Check that `stride` fits a row of `width` pixels and `len` bytes hold
+/// `height` rows; the last row needn't carry its padding
+fn check_layout(
+    len: usize,
+    width: u32,
+    height: u32,
+    stride: u32,
+    format: PixelFormat,
+) -> Result<(), RedactError> {
+    let row = width as usize * format.bytes_per_pixel();
+    if (stride as usize) < row {
+        return Err(RedactError::InvalidParameter {
+            name: "stride",
+            value: stride as f64,
+            expected: format!("at least {} bytes for a {} pixel row", row, width),
+        });
+    }
+    let needed = match height {
+        0 => 0,
+        h => stride as usize * (h as usize - 1) + row,
+    };
+    if len < needed {
+        return Err(RedactError::InvalidParameter {
+            name: "data length",
+            value: len as f64,
+            expected: format!("at least {} bytes for {} rows", needed, height),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn apply_formatted(
+    effect: &Effect,
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    format: PixelFormat,
+    rect: Rect,
+) -> Result<(), RedactError> {
+    check_layout(data.len(), width, height, stride, format)?;
+    check_region(rect, width, height)?;
+    effect.validate()?;
+    let effect = policed(*effect, rect)?;
+    let Some(rect) = rect.clip(width, height) else {
+        return Ok(());
+    };
+    let bpp = format.bytes_per_pixel();
+    let offset = |x: u32, y: u32| y as usize * stride as usize + x as usize * bpp;
+
+    // Grid-aligned pixelation reads whole cells, which may reach past `rect`
+    let outer = effect.footprint(rect, width, height);
+    let mut region = Scratch::from(Vec::with_capacity((outer.w * outer.h * 4) as usize));
+    for y in outer.y..outer.bottom() {
+        for x in outer.x..outer.right() {
+            let i = offset(x, y);
+            region.extend_from_slice(&format.unpack(&data[i..i + bpp]));
+        }
+    }
+    let local = Rect::new(rect.x - outer.x, rect.y - outer.y, rect.w, rect.h);
+    effect.apply_rect_at(&mut region, outer.w, outer.h, local, (outer.x, outer.y));
+    for (n, rgba) in region.chunks_exact(4).enumerate() {
+        let (x, y) = (outer.x + n as u32 % outer.w, outer.y + n as u32 / outer.w);
+        if rect.contains(&Rect::new(x, y, 1, 1)) {
+            let i = offset(x, y);
+            format.pack(rgba, &mut data[i..i + bpp]);
+        }
+    }
+    Ok(())
+}
+
+/// Apply an effect, given as `{"effect": "pixelate", "params": {...}}`, to
+/// a region of a buffer in `format` with rows `stride` bytes apart
+#[wasm_bindgen]
+pub fn apply_effect_formatted(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    format: PixelFormat,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    effect_json: &str,
+) -> Result<(), JsError> {
+    let effect = Effect::from_spec(effect_json)?;
+    let rect = Rect::new(x, y, w, h);
+    Ok(apply_formatted(
+        &effect, data, width, height, stride, format, rect,
+    )?)
+}
+
+fn apply_strided(
+    effect: Effect,
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    rect: Rect,
+) -> Result<(), JsError> {
+    Ok(apply_formatted(
+        &effect,
+        data,
+        width,
+        height,
+        stride,
+        PixelFormat::Rgba8,
+        rect,
+    )?)
+}
+
+/// `solid_fill` on RGBA rows `stride` bytes apart
+#[wasm_bindgen]
+pub fn solid_fill_strided(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+) -> Result<(), JsError> {
+    let effect = Effect::SolidFill {
+        color: Color::new(r, g, b),
+    };
+    apply_strided(effect, data, width, height, stride, Rect::new(x, y, w, h))
+}
+
+/// `pixelate` on RGBA rows `stride` bytes apart
+#[wasm_bindgen]
+pub fn pixelate_strided(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    block_size: u32,
+) -> Result<(), JsError> {
+    let effect = Effect::pixelate(block_size);
+    apply_strided(effect, data, width, height, stride, Rect::new(x, y, w, h))
+}
+
+/// `gaussian_blur` on RGBA rows `stride` bytes apart
+#[wasm_bindgen]
+pub fn gaussian_blur_strided(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    radius: u32,
+) -> Result<(), JsError> {
+    let effect = Effect::GaussianBlur { radius };
+    apply_strided(effect, data, width, height, stride, Rect::new(x, y, w, h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| [(i * 29 % 256) as u8, (i * 7 % 256) as u8, 180, 255])
+            .collect()
+    }
+
+    /// `data` repacked as `format` with `pad` bytes after every row
+    fn repack(data: &[u8], width: u32, format: PixelFormat, pad: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for row in data.chunks_exact(width as usize * 4) {
+            for px in row.chunks_exact(4) {
+                let mut packed = [0; 4];
+                format.pack(px, &mut packed[..format.bytes_per_pixel()]);
+                out.extend_from_slice(&packed[..format.bytes_per_pixel()]);
+            }
+            out.extend(std::iter::repeat_n(0xEE, pad));
+        }
+        out
+    }
+
+    #[test]
+    fn test_formats_match_the_rgba_path() {
+        let (w, h) = (12, 9);
+        let rect = Rect::new(2, 1, 8, 7);
+        for effect in [Effect::pixelate(3), Effect::GaussianBlur { radius: 2 }] {
+            let mut expected = rgba(w, h);
+            effect.apply_rect(&mut expected, w, h, rect);
+            for format in [PixelFormat::Rgba8, PixelFormat::Bgra8, PixelFormat::Rgb8] {
+                let pad = 5;
+                let stride = w * format.bytes_per_pixel() as u32 + pad as u32;
+                let mut data = repack(&rgba(w, h), w, format, pad);
+                apply_formatted(&effect, &mut data, w, h, stride, format, rect).unwrap();
+                assert_eq!(data, repack(&expected, w, format, pad), "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn test_strided_rows_stay_aligned() {
+        // 10 pixels of RGBA padded to a 64-byte pitch
+        let (w, h, stride) = (10, 6, 64);
+        let mut data = repack(&rgba(w, h), w, PixelFormat::Rgba8, 24);
+        pixelate_strided(&mut data, w, h, stride, 1, 1, 8, 4, 4).unwrap();
+        let mut expected = rgba(w, h);
+        crate::pixelate(&mut expected, w, h, 1, 1, 8, 4, 4);
+        assert_eq!(data, repack(&expected, w, PixelFormat::Rgba8, 24));
+
+        solid_fill_strided(&mut data, w, h, stride, 0, 5, 10, 1, 1, 2, 3).unwrap();
+        assert_eq!(data[5 * 64..5 * 64 + 4], [1, 2, 3, 255]);
+        assert_eq!(data[5 * 64 + 36..5 * 64 + 40], [1, 2, 3, 255]);
+        assert!(data[5 * 64 + 40..].iter().all(|&b| b == 0xEE));
+    }
+
+    #[test]
+    fn test_gray_fill_and_padding_untouched() {
+        let (w, h) = (6, 4);
+        let mut data: Vec<u8> = (0..8 * h as usize).map(|i| i as u8).collect();
+        let fill =
+            Effect::from_spec(r##"{"effect": "solid_fill", "params": {"color": "#ff0000"}}"##)
+                .unwrap();
+        apply_formatted(
+            &fill,
+            &mut data,
+            w,
+            h,
+            8,
+            PixelFormat::Gray8,
+            Rect::new(0, 0, 6, 4),
+        )
+        .unwrap();
+        for row in data.chunks_exact(8) {
+            assert_eq!(row[..6], [76; 6]);
+        }
+        assert_eq!(data[6..8], [6, 7]);
+        assert_eq!(data[30..32], [30, 31]);
+
+        let err = apply_formatted(
+            &fill,
+            &mut data,
+            w,
+            h,
+            5,
+            PixelFormat::Gray8,
+            Rect::new(0, 0, 1, 1),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid stride 5: expected at least 6 bytes for a 6 pixel row"
+        );
+        assert!(apply_formatted(
+            &fill,
+            &mut data[..28],
+            w,
+            h,
+            8,
+            PixelFormat::Gray8,
+            Rect::new(0, 0, 1, 1)
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,309 @@
+//! Frame bookkeeping for redacting animated GIFs.
+//!
+//! GIF frames are usually partial: each one patches a sub-rectangle of the
+//! canvas left by the previous frames, according to their disposal methods.
+//! Redacting the patches alone leaks whatever earlier frames left under a
+//! region. Instead, the host decodes each patch into `composite`, redacts
+//! the full composited frame, and hands it to `difference`, which yields the
+//! patch to encode against the previously emitted (redacted) frame.
+//!
+//! Decoding and LZW encoding stay on the host; this module only tracks the
+//! canvas state on both sides.
+
+use wasm_bindgen::prelude::*;
+
+use crate::buffer::{read_region, write_region};
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::scratch::Scratch;
+use crate::types::Rect;
+
+/// GIF disposal methods, as stored in the graphic control extension
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposal {
+    /// Unspecified; treated like `Keep`
+    None = 0,
+    Keep = 1,
+    /// Clear the frame's rectangle to transparent
+    Background = 2,
+    /// Restore the rectangle to what was there before the frame
+    Previous = 3,
+}
+
+/// A patch to encode, relative to the previously emitted frame
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct GifFrame {
+    rect: Rect,
+    pixels: Vec<u8>,
+    replace: bool,
+}
+
+#[wasm_bindgen]
+impl GifFrame {
+    #[wasm_bindgen(getter)]
+    pub fn x(&self) -> u32 {
+        self.rect.x
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn y(&self) -> u32 {
+        self.rect.y
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.rect.w
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.rect.h
+    }
+
+    /// Whether the patch is a full canvas that must not be drawn over the
+    /// previous frame (encode the previous frame with `Disposal.Background`).
+    /// Needed when pixels turn transparent, which a kept frame can't express.
+    #[wasm_bindgen(getter)]
+    pub fn replace(&self) -> bool {
+        self.replace
+    }
+
+    /// RGBA patch; unchanged pixels are fully transparent
+    pub fn pixels(&self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+}
+
+/// Composited canvas state of the source and of the redacted output
+#[wasm_bindgen]
+pub struct GifCoalescer {
+    width: u32,
+    height: u32,
+    canvas: Vec<u8>,
+    /// Disposal of the last composited frame, applied before the next one
+    pending: Option<(Disposal, Rect, Option<Scratch>)>,
+    /// Last frame passed to `difference`
+    output: Option<Vec<u8>>,
+}
+
+impl GifCoalescer {
+    pub(crate) fn composite_patch(
+        &mut self,
+        patch: &[u8],
+        rect: Rect,
+        disposal: Disposal,
+    ) -> Result<&[u8], RedactError> {
+        check_buffer(patch.len(), rect.w, rect.h)?;
+        check_region(rect, self.width, self.height)?;
+        // GIF frames must lie within the logical screen
+        if rect.right() > self.width || rect.bottom() > self.height {
+            return Err(RedactError::RegionOutOfBounds {
+                region: rect,
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        match self.pending.take() {
+            Some((Disposal::Background, r, _)) => write_region(
+                &mut self.canvas,
+                self.width,
+                r,
+                &vec![0; (r.w * r.h * 4) as usize],
+            ),
+            Some((Disposal::Previous, r, Some(saved))) => {
+                write_region(&mut self.canvas, self.width, r, &saved)
+            }
+            _ => {}
+        }
+        let saved =
+            (disposal == Disposal::Previous).then(|| read_region(&self.canvas, self.width, rect));
+        self.pending = Some((disposal, rect, saved));
+
+        // GIF transparency is binary: transparent patch pixels show the canvas
+        for row in 0..rect.h {
+            for col in 0..rect.w {
+                let src = ((row * rect.w + col) * 4) as usize;
+                if patch[src + 3] == 0 {
+                    continue;
+                }
+                let dst = (((rect.y + row) * self.width + rect.x + col) * 4) as usize;
+                self.canvas[dst..dst + 4].copy_from_slice(&patch[src..src + 4]);
+            }
+        }
+        Ok(&self.canvas)
+    }
+
+    pub(crate) fn difference_frame(&mut self, redacted: &[u8]) -> Result<GifFrame, RedactError> {
+        check_buffer(redacted.len(), self.width, self.height)?;
+        let full = Rect::new(0, 0, self.width, self.height);
+        let Some(previous) = self.output.replace(redacted.to_vec()) else {
+            return Ok(GifFrame {
+                rect: full,
+                pixels: redacted.to_vec(),
+                replace: true,
+            });
+        };
+
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        let mut cleared = false;
+        for (i, (a, b)) in previous
+            .chunks_exact(4)
+            .zip(redacted.chunks_exact(4))
+            .enumerate()
+        {
+            if a == b {
+                continue;
+            }
+            cleared |= b[3] == 0;
+            let (x, y) = (i as u32 % self.width, i as u32 / self.width);
+            let r = bounds.get_or_insert((x, y, x, y));
+            *r = (r.0.min(x), r.1.min(y), r.2.max(x), r.3.max(y));
+        }
+        if cleared {
+            return Ok(GifFrame {
+                rect: full,
+                pixels: redacted.to_vec(),
+                replace: true,
+            });
+        }
+        // GIF frames can't be empty; an unchanged frame is a 1x1 no-op
+        let (x0, y0, x1, y1) = bounds.unwrap_or((0, 0, 0, 0));
+        let rect = Rect::new(x0, y0, x1 - x0 + 1, y1 - y0 + 1);
+        let mut pixels = read_region(redacted, self.width, rect).to_vec();
+        let before = read_region(&previous, self.width, rect);
+        for (out, old) in pixels.chunks_exact_mut(4).zip(before.chunks_exact(4)) {
+            if out == old {
+                out.fill(0);
+            }
+        }
+        Ok(GifFrame {
+            rect,
+            pixels,
+            replace: false,
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl GifCoalescer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: u32, height: u32) -> GifCoalescer {
+        GifCoalescer {
+            width,
+            height,
+            canvas: vec![0; (width * height * 4) as usize],
+            pending: None,
+            output: None,
+        }
+    }
+
+    /// Draw the next decoded frame patch (`w * h` RGBA at `x, y`) onto the
+    /// canvas, after applying the previous frame's disposal. Returns the
+    /// full composited frame to redact.
+    pub fn composite(
+        &mut self,
+        patch: &[u8],
+        x: u32,
+        y: u32,
+        w: u32,
+        h: u32,
+        disposal: Disposal,
+    ) -> Result<Vec<u8>, JsError> {
+        Ok(self
+            .composite_patch(patch, Rect::new(x, y, w, h), disposal)?
+            .to_vec())
+    }
+
+    /// Patch that turns the previously emitted frame into `redacted`, to be
+    /// encoded with `Disposal.Keep`
+    pub fn difference(&mut self, redacted: &[u8]) -> Result<GifFrame, JsError> {
+        Ok(self.difference_frame(redacted)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solid_fill;
+
+    /// Draw an encoded patch over `canvas` the way a GIF decoder would
+    fn draw(canvas: &mut [u8], width: u32, frame: &GifFrame) {
+        if frame.replace {
+            canvas.fill(0);
+        }
+        for (i, px) in frame.pixels.chunks_exact(4).enumerate() {
+            let (x, y) = (
+                frame.rect.x + i as u32 % frame.rect.w,
+                frame.rect.y + i as u32 / frame.rect.w,
+            );
+            if px[3] != 0 {
+                let dst = ((y * width + x) * 4) as usize;
+                canvas[dst..dst + 4].copy_from_slice(px);
+            }
+        }
+    }
+
+    #[test]
+    fn test_partial_frames_never_reveal_the_region() {
+        let mut gif = GifCoalescer::new(8, 8);
+        let region = Rect::new(0, 0, 4, 4);
+        let mut decoded = vec![0u8; 8 * 8 * 4];
+
+        let first: Vec<u8> = (0..8 * 8)
+            .flat_map(|i| [i as u8 * 3, 50, 90, 255])
+            .collect();
+        // The second frame only touches the bottom-right corner
+        let second = vec![200u8; 4 * 4 * 4];
+        let patches = [
+            (first, Rect::new(0, 0, 8, 8)),
+            (second, Rect::new(4, 4, 4, 4)),
+        ];
+        for (i, (patch, rect)) in patches.iter().enumerate() {
+            let mut frame = gif
+                .composite_patch(patch, *rect, Disposal::Keep)
+                .unwrap()
+                .to_vec();
+            solid_fill(
+                &mut frame, 8, 8, region.x, region.y, region.w, region.h, 0, 0, 0,
+            );
+            let out = gif.difference_frame(&frame).unwrap();
+            assert_eq!(out.replace, i == 0);
+            if i == 1 {
+                assert_eq!(out.rect, Rect::new(4, 4, 4, 4));
+            }
+            draw(&mut decoded, 8, &out);
+            assert_eq!(decoded, frame);
+        }
+    }
+
+    #[test]
+    fn test_disposal_methods() {
+        let mut gif = GifCoalescer::new(4, 4);
+        let red = [255, 0, 0, 255].repeat(16);
+        let green = [0, 255, 0, 255].repeat(4);
+        gif.composite_patch(&red, Rect::new(0, 0, 4, 4), Disposal::Keep)
+            .unwrap();
+        gif.composite_patch(&green, Rect::new(0, 0, 2, 2), Disposal::Previous)
+            .unwrap();
+        let frame = gif
+            .composite_patch(&green, Rect::new(2, 2, 2, 2), Disposal::Background)
+            .unwrap();
+        // The first green patch was restored to red
+        assert_eq!(frame[..4], [255, 0, 0, 255]);
+        assert_eq!(frame[(10 * 4)..(10 * 4 + 4)], [0, 255, 0, 255]);
+        let frame = gif
+            .composite_patch(&[0; 4], Rect::new(0, 0, 1, 1), Disposal::Keep)
+            .unwrap()
+            .to_vec();
+        assert_eq!(frame[(10 * 4)..(10 * 4 + 4)], [0, 0, 0, 0]);
+
+        // Transparency appearing forces a replacing frame
+        gif.difference_frame(&red).unwrap();
+        assert!(gif.difference_frame(&frame).unwrap().replace);
+        assert!(gif
+            .composite_patch(&green, Rect::new(3, 3, 2, 2), Disposal::Keep)
+            .is_err());
+    }
+}
@@ -0,0 +1,353 @@
+//! Pixelation hardened against depixelation attacks.
+//!
+//! Plain block averages of a known font can be matched back to text
+//! (Depix-style). The hardened mode perturbs each block average with seeded
+//! jitter, snaps it to a coarse set of levels, and refuses block sizes
+//! smaller than the estimated glyph height, so averages neither match a
+//! rendered candidate exactly nor resolve individual strokes.
+
+use wasm_bindgen::prelude::*;
+
+use crate::effect::Effect;
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::policy::{or_throw, policed_size};
+use crate::rng::SplitMix64;
+use crate::types::Rect;
+use crate::verify::luma;
+
+/// Spacing of the quantized levels per channel
+pub const QUANT_STEP: u32 = 24;
+/// Amplitude of the seeded jitter added before quantizing
+pub const JITTER: u32 = 12;
+/// Minimum block size as a multiple of the glyph height
+pub const MIN_BLOCK_TO_GLYPH: f32 = 1.0;
+
+/// Luma step between neighbours that counts as a stroke edge
+const EDGE_THRESHOLD: f64 = 48.0;
+
+/// Estimate the height of text glyphs in `rect` from its row profile: rows
+/// crossed by strokes have many strong horizontal edges, and runs of such
+/// rows are text lines. Returns the median run length, or 0 if no text-like
+/// rows are found.
+pub(crate) fn estimate_glyph_height(data: &[u8], width: u32, rect: Rect) -> u32 {
+    let min_edges = (rect.w / 50).max(2);
+    let mut runs = Vec::new();
+    let mut run = 0;
+    for y in rect.y..rect.bottom() {
+        let mut edges = 0;
+        let mut prev = None;
+        for x in rect.x..rect.right() {
+            let idx = ((y * width + x) * 4) as usize;
+            let l = luma(&data[idx..idx + 4]);
+            if prev.is_some_and(|p: f64| (l - p).abs() > EDGE_THRESHOLD) {
+                edges += 1;
+            }
+            prev = Some(l);
+        }
+        if edges >= min_edges {
+            run += 1;
+        } else if run > 0 {
+            runs.push(run);
+            run = 0;
+        }
+    }
+    if run > 0 {
+        runs.push(run);
+    }
+    runs.sort_unstable();
+    runs.get(runs.len() / 2).copied().unwrap_or(0)
+}
+
+/// Block size actually used for a requested size and glyph height
+pub(crate) fn hardened_block_size(block_size: u32, glyph_height: u32) -> u32 {
+    let min = (glyph_height as f32 * MIN_BLOCK_TO_GLYPH).ceil() as u32;
+    block_size.max(min).max(1)
+}
+
+/// Fail if `block_size` is small enough relative to the text in `rect` for
+/// OCR or depixelation to plausibly recover it. A `glyph_height` of 0 means
+/// estimate it; regions without detectable text always pass.
+pub fn check_block_size(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+    block_size: u32,
+    glyph_height: u32,
+) -> Result<(), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    check_region(rect, width, height)?;
+    let glyph_height = match glyph_height {
+        0 => estimate_glyph_height(data, width, rect.clip(width, height).unwrap_or(rect)),
+        h => h,
+    };
+    if glyph_height == 0 {
+        return Ok(());
+    }
+    let minimum = hardened_block_size(1, glyph_height);
+    if block_size < minimum {
+        return Err(RedactError::BlockTooSmall {
+            block_size,
+            glyph_height,
+            minimum,
+        });
+    }
+    Ok(())
+}
+
+fn harden_channel(avg: u32, rng: &mut SplitMix64) -> u8 {
+    let jittered = (avg as i32 + rng.jitter(JITTER)).clamp(0, 255) as f32;
+    let level = (jittered / QUANT_STEP as f32).round() as u32 * QUANT_STEP;
+    level.min(255) as u8
+}
+
+/// Hardened pixelation of `rect`; `glyph_height` of 0 means estimate it.
+/// Returns the block size used, or the requested one when nothing was
+/// written (a buffer too short for the image, or a region outside it).
+pub(crate) fn harden_rect(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+    block_size: u32,
+    seed: u32,
+    glyph_height: u32,
+) -> u32 {
+    if check_buffer(data.len(), width, height).is_err() {
+        return block_size.max(1);
+    }
+    let Some(rect) = rect.clip(width, height) else {
+        return block_size.max(1);
+    };
+    let glyph_height = match glyph_height {
+        0 => estimate_glyph_height(data, width, rect),
+        h => h,
+    };
+    let block_size = hardened_block_size(block_size, glyph_height);
+
+    let mut by = rect.y;
+    while by < rect.bottom() {
+        let mut bx = rect.x;
+        while bx < rect.right() {
+            let block_w = block_size.min(rect.right() - bx);
+            let block_h = block_size.min(rect.bottom() - by);
+
+            let mut sum = [0u32; 3];
+            for py in by..by + block_h {
+                for px in bx..bx + block_w {
+                    let idx = ((py * width + px) * 4) as usize;
+                    for c in 0..3 {
+                        sum[c] += data[idx + c] as u32;
+                    }
+                }
+            }
+
+            // Keyed by block position so the output is independent of traversal
+            let mut rng = SplitMix64::at(seed as u64, bx, by);
+            let count = block_w * block_h;
+            let color = sum.map(|s| harden_channel(s / count, &mut rng));
+
+            for py in by..by + block_h {
+                for px in bx..bx + block_w {
+                    let idx = ((py * width + px) * 4) as usize;
+                    data[idx..idx + 3].copy_from_slice(&color);
+                }
+            }
+
+            bx += block_size;
+        }
+        by += block_size;
+    }
+    block_size
+}
+
+/// Pixelate with quantized, seeded-jitter block colors. The block size is
+/// raised to at least the glyph height (pass 0 to estimate it from the
+/// region). Returns the block size actually used.
+#[wasm_bindgen]
+pub fn hardened_pixelate(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    block_size: u32,
+    seed: u32,
+    glyph_height: u32,
+) -> u32 {
+    let rect = Rect::new(x, y, w, h);
+    let effect = Effect::HardenedPixelate {
+        block_size,
+        seed,
+        glyph_height,
+    };
+    let block_size = or_throw(policed_size(effect, rect));
+    harden_rect(data, width, height, rect, block_size, seed, glyph_height)
+}
+
+/// `hardened_pixelate` with the glyph height estimated from the region:
+/// the drop-in replacement for `pixelate` wherever the region may hold
+/// text. Returns the block size actually used; a buffer too short for the
+/// image is left as it is.
+#[wasm_bindgen]
+pub fn secure_pixelate(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    block_size: u32,
+    seed: u32,
+) -> u32 {
+    if check_buffer(data.len(), width, height).is_err() {
+        return block_size.max(1);
+    }
+    hardened_pixelate(data, width, height, x, y, w, h, block_size, seed, 0)
+}
+
+/// Error if pixelating `x, y, w, h` with `block_size` would leave text
+/// recoverable. Pass the detected text height as `glyph_height`, or 0 to
+/// estimate it from the pixels.
+#[wasm_bindgen]
+pub fn check_pixelation_block_size(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    block_size: u32,
+    glyph_height: u32,
+) -> Result<(), JsError> {
+    let rect = Rect::new(x, y, w, h);
+    Ok(check_block_size(
+        data,
+        width,
+        height,
+        rect,
+        block_size,
+        glyph_height,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixelate;
+
+    /// White page with black "text" lines `line` pixels tall
+    fn text_page(width: u32, height: u32, line: u32) -> Vec<u8> {
+        let mut data = vec![255u8; (width * height * 4) as usize];
+        for y in 0..height {
+            let in_line = (y / line) % 2 == 1;
+            for x in 0..width {
+                if in_line && (x / 2) % 2 == 0 {
+                    let idx = ((y * width + x) * 4) as usize;
+                    data[idx..idx + 3].copy_from_slice(&[0, 0, 0]);
+                }
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_estimates_glyph_height() {
+        let data = text_page(64, 64, 6);
+        assert_eq!(estimate_glyph_height(&data, 64, Rect::new(0, 0, 64, 64)), 6);
+
+        let blank = vec![255u8; 16 * 16 * 4];
+        assert_eq!(
+            estimate_glyph_height(&blank, 16, Rect::new(0, 0, 16, 16)),
+            0
+        );
+    }
+
+    #[test]
+    fn test_block_size_raised_to_glyph_height() {
+        let mut data = text_page(64, 64, 10);
+        let used = hardened_pixelate(&mut data, 64, 64, 0, 0, 64, 64, 4, 1, 0);
+        assert_eq!(used, 10);
+
+        let mut data = text_page(64, 64, 10);
+        assert_eq!(
+            hardened_pixelate(&mut data, 64, 64, 0, 0, 64, 64, 16, 1, 0),
+            16
+        );
+    }
+
+    #[test]
+    fn test_small_blocks_over_text_are_rejected() {
+        let data = text_page(64, 64, 10);
+        let rect = Rect::new(0, 0, 64, 64);
+        assert_eq!(
+            check_block_size(&data, 64, 64, rect, 4, 0)
+                .unwrap_err()
+                .to_string(),
+            "block size 4 is too small for 10px text: use at least 10"
+        );
+        assert!(check_block_size(&data, 64, 64, rect, 12, 0).is_ok());
+        // A detector-supplied height overrides the estimate
+        assert!(check_block_size(&data, 64, 64, rect, 12, 20).is_err());
+
+        let blank = vec![255u8; 16 * 16 * 4];
+        assert!(check_block_size(&blank, 16, 16, Rect::new(0, 0, 16, 16), 2, 0).is_ok());
+    }
+
+    #[test]
+    fn test_short_buffer_is_left_alone() {
+        let mut data = vec![7u8; 8];
+        assert_eq!(hardened_pixelate(&mut data, 4, 4, 0, 0, 4, 4, 2, 1, 0), 2);
+        assert_eq!(data, [7; 8]);
+        assert_eq!(secure_pixelate(&mut data, 4, 4, 0, 0, 4, 4, 3, 1), 3);
+        assert_eq!(data, [7; 8]);
+        Effect::HardenedPixelate {
+            block_size: 2,
+            seed: 1,
+            glyph_height: 0,
+        }
+        .apply_rect(&mut data, 4, 4, Rect::new(0, 0, 4, 4));
+        assert_eq!(data, [7; 8]);
+    }
+
+    #[test]
+    fn test_colors_are_quantized_and_differ_from_averages() {
+        let original: Vec<u8> = (0..32 * 32 * 4).map(|i| (i * 37 % 256) as u8).collect();
+        let mut plain = original.clone();
+        pixelate(&mut plain, 32, 32, 0, 0, 32, 32, 8);
+        let mut data = original.clone();
+        hardened_pixelate(&mut data, 32, 32, 0, 0, 32, 32, 8, 99, 1);
+
+        assert!(data.chunks(4).all(|px| px[..3]
+            .iter()
+            .all(|&c| (c as u32).is_multiple_of(QUANT_STEP) || c == 255)));
+        assert_ne!(data, plain);
+        // Alpha untouched
+        assert!(data
+            .chunks(4)
+            .zip(original.chunks(4))
+            .all(|(a, b)| a[3] == b[3]));
+    }
+
+    #[test]
+    fn test_seed_is_deterministic() {
+        let original: Vec<u8> = (0..16 * 16 * 4).map(|i| (i * 5 % 256) as u8).collect();
+        let run = |seed| {
+            let mut data = original.clone();
+            hardened_pixelate(&mut data, 16, 16, 0, 0, 16, 16, 4, seed, 1);
+            data
+        };
+        assert_eq!(run(3), run(3));
+        assert_ne!(run(3), run(4));
+
+        let mut secure = text_page(64, 64, 10);
+        assert_eq!(secure_pixelate(&mut secure, 64, 64, 0, 0, 64, 64, 4, 3), 10);
+        let mut hardened = text_page(64, 64, 10);
+        hardened_pixelate(&mut hardened, 64, 64, 0, 0, 64, 64, 4, 3, 0);
+        assert_eq!(secure, hardened);
+    }
+}
@@ -0,0 +1,435 @@
+use wasm_bindgen::prelude::*;
+
+use crate::audit::{op_params, AuditLog};
+use crate::blend::apply_blended;
+use crate::buffer::{read_region, write_region};
+use crate::classify::PiiClass;
+use crate::crop::downscale;
+use crate::effect::Effect;
+use crate::error::{check_buffer, RedactError};
+use crate::font::draw_text;
+use crate::hash::sha256;
+use crate::json::Json;
+use crate::pipeline::Op;
+use crate::policy::{enforce_active, policed};
+use crate::presets::resolve_preset;
+use crate::scratch::Scratch;
+use crate::stack::{apply_stack, EffectStack};
+use crate::types::{Color, Rect};
+use crate::{policed_brush_gaussian_blur, policed_brush_pixelate, policed_brush_solid_fill};
+
+/// RGBA image owned by the wasm side.
+///
+/// Holds its dimensions alongside the pixels so effects can be applied
+/// without re-passing width/height, and a mismatched buffer is rejected
+/// once at construction.
+///
+/// The most recent rect operation keeps a copy of the pixels it replaced,
+/// so its parameters can be changed with the `rerender_*` methods (e.g.
+/// while dragging a radius slider) without the host restoring anything.
+///
+/// With `enable_audit`, every operation (including re-renders) is recorded
+/// in an `AuditLog` with the pixel hashes before and after it.
+///
+/// Operations throw if the active policy rejects them, leaving the image
+/// as it was; with `auto_upgrade` the upgraded effect is what runs and
+/// what a re-render starts from.
+#[wasm_bindgen]
+pub struct RedactrImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    last: Option<LastOp>,
+    audit: Option<AuditLog>,
+}
+
+/// The last rect operation and the clipped region it overwrote
+struct LastOp {
+    op: Op,
+    rect: Rect,
+    before: Scratch,
+}
+
+impl RedactrImage {
+    pub(crate) fn from_rgba(width: u32, height: u32, pixels: Vec<u8>) -> Result<Self, RedactError> {
+        check_buffer(pixels.len(), width, height)?;
+        Ok(Self {
+            width,
+            height,
+            pixels,
+            last: None,
+            audit: None,
+        })
+    }
+
+    /// Apply one rect operation, caching the pixels it replaces
+    pub(crate) fn apply_op(&mut self, mut op: Op) -> Result<(), RedactError> {
+        self.last = None;
+        enforce_active(&mut op)?;
+        let Some(rect) = op.region.clip(self.width, self.height) else {
+            return Ok(());
+        };
+        let before = read_region(&self.pixels, self.width, rect);
+        self.run(&op)?;
+        self.last = Some(LastOp { op, rect, before });
+        Ok(())
+    }
+
+    fn run(&mut self, op: &Op) -> Result<(), RedactError> {
+        self.record(
+            op.effect.name(),
+            || op_params(op),
+            Some(op.region),
+            op.class.clone(),
+            |data, w, h| apply_blended(&op.effect, data, w, h, op.region, op.channels, op.strength),
+        )
+    }
+
+    /// Run `apply` over the pixels, logging it when auditing is enabled
+    fn record(
+        &mut self,
+        operation: &str,
+        params: impl FnOnce() -> Json,
+        region: Option<Rect>,
+        class: Option<PiiClass>,
+        apply: impl FnOnce(&mut [u8], u32, u32) -> Result<(), RedactError>,
+    ) -> Result<(), RedactError> {
+        let input_hash = self.audit.as_ref().map(|_| sha256(&self.pixels));
+        apply(&mut self.pixels, self.width, self.height)?;
+        if let (Some(log), Some(input_hash)) = (self.audit.as_mut(), input_hash) {
+            log.push(
+                operation,
+                params(),
+                region,
+                class,
+                input_hash,
+                sha256(&self.pixels),
+            );
+        }
+        Ok(())
+    }
+
+    /// Restore the cached region and re-run the last operation after
+    /// `edit`. A rejected edit leaves the last operation in place.
+    fn rerender(&mut self, edit: impl FnOnce(&mut Op)) -> Result<bool, RedactError> {
+        let Some(last) = &self.last else {
+            return Ok(false);
+        };
+        let mut op = last.op.clone();
+        edit(&mut op);
+        enforce_active(&mut op)?;
+        let mut last = self.last.take().expect("checked above");
+        last.op = op;
+        write_region(&mut self.pixels, self.width, last.rect, &last.before);
+        self.run(&last.op)?;
+        self.last = Some(last);
+        Ok(true)
+    }
+
+    pub(crate) fn pixels_mut(&mut self) -> &mut [u8] {
+        &mut self.pixels
+    }
+}
+
+#[wasm_bindgen]
+impl RedactrImage {
+    /// Wrap a copy of `pixels`, which must be `width * height * 4` bytes
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Result<RedactrImage, JsError> {
+        Ok(Self::from_rgba(width, height, pixels)?)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn solid_fill(&mut self, region: &Rect, color: &Color) -> Result<(), JsError> {
+        Ok(self.apply_op(Op::new(*region, Effect::SolidFill { color: *color }))?)
+    }
+
+    pub fn pixelate(&mut self, region: &Rect, block_size: u32) -> Result<(), JsError> {
+        Ok(self.apply_op(Op::new(*region, Effect::pixelate(block_size)))?)
+    }
+
+    pub fn gaussian_blur(&mut self, region: &Rect, radius: u32) -> Result<(), JsError> {
+        Ok(self.apply_op(Op::new(*region, Effect::GaussianBlur { radius }))?)
+    }
+
+    /// Fill `region` and draw `label` over it (see `pseudonymize`)
+    pub fn pseudonymize(
+        &mut self,
+        region: &Rect,
+        label: &str,
+        fill: &Color,
+        ink: &Color,
+    ) -> Result<(), JsError> {
+        self.last = None;
+        let params = || {
+            Json::object()
+                .with("label", label)
+                .with("color", fill.hex())
+                .with("text_color", ink.hex())
+        };
+        let (region, ink) = (*region, *ink);
+        let fill = policed(Effect::SolidFill { color: *fill }, region)?;
+        self.record("pseudonym", params, Some(region), None, |data, w, h| {
+            if region.clip(w, h).is_some() {
+                fill.apply_rect(data, w, h, region);
+                draw_text(data, w, h, region, label, ink);
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Apply every effect of `stack` to `region` in one pass
+    pub fn apply_stack(&mut self, region: &Rect, stack: &EffectStack) -> Result<(), JsError> {
+        self.last = None;
+        let params = || {
+            let effects = stack
+                .effects()
+                .iter()
+                .map(|effect| {
+                    Json::object()
+                        .with("effect", effect.name())
+                        .with("params", effect.params_json())
+                })
+                .collect();
+            Json::object().with("effects", Json::Array(effects))
+        };
+        self.record("stack", params, Some(*region), None, |data, w, h| {
+            apply_stack(stack.effects(), data, w, h, *region)
+        })?;
+        Ok(())
+    }
+
+    /// Apply the named built-in or registered preset to `region`
+    pub fn apply_preset(&mut self, region: &Rect, name: &str) -> Result<(), JsError> {
+        let stack = resolve_preset(name)?;
+        self.apply_stack(region, &stack)
+    }
+
+    /// Re-run the last rect operation as a solid fill with a new color.
+    /// Returns `false` if there is no operation to re-render.
+    pub fn rerender_fill(&mut self, color: &Color) -> Result<bool, JsError> {
+        let color = *color;
+        Ok(self.rerender(|op| op.effect = Effect::SolidFill { color })?)
+    }
+
+    /// Re-run the last rect operation as a pixelation with a new block size
+    pub fn rerender_pixelate(&mut self, block_size: u32) -> Result<bool, JsError> {
+        Ok(self.rerender(|op| op.effect = Effect::pixelate(block_size))?)
+    }
+
+    /// Re-run the last rect operation as a blur with a new radius
+    pub fn rerender_blur(&mut self, radius: u32) -> Result<bool, JsError> {
+        Ok(self.rerender(|op| op.effect = Effect::GaussianBlur { radius })?)
+    }
+
+    /// Re-run the last rect operation mixed at a new strength (0..=1)
+    pub fn rerender_strength(&mut self, strength: f32) -> Result<bool, JsError> {
+        Ok(self.rerender(|op| op.strength = strength)?)
+    }
+
+    /// Drop the cached pre-effect pixels of the last operation
+    pub fn commit(&mut self) {
+        self.last = None;
+    }
+
+    /// Zeroize and drop the cached pre-effect pixels, whether or not
+    /// `set_zeroize_scratch` is enabled. Re-rendering is no longer possible.
+    pub fn wipe_scratch(&mut self) {
+        if let Some(mut last) = self.last.take() {
+            last.before.wipe();
+        }
+    }
+
+    pub fn brush_solid_fill(
+        &mut self,
+        points: &[f32],
+        brush_size: u32,
+        color: &Color,
+    ) -> Result<(), JsError> {
+        self.last = None;
+        let params = || {
+            Json::object()
+                .with("color", color.hex())
+                .with("brush_size", brush_size)
+                .with("points", points.len() / 2)
+        };
+        self.record("brush_solid_fill", params, None, None, |data, w, h| {
+            policed_brush_solid_fill(data, w, h, points, brush_size, *color)
+        })?;
+        Ok(())
+    }
+
+    pub fn brush_pixelate(
+        &mut self,
+        points: &[f32],
+        brush_size: u32,
+        block_size: u32,
+    ) -> Result<(), JsError> {
+        self.last = None;
+        let params = || {
+            Json::object()
+                .with("block_size", block_size)
+                .with("brush_size", brush_size)
+                .with("points", points.len() / 2)
+        };
+        self.record("brush_pixelate", params, None, None, |data, w, h| {
+            policed_brush_pixelate(data, w, h, points, brush_size, block_size)
+        })?;
+        Ok(())
+    }
+
+    pub fn brush_gaussian_blur(
+        &mut self,
+        points: &[f32],
+        brush_size: u32,
+        radius: u32,
+    ) -> Result<(), JsError> {
+        self.last = None;
+        let params = || {
+            Json::object()
+                .with("radius", radius)
+                .with("brush_size", brush_size)
+                .with("points", points.len() / 2)
+        };
+        self.record("brush_gaussian_blur", params, None, None, |data, w, h| {
+            policed_brush_gaussian_blur(data, w, h, points, brush_size, radius)
+        })?;
+        Ok(())
+    }
+
+    /// Start recording every operation; an existing log is kept
+    pub fn enable_audit(&mut self) {
+        self.audit.get_or_insert_with(AuditLog::default);
+    }
+
+    /// Copy of the audit log, or `undefined` if auditing isn't enabled
+    pub fn audit_log(&self) -> Option<AuditLog> {
+        self.audit.clone()
+    }
+
+    /// Copy of the current pixels as tightly packed RGBA
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+
+    /// Downscaled copy with the longer side at most `max_edge`, made from
+    /// the current (redacted) pixels only
+    pub fn thumbnail(&self, max_edge: u32) -> Result<RedactrImage, JsError> {
+        let (w, h, pixels) = downscale(&self.pixels, self.width, self.height, max_edge)?;
+        Ok(Self::from_rgba(w, h, pixels)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gaussian_blur, pixelate, solid_fill};
+
+    #[test]
+    fn test_from_rgba_rejects_wrong_length() {
+        let err = RedactrImage::from_rgba(20, 20, vec![0; 400]).err();
+        assert_eq!(
+            err,
+            Some(RedactError::BufferSize {
+                len: 400,
+                width: 20,
+                height: 20
+            })
+        );
+        assert_eq!(
+            err.unwrap().to_string(),
+            "data length 400 does not match 20x20x4"
+        );
+    }
+
+    #[test]
+    fn test_methods_match_free_functions() {
+        let original: Vec<u8> = (0..16 * 16 * 4).map(|i| (i % 256) as u8).collect();
+        let mut expected = original.clone();
+        solid_fill(&mut expected, 16, 16, 1, 1, 4, 4, 9, 8, 7);
+        pixelate(&mut expected, 16, 16, 6, 6, 8, 8, 3);
+
+        let mut image = RedactrImage::from_rgba(16, 16, original).unwrap();
+        image
+            .solid_fill(&Rect::new(1, 1, 4, 4), &Color::new(9, 8, 7))
+            .unwrap();
+        image.pixelate(&Rect::new(6, 6, 8, 8), 3).unwrap();
+        assert_eq!(image.to_rgba(), expected);
+        assert_eq!((image.width(), image.height()), (16, 16));
+    }
+
+    #[test]
+    fn test_rerender_matches_fresh_apply() {
+        let original: Vec<u8> = (0..20 * 20 * 4).map(|i| (i * 7 % 256) as u8).collect();
+        let mut expected = original.clone();
+        gaussian_blur(&mut expected, 20, 20, 3, 3, 12, 12, 5);
+
+        let mut image = RedactrImage::from_rgba(20, 20, original).unwrap();
+        image.gaussian_blur(&Rect::new(3, 3, 12, 12), 2).unwrap();
+        assert!(image.rerender_blur(9).unwrap());
+        assert!(image.rerender_blur(5).unwrap());
+        assert_eq!(image.to_rgba(), expected);
+
+        // Switching effect keeps the region
+        let mut expected_px = expected.clone();
+        let mut image = RedactrImage::from_rgba(20, 20, expected.clone()).unwrap();
+        image
+            .solid_fill(&Rect::new(0, 0, 4, 4), &Color::new(1, 1, 1))
+            .unwrap();
+        assert!(image.rerender_pixelate(2).unwrap());
+        pixelate(&mut expected_px, 20, 20, 0, 0, 4, 4, 2);
+        assert_eq!(image.to_rgba(), expected_px);
+    }
+
+    #[test]
+    fn test_audit_records_every_operation() {
+        let original: Vec<u8> = (0..8 * 8 * 4).map(|i| (i * 3 % 256) as u8).collect();
+        let mut image = RedactrImage::from_rgba(8, 8, original.clone()).unwrap();
+        image.pixelate(&Rect::new(0, 0, 4, 4), 2).unwrap();
+        assert!(image.audit_log().is_none());
+
+        image.enable_audit();
+        image.gaussian_blur(&Rect::new(0, 0, 8, 8), 1).unwrap();
+        assert!(image.rerender_blur(3).unwrap());
+        image
+            .brush_solid_fill(&[2.0, 2.0, 6.0, 6.0], 2, &Color::new(0, 0, 0))
+            .unwrap();
+
+        let log = image.audit_log().unwrap();
+        let ops: Vec<&str> = log.entries().iter().map(|e| e.operation.as_str()).collect();
+        assert_eq!(ops, ["gaussian_blur", "gaussian_blur", "brush_solid_fill"]);
+        assert_eq!(
+            log.entries()[1].params.to_string(),
+            r#"{"radius":3,"channels":7,"strength":1}"#
+        );
+        assert_eq!(log.entries()[2].output_hash, sha256(&image.to_rgba()));
+    }
+
+    #[test]
+    fn test_rerender_strength_and_commit() {
+        let original = vec![100u8; 4 * 4 * 4];
+        let mut image = RedactrImage::from_rgba(4, 4, original).unwrap();
+        image
+            .solid_fill(&Rect::new(0, 0, 4, 4), &Color::new(200, 200, 200))
+            .unwrap();
+        assert!(image.rerender_strength(0.5).unwrap());
+        assert_eq!(image.to_rgba()[..4], [150, 150, 150, 100]);
+
+        image.commit();
+        assert!(!image.rerender_strength(1.0).unwrap());
+
+        image.gaussian_blur(&Rect::new(0, 0, 4, 4), 1).unwrap();
+        image.wipe_scratch();
+        assert!(!image.rerender_blur(2).unwrap());
+    }
+}
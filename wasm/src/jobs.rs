@@ -0,0 +1,271 @@
+//! Resumable versions of the rectangle effects.
+//!
+//! Each job processes the region a band of rows at a time so a driver can
+//! yield between steps. Running a job to completion produces exactly the
+//! same pixels as the corresponding one-shot function.
+
+use crate::{pixelate, solid_fill, BlurPass};
+
+pub(crate) trait Job {
+    /// Advance by roughly `rows` image rows; returns `true` once finished.
+    fn step(&mut self, data: &mut [u8], rows: u32) -> bool;
+
+    /// Fraction of the work done so far, 0..=1
+    fn progress(&self) -> f32;
+}
+
+fn fraction(done: u32, total: u32) -> f32 {
+    if total == 0 {
+        1.0
+    } else {
+        (done as f32 / total as f32).min(1.0)
+    }
+}
+
+/// Rows remaining in `next..end`, capped at `rows` (and at least one)
+fn band(next: u32, end: u32, rows: u32) -> u32 {
+    rows.max(1).min(end.saturating_sub(next))
+}
+
+pub(crate) struct SolidFillJob {
+    width: u32,
+    height: u32,
+    x: u32,
+    w: u32,
+    y: u32,
+    next_y: u32,
+    y_end: u32,
+    color: [u8; 3],
+}
+
+impl SolidFillJob {
+    pub(crate) fn new(
+        width: u32,
+        height: u32,
+        x: u32,
+        y: u32,
+        w: u32,
+        h: u32,
+        color: [u8; 3],
+    ) -> Self {
+        Self {
+            width,
+            height,
+            x,
+            w,
+            y,
+            next_y: y,
+            y_end: (y + h).min(height),
+            color,
+        }
+    }
+}
+
+impl Job for SolidFillJob {
+    fn step(&mut self, data: &mut [u8], rows: u32) -> bool {
+        let n = band(self.next_y, self.y_end, rows);
+        let [r, g, b] = self.color;
+        solid_fill(
+            data,
+            self.width,
+            self.height,
+            self.x,
+            self.next_y,
+            self.w,
+            n,
+            r,
+            g,
+            b,
+        );
+        self.next_y += n;
+        self.next_y >= self.y_end
+    }
+
+    fn progress(&self) -> f32 {
+        fraction(self.next_y - self.y, self.y_end.saturating_sub(self.y))
+    }
+}
+
+pub(crate) struct PixelateJob {
+    width: u32,
+    height: u32,
+    x: u32,
+    w: u32,
+    y: u32,
+    next_y: u32,
+    y_end: u32,
+    block_size: u32,
+}
+
+impl PixelateJob {
+    pub(crate) fn new(
+        width: u32,
+        height: u32,
+        x: u32,
+        y: u32,
+        w: u32,
+        h: u32,
+        block_size: u32,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            x,
+            w,
+            y,
+            next_y: y,
+            y_end: (y + h).min(height),
+            block_size: block_size.max(1),
+        }
+    }
+}
+
+impl Job for PixelateJob {
+    fn step(&mut self, data: &mut [u8], rows: u32) -> bool {
+        // Bands must cover whole rows of blocks so averages stay identical
+        let rows = rows.div_ceil(self.block_size) * self.block_size;
+        let n = band(self.next_y, self.y_end, rows);
+        pixelate(
+            data,
+            self.width,
+            self.height,
+            self.x,
+            self.next_y,
+            self.w,
+            n,
+            self.block_size,
+        );
+        self.next_y += n;
+        self.next_y >= self.y_end
+    }
+
+    fn progress(&self) -> f32 {
+        fraction(self.next_y - self.y, self.y_end.saturating_sub(self.y))
+    }
+}
+
+pub(crate) struct BlurJob {
+    pass: Option<BlurPass>,
+    next_row: usize,
+    vertical: bool,
+}
+
+impl BlurJob {
+    pub(crate) fn new(
+        data: &[u8],
+        width: u32,
+        height: u32,
+        x: u32,
+        y: u32,
+        w: u32,
+        h: u32,
+        radius: u32,
+    ) -> Self {
+        Self {
+            pass: BlurPass::new(data, width, height, x, y, w, h, radius),
+            next_row: 0,
+            vertical: false,
+        }
+    }
+}
+
+impl Job for BlurJob {
+    fn step(&mut self, data: &mut [u8], rows: u32) -> bool {
+        let Some(pass) = self.pass.as_mut() else {
+            return true;
+        };
+
+        // Each row is visited twice (horizontal then vertical), so spend half
+        // the budget per pass to keep steps roughly the same cost.
+        let rows = (rows as usize / 2).max(1);
+        let end = (self.next_row + rows).min(pass.rows());
+        if self.vertical {
+            pass.vertical_rows(data, self.next_row, end);
+        } else {
+            pass.horizontal_rows(self.next_row, end);
+        }
+        self.next_row = end;
+
+        if self.next_row >= pass.rows() {
+            if self.vertical {
+                self.pass = None;
+                return true;
+            }
+            self.vertical = true;
+            self.next_row = 0;
+        }
+        false
+    }
+
+    fn progress(&self) -> f32 {
+        let Some(pass) = self.pass.as_ref() else {
+            return 1.0;
+        };
+        let done = self.next_row + if self.vertical { pass.rows() } else { 0 };
+        fraction(done as u32, pass.rows() as u32 * 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gaussian_blur, pattern};
+
+    fn run(job: &mut dyn Job, data: &mut [u8], rows: u32) -> usize {
+        let mut steps = 1;
+        let mut last = job.progress();
+        while !job.step(data, rows) {
+            assert!(job.progress() > last, "progress must increase");
+            last = job.progress();
+            steps += 1;
+        }
+        assert_eq!(job.progress(), 1.0);
+        steps
+    }
+
+    #[test]
+    fn test_solid_fill_job_matches_one_shot() {
+        let mut expected = pattern(16, 16);
+        solid_fill(&mut expected, 16, 16, 2, 3, 10, 9, 1, 2, 3);
+
+        let mut data = pattern(16, 16);
+        let steps = run(
+            &mut SolidFillJob::new(16, 16, 2, 3, 10, 9, [1, 2, 3]),
+            &mut data,
+            2,
+        );
+        assert_eq!(data, expected);
+        assert_eq!(steps, 5);
+    }
+
+    #[test]
+    fn test_pixelate_job_matches_one_shot() {
+        let mut expected = pattern(20, 20);
+        pixelate(&mut expected, 20, 20, 1, 1, 17, 18, 4);
+
+        let mut data = pattern(20, 20);
+        // A 3-row budget is rounded up to whole block rows
+        run(&mut PixelateJob::new(20, 20, 1, 1, 17, 18, 4), &mut data, 3);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_blur_job_matches_one_shot() {
+        let mut expected = pattern(24, 24);
+        gaussian_blur(&mut expected, 24, 24, 3, 2, 15, 19, 3);
+
+        let mut data = pattern(24, 24);
+        let mut job = BlurJob::new(&data, 24, 24, 3, 2, 15, 19, 3);
+        run(&mut job, &mut data, 4);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_blur_job_empty_region_finishes_immediately() {
+        let original = pattern(8, 8);
+        let mut data = original.clone();
+        let mut job = BlurJob::new(&data, 8, 8, 10, 10, 4, 4, 2);
+        assert!(job.step(&mut data, 1));
+        assert_eq!(data, original);
+    }
+}
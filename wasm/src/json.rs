@@ -0,0 +1,314 @@
+//! JSON value used for the string-based parts of the API.
+//!
+//! Machine-readable output and input (policies, plans) go through this small
+//! value type; reading and writing the text is left to `serde_json`. Objects
+//! keep their insertion order so the output is stable and diffable.
+
+use std::fmt;
+
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_json::error::Category;
+
+use crate::error::RedactError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Start an empty object for use with `with`
+    pub fn object() -> Json {
+        Json::Object(Vec::new())
+    }
+
+    /// Builder-style insert; does nothing on non-objects
+    pub fn with(mut self, key: &str, value: impl Into<Json>) -> Json {
+        if let Json::Object(fields) = &mut self {
+            fields.push((key.to_string(), value.into()));
+        }
+        self
+    }
+
+    /// Parse a complete JSON document
+    pub fn parse(text: &str) -> Result<Json, RedactError> {
+        serde_json::from_str(text).map_err(|err| {
+            // serde_json reports 1-based lines and columns
+            let line_start: usize = text
+                .split_inclusive('\n')
+                .take(err.line().saturating_sub(1))
+                .map(str::len)
+                .sum();
+            let at = (line_start + err.column().saturating_sub(1)).min(text.len());
+            let (offset, detail) = match err.classify() {
+                Category::Eof => (text.len(), "unexpected end of input"),
+                Category::Syntax => (at, "syntax error"),
+                _ => (at, "invalid value"),
+            };
+            RedactError::InvalidJson { offset, detail }
+        })
+    }
+
+    /// Field of an object; `None` for missing keys and non-objects
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Json)]> {
+        match self {
+            Json::Object(fields) => Some(fields),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for Json {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Json::Null => serializer.serialize_unit(),
+            Json::Bool(b) => serializer.serialize_bool(*b),
+            // JSON has no NaN/Infinity
+            Json::Number(n) if !n.is_finite() => serializer.serialize_unit(),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => {
+                serializer.serialize_i64(*n as i64)
+            }
+            Json::Number(n) => serializer.serialize_f64(*n),
+            Json::String(s) => serializer.serialize_str(s),
+            Json::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Json::Object(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (key, value) in fields {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+struct JsonVisitor;
+
+impl<'de> Visitor<'de> for JsonVisitor {
+    type Value = Json;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_unit<E>(self) -> Result<Json, E> {
+        Ok(Json::Null)
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Json, E> {
+        Ok(Json::Bool(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Json, E> {
+        Ok(Json::Number(value as f64))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Json, E> {
+        Ok(Json::Number(value as f64))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Json, E> {
+        Ok(Json::Number(value))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Json, E> {
+        Ok(Json::String(value.to_string()))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Json, E> {
+        Ok(Json::String(value))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Json, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Json::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Json, A::Error> {
+        let mut fields = Vec::new();
+        while let Some(field) = map.next_entry()? {
+            fields.push(field);
+        }
+        Ok(Json::Object(fields))
+    }
+}
+
+impl<'de> Deserialize<'de> for Json {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Json, D::Error> {
+        deserializer.deserialize_any(JsonVisitor)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Json {
+        Json::Number(value)
+    }
+}
+
+impl From<f32> for Json {
+    fn from(value: f32) -> Json {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(value: u32) -> Json {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Json {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Json {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Json {
+        Json::String(value)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(values: Vec<T>) -> Json {
+        Json::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Serializes compactly, e.g. `{"a":[1,2.5],"b":null}`
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Keys are strings and numbers are finite or null, so this can't fail
+        f.write_str(&serde_json::to_string(self).map_err(|_| fmt::Error)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_nested() {
+        let value = Json::object()
+            .with("name", "blur")
+            .with("radius", 8u32)
+            .with("strength", 0.5)
+            .with("tags", vec!["a", "b"])
+            .with("max", None::<u32>);
+        assert_eq!(
+            value.to_string(),
+            r#"{"name":"blur","radius":8,"strength":0.5,"tags":["a","b"],"max":null}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_round_trips() {
+        // Duplicate keys are kept in order, like any other field
+        let text = r#"{"b":1,"a":[true,null],"b":2.25}"#;
+        assert_eq!(Json::parse(text).unwrap().to_string(), text);
+
+        let text =
+            r#"{"name":"blur","radius":8,"strength":0.5,"tags":["a","b"],"max":null,"on":true}"#;
+        let value = Json::parse(text).unwrap();
+        assert_eq!(value.to_string(), text);
+        assert_eq!(value.get("radius").and_then(Json::as_f64), Some(8.0));
+        assert_eq!(
+            value.get("tags").and_then(Json::as_array).map(|t| t.len()),
+            Some(2)
+        );
+        assert_eq!(
+            Json::parse(r#" [ "a\"\u00e9\ud83d\ude00" , -1.5e2 ] "#).unwrap(),
+            Json::Array(vec![Json::from("a\"é😀"), Json::Number(-150.0)])
+        );
+    }
+
+    #[test]
+    fn test_parse_reports_offset() {
+        let err = Json::parse(r#"{"a" 1}"#).unwrap_err();
+        assert_eq!(err.to_string(), "invalid JSON at byte 5: syntax error");
+        let err = Json::parse("{\n  \"a\": [1,]}").unwrap_err();
+        assert_eq!(err.to_string(), "invalid JSON at byte 12: syntax error");
+        assert_eq!(
+            Json::parse("[1").unwrap_err().to_string(),
+            "invalid JSON at byte 2: unexpected end of input"
+        );
+        assert!(Json::parse("1 2").is_err());
+        // Hostile nesting is an error, not a stack overflow
+        let deep = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        assert!(Json::parse(&deep).is_err());
+    }
+
+    #[test]
+    fn test_escapes_strings() {
+        let value = Json::from("quote \" slash \\ line\n\u{1}");
+        assert_eq!(value.to_string(), r#""quote \" slash \\ line\n\u0001""#);
+    }
+}
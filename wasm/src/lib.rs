@@ -1,12 +1,181 @@
+// The exported API passes image geometry as flat scalars for wasm-bindgen.
+#![allow(clippy::too_many_arguments)]
+
 use wasm_bindgen::prelude::*;
 
+pub(crate) use redactr_core::{for_each_brush_pixel, relative_radius, BlurPass};
+
+use crate::policy::{or_throw, policed, policed_size};
+
+mod alpha;
+mod analysis;
+mod async_api;
+mod audio;
+mod audit;
+mod barcodes;
+mod bars;
+mod blend;
+mod buffer;
+mod calibrate;
+mod callbacks;
+mod captions;
+mod catalog;
+mod certificate;
+mod checked;
+mod classify;
+mod compare;
+mod crop;
+mod diff;
+mod dryrun;
+mod effect;
+mod encoded;
+mod error;
+#[cfg(feature = "face-detect")]
+mod faces;
+mod fastblur;
+mod font;
+mod format;
+mod gif;
+mod harden;
+mod hash;
+mod image;
+mod jobs;
+mod json;
+mod manifest;
+mod mask;
+mod metadata;
+mod mosaic;
+mod node;
+mod noise;
+mod orient;
+mod palettes;
+mod pipeline;
+mod plan;
+mod policy;
+mod presets;
+mod regex;
+mod region;
+mod resize;
+mod review;
+mod rng;
+mod scene;
+mod scratch;
+mod sealed;
+#[cfg(feature = "segment")]
+mod segment;
+mod session;
+mod shape;
+mod sign;
+mod sprite;
+mod stack;
+mod stats;
+mod table;
+mod text;
+#[cfg(feature = "text-detect")]
+mod textdetect;
+#[cfg(feature = "threads")]
+mod threads;
+mod tiles;
+mod tone;
+mod types;
+mod verify;
+mod video;
+mod watermark;
+
+pub use alpha::{brush_solid_fill_alpha, solid_fill_alpha, AlphaMode};
+pub use analysis::{analyze_leakage, LeakAnalysis};
+pub use async_api::*;
+pub use audio::{redact_audio, AudioMode, AudioRange};
+pub use audit::{AuditEntry, AuditLog};
+pub use barcodes::{detect_barcodes, redact_barcodes};
+pub use bars::{fit_text_bars, redact_bar, redact_text_bars};
+pub use blend::*;
+pub use calibrate::{face_block_size, face_blur_radius};
+pub use captions::detect_caption;
+pub use catalog::{describe_effects, EffectInfo, ParamDefault, ParamInfo, ParamKind};
+pub use certificate::RedactionCertificate;
+pub use checked::{
+    try_brush_gaussian_blur, try_brush_pixelate, try_brush_solid_fill, try_gaussian_blur,
+    try_pixelate, try_solid_fill,
+};
+pub use classify::{class_preset, reset_class_presets, set_class_preset, PiiClass};
+pub use compare::{compare_images, CompareLayout};
+pub use crop::{crop, thumbnail};
+pub use diff::{diff, ImageDiff};
+pub use dryrun::DryRunReport;
+pub use effect::Effect;
+pub use encoded::redact_encoded;
+pub use error::RedactError;
+#[cfg(feature = "face-detect")]
+pub use faces::{detect_faces, set_face_cascade, FaceCascade};
+pub use fastblur::{blur, fast_blur, BlurQuality};
+pub use font::pseudonymize;
+pub use format::{
+    apply_effect_formatted, gaussian_blur_strided, pixelate_strided, solid_fill_strided,
+    PixelFormat,
+};
+pub use gif::{Disposal, GifCoalescer, GifFrame};
+pub use harden::{check_pixelation_block_size, hardened_pixelate, secure_pixelate};
+pub use hash::{hash_image, hash_region};
+pub use image::RedactrImage;
+pub use manifest::{embed_manifest, read_manifest, RedactionManifest};
+pub use mask::apply_with_mask;
+pub use metadata::{
+    scrub_metadata, strip_metadata, verify_metadata_scrubbed, MetadataBlock, MetadataReport,
+};
+pub use mosaic::{mosaic, CellShape};
+pub use node::redact_pixels;
+pub use noise::{brush_noise_fill, noise_fill, MIN_SEED_BYTES};
+pub use orient::{transform_image, transform_region, Transform};
+pub use palettes::{
+    contrast_ratio, label_ink, palette, palette_names, region_label_ink, register_palette,
+    unregister_palette, Palette,
+};
+pub use pipeline::{Op, Pipeline};
+pub use plan::{apply_plan, RedactionPlan, PLAN_VERSION};
+pub use policy::{check_active_export, clear_active_policy, set_active_policy, Policy};
+pub use presets::*;
+pub use region::{apply_regions, Region};
+pub use resize::{resize, ResizeFilter};
+pub use scratch::{set_zeroize_scratch, zeroize_scratch_enabled};
+pub use sealed::{seal_region, unseal_region, RegionPixels};
+#[cfg(feature = "segment")]
+pub use segment::PersonSegmenter;
+pub use session::{RedactionSession, DEFAULT_HISTORY};
+pub use shape::{
+    feathered_ellipse, feathered_rect, gaussian_blur_ellipse, gaussian_blur_polygon,
+    gaussian_blur_rotated_rect, pixelate_ellipse, pixelate_polygon, pixelate_rotated_rect,
+    solid_fill_ellipse, solid_fill_polygon, solid_fill_rotated_rect,
+};
+pub use sign::signing_digest;
+pub use sprite::overlay_sprite;
+pub use stack::EffectStack;
+pub use stats::{background_color, region_stats, RegionStats};
+pub use table::{detect_table, TableGrid};
+pub use text::{
+    find_text_matches, redact_text_boxes, redact_text_matches, OcrWord, TextPattern, Wordlist,
+};
+#[cfg(feature = "text-detect")]
+pub use textdetect::{auto_redact_text, detect_text_regions};
+#[cfg(feature = "threads")]
+pub use threads::set_thread_pool_size;
+pub use tiles::TiledRedactor;
+pub use tone::{brush_desaturate, desaturate, desaturate_ellipse, dim};
+pub use types::{Channel, Channels, Color, Rect};
+pub use verify::*;
+pub use video::VideoRedactor;
+pub use watermark::{detect_watermark, embed_watermark, Watermark};
+
 #[wasm_bindgen(start)]
 pub fn init() {
-    #[cfg(feature = "console_error_panic_hook")]
+    // Panic messages go to `console.error`, in the browser and in Node;
+    // a native build has no console to send them to
+    #[cfg(all(feature = "console_error_panic_hook", target_arch = "wasm32"))]
     console_error_panic_hook::set_once();
 }
 
-/// Apply solid fill redaction to a region of the image
+/// Apply solid fill redaction to a region of the image. Like every effect
+/// function, throws if the active policy rejects the effect.
 #[wasm_bindgen]
 pub fn solid_fill(
     data: &mut [u8],
@@ -20,20 +189,11 @@ pub fn solid_fill(
     g: u8,
     b: u8,
 ) {
-    let x_end = (x + w).min(width);
-    let y_end = (y + h).min(height);
-
-    for py in y..y_end {
-        for px in x..x_end {
-            let idx = ((py * width + px) * 4) as usize;
-            if idx + 3 < data.len() {
-                data[idx] = r;
-                data[idx + 1] = g;
-                data[idx + 2] = b;
-                // Keep alpha unchanged
-            }
-        }
-    }
+    let rect = Rect::new(x, y, w, h);
+    let fill = Effect::SolidFill {
+        color: Color::new(r, g, b),
+    };
+    or_throw(policed(fill, rect)).apply_rect(data, width, height, rect);
 }
 
 /// Apply pixelation effect to a region of the image
@@ -48,58 +208,31 @@ pub fn pixelate(
     h: u32,
     block_size: u32,
 ) {
-    let block_size = block_size.max(1);
-    let x_end = (x + w).min(width);
-    let y_end = (y + h).min(height);
-
-    // Process each block
-    let mut by = y;
-    while by < y_end {
-        let mut bx = x;
-        while bx < x_end {
-            let block_w = block_size.min(x_end - bx);
-            let block_h = block_size.min(y_end - by);
-
-            // Calculate average color for this block
-            let mut sum_r: u32 = 0;
-            let mut sum_g: u32 = 0;
-            let mut sum_b: u32 = 0;
-            let mut count: u32 = 0;
-
-            for py in by..(by + block_h) {
-                for px in bx..(bx + block_w) {
-                    let idx = ((py * width + px) * 4) as usize;
-                    if idx + 2 < data.len() {
-                        sum_r += data[idx] as u32;
-                        sum_g += data[idx + 1] as u32;
-                        sum_b += data[idx + 2] as u32;
-                        count += 1;
-                    }
-                }
-            }
-
-            if count > 0 {
-                let avg_r = (sum_r / count) as u8;
-                let avg_g = (sum_g / count) as u8;
-                let avg_b = (sum_b / count) as u8;
-
-                // Apply average color to entire block
-                for py in by..(by + block_h) {
-                    for px in bx..(bx + block_w) {
-                        let idx = ((py * width + px) * 4) as usize;
-                        if idx + 2 < data.len() {
-                            data[idx] = avg_r;
-                            data[idx + 1] = avg_g;
-                            data[idx + 2] = avg_b;
-                        }
-                    }
-                }
-            }
+    let rect = Rect::new(x, y, w, h);
+    let pixelate = Effect::pixelate(block_size);
+    or_throw(policed(pixelate, rect)).apply_rect(data, width, height, rect);
+}
 
-            bx += block_size;
-        }
-        by += block_size;
-    }
+/// Pixelate with blocks anchored to the image grid rather than the
+/// region's corner, so nudging a selection leaves its blocks unchanged
+#[wasm_bindgen]
+pub fn pixelate_grid_aligned(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    block_size: u32,
+) {
+    let rect = Rect::new(x, y, w, h);
+    let pixelate = Effect::Pixelate {
+        block_size,
+        grid_aligned: true,
+        min_blocks: 0,
+    };
+    or_throw(policed(pixelate, rect)).apply_rect(data, width, height, rect);
 }
 
 /// Apply gaussian blur to a region of the image
@@ -114,110 +247,27 @@ pub fn gaussian_blur(
     h: u32,
     radius: u32,
 ) {
-    if radius == 0 {
-        return;
-    }
-
-    let x_end = (x + w).min(width);
-    let y_end = (y + h).min(height);
-
-    // Create a copy of the region for reading
-    let region_w = (x_end - x) as usize;
-    let region_h = (y_end - y) as usize;
-    let mut temp = vec![0u8; region_w * region_h * 4];
-
-    // Copy region to temp buffer
-    for py in y..y_end {
-        for px in x..x_end {
-            let src_idx = ((py * width + px) * 4) as usize;
-            let dst_idx = (((py - y) as usize * region_w + (px - x) as usize) * 4) as usize;
-            if src_idx + 3 < data.len() && dst_idx + 3 < temp.len() {
-                temp[dst_idx] = data[src_idx];
-                temp[dst_idx + 1] = data[src_idx + 1];
-                temp[dst_idx + 2] = data[src_idx + 2];
-                temp[dst_idx + 3] = data[src_idx + 3];
-            }
-        }
-    }
-
-    // Generate gaussian kernel
-    let kernel = generate_gaussian_kernel(radius);
-    let kernel_size = (radius * 2 + 1) as i32;
-    let half_kernel = radius as i32;
-
-    // Horizontal pass
-    let mut h_pass = vec![0u8; region_w * region_h * 4];
-    for py in 0..region_h {
-        for px in 0..region_w {
-            let mut sum_r: f32 = 0.0;
-            let mut sum_g: f32 = 0.0;
-            let mut sum_b: f32 = 0.0;
-            let mut sum_weight: f32 = 0.0;
-
-            for k in 0..kernel_size {
-                let sample_x = px as i32 + k - half_kernel;
-                if sample_x >= 0 && sample_x < region_w as i32 {
-                    let idx = (py * region_w + sample_x as usize) * 4;
-                    let weight = kernel[k as usize];
-                    sum_r += temp[idx] as f32 * weight;
-                    sum_g += temp[idx + 1] as f32 * weight;
-                    sum_b += temp[idx + 2] as f32 * weight;
-                    sum_weight += weight;
-                }
-            }
-
-            let idx = (py * region_w + px) * 4;
-            h_pass[idx] = (sum_r / sum_weight) as u8;
-            h_pass[idx + 1] = (sum_g / sum_weight) as u8;
-            h_pass[idx + 2] = (sum_b / sum_weight) as u8;
-            h_pass[idx + 3] = temp[idx + 3];
-        }
-    }
-
-    // Vertical pass and write back
-    for py in 0..region_h {
-        for px in 0..region_w {
-            let mut sum_r: f32 = 0.0;
-            let mut sum_g: f32 = 0.0;
-            let mut sum_b: f32 = 0.0;
-            let mut sum_weight: f32 = 0.0;
-
-            for k in 0..kernel_size {
-                let sample_y = py as i32 + k - half_kernel;
-                if sample_y >= 0 && sample_y < region_h as i32 {
-                    let idx = (sample_y as usize * region_w + px) * 4;
-                    let weight = kernel[k as usize];
-                    sum_r += h_pass[idx] as f32 * weight;
-                    sum_g += h_pass[idx + 1] as f32 * weight;
-                    sum_b += h_pass[idx + 2] as f32 * weight;
-                    sum_weight += weight;
-                }
-            }
-
-            let dst_idx = (((py as u32 + y) * width + (px as u32 + x)) * 4) as usize;
-            if dst_idx + 2 < data.len() {
-                data[dst_idx] = (sum_r / sum_weight) as u8;
-                data[dst_idx + 1] = (sum_g / sum_weight) as u8;
-                data[dst_idx + 2] = (sum_b / sum_weight) as u8;
-            }
-        }
-    }
+    let rect = Rect::new(x, y, w, h);
+    let blur = Effect::GaussianBlur { radius };
+    or_throw(policed(blur, rect)).apply_rect(data, width, height, rect);
 }
 
-fn generate_gaussian_kernel(radius: u32) -> Vec<f32> {
-    let size = (radius * 2 + 1) as usize;
-    let sigma = radius as f32 / 2.0;
-    let mut kernel = vec![0.0f32; size];
-
-    let two_sigma_sq = 2.0 * sigma * sigma;
-    let norm = 1.0 / (std::f32::consts::PI * two_sigma_sq).sqrt();
-
-    for i in 0..size {
-        let x = i as f32 - radius as f32;
-        kernel[i] = norm * (-x * x / two_sigma_sq).exp();
-    }
-
-    kernel
+/// `gaussian_blur` with `radius_fraction` a fraction of the image width
+/// (0.01 is 8px on an 800px image, 40px on a 4000px one), so one slider
+/// value looks equally strong at any resolution
+#[wasm_bindgen]
+pub fn gaussian_blur_relative(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    radius_fraction: f32,
+) {
+    let radius = relative_radius(width, radius_fraction);
+    gaussian_blur(data, width, height, x, y, w, h, radius);
 }
 
 /// Apply redaction to freehand brush strokes (array of points)
@@ -232,33 +282,25 @@ pub fn brush_solid_fill(
     g: u8,
     b: u8,
 ) {
-    let radius = (brush_size / 2) as i32;
-    let radius_sq = (radius * radius) as f32;
+    let color = Color::new(r, g, b);
+    or_throw(policed_brush_solid_fill(data, width, height, points, brush_size, color));
+}
 
-    for i in (0..points.len()).step_by(2) {
-        if i + 1 >= points.len() {
-            break;
-        }
-        let cx = points[i] as i32;
-        let cy = points[i + 1] as i32;
-
-        for dy in -radius..=radius {
-            for dx in -radius..=radius {
-                if (dx * dx + dy * dy) as f32 <= radius_sq {
-                    let px = cx + dx;
-                    let py = cy + dy;
-                    if px >= 0 && px < width as i32 && py >= 0 && py < height as i32 {
-                        let idx = ((py as u32 * width + px as u32) * 4) as usize;
-                        if idx + 2 < data.len() {
-                            data[idx] = r;
-                            data[idx + 1] = g;
-                            data[idx + 2] = b;
-                        }
-                    }
-                }
-            }
-        }
-    }
+/// `brush_solid_fill` for callers that handle a policy rejection. A stroke
+/// can reach anywhere, so the policy sees the whole image as its region.
+pub(crate) fn policed_brush_solid_fill(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    color: Color,
+) -> Result<(), RedactError> {
+    // A policy can only allow or reject a fill
+    policed(Effect::SolidFill { color }, Rect::new(0, 0, width, height))?;
+    let Color { r, g, b, .. } = color;
+    redactr_core::brush_solid_fill(data, width, height, points, brush_size, r, g, b);
+    Ok(())
 }
 
 /// Apply pixelation to brush strokes
@@ -271,120 +313,102 @@ pub fn brush_pixelate(
     brush_size: u32,
     block_size: u32,
 ) {
-    // Create a mask of affected pixels
-    let mut mask = vec![false; (width * height) as usize];
-    let radius = (brush_size / 2) as i32;
-    let radius_sq = (radius * radius) as f32;
+    or_throw(policed_brush_pixelate(data, width, height, points, brush_size, block_size));
+}
 
-    // Mark pixels in brush path
-    for i in (0..points.len()).step_by(2) {
-        if i + 1 >= points.len() {
-            break;
-        }
-        let cx = points[i] as i32;
-        let cy = points[i + 1] as i32;
-
-        for dy in -radius..=radius {
-            for dx in -radius..=radius {
-                if (dx * dx + dy * dy) as f32 <= radius_sq {
-                    let px = cx + dx;
-                    let py = cy + dy;
-                    if px >= 0 && px < width as i32 && py >= 0 && py < height as i32 {
-                        mask[(py as u32 * width + px as u32) as usize] = true;
-                    }
-                }
-            }
-        }
-    }
+/// `brush_pixelate` for callers that handle a policy rejection
+pub(crate) fn policed_brush_pixelate(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    block_size: u32,
+) -> Result<(), RedactError> {
+    let pixelate = Effect::pixelate(block_size);
+    let whole = Rect::new(0, 0, width, height);
+    let block_size = policed_size(pixelate, whole)?;
+    redactr_core::brush_pixelate(data, width, height, points, brush_size, block_size);
+    Ok(())
+}
 
-    // Find bounding box
-    let mut min_x = width;
-    let mut min_y = height;
-    let mut max_x = 0u32;
-    let mut max_y = 0u32;
-
-    for y in 0..height {
-        for x in 0..width {
-            if mask[(y * width + x) as usize] {
-                min_x = min_x.min(x);
-                min_y = min_y.min(y);
-                max_x = max_x.max(x);
-                max_y = max_y.max(y);
-            }
-        }
-    }
+/// Apply gaussian blur to brush strokes. The stroke's bounding box is
+/// blurred as a whole, so pixels at the edge of the stroke sample their
+/// unmasked neighbours, then only pixels under the brush are kept.
+#[wasm_bindgen]
+pub fn brush_gaussian_blur(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    radius: u32,
+) {
+    or_throw(policed_brush_gaussian_blur(data, width, height, points, brush_size, radius));
+}
 
+/// `brush_gaussian_blur` for callers that handle a policy rejection
+pub(crate) fn policed_brush_gaussian_blur(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    radius: u32,
+) -> Result<(), RedactError> {
+    let whole = Rect::new(0, 0, width, height);
+    let radius = policed_size(Effect::GaussianBlur { radius }, whole)?;
+    if data.len() < (width * height * 4) as usize {
+        return Ok(());
+    }
+    let mut mask = vec![false; (width * height) as usize];
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (width, height, 0u32, 0u32);
+    for_each_brush_pixel(width, height, points, brush_size, |px, py| {
+        mask[(py * width + px) as usize] = true;
+        min_x = min_x.min(px);
+        min_y = min_y.min(py);
+        max_x = max_x.max(px);
+        max_y = max_y.max(py);
+    });
     if min_x > max_x {
-        return;
+        return Ok(());
     }
 
-    // Pixelate with mask
-    let block_size = block_size.max(1);
-    let mut by = min_y;
-    while by <= max_y {
-        let mut bx = min_x;
-        while bx <= max_x {
-            let block_w = block_size.min(max_x + 1 - bx);
-            let block_h = block_size.min(max_y + 1 - by);
-
-            // Check if any pixel in block is masked
-            let mut has_masked = false;
-            for py in by..(by + block_h) {
-                for px in bx..(bx + block_w) {
-                    if mask[(py * width + px) as usize] {
-                        has_masked = true;
-                        break;
-                    }
-                }
-                if has_masked {
-                    break;
-                }
-            }
-
-            if has_masked {
-                // Calculate average and apply only to masked pixels
-                let mut sum_r: u32 = 0;
-                let mut sum_g: u32 = 0;
-                let mut sum_b: u32 = 0;
-                let mut count: u32 = 0;
-
-                for py in by..(by + block_h) {
-                    for px in bx..(bx + block_w) {
-                        let idx = ((py * width + px) * 4) as usize;
-                        sum_r += data[idx] as u32;
-                        sum_g += data[idx + 1] as u32;
-                        sum_b += data[idx + 2] as u32;
-                        count += 1;
-                    }
-                }
-
-                if count > 0 {
-                    let avg_r = (sum_r / count) as u8;
-                    let avg_g = (sum_g / count) as u8;
-                    let avg_b = (sum_b / count) as u8;
-
-                    for py in by..(by + block_h) {
-                        for px in bx..(bx + block_w) {
-                            if mask[(py * width + px) as usize] {
-                                let idx = ((py * width + px) * 4) as usize;
-                                data[idx] = avg_r;
-                                data[idx + 1] = avg_g;
-                                data[idx + 2] = avg_b;
-                            }
-                        }
-                    }
-                }
+    let rect = Rect::new(min_x, min_y, max_x + 1 - min_x, max_y + 1 - min_y);
+    let original = buffer::read_region(data, width, rect);
+    redactr_core::gaussian_blur(data, width, height, rect.x, rect.y, rect.w, rect.h, radius);
+    for y in 0..rect.h {
+        for x in 0..rect.w {
+            let i = ((rect.y + y) * width + rect.x + x) as usize;
+            if !mask[i] {
+                let from = ((y * rect.w + x) * 4) as usize;
+                data[i * 4..i * 4 + 4].copy_from_slice(&original[from..from + 4]);
             }
-
-            bx += block_size;
         }
-        by += block_size;
     }
+    Ok(())
 }
 
+/// Test image whose neighbouring bytes all differ, shared by the module
+/// tests
 #[cfg(test)]
+pub(crate) fn pattern(width: u32, height: u32) -> Vec<u8> {
+    (0..width * height * 4)
+        .map(|i| (i * 37 % 251) as u8)
+        .collect()
+}
+
+#[cfg(test)]
+// Tests spell pixel offsets out as (y * width + x) * 4, even at the origin
+#[allow(
+    clippy::erasing_op,
+    clippy::identity_op,
+    clippy::manual_slice_fill,
+    clippy::needless_range_loop
+)]
 mod tests {
     use super::*;
+    use crate::json::Json;
 
     fn create_test_image(width: u32, height: u32) -> Vec<u8> {
         let size = (width * height * 4) as usize;
@@ -500,6 +524,24 @@ mod tests {
         assert_eq!(data.len(), 400);
     }
 
+    #[test]
+    fn test_grid_aligned_pixelate_from_json() {
+        let params =
+            Json::parse(r#"{"block_size": 4, "grid_aligned": true, "min_blocks": 0}"#).unwrap();
+        let effect = Effect::from_json("pixelate", Some(&params)).unwrap();
+        let mut via_effect = create_test_image(16, 16);
+        effect.apply_rect(&mut via_effect, 16, 16, Rect::new(3, 5, 9, 7));
+        let mut direct = create_test_image(16, 16);
+        pixelate_grid_aligned(&mut direct, 16, 16, 3, 5, 9, 7, 4);
+        assert_eq!(via_effect, direct);
+        assert_eq!(effect.params_json(), params);
+        // Left out, the grid follows the region as before
+        let plain = Effect::from_json("pixelate", None).unwrap();
+        assert!(matches!(plain, Effect::Pixelate { grid_aligned: false, .. }));
+        let bad = Json::parse(r#"{"grid_aligned": 1}"#).unwrap();
+        assert!(Effect::from_json("pixelate", Some(&bad)).is_err());
+    }
+
     #[test]
     fn test_gaussian_blur_zero_radius() {
         let original = create_test_image(10, 10);
@@ -533,23 +575,19 @@ mod tests {
     }
 
     #[test]
-    fn test_generate_gaussian_kernel() {
-        let kernel = generate_gaussian_kernel(2);
-        
-        // Kernel size should be 2*radius + 1 = 5
-        assert_eq!(kernel.len(), 5);
-        
-        // Center should be the largest value
-        let center_idx = 2;
-        for (i, &val) in kernel.iter().enumerate() {
-            if i != center_idx {
-                assert!(kernel[center_idx] >= val);
-            }
-        }
-        
-        // Kernel should be symmetric
-        assert!((kernel[0] - kernel[4]).abs() < 0.0001);
-        assert!((kernel[1] - kernel[3]).abs() < 0.0001);
+    fn test_gaussian_blur_relative_scales_with_width() {
+        assert_eq!(relative_radius(800, 0.01), 8);
+        assert_eq!(relative_radius(4000, 0.01), 40);
+        assert_eq!(relative_radius(50, 0.001), 1);
+        assert_eq!(relative_radius(800, 0.0), 0);
+        assert_eq!(relative_radius(800, f32::NAN), 0);
+        assert_eq!(relative_radius(800, f32::INFINITY), 800);
+
+        let mut data = create_test_image(40, 20);
+        let mut expected = data.clone();
+        gaussian_blur_relative(&mut data, 40, 20, 0, 0, 40, 20, 0.05);
+        gaussian_blur(&mut expected, 40, 20, 0, 0, 40, 20, 2);
+        assert_eq!(data, expected);
     }
 
     #[test]
@@ -609,6 +647,24 @@ mod tests {
         assert_eq!(data, original);
     }
 
+    #[test]
+    fn test_brush_solid_fill_joins_distant_points() {
+        let mut data = vec![0u8; 50 * 50 * 4];
+
+        // Two samples far apart, as from a fast pointer movement
+        let points = vec![5.0, 5.0, 40.0, 30.0];
+        brush_solid_fill(&mut data, 50, 50, &points, 2, 255, 0, 0);
+
+        // Every pixel along the segment is covered
+        for step in 0..=35 {
+            let x = 5 + step;
+            let y = (5.0 + step as f32 * 25.0 / 35.0).round() as usize;
+            assert_eq!(data[(y * 50 + x) * 4], 255, "gap at ({}, {})", x, y);
+        }
+        // But not pixels beyond the brush radius
+        assert_eq!(data[(30 * 50 + 5) * 4], 0);
+    }
+
     #[test]
     fn test_brush_solid_fill_odd_points_count() {
         let mut data = vec![0u8; 50 * 50 * 4];
@@ -656,4 +712,28 @@ mod tests {
         // Should not panic
         assert_eq!(data.len(), 400);
     }
+
+    #[test]
+    fn test_brush_gaussian_blur_only_under_brush() {
+        // Vertical stripes, so a blur visibly changes every pixel
+        let stripes: Vec<u8> = (0..30 * 30)
+            .flat_map(|i| if i % 2 == 0 { [0, 0, 0, 255] } else { [255; 4] })
+            .collect();
+        let mut data = stripes.clone();
+        let points = vec![5.0, 15.0, 25.0, 15.0];
+        brush_gaussian_blur(&mut data, 30, 30, &points, 6, 2);
+
+        let at = |buf: &[u8], x: usize, y: usize| buf[(y * 30 + x) * 4];
+        // Blurred along the stroke
+        let mid = at(&data, 15, 15);
+        assert!(mid > 40 && mid < 215, "not blurred: {}", mid);
+        // Inside the bounding box but off the brush: untouched
+        assert_eq!(at(&data, 2, 12), at(&stripes, 2, 12));
+        assert_eq!(at(&data, 15, 10), at(&stripes, 15, 10));
+
+        // No points, no change
+        let mut untouched = stripes.clone();
+        brush_gaussian_blur(&mut untouched, 30, 30, &[], 6, 2);
+        assert_eq!(untouched, stripes);
+    }
 }
@@ -0,0 +1,508 @@
+//! Compact manifest embedded in exported files so downstream systems can
+//! tell an image was already redacted, by which plan and under which policy.
+//!
+//! The manifest holds the tool version, hashes of the plan and policy and
+//! the number of regions. Region geometry reveals where the sensitive content
+//! was, so it's only included on request. PNG files carry it in an `iTXt`
+//! chunk, JPEG and WebP in an XMP packet. The metadata scanner only counts
+//! such a block as expected when it parses as a manifest without region
+//! geometry, and scrubbing always removes it: `embed_manifest` scrubs the
+//! file and writes a fresh one, so nothing rides along under the keyword.
+
+use wasm_bindgen::prelude::*;
+
+use crate::audit::now;
+use crate::error::RedactError;
+use crate::hash::{crc32, hex, sha256};
+use crate::json::Json;
+use crate::metadata::{scan_metadata, scrub, MetadataBlock};
+use crate::pipeline::Pipeline;
+use crate::policy::Policy;
+use crate::types::Rect;
+
+/// Keyword (with its terminator) of the PNG `iTXt` chunk
+pub(crate) const PNG_KEYWORD: &[u8] = b"redactr:manifest\0";
+/// Identifier that starts every JPEG XMP segment
+pub(crate) const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// Start of the XMP packet written by `embed`, up to the manifest JSON
+pub(crate) const XMP_PREFIX: &str = concat!(
+    r#"<x:xmpmeta xmlns:x="adobe:ns:meta/" x:xmptk="redactr">"#,
+    r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#,
+    r#"<rdf:Description rdf:about="" xmlns:redactr="urn:redactr:manifest:1" redactr:manifest=""#,
+);
+const XMP_SUFFIX: &str = r#""/></rdf:RDF></x:xmpmeta>"#;
+
+/// VP8X feature flags
+const VP8X_ALPHA: u8 = 0x10;
+const VP8X_XMP: u8 = 0x04;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedactionManifest {
+    version: String,
+    issued: f64,
+    plan_sha256: String,
+    region_count: usize,
+    policy_sha256: Option<String>,
+    regions: Option<Vec<Rect>>,
+}
+
+fn field_error(field: &str, expected: &'static str) -> RedactError {
+    RedactError::InvalidField {
+        field: field.to_string(),
+        expected,
+    }
+}
+
+fn distinct_regions(plan: &Pipeline) -> Vec<Rect> {
+    let mut regions: Vec<Rect> = Vec::new();
+    for op in plan.ops() {
+        if !regions.contains(&op.region) {
+            regions.push(op.region);
+        }
+    }
+    regions
+}
+
+impl RedactionManifest {
+    pub fn for_plan(plan: &Pipeline) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            issued: now(),
+            plan_sha256: hex(&sha256(plan.json().to_string().as_bytes())),
+            region_count: distinct_regions(plan).len(),
+            policy_sha256: None,
+            regions: None,
+        }
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        let json = Json::object()
+            .with("tool", "redactr")
+            .with("version", self.version.as_str())
+            .with("issued", self.issued)
+            .with("plan_sha256", self.plan_sha256.as_str())
+            .with("region_count", self.region_count)
+            .with("policy_sha256", self.policy_sha256.clone());
+        match &self.regions {
+            Some(regions) => json.with(
+                "regions",
+                Json::Array(regions.iter().map(Rect::json).collect()),
+            ),
+            None => json,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, RedactError> {
+        let json = Json::parse(text)?;
+        if json.get("tool").and_then(Json::as_str) != Some("redactr") {
+            return Err(field_error("tool", "\"redactr\""));
+        }
+        let string = |key: &str| {
+            json.get(key)
+                .and_then(Json::as_str)
+                .map(str::to_string)
+                .ok_or_else(|| field_error(key, "a string"))
+        };
+        let number = |key: &str| {
+            json.get(key)
+                .and_then(Json::as_f64)
+                .filter(|n| *n >= 0.0)
+                .ok_or_else(|| field_error(key, "a non-negative number"))
+        };
+        let policy_sha256 = match json.get("policy_sha256") {
+            None | Some(Json::Null) => None,
+            Some(_) => Some(string("policy_sha256")?),
+        };
+        let regions = match json.get("regions") {
+            None | Some(Json::Null) => None,
+            Some(value) => {
+                let items = value
+                    .as_array()
+                    .ok_or_else(|| field_error("regions", "an array of rects"))?;
+                let rects = items
+                    .iter()
+                    .map(|r| {
+                        let v = |k| r.get(k).and_then(Json::as_f64).map(|n| n as u32);
+                        Some(Rect::new(v("x")?, v("y")?, v("w")?, v("h")?))
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| field_error("regions", "an array of rects"))?;
+                Some(rects)
+            }
+        };
+        Ok(Self {
+            version: string("version")?,
+            issued: number("issued")?,
+            plan_sha256: string("plan_sha256")?,
+            region_count: number("region_count")? as usize,
+            policy_sha256,
+            regions,
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl RedactionManifest {
+    /// Manifest for an export produced by `plan`
+    #[wasm_bindgen(constructor)]
+    pub fn new(plan: &Pipeline) -> RedactionManifest {
+        Self::for_plan(plan)
+    }
+
+    /// Record the policy the plan was checked against
+    pub fn set_policy(&mut self, policy: &Policy) {
+        self.policy_sha256 = Some(hex(&sha256(policy.json().to_string().as_bytes())));
+    }
+
+    /// Also embed the region geometry. Off by default: it tells anyone
+    /// reading the file where the sensitive content was.
+    pub fn include_regions(&mut self, plan: &Pipeline) {
+        self.regions = Some(distinct_regions(plan));
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn version(&self) -> String {
+        self.version.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn plan_sha256(&self) -> String {
+        self.plan_sha256.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn region_count(&self) -> usize {
+        self.region_count
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn policy_sha256(&self) -> Option<String> {
+        self.policy_sha256.clone()
+    }
+
+    /// Whether the manifest was written for `plan`
+    pub fn matches_plan(&self, plan: &Pipeline) -> bool {
+        self.plan_sha256 == hex(&sha256(plan.json().to_string().as_bytes()))
+    }
+
+    /// Whether the manifest records `policy`
+    pub fn matches_policy(&self, policy: &Policy) -> bool {
+        self.policy_sha256.as_deref()
+            == Some(hex(&sha256(policy.json().to_string().as_bytes())).as_str())
+    }
+
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+}
+
+pub(crate) fn is_manifest(block: &MetadataBlock) -> bool {
+    block.name.ends_with("/redactr")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&gt;", ">")
+        .replace("&lt;", "<")
+        .replace("&amp;", "&")
+}
+
+pub(crate) fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+    chunk
+}
+
+fn webp_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = kind.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+/// Canvas size and alpha use of a simple (VP8 or VP8L) WebP bitstream
+fn webp_canvas(bytes: &[u8], block: &MetadataBlock) -> Result<(u32, u32, bool), RedactError> {
+    let payload = &bytes[block.offset + 8..block.offset + 8 + block.length];
+    let malformed = RedactError::MalformedImage {
+        detail: "unreadable WebP frame header",
+        offset: block.offset,
+    };
+    match block.name.as_str() {
+        "VP8 " if payload.len() >= 10 && payload[3..6] == [0x9d, 0x01, 0x2a] => {
+            let w = u16::from_le_bytes([payload[6], payload[7]]) & 0x3fff;
+            let h = u16::from_le_bytes([payload[8], payload[9]]) & 0x3fff;
+            Ok((w as u32, h as u32, false))
+        }
+        "VP8L" if payload.len() >= 5 && payload[0] == 0x2f => {
+            let bits = u32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]]);
+            Ok((
+                (bits & 0x3fff) + 1,
+                ((bits >> 14) & 0x3fff) + 1,
+                bits >> 28 & 1 == 1,
+            ))
+        }
+        _ => Err(malformed),
+    }
+}
+
+fn vp8x(width: u32, height: u32, flags: u8) -> Vec<u8> {
+    let mut data = vec![flags, 0, 0, 0];
+    data.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+    data.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+    webp_chunk(b"VP8X", &data)
+}
+
+/// Scrubbed copy of an encoded PNG, JPEG or WebP with `manifest` embedded;
+/// any manifest already there is dropped with the rest of the metadata
+pub fn embed(bytes: &[u8], manifest: &RedactionManifest) -> Result<Vec<u8>, RedactError> {
+    let scrubbed = scrub(bytes)?;
+    let bytes = scrubbed.as_slice();
+    let report = scan_metadata(bytes)?;
+    let text = manifest.json().to_string();
+    let blocks: Vec<&MetadataBlock> = report.blocks().iter().collect();
+    let header = report.blocks().first().map_or(bytes.len(), |b| b.offset);
+    let mut out = bytes[..header].to_vec();
+    let xmp = format!("{}{}{}", XMP_PREFIX, xml_escape(&text), XMP_SUFFIX);
+
+    match report.format().as_str() {
+        "png" => {
+            let mut data = PNG_KEYWORD.to_vec();
+            // Uncompressed, no language tag or translated keyword
+            data.extend_from_slice(&[0, 0, 0, 0]);
+            data.extend_from_slice(text.as_bytes());
+            for b in blocks {
+                out.extend_from_slice(&bytes[b.offset..b.end]);
+                if b.name == "IHDR" {
+                    out.extend(png_chunk(b"iTXt", &data));
+                }
+            }
+        }
+        "jpeg" => {
+            let len = 2 + XMP_NAMESPACE.len() + xmp.len();
+            if len > u16::MAX as usize {
+                return Err(RedactError::InvalidParameter {
+                    name: "manifest",
+                    value: len as f64,
+                    expected: format!("at most {} bytes in a JPEG segment", u16::MAX),
+                });
+            }
+            let mut segment = vec![0xFF, 0xE1];
+            segment.extend_from_slice(&(len as u16).to_be_bytes());
+            segment.extend_from_slice(XMP_NAMESPACE);
+            segment.extend_from_slice(xmp.as_bytes());
+            // XMP goes after the JFIF header, if there is one
+            let after_jfif = blocks.first().is_some_and(|b| b.name == "APP0/JFIF");
+            for (i, b) in blocks.iter().enumerate() {
+                if i == after_jfif as usize {
+                    out.extend_from_slice(&segment);
+                }
+                out.extend_from_slice(&bytes[b.offset..b.end]);
+            }
+        }
+        _ => {
+            // XMP needs the extended format; synthesize VP8X for simple files
+            let first = blocks.first().ok_or(RedactError::MalformedImage {
+                detail: "WebP file has no image data",
+                offset: header,
+            })?;
+            if first.name != "VP8X" {
+                let (w, h, alpha) = webp_canvas(bytes, first)?;
+                out.extend(vp8x(w, h, if alpha { VP8X_ALPHA } else { 0 }));
+            }
+            for b in &blocks {
+                out.extend_from_slice(&bytes[b.offset..b.end]);
+            }
+            out[header + 8] |= VP8X_XMP;
+            out.extend(webp_chunk(b"XMP ", xmp.as_bytes()));
+            let riff_size = (out.len() - 8) as u32;
+            out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        }
+    }
+    Ok(out)
+}
+
+/// Parse the manifest in `block`, a `*/redactr` block of a `format` file
+pub(crate) fn parse_block(
+    bytes: &[u8],
+    format: &str,
+    block: &MetadataBlock,
+) -> Result<RedactionManifest, RedactError> {
+    let unreadable = RedactError::MalformedImage {
+        detail: "unreadable manifest packet",
+        offset: block.offset,
+    };
+    let text = match format {
+        "png" => {
+            let data = &bytes[block.offset + 8..block.offset + 8 + block.length];
+            let text = data.get(PNG_KEYWORD.len() + 4..).ok_or(unreadable)?;
+            String::from_utf8_lossy(text).into_owned()
+        }
+        format => {
+            let start = if format == "jpeg" {
+                block.offset + 4 + XMP_NAMESPACE.len()
+            } else {
+                block.offset + 8
+            };
+            let end = if format == "jpeg" {
+                block.offset + 2 + block.length
+            } else {
+                block.offset + 8 + block.length
+            };
+            let packet = String::from_utf8_lossy(bytes.get(start..end).ok_or(unreadable.clone())?);
+            let json = packet
+                .strip_prefix(XMP_PREFIX)
+                .and_then(|rest| rest.strip_suffix(XMP_SUFFIX))
+                .ok_or(unreadable)?;
+            xml_unescape(json)
+        }
+    };
+    RedactionManifest::parse(&text)
+}
+
+/// Whether `block` holds a manifest that may stay in scrubbed output: one
+/// that parses and carries no region geometry
+pub(crate) fn is_clean_manifest(bytes: &[u8], format: &str, block: &MetadataBlock) -> bool {
+    parse_block(bytes, format, block).is_ok_and(|m| m.regions.is_none())
+}
+
+/// The manifest embedded in an encoded image, if any
+pub fn read(bytes: &[u8]) -> Result<Option<RedactionManifest>, RedactError> {
+    let report = scan_metadata(bytes)?;
+    let Some(block) = report.blocks().iter().find(|b| is_manifest(b)) else {
+        return Ok(None);
+    };
+    parse_block(bytes, report.format().as_str(), block).map(Some)
+}
+
+/// Embed `manifest` in an encoded PNG, JPEG or WebP (e.g. the exported
+/// `Blob`'s bytes)
+#[wasm_bindgen]
+pub fn embed_manifest(bytes: &[u8], manifest: &RedactionManifest) -> Result<Vec<u8>, JsError> {
+    Ok(embed(bytes, manifest)?)
+}
+
+/// Extract an embedded manifest, or `undefined` if the file has none
+#[wasm_bindgen]
+pub fn read_manifest(bytes: &[u8]) -> Result<Option<RedactionManifest>, JsError> {
+    Ok(read(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Color;
+
+    fn plan() -> Pipeline {
+        Pipeline::new()
+            .fill(&Rect::new(0, 0, 4, 4), &Color::new(0, 0, 0))
+            .blur(&Rect::new(4, 4, 4, 4), 16)
+    }
+
+    fn png() -> Vec<u8> {
+        let mut file = b"\x89PNG\r\n\x1a\n".to_vec();
+        file.extend(png_chunk(b"IHDR", &[0; 13]));
+        file.extend(png_chunk(b"IDAT", &[1, 2, 3]));
+        file.extend(png_chunk(b"IEND", &[]));
+        file
+    }
+
+    #[test]
+    fn test_png_round_trip_and_scrub_drops_it() {
+        let plan = plan();
+        let mut manifest = RedactionManifest::new(&plan);
+        manifest.set_policy(&Policy::new());
+        let file = embed(&embed(&png(), &manifest).unwrap(), &manifest).unwrap();
+
+        let report = scan_metadata(&file).unwrap();
+        assert!(report.clean());
+        assert_eq!(report.blocks()[1].name, "iTXt/redactr");
+        assert_eq!(report.blocks().len(), 4);
+
+        let read_back = read(&file).unwrap().unwrap();
+        assert_eq!(read_back, manifest);
+        assert_eq!(read(&scrub(&file).unwrap()).unwrap(), None);
+        assert!(read_back.matches_plan(&plan));
+        assert!(read_back.matches_policy(&Policy::new()));
+        assert_eq!(read_back.region_count(), 2);
+        assert!(!read_back.to_json().contains("regions"));
+        assert_eq!(read(&png()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_jpeg_manifest_follows_jfif() {
+        let mut file = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 7];
+        file.extend_from_slice(b"JFIF\0");
+        file.extend_from_slice(&[0xFF, 0xDA, 0, 2, 0x12, 0x34, 0xFF, 0xD9]);
+        let plan = plan();
+        let mut manifest = RedactionManifest::new(&plan);
+        manifest.include_regions(&plan);
+
+        let out = embed(&file, &manifest).unwrap();
+        let report = scan_metadata(&out).unwrap();
+        let names: Vec<&str> = report.blocks().iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["APP0/JFIF", "APP1/redactr", "SOS", "EOI"]);
+        // Geometry says where the secrets were: flagged, and scrubbed away
+        assert_eq!(report.unexpected(), ["APP1/redactr"]);
+        assert_eq!(read(&scrub(&out).unwrap()).unwrap(), None);
+        let read_back = read(&out).unwrap().unwrap();
+        assert_eq!(read_back, manifest);
+        assert!(read_back.to_json().contains(r#""regions":[{"x":0"#));
+    }
+
+    #[test]
+    fn test_simple_webp_gains_vp8x() {
+        // 3x2 lossless frame, alpha used
+        let frame = [0x2f, 0x02, 0x40, 0x00, 0x10];
+        let mut body = b"WEBP".to_vec();
+        body.extend(webp_chunk(b"VP8L", &frame));
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        file.extend(body);
+
+        let manifest = RedactionManifest::new(&plan());
+        let out = embed(&file, &manifest).unwrap();
+        let report = scan_metadata(&out).unwrap();
+        assert!(report.clean());
+        assert_eq!(report.blocks()[0].name, "VP8X");
+        assert_eq!(out[20], VP8X_ALPHA | VP8X_XMP);
+        assert_eq!(out[24..30], [2, 0, 0, 1, 0, 0]);
+        assert_eq!(read(&out).unwrap(), Some(manifest));
+        // Scrubbing drops the packet and its VP8X flag
+        let scrubbed = scrub(&out).unwrap();
+        assert_eq!(scrubbed[20], VP8X_ALPHA);
+        assert_eq!(read(&scrubbed).unwrap(), None);
+    }
+
+    #[test]
+    fn test_data_under_the_manifest_keyword_is_not_trusted() {
+        let mut forged = PNG_KEYWORD.to_vec();
+        forged.extend_from_slice(&[0, 0, 0, 0]);
+        forged.extend_from_slice(b"GPS 51.5007,-0.1246");
+        let mut file = png();
+        let iend = file.len() - 12;
+        file.splice(iend..iend, png_chunk(b"iTXt", &forged));
+        let report = scan_metadata(&file).unwrap();
+        assert_eq!(report.unexpected(), ["iTXt/redactr"]);
+        assert_eq!(scrub(&file).unwrap(), png());
+
+        // Embedding replaces it with a fresh, clean manifest
+        let manifest = RedactionManifest::new(&plan());
+        let out = embed(&file, &manifest).unwrap();
+        assert!(scan_metadata(&out).unwrap().clean());
+        assert_eq!(read(&out).unwrap(), Some(manifest));
+    }
+}
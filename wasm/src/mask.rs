@@ -0,0 +1,143 @@
+//! Effects weighted by a host-rasterized coverage mask.
+//!
+//! The host draws any selection (magic wand, lasso, an ML segmentation)
+//! into one byte per pixel, 0 for untouched through 255 for full effect,
+//! and `apply_with_mask` blends the effect in by that coverage. The effect
+//! runs once over the mask's bounding box, so a blur or pixelate near the
+//! selection's edge samples the same pixels it would for a rect.
+//!
+//! Only full coverage hides content; the partial coverage of an
+//! antialiased edge leaves a faint trace of the original by design.
+
+use wasm_bindgen::prelude::*;
+
+use crate::blend::mix;
+use crate::buffer::read_region;
+use crate::effect::Effect;
+use crate::error::{check_buffer, RedactError};
+use crate::policy::policed;
+use crate::types::Rect;
+
+/// Smallest rect holding every non-zero mask value
+fn mask_bounds(mask: &[u8], width: u32) -> Option<Rect> {
+    let (mut x0, mut y0, mut x1, mut y1) = (u32::MAX, u32::MAX, 0, 0);
+    for (i, _) in mask.iter().enumerate().filter(|(_, &m)| m > 0) {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+    }
+    (x0 <= x1).then(|| Rect::new(x0, y0, x1 - x0 + 1, y1 - y0 + 1))
+}
+
+pub(crate) fn apply_masked(
+    effect: &Effect,
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    mask: &[u8],
+) -> Result<(), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    let pixels = width as usize * height as usize;
+    if mask.len() != pixels {
+        return Err(RedactError::InvalidParameter {
+            name: "mask",
+            value: mask.len() as f64,
+            expected: format!("{} values, one per pixel", pixels),
+        });
+    }
+    effect.validate()?;
+    let Some(rect) = mask_bounds(mask, width) else {
+        return Ok(());
+    };
+    let effect = policed(*effect, rect)?;
+    let original = read_region(data, width, rect);
+    effect.apply_rect(data, width, height, rect);
+    for y in 0..rect.h {
+        for x in 0..rect.w {
+            let (px, py) = (rect.x + x, rect.y + y);
+            let coverage = mask[(py * width + px) as usize];
+            if coverage == 255 {
+                continue;
+            }
+            let from = ((y * rect.w + x) * 4) as usize;
+            let to = ((py * width + px) * 4) as usize;
+            let strength = coverage as f32 / 255.0;
+            for c in 0..4 {
+                data[to + c] = mix(original[from + c], data[to + c], strength);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Apply one effect weighted by `mask`, one coverage byte (0..=255) per
+/// pixel. `effect_json` names the effect as in a region:
+/// `{"effect": "gaussian_blur", "params": {"radius": 8}}`, with missing
+/// parameters taking the catalog defaults.
+#[wasm_bindgen]
+pub fn apply_with_mask(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    mask: &[u8],
+    effect_json: &str,
+) -> Result<(), JsError> {
+    let effect = Effect::from_spec(effect_json)?;
+    Ok(apply_masked(&effect, data, width, height, mask)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern;
+    use crate::types::Color;
+
+    #[test]
+    fn test_coverage_weights_the_effect() {
+        let (w, h) = (8, 6);
+        let mut mask = vec![0u8; 48];
+        mask[8 + 2] = 255;
+        mask[8 + 3] = 128;
+        mask[3 * 8 + 5] = 255;
+        let black = Effect::SolidFill {
+            color: Color::new(0, 0, 0),
+        };
+        let original = pattern(w, h);
+        let mut data = original.clone();
+        apply_masked(&black, &mut data, w, h, &mask).unwrap();
+        for (i, &m) in mask.iter().enumerate() {
+            let px = &data[i * 4..i * 4 + 3];
+            let was = &original[i * 4..i * 4 + 3];
+            match m {
+                255 => assert_eq!(px, [0, 0, 0]),
+                0 => assert_eq!(px, was),
+                _ => assert!(px
+                    .iter()
+                    .zip(was)
+                    .all(|(&p, &o)| p == mix(o, 0, m as f32 / 255.0))),
+            }
+        }
+    }
+
+    #[test]
+    fn test_full_mask_matches_the_rect_effect() {
+        let (w, h) = (10, 10);
+        let mut mask = vec![0u8; 100];
+        for y in 2..7 {
+            for x in 1..9 {
+                mask[y * 10 + x] = 255;
+            }
+        }
+        let blur = Effect::GaussianBlur { radius: 2 };
+        let mut data = pattern(w, h);
+        apply_masked(&blur, &mut data, w, h, &mask).unwrap();
+        let mut expected = pattern(w, h);
+        blur.apply_rect(&mut expected, w, h, Rect::new(1, 2, 8, 5));
+        assert_eq!(data, expected);
+
+        let err = apply_masked(&blur, &mut data, w, h, &mask[..99]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid mask 99: expected 100 values, one per pixel"
+        );
+    }
+}
@@ -0,0 +1,541 @@
+//! Post-export check that an encoded image carries no leftover metadata.
+//!
+//! Walks the container structure of PNG, JPEG and WebP files and lists
+//! every chunk/segment, flagging anything outside a small allowlist of
+//! structural and color-management blocks (EXIF, XMP, text chunks,
+//! comments, timestamps, and bytes after the end marker all count).
+//! `scrub` removes the flagged blocks and any redaction manifest; `strip`
+//! also drops color profiles, for uploads whose provenance is unknown.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::RedactError;
+use crate::json::Json;
+use crate::manifest::{is_clean_manifest, is_manifest, PNG_KEYWORD, XMP_NAMESPACE, XMP_PREFIX};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataBlock {
+    /// Chunk type (`tEXt`), segment name (`APP1/Exif`) or `trailing`
+    pub name: String,
+    pub offset: usize,
+    /// Payload length, excluding chunk/segment headers
+    pub length: usize,
+    /// One past the last byte of the block, headers and padding included
+    pub end: usize,
+    /// Whether the block is structural or otherwise allowed in scrubbed output
+    pub expected: bool,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataReport {
+    pub(crate) format: &'static str,
+    blocks: Vec<MetadataBlock>,
+}
+
+pub(crate) const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+// Ancillary chunks that only describe color or pixel geometry
+const PNG_ALLOWED: &[&str] = &[
+    "IHDR", "PLTE", "IDAT", "IEND", "tRNS", "sRGB", "gAMA", "cHRM", "iCCP", "sBIT", "pHYs",
+];
+const WEBP_ALLOWED: &[&str] = &["VP8 ", "VP8L", "VP8X", "ALPH", "ANIM", "ANMF", "ICCP"];
+
+fn malformed(detail: &'static str, offset: usize) -> RedactError {
+    RedactError::MalformedImage { detail, offset }
+}
+
+pub(crate) fn be32(bytes: &[u8], at: usize) -> Option<usize> {
+    let b = bytes.get(at..at + 4)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+fn le32(bytes: &[u8], at: usize) -> Option<usize> {
+    let b = bytes.get(at..at + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+fn block(
+    name: impl Into<String>,
+    offset: usize,
+    length: usize,
+    end: usize,
+    expected: bool,
+) -> MetadataBlock {
+    MetadataBlock {
+        name: name.into(),
+        offset,
+        length,
+        end,
+        expected,
+    }
+}
+
+fn trailing(blocks: &mut Vec<MetadataBlock>, bytes: &[u8], end: usize) {
+    if end < bytes.len() {
+        blocks.push(block(
+            "trailing",
+            end,
+            bytes.len() - end,
+            bytes.len(),
+            false,
+        ));
+    }
+}
+
+fn scan_png(bytes: &[u8]) -> Result<Vec<MetadataBlock>, RedactError> {
+    let mut blocks = Vec::new();
+    let mut at = PNG_SIGNATURE.len();
+    loop {
+        let len = be32(bytes, at).ok_or(malformed("truncated PNG chunk", at))?;
+        let kind = bytes
+            .get(at + 4..at + 8)
+            .ok_or(malformed("truncated PNG chunk", at))?;
+        let name = String::from_utf8_lossy(kind).into_owned();
+        // Lengths near u32::MAX would wrap a 32-bit usize back to `at`
+        let end = at
+            .checked_add(12)
+            .and_then(|n| n.checked_add(len))
+            .filter(|&end| end <= bytes.len())
+            .ok_or(malformed("PNG chunk overruns the file", at))?;
+        let is_end = name == "IEND";
+        let expected = PNG_ALLOWED.contains(&name.as_str());
+        let manifest = name == "iTXt" && bytes[at + 8..].starts_with(PNG_KEYWORD);
+        let (name, expected) = if manifest {
+            // Checked against the payload in `scan_metadata`
+            ("iTXt/redactr".to_string(), false)
+        } else {
+            (name, expected)
+        };
+        blocks.push(block(name, at, len, end, expected));
+        at = end;
+        if is_end {
+            break;
+        }
+    }
+    trailing(&mut blocks, bytes, at);
+    Ok(blocks)
+}
+
+fn jpeg_name(marker: u8, payload: &[u8]) -> (String, bool) {
+    let starts = |tag: &[u8]| payload.starts_with(tag);
+    match marker {
+        0xE0 if starts(b"JFIF\0") => ("APP0/JFIF".into(), true),
+        0xE1 if starts(b"Exif\0") => ("APP1/Exif".into(), false),
+        0xE1 if starts(XMP_NAMESPACE)
+            && payload[XMP_NAMESPACE.len()..].starts_with(XMP_PREFIX.as_bytes()) =>
+        {
+            ("APP1/redactr".into(), false)
+        }
+        0xE1 if starts(b"http://ns.adobe.com/xap/") => ("APP1/XMP".into(), false),
+        0xE2 if starts(b"ICC_PROFILE\0") => ("APP2/ICC".into(), true),
+        // Multi-picture index; the preview images follow EOI
+        0xE2 if starts(b"MPF\0") => ("APP2/MPF".into(), false),
+        0xE0..=0xEF => (format!("APP{}", marker - 0xE0), false),
+        0xFE => ("COM".into(), false),
+        0xC4 => ("DHT".into(), true),
+        0xCC => ("DAC".into(), true),
+        0xC0..=0xCF if marker != 0xC8 => (format!("SOF/{:02X}", marker), true),
+        0xDB => ("DQT".into(), true),
+        0xDD => ("DRI".into(), true),
+        0xDA => ("SOS".into(), true),
+        _ => (format!("marker {:02X}", marker), false),
+    }
+}
+
+fn scan_jpeg(bytes: &[u8]) -> Result<Vec<MetadataBlock>, RedactError> {
+    let mut blocks = Vec::new();
+    let mut at = 2;
+    loop {
+        if bytes.get(at) != Some(&0xFF) {
+            return Err(malformed("expected a JPEG marker", at));
+        }
+        // Markers may be preceded by any number of 0xFF fill bytes
+        while bytes.get(at + 1) == Some(&0xFF) {
+            at += 1;
+        }
+        let marker = *bytes
+            .get(at + 1)
+            .ok_or(malformed("truncated JPEG marker", at))?;
+        match marker {
+            0xD9 => {
+                blocks.push(block("EOI", at, 0, at + 2, true));
+                at += 2;
+                break;
+            }
+            0x01 | 0xD0..=0xD7 => {
+                at += 2;
+                continue;
+            }
+            _ => {}
+        }
+        let len = bytes
+            .get(at + 2..at + 4)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+            .ok_or(malformed("truncated JPEG segment", at))?;
+        let end = at + 2 + len;
+        let payload = bytes
+            .get(at + 4..end)
+            .ok_or(malformed("JPEG segment overruns the file", at))?;
+        let (name, expected) = jpeg_name(marker, payload);
+        let start = at;
+        at = end;
+
+        if marker == 0xDA {
+            // Skip entropy-coded data up to the next real marker
+            while at + 1 < bytes.len() {
+                if bytes[at] == 0xFF && !matches!(bytes[at + 1], 0x00 | 0xD0..=0xD7) {
+                    break;
+                }
+                at += 1;
+            }
+        }
+        blocks.push(block(name, start, len, at, expected));
+    }
+    trailing(&mut blocks, bytes, at);
+    Ok(blocks)
+}
+
+fn scan_webp(bytes: &[u8]) -> Result<Vec<MetadataBlock>, RedactError> {
+    let riff_end = le32(bytes, 4)
+        .ok_or(malformed("truncated RIFF header", 4))?
+        .checked_add(8)
+        .filter(|&end| end <= bytes.len())
+        .ok_or(malformed("RIFF size overruns the file", 4))?;
+    let mut blocks = Vec::new();
+    let mut at = 12;
+    while at < riff_end {
+        let kind = bytes
+            .get(at..at + 4)
+            .ok_or(malformed("truncated WebP chunk", at))?;
+        let name = String::from_utf8_lossy(kind).into_owned();
+        let len = le32(bytes, at + 4).ok_or(malformed("truncated WebP chunk", at))?;
+        // Chunk payloads are padded to an even length
+        let end = at
+            .checked_add(8 + (len & 1))
+            .and_then(|n| n.checked_add(len))
+            .filter(|&end| end <= riff_end)
+            .ok_or(malformed("WebP chunk overruns the file", at))?;
+        let (name, expected) =
+            if name == "XMP " && bytes[at + 8..].starts_with(XMP_PREFIX.as_bytes()) {
+                ("XMP/redactr".to_string(), false)
+            } else {
+                let expected = WEBP_ALLOWED.contains(&name.as_str());
+                (name, expected)
+            };
+        blocks.push(block(name, at, len, end, expected));
+        at = end;
+    }
+    trailing(&mut blocks, bytes, riff_end);
+    Ok(blocks)
+}
+
+/// List the blocks of an encoded PNG, JPEG or WebP file
+pub fn scan_metadata(bytes: &[u8]) -> Result<MetadataReport, RedactError> {
+    let (format, mut blocks) = if bytes.starts_with(PNG_SIGNATURE) {
+        ("png", scan_png(bytes)?)
+    } else if bytes.starts_with(&[0xFF, 0xD8]) {
+        ("jpeg", scan_jpeg(bytes)?)
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        ("webp", scan_webp(bytes)?)
+    } else if bytes.starts_with(b"%PDF-") {
+        return Err(RedactError::PdfNotSupported);
+    } else {
+        return Err(RedactError::UnknownFormat);
+    };
+    for b in blocks.iter_mut().filter(|b| is_manifest(b)) {
+        b.expected = is_clean_manifest(bytes, format, b);
+    }
+    Ok(MetadataReport { format, blocks })
+}
+
+/// VP8X feature flags for ICC, EXIF and XMP chunks
+const VP8X_ICC: u8 = 0x20;
+const VP8X_EXIF: u8 = 0x08;
+const VP8X_XMP: u8 = 0x04;
+/// Color profile blocks, kept by `scrub` and dropped by `strip`
+const ICC_BLOCKS: &[&str] = &["iCCP", "APP2/ICC", "ICCP"];
+
+/// Copy of an encoded image with every unexpected block removed: EXIF and
+/// its thumbnail, XMP, text chunks, comments, the JPEG multi-picture index
+/// and the previews appended after EOI. Previews are dropped rather than
+/// regenerated; nothing downstream needs them and a missing preview can't
+/// leak. An embedded redaction manifest is dropped too, whatever it holds;
+/// `embed_manifest` writes a fresh one. TIFF and PSD containers aren't
+/// supported.
+pub fn scrub(bytes: &[u8]) -> Result<Vec<u8>, RedactError> {
+    rewrite(bytes, &scan_metadata(bytes)?, false)
+}
+
+/// Like `scrub`, but also drops ICC color profiles, which can carry vendor
+/// and device descriptions; the image then renders as sRGB. Fails unless
+/// `bytes` are in `format` (`"png"`, `"jpeg"`/`"jpg"` or `"webp"`).
+pub fn strip(bytes: &[u8], format: &str) -> Result<Vec<u8>, RedactError> {
+    rewrite(bytes, &scan_as(bytes, format)?, true)
+}
+
+/// Scan `bytes`, failing unless they are in the caller-declared `format`
+/// (case-insensitive, `jpg` accepted for `jpeg`)
+pub(crate) fn scan_as(bytes: &[u8], format: &str) -> Result<MetadataReport, RedactError> {
+    let wanted = match format.to_ascii_lowercase().as_str() {
+        "png" => "png",
+        "jpeg" | "jpg" => "jpeg",
+        "webp" => "webp",
+        _ => {
+            return Err(RedactError::InvalidField {
+                field: format.to_string(),
+                expected: "png, jpeg or webp",
+            })
+        }
+    };
+    let report = scan_metadata(bytes)?;
+    if report.format != wanted {
+        return Err(malformed("bytes are not in the given format", 0));
+    }
+    Ok(report)
+}
+
+/// Copy the header and every expected block except manifests, minus color
+/// profiles when `drop_icc` is set, and patch the WebP flags and size to
+/// match
+fn rewrite(bytes: &[u8], report: &MetadataReport, drop_icc: bool) -> Result<Vec<u8>, RedactError> {
+    let mut cleared = VP8X_EXIF | VP8X_XMP;
+    if drop_icc {
+        cleared |= VP8X_ICC;
+    }
+    let header = report.blocks.first().map_or(bytes.len(), |b| b.offset);
+    let mut out = bytes[..header].to_vec();
+    let kept = report
+        .blocks
+        .iter()
+        .filter(|b| b.expected && !is_manifest(b))
+        .filter(|b| !(drop_icc && ICC_BLOCKS.contains(&b.name.as_str())));
+    for b in kept {
+        let start = out.len();
+        out.extend_from_slice(&bytes[b.offset..b.end]);
+        if report.format == "webp" && b.name == "VP8X" {
+            out[start + 8] &= !cleared;
+        }
+    }
+    if report.format == "webp" {
+        let riff_size = (out.len() - 8) as u32;
+        out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    }
+    Ok(out)
+}
+
+impl MetadataReport {
+    pub fn blocks(&self) -> &[MetadataBlock] {
+        &self.blocks
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        let blocks = self
+            .blocks
+            .iter()
+            .map(|b| {
+                Json::object()
+                    .with("name", b.name.as_str())
+                    .with("offset", b.offset)
+                    .with("length", b.length)
+                    .with("expected", b.expected)
+            })
+            .collect();
+        Json::object()
+            .with("format", self.format)
+            .with("clean", self.clean())
+            .with("unexpected", self.unexpected())
+            .with("blocks", Json::Array(blocks))
+    }
+}
+
+#[wasm_bindgen]
+impl MetadataReport {
+    /// `"png"`, `"jpeg"` or `"webp"`
+    #[wasm_bindgen(getter)]
+    pub fn format(&self) -> String {
+        self.format.to_string()
+    }
+
+    /// Whether no unexpected blocks remain
+    #[wasm_bindgen(getter)]
+    pub fn clean(&self) -> bool {
+        self.blocks.iter().all(|b| b.expected)
+    }
+
+    /// Names of the unexpected blocks, in file order
+    pub fn unexpected(&self) -> Vec<String> {
+        self.blocks
+            .iter()
+            .filter(|b| !b.expected)
+            .map(|b| b.name.clone())
+            .collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+}
+
+/// Enumerate the metadata left in an encoded image (e.g. the exported
+/// `Blob`'s bytes) and flag anything that shouldn't survive scrubbing
+#[wasm_bindgen]
+pub fn verify_metadata_scrubbed(bytes: &[u8]) -> Result<MetadataReport, JsError> {
+    Ok(scan_metadata(bytes)?)
+}
+
+/// Remove embedded metadata and previews from an encoded PNG, JPEG or WebP
+#[wasm_bindgen]
+pub fn scrub_metadata(bytes: &[u8]) -> Result<Vec<u8>, JsError> {
+    Ok(scrub(bytes)?)
+}
+
+/// Remove EXIF (with its thumbnail), XMP, IPTC, ICC profiles, comments and
+/// previews from an encoded image in `format` (`"png"`, `"jpeg"` or
+/// `"webp"`), e.g. an upload before it is opened for redaction
+#[wasm_bindgen]
+pub fn strip_metadata(bytes: &[u8], format: &str) -> Result<Vec<u8>, JsError> {
+    Ok(strip(bytes, format)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        // CRC isn't checked
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    fn png(chunks: &[(&[u8], &[u8])]) -> Vec<u8> {
+        let mut file = PNG_SIGNATURE.to_vec();
+        for (kind, data) in chunks {
+            file.extend(png_chunk(kind, data));
+        }
+        file
+    }
+
+    #[test]
+    fn test_clean_png() {
+        let file = png(&[(b"IHDR", &[0; 13]), (b"IDAT", &[1, 2, 3]), (b"IEND", &[])]);
+        let report = scan_metadata(&file).unwrap();
+        assert_eq!(report.format(), "png");
+        assert!(report.clean());
+        assert_eq!(report.blocks().len(), 3);
+    }
+
+    #[test]
+    fn test_png_text_and_trailing_data_are_flagged() {
+        let mut file = png(&[
+            (b"IHDR", &[0; 13]),
+            (b"tEXt", b"Author\0someone"),
+            (b"IDAT", &[1]),
+            (b"IEND", &[]),
+        ]);
+        file.extend_from_slice(b"hidden");
+        let report = scan_metadata(&file).unwrap();
+        assert!(!report.clean());
+        assert_eq!(report.unexpected(), ["tEXt", "trailing"]);
+    }
+
+    #[test]
+    fn test_jpeg_exif_is_flagged() {
+        let mut file = vec![0xFF, 0xD8];
+        file.extend_from_slice(&[0xFF, 0xE0, 0, 7]);
+        file.extend_from_slice(b"JFIF\0");
+        file.extend_from_slice(&[0xFF, 0xE1, 0, 8]);
+        file.extend_from_slice(b"Exif\0\0");
+        file.extend_from_slice(&[0xFF, 0xDA, 0, 2, 0x12, 0xFF, 0x00, 0x34]);
+        file.extend_from_slice(&[0xFF, 0xD9]);
+        let report = scan_metadata(&file).unwrap();
+        assert_eq!(report.format(), "jpeg");
+        assert_eq!(report.unexpected(), ["APP1/Exif"]);
+        assert_eq!(report.blocks().last().unwrap().name, "EOI");
+    }
+
+    #[test]
+    fn test_scrub_removes_previews_and_metadata() {
+        let mut file = vec![0xFF, 0xD8];
+        file.extend_from_slice(&[0xFF, 0xE1, 0, 8]);
+        file.extend_from_slice(b"Exif\0\0");
+        file.extend_from_slice(&[0xFF, 0xE2, 0, 6]);
+        file.extend_from_slice(b"MPF\0");
+        file.extend_from_slice(&[0xFF, 0xDB, 0, 3, 9]);
+        file.extend_from_slice(&[0xFF, 0xDA, 0, 2, 0x12, 0xFF, 0x00, 0x34]);
+        file.extend_from_slice(&[0xFF, 0xD9]);
+        // Second MPF picture (a preview) appended after EOI
+        file.extend_from_slice(&[0xFF, 0xD8, 0xFF, 0xD9]);
+
+        let clean = scrub(&file).unwrap();
+        let mut expected = vec![0xFF, 0xD8, 0xFF, 0xDB, 0, 3, 9];
+        expected.extend_from_slice(&[0xFF, 0xDA, 0, 2, 0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD9]);
+        assert_eq!(clean, expected);
+        assert!(scan_metadata(&clean).unwrap().clean());
+
+        let png = png(&[(b"IHDR", &[0; 13]), (b"eXIf", &[1, 2]), (b"IEND", &[])]);
+        assert!(scan_metadata(&scrub(&png).unwrap()).unwrap().clean());
+    }
+
+    #[test]
+    fn test_strip_also_drops_color_profiles_and_iptc() {
+        let mut file = vec![0xFF, 0xD8];
+        file.extend_from_slice(&[0xFF, 0xE2, 0, 16]);
+        file.extend_from_slice(b"ICC_PROFILE\0\x01\x01");
+        file.extend_from_slice(&[0xFF, 0xED, 0, 6]);
+        file.extend_from_slice(b"8BIM");
+        file.extend_from_slice(&[0xFF, 0xDA, 0, 2, 0x12, 0xFF, 0xD9]);
+        assert_eq!(scan_metadata(&file).unwrap().unexpected(), ["APP13"]);
+        assert_eq!(scrub(&file).unwrap().len(), file.len() - 8);
+        assert_eq!(
+            strip(&file, "JPG").unwrap(),
+            [0xFF, 0xD8, 0xFF, 0xDA, 0, 2, 0x12, 0xFF, 0xD9]
+        );
+
+        let png = png(&[(b"IHDR", &[0; 13]), (b"iCCP", &[7; 5]), (b"IEND", &[])]);
+        assert_eq!(strip(&png, "png").unwrap().len(), png.len() - 17);
+        assert!(strip(&png, "jpeg").is_err());
+        assert!(strip(&png, "gif").is_err());
+    }
+
+    #[test]
+    fn test_webp_exif_and_bad_input() {
+        let mut body = b"WEBP".to_vec();
+        body.extend_from_slice(b"VP8L");
+        body.extend_from_slice(&3u32.to_le_bytes());
+        body.extend_from_slice(&[1, 2, 3, 0]);
+        body.extend_from_slice(b"EXIF");
+        body.extend_from_slice(&2u32.to_le_bytes());
+        body.extend_from_slice(&[0, 0]);
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        file.extend(body);
+        assert_eq!(scan_metadata(&file).unwrap().unexpected(), ["EXIF"]);
+        let clean = scrub(&file).unwrap();
+        assert_eq!(clean.len(), file.len() - 10);
+        assert!(scan_metadata(&clean).unwrap().clean());
+
+        assert_eq!(scan_metadata(b"GIF89a"), Err(RedactError::UnknownFormat));
+        assert_eq!(
+            scan_metadata(b"%PDF-1.7\n"),
+            Err(RedactError::PdfNotSupported)
+        );
+        // A length near u32::MAX must not wrap the chunk end around
+        let mut huge = png(&[(b"IHDR", &[0; 13]), (b"IEND", &[])]);
+        huge[33..37].copy_from_slice(&0xFFFF_FFF4u32.to_be_bytes());
+        assert!(matches!(
+            scan_metadata(&huge),
+            Err(RedactError::MalformedImage { offset: 33, .. })
+        ));
+        let mut riff = b"RIFF\xF8\xFF\xFF\xFFWEBP".to_vec();
+        riff.extend_from_slice(b"VP8L\xF4\xFF\xFF\xFF");
+        assert!(scan_metadata(&riff).is_err());
+        let truncated = &png(&[(b"IHDR", &[0; 13])])[..12];
+        assert!(matches!(
+            scan_metadata(truncated),
+            Err(RedactError::MalformedImage { offset: 8, .. })
+        ));
+    }
+}
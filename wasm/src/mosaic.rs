@@ -0,0 +1,293 @@
+//! Mosaic: pixelation over square, hexagonal or triangular cells.
+//!
+//! Each cell is filled with the mean color of the region's pixels inside
+//! it, like `pixelate` blocks, with the grid anchored at the region's
+//! top-left corner. `cell_size` is the square's edge, the hexagon's width
+//! across flats, or the triangle's side. An optional grout line of
+//! `grout_width` pixels is drawn along the cell edges, as in tile mosaics.
+
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::effect::Effect;
+use crate::policy::{or_throw, policed_size};
+use crate::types::{Color, Rect};
+
+const SQRT_3: f32 = 1.732_050_8;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellShape {
+    Square = 0,
+    /// Pointy-top hexagons in offset rows
+    Hexagon = 1,
+    /// Alternating up and down equilateral triangles
+    Triangle = 2,
+}
+
+impl CellShape {
+    pub(crate) const NAMES: &'static [&'static str] = &["square", "hexagon", "triangle"];
+
+    pub(crate) fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<CellShape> {
+        match name {
+            "square" => Some(CellShape::Square),
+            "hexagon" => Some(CellShape::Hexagon),
+            "triangle" => Some(CellShape::Triangle),
+            _ => None,
+        }
+    }
+
+    /// The cell containing `(x, y)` (relative to the grid origin) and the
+    /// distance from the point to the nearest cell edge
+    fn locate(self, x: f32, y: f32, size: f32) -> ((i32, i32, i32), f32) {
+        match self {
+            CellShape::Square => {
+                let (fx, fy) = (x / size, y / size);
+                let (cx, cy) = (fx.floor(), fy.floor());
+                let edge = (fx - cx).min(cx + 1.0 - fx).min(fy - cy).min(cy + 1.0 - fy);
+                ((cx as i32, cy as i32, 0), edge * size)
+            }
+            CellShape::Hexagon => {
+                // Circumradius; flats are `size` apart
+                let r = size / SQRT_3;
+                let q = (SQRT_3 / 3.0 * x - y / 3.0) / r;
+                let s = 2.0 / 3.0 * y / r;
+                let (q, s) = round_axial(q, s);
+                let cx = r * SQRT_3 * (q as f32 + s as f32 / 2.0);
+                let cy = r * 1.5 * s as f32;
+                let (dx, dy) = (x - cx, y - cy);
+                // Flats face 0, 60 and 120 degrees
+                let reach = dx
+                    .abs()
+                    .max((dx * 0.5 + dy * SQRT_3 / 2.0).abs())
+                    .max((dx * 0.5 - dy * SQRT_3 / 2.0).abs());
+                ((q, s, 0), size / 2.0 - reach)
+            }
+            CellShape::Triangle => {
+                // Three families of parallel lines, `height` apart, cut the
+                // plane into triangles; the floors along each identify one
+                let height = size * SQRT_3 / 2.0;
+                let along = [
+                    y / height,
+                    (SQRT_3 / 2.0 * x - y / 2.0) / height,
+                    (-SQRT_3 / 2.0 * x - y / 2.0) / height,
+                ];
+                let cell = along.map(|t| t.floor() as i32);
+                let edge = along
+                    .iter()
+                    .map(|t| (t - t.floor()).min(t.ceil() - t))
+                    .fold(f32::INFINITY, f32::min);
+                ((cell[0], cell[1], cell[2]), edge * height)
+            }
+        }
+    }
+}
+
+/// Round fractional axial hex coordinates to the containing hexagon
+fn round_axial(q: f32, s: f32) -> (i32, i32) {
+    let t = -q - s;
+    let (mut rq, mut rs, rt) = (q.round(), s.round(), t.round());
+    let (dq, ds, dt) = ((rq - q).abs(), (rs - s).abs(), (rt - t).abs());
+    if dq > ds && dq > dt {
+        rq = -rs - rt;
+    } else if ds > dt {
+        rs = -rq - rt;
+    }
+    (rq as i32, rs as i32)
+}
+
+/// Mosaic `rect` (clamped to the image) in place; a `grout_width` of 0
+/// draws no grout
+pub(crate) fn mosaic_rect(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+    cell_size: u32,
+    shape: CellShape,
+    grout: Color,
+    grout_width: u32,
+) {
+    let Some(rect) = rect.clip(width, height) else {
+        return;
+    };
+    let size = cell_size.max(1) as f32;
+    let half_grout = grout_width as f32 / 2.0;
+    let mut cells: HashMap<(i32, i32, i32), [u32; 4]> = HashMap::new();
+    let mut located = Vec::with_capacity((rect.w * rect.h) as usize);
+    for y in 0..rect.h {
+        for x in 0..rect.w {
+            let idx = (((rect.y + y) * width + rect.x + x) * 4) as usize;
+            let (cell, edge) = shape.locate(x as f32 + 0.5, y as f32 + 0.5, size);
+            if let Some(px) = data.get(idx..idx + 3) {
+                let sums = cells.entry(cell).or_default();
+                for (sum, &v) in sums.iter_mut().zip(px) {
+                    *sum += v as u32;
+                }
+                sums[3] += 1;
+            }
+            located.push((idx, cell, edge < half_grout));
+        }
+    }
+    for (idx, cell, in_grout) in located {
+        let Some(px) = data.get_mut(idx..idx + 3) else {
+            continue;
+        };
+        if in_grout {
+            px.copy_from_slice(&[grout.r, grout.g, grout.b]);
+        } else {
+            let [r, g, b, count] = cells[&cell];
+            px.copy_from_slice(&[(r / count) as u8, (g / count) as u8, (b / count) as u8]);
+        }
+    }
+}
+
+/// Mosaic a region with cells of `shape`, `cell_size` pixels across, and
+/// grout lines `grout_width` pixels wide (0 for none) in the given color
+#[wasm_bindgen]
+pub fn mosaic(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    cell_size: u32,
+    shape: CellShape,
+    grout_width: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+) {
+    let rect = Rect::new(x, y, w, h);
+    let grout = Color::new(r, g, b);
+    let effect = Effect::Mosaic {
+        cell_size,
+        shape,
+        grout,
+        grout_width,
+    };
+    let cell_size = or_throw(policed_size(effect, rect));
+    mosaic_rect(
+        data,
+        width,
+        height,
+        rect,
+        cell_size,
+        shape,
+        grout,
+        grout_width,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::Effect;
+    use crate::json::Json;
+
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| [(i % width * 4) as u8, (i / width * 4) as u8, 90, 255])
+            .collect()
+    }
+
+    fn colors(data: &[u8]) -> std::collections::HashSet<[u8; 3]> {
+        data.chunks_exact(4)
+            .map(|px| [px[0], px[1], px[2]])
+            .collect()
+    }
+
+    #[test]
+    fn test_square_cells_match_pixelate() {
+        let mut data = gradient(30, 20);
+        let mut expected = data.clone();
+        mosaic(
+            &mut data,
+            30,
+            20,
+            3,
+            2,
+            24,
+            16,
+            6,
+            CellShape::Square,
+            0,
+            0,
+            0,
+            0,
+        );
+        crate::pixelate(&mut expected, 30, 20, 3, 2, 24, 16, 6);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_hexagons_and_triangles_are_flat_cells() {
+        for (shape, cells) in [
+            (CellShape::Hexagon, 20..=40),
+            (CellShape::Triangle, 40..=90),
+        ] {
+            let mut data = gradient(40, 40);
+            mosaic(&mut data, 40, 40, 0, 0, 40, 40, 10, shape, 0, 0, 0, 0);
+            let found = colors(&data).len();
+            assert!(cells.contains(&found), "{:?}: {} cells", shape, found);
+            // Neighbouring pixels deep inside a cell share its color
+            let center = |x: usize, y: usize| &data[(y * 40 + x) * 4..][..3];
+            let (x, y) = match shape {
+                CellShape::Hexagon => (5, 8),
+                _ => (5, 4),
+            };
+            assert_eq!(center(x, y), center(x + 1, y));
+        }
+    }
+
+    #[test]
+    fn test_grout_outlines_cells() {
+        let mut data = gradient(40, 40);
+        mosaic(
+            &mut data,
+            40,
+            40,
+            0,
+            0,
+            40,
+            40,
+            10,
+            CellShape::Square,
+            2,
+            255,
+            0,
+            255,
+        );
+        let grout = |x: usize, y: usize| data[(y * 40 + x) * 4..][..3] == [255, 0, 255];
+        assert!(grout(10, 5) && grout(9, 5) && grout(5, 0));
+        assert!(!grout(5, 5) && !grout(15, 15));
+        // Alpha is untouched
+        assert!(data.chunks_exact(4).all(|px| px[3] == 255));
+    }
+
+    #[test]
+    fn test_effect_round_trips_through_json() {
+        let effect = Effect::Mosaic {
+            cell_size: 14,
+            shape: CellShape::Triangle,
+            grout: Color::new(255, 255, 255),
+            grout_width: 2,
+        };
+        let params = effect.params_json();
+        assert_eq!(Effect::from_json("mosaic", Some(&params)).unwrap(), effect);
+        // Missing parameters take the catalog defaults
+        let Effect::Mosaic { shape, .. } = Effect::from_json("mosaic", None).unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(shape, CellShape::Hexagon);
+        let bad = Json::parse(r#"{"shape": "circle"}"#).unwrap();
+        assert!(Effect::from_json("mosaic", Some(&bad)).is_err());
+    }
+}
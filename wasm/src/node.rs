@@ -0,0 +1,57 @@
+//! Entry points for hosts without a canvas: Node, Deno, serverless.
+//!
+//! The in-place functions take `&mut [u8]`, which a browser fills from
+//! `ImageData`. `redact_pixels` takes a `Uint8Array` (a Node `Buffer` is
+//! one) and returns a new one, so a server can pipe pixels through without
+//! keeping a mutable pixel view around; `redact_encoded` does the same for
+//! whole PNG, JPEG or WebP files. The module itself needs nothing from the
+//! browser; build it with `wasm-pack build --target nodejs`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, RedactError};
+use crate::RedactionPlan;
+
+pub(crate) fn redacted_pixels(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    plan_json: &str,
+) -> Result<Vec<u8>, RedactError> {
+    check_buffer(pixels.len(), width, height)?;
+    let plan = RedactionPlan::parse(plan_json)?;
+    let mut out = pixels.to_vec();
+    plan.apply_to(&mut out, width, height)?;
+    Ok(out)
+}
+
+/// A redacted copy of RGBA `pixels`, with a plan (`RedactionPlan` JSON)
+/// scaled to the image; the input is left untouched
+#[wasm_bindgen]
+pub fn redact_pixels(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    plan_json: &str,
+) -> Result<Vec<u8>, JsError> {
+    Ok(redacted_pixels(pixels, width, height, plan_json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAN: &str = r##"{"version": 1, "width": 8, "height": 4, "regions": [
+        {"rect": {"x": 0, "y": 0, "w": 4, "h": 4}, "effect": "solid_fill",
+         "params": {"color": "#000000"}}]}"##;
+
+    #[test]
+    fn test_pixels_are_copied_not_mutated() {
+        let pixels = vec![200u8; 8 * 4 * 4];
+        let out = redacted_pixels(&pixels, 8, 4, PLAN).unwrap();
+        assert!(pixels.iter().all(|&b| b == 200));
+        assert_eq!(out[..4], [0, 0, 0, 200]);
+        assert_eq!(out[4 * 4..4 * 4 + 4], [200; 4]);
+        assert!(redacted_pixels(&pixels[..12], 8, 4, PLAN).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+//! Noise fill: regions replaced with unpredictable random color.
+//!
+//! Blur and pixelation keep a function of the original pixels, which can
+//! sometimes be inverted or matched against candidates. Noise keeps
+//! nothing: every covered pixel is overwritten with bytes from a SHA-256
+//! keystream over a caller-supplied secret seed. Pass fresh bytes from
+//! `crypto.getRandomValues` for every image; the same seed reproduces the
+//! same noise, which is only useful in tests.
+//!
+//! As an `Effect::NoiseFill` the seed is held as 32 bytes: a 32-byte seed
+//! is used as is, any other length is first hashed down to 32 with SHA-256.
+
+use wasm_bindgen::prelude::*;
+
+use crate::effect::Effect;
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::for_each_brush_pixel;
+use crate::hash::sha256;
+use crate::policy::policed;
+use crate::types::Rect;
+
+/// Shortest seed accepted, in bytes (128 bits)
+pub const MIN_SEED_BYTES: usize = 16;
+
+/// A caller's seed as the 32 bytes the keystream is keyed with
+pub(crate) fn noise_key(seed: &[u8]) -> Result<[u8; 32], RedactError> {
+    if seed.len() < MIN_SEED_BYTES {
+        return Err(RedactError::InvalidParameter {
+            name: "seed length",
+            value: seed.len() as f64,
+            expected: format!("at least {} random bytes", MIN_SEED_BYTES),
+        });
+    }
+    Ok(seed.try_into().unwrap_or_else(|_| sha256(seed)))
+}
+
+/// SHA-256 in counter mode: block `n` is `sha256(key || n)`
+struct NoiseStream {
+    key: [u8; 32],
+    counter: u64,
+    block: [u8; 32],
+    used: usize,
+}
+
+impl NoiseStream {
+    fn new(key: [u8; 32]) -> NoiseStream {
+        NoiseStream {
+            key,
+            counter: 0,
+            block: [0; 32],
+            used: 32,
+        }
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        if self.used == self.block.len() {
+            let mut input = self.key.to_vec();
+            input.extend_from_slice(&self.counter.to_le_bytes());
+            self.block = sha256(&input);
+            self.counter += 1;
+            self.used = 0;
+        }
+        self.used += 1;
+        self.block[self.used - 1]
+    }
+
+    /// Overwrite the RGB of the pixel at byte offset `i`, keeping alpha
+    fn paint(&mut self, data: &mut [u8], i: usize) {
+        for byte in &mut data[i..i + 3] {
+            *byte = self.next_byte();
+        }
+    }
+}
+
+/// Noise over `rect` (clamped to the image) keyed with `key`
+pub(crate) fn paint_rect(data: &mut [u8], width: u32, height: u32, rect: Rect, key: [u8; 32]) {
+    let Some(rect) = rect.clip(width, height) else {
+        return;
+    };
+    let mut stream = NoiseStream::new(key);
+    for y in rect.y..rect.bottom() {
+        for x in rect.x..rect.right() {
+            stream.paint(data, ((y * width + x) * 4) as usize);
+        }
+    }
+}
+
+pub(crate) fn noise_rect(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+    seed: &[u8],
+) -> Result<(), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    check_region(rect, width, height)?;
+    let noise = Effect::NoiseFill {
+        seed: noise_key(seed)?,
+    };
+    policed(noise, rect)?.apply_rect(data, width, height, rect);
+    Ok(())
+}
+
+pub(crate) fn noise_brush(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    seed: &[u8],
+) -> Result<(), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    let seed = noise_key(seed)?;
+    // A policy may refuse noise but never changes its seed
+    policed(Effect::NoiseFill { seed }, Rect::new(0, 0, width, height))?;
+    let mut stream = NoiseStream::new(seed);
+    for_each_brush_pixel(width, height, points, brush_size, |x, y| {
+        stream.paint(data, ((y * width + x) * 4) as usize);
+    });
+    Ok(())
+}
+
+/// Replace a region with random RGB noise; `seed` must be at least 16
+/// bytes from `crypto.getRandomValues`, fresh for each image
+#[wasm_bindgen]
+pub fn noise_fill(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    seed: &[u8],
+) -> Result<(), JsError> {
+    Ok(noise_rect(
+        data,
+        width,
+        height,
+        Rect::new(x, y, w, h),
+        seed,
+    )?)
+}
+
+/// Replace brush strokes with random RGB noise (see `noise_fill`)
+#[wasm_bindgen]
+pub fn brush_noise_fill(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    seed: &[u8],
+) -> Result<(), JsError> {
+    Ok(noise_brush(data, width, height, points, brush_size, seed)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: [u8; 16] = *b"0123456789abcdef";
+
+    #[test]
+    fn test_noise_replaces_region_and_keeps_alpha() {
+        let original: Vec<u8> = (0..16 * 16).flat_map(|_| [90, 90, 90, 200]).collect();
+        let mut data = original.clone();
+        noise_rect(&mut data, 16, 16, Rect::new(4, 4, 8, 8), &SEED).unwrap();
+
+        let inside: Vec<&[u8]> = (4..12)
+            .flat_map(|y| (4..12).map(move |x| (y * 16 + x) * 4))
+            .map(|i| &data[i..i + 4])
+            .collect();
+        assert!(inside.iter().all(|px| px[3] == 200));
+        // Noise, not a flat color: nearly every pixel differs from the next
+        let distinct = inside.windows(2).filter(|p| p[0] != p[1]).count();
+        assert!(distinct > 60, "{}", distinct);
+        assert_eq!(&data[..4], &original[..4]);
+
+        // Same seed, same noise; another seed, different noise
+        let mut again = original.clone();
+        noise_rect(&mut again, 16, 16, Rect::new(4, 4, 8, 8), &SEED).unwrap();
+        assert_eq!(again, data);
+        let mut other = original.clone();
+        noise_rect(
+            &mut other,
+            16,
+            16,
+            Rect::new(4, 4, 8, 8),
+            b"fedcba9876543210",
+        )
+        .unwrap();
+        assert_ne!(other, data);
+    }
+
+    #[test]
+    fn test_noise_rejects_short_seeds() {
+        let mut data = vec![0u8; 4 * 4 * 4];
+        assert_eq!(
+            noise_rect(&mut data, 4, 4, Rect::new(0, 0, 2, 2), &SEED[..8])
+                .unwrap_err()
+                .to_string(),
+            "invalid seed length 8: expected at least 16 random bytes"
+        );
+        assert!(noise_brush(&mut data, 4, 4, &[1.0, 1.0], 2, &[]).is_err());
+        assert_eq!(data, vec![0u8; 64]);
+
+        noise_brush(&mut data, 4, 4, &[1.0, 1.0], 2, &SEED).unwrap();
+        assert_ne!(data, vec![0u8; 64]);
+    }
+}
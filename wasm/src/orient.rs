@@ -0,0 +1,155 @@
+//! Whole-image rotation by quarter turns and mirroring.
+//!
+//! Normalizing orientation here, before any region is applied, keeps every
+//! coordinate in one frame; `transform_region` maps a rectangle found on
+//! the untransformed image into the transformed one with the same rules.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, RedactError};
+use crate::image::RedactrImage;
+use crate::types::Rect;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    /// Quarter turn clockwise
+    Rotate90 = 0,
+    Rotate180 = 1,
+    /// Quarter turn counter-clockwise
+    Rotate270 = 2,
+    /// Mirror left to right
+    FlipHorizontal = 3,
+    /// Mirror top to bottom
+    FlipVertical = 4,
+}
+
+impl Transform {
+    /// Output dimensions for a `width` x `height` input
+    fn size(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Transform::Rotate90 | Transform::Rotate270 => (height, width),
+            _ => (width, height),
+        }
+    }
+
+    /// Where the `w` x `h` block at (`x`, `y`) lands, as its new top-left
+    /// corner and size
+    fn map(self, rect: Rect, width: u32, height: u32) -> Rect {
+        let Rect { x, y, w, h } = rect;
+        match self {
+            Transform::Rotate90 => Rect::new(height - y - h, x, h, w),
+            Transform::Rotate180 => Rect::new(width - x - w, height - y - h, w, h),
+            Transform::Rotate270 => Rect::new(y, width - x - w, h, w),
+            Transform::FlipHorizontal => Rect::new(width - x - w, y, w, h),
+            Transform::FlipVertical => Rect::new(x, height - y - h, w, h),
+        }
+    }
+}
+
+/// Transformed copy of the image and its new dimensions
+pub(crate) fn transform_pixels(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    transform: Transform,
+) -> Result<(u32, u32, Vec<u8>), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    let (out_w, out_h) = transform.size(width, height);
+    let mut out = vec![0u8; data.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let to = transform.map(Rect::new(x, y, 1, 1), width, height);
+            let (src, dst) = ((y * width + x) * 4, (to.y * out_w + to.x) * 4);
+            out[dst as usize..][..4].copy_from_slice(&data[src as usize..][..4]);
+        }
+    }
+    Ok((out_w, out_h, out))
+}
+
+/// Rotate or mirror the whole image; the result carries the new dimensions
+#[wasm_bindgen]
+pub fn transform_image(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    transform: Transform,
+) -> Result<RedactrImage, JsError> {
+    let (w, h, pixels) = transform_pixels(data, width, height, transform)?;
+    Ok(RedactrImage::from_rgba(w, h, pixels)?)
+}
+
+/// Map `region` of a `width` x `height` image through `transform`, after
+/// clipping it to the image
+#[wasm_bindgen]
+pub fn transform_region(
+    region: &Rect,
+    width: u32,
+    height: u32,
+    transform: Transform,
+) -> Result<Rect, JsError> {
+    let clipped = region
+        .clip(width, height)
+        .ok_or(RedactError::RegionOutOfBounds {
+            region: *region,
+            width,
+            height,
+        })?;
+    Ok(transform.map(clipped, width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3x2 image whose red channel is the pixel index
+    fn indexed() -> Vec<u8> {
+        (0..6u8).flat_map(|i| [i, 0, 0, 255]).collect()
+    }
+
+    fn reds(data: &[u8]) -> Vec<u8> {
+        data.chunks_exact(4).map(|px| px[0]).collect()
+    }
+
+    #[test]
+    fn test_rotations_and_flips() {
+        // 0 1 2
+        // 3 4 5
+        let cases = [
+            (Transform::Rotate90, (2, 3), vec![3, 0, 4, 1, 5, 2]),
+            (Transform::Rotate180, (3, 2), vec![5, 4, 3, 2, 1, 0]),
+            (Transform::Rotate270, (2, 3), vec![2, 5, 1, 4, 0, 3]),
+            (Transform::FlipHorizontal, (3, 2), vec![2, 1, 0, 5, 4, 3]),
+            (Transform::FlipVertical, (3, 2), vec![3, 4, 5, 0, 1, 2]),
+        ];
+        for (transform, size, expected) in cases {
+            let (w, h, out) = transform_pixels(&indexed(), 3, 2, transform).unwrap();
+            assert_eq!(((w, h), reds(&out)), (size, expected), "{:?}", transform);
+        }
+        assert!(transform_pixels(&indexed()[..20], 3, 2, Transform::Rotate90).is_err());
+    }
+
+    #[test]
+    fn test_regions_follow_their_pixels() {
+        let (width, height) = (10, 6);
+        let data: Vec<u8> = (0..width * height)
+            .flat_map(|i| [(i % 251) as u8, 0, 0, 255])
+            .collect();
+        let rect = Rect::new(2, 1, 3, 4);
+        for transform in [
+            Transform::Rotate90,
+            Transform::Rotate270,
+            Transform::FlipVertical,
+        ] {
+            let (w, _, out) = transform_pixels(&data, width, height, transform).unwrap();
+            let moved = transform.map(rect, width, height);
+            let sum = |buf: &[u8], r: Rect, stride: u32| -> u32 {
+                (r.y..r.bottom())
+                    .flat_map(|y| (r.x..r.right()).map(move |x| (y * stride + x) as usize))
+                    .map(|i| buf[i * 4] as u32)
+                    .sum()
+            };
+            assert_eq!(sum(&out, moved, w), sum(&data, rect, width));
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! Named fill palettes checked for contrast.
+//!
+//! A palette is a set of fill colors for a given page color plus the ink
+//! for labels drawn on them. Every fill must stand out from the page by
+//! WCAG's 3:1 for graphics and carry its ink at the 4.5:1 required for
+//! text, so exported documents pass accessibility review without each team
+//! vetting its own RGB triples. Built-ins cover the common cases; brand
+//! palettes are registered at runtime and checked the same way.
+//!
+//! Labels stamped over a fill or straight over the image pick their ink
+//! here too: whichever of black and white (or of a palette's colors)
+//! contrasts most with what's underneath.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::types::{Color, Rect};
+
+/// Built-in palette names, in the order `palette_names` lists them
+pub const BUILTIN_PALETTES: [&str; 3] = ["high_contrast", "colorblind_safe", "dark_mode"];
+/// Minimum contrast of a fill against the page (WCAG 1.4.11)
+pub const MIN_FILL_CONTRAST: f32 = 3.0;
+/// Minimum contrast of label ink on a fill (WCAG 1.4.3)
+pub const MIN_INK_CONTRAST: f32 = 4.5;
+
+thread_local! {
+    static USER_PALETTES: RefCell<BTreeMap<String, Palette>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// WCAG relative luminance, 0 (black) ..= 1 (white)
+pub(crate) fn relative_luminance(color: Color) -> f32 {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(color.r) + 0.7152 * linear(color.g) + 0.0722 * linear(color.b)
+}
+
+/// WCAG contrast ratio between two colors, 1 ..= 21
+#[wasm_bindgen]
+pub fn contrast_ratio(a: &Color, b: &Color) -> f32 {
+    luminance_contrast(relative_luminance(*a), relative_luminance(*b))
+}
+
+/// Black or white, whichever is more legible on a `background` fill
+#[wasm_bindgen]
+pub fn label_ink(background: &Color) -> Color {
+    legible_ink(*background)
+}
+
+/// Mean relative luminance of the pixels in `rect`
+pub(crate) fn region_luminance(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+) -> Result<f32, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    check_region(rect, width, height)?;
+    let rect = rect.clip(width, height).unwrap_or(rect);
+    let mut total = 0.0;
+    for y in rect.y..rect.bottom() {
+        let start = ((y * width + rect.x) * 4) as usize;
+        for px in data[start..start + rect.w as usize * 4].chunks_exact(4) {
+            total += relative_luminance(Color::new(px[0], px[1], px[2]));
+        }
+    }
+    Ok(total / (rect.w * rect.h) as f32)
+}
+
+/// Black or white, whichever is more legible stamped straight over the
+/// image region (`x`, `y`, `w`, `h`)
+#[wasm_bindgen]
+pub fn region_label_ink(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+) -> Result<Color, JsError> {
+    let luminance = region_luminance(data, width, height, Rect::new(x, y, w, h))?;
+    Ok(most_legible(luminance, &[]))
+}
+
+fn luminance_contrast(a: f32, b: f32) -> f32 {
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Of `candidates`, the one with the most contrast on a background of
+/// relative luminance `background`; black or white when there are none
+fn most_legible(background: f32, candidates: &[Color]) -> Color {
+    let black_white = [Color::new(0, 0, 0), Color::new(255, 255, 255)];
+    let candidates = if candidates.is_empty() {
+        &black_white[..]
+    } else {
+        candidates
+    };
+    candidates
+        .iter()
+        .copied()
+        .max_by(|a, b| {
+            let contrast = |c: &Color| luminance_contrast(relative_luminance(*c), background);
+            contrast(a).total_cmp(&contrast(b))
+        })
+        .unwrap()
+}
+
+/// Black or white, whichever reads better on `background`
+pub(crate) fn legible_ink(background: Color) -> Color {
+    most_legible(relative_luminance(background), &[])
+}
+
+fn check_contrast(foreground: Color, background: Color, minimum: f32) -> Result<(), RedactError> {
+    let ratio = contrast_ratio(&foreground, &background);
+    if ratio < minimum {
+        return Err(RedactError::LowContrast {
+            foreground: foreground.hex(),
+            background: background.hex(),
+            ratio,
+            minimum,
+        });
+    }
+    Ok(())
+}
+
+/// Fill colors for one page color and the ink for labels on them
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    fills: Vec<Color>,
+    ink: Color,
+    page: Color,
+}
+
+impl Palette {
+    /// Check every fill against the page and the ink
+    pub(crate) fn validate(&self) -> Result<(), RedactError> {
+        if self.fills.is_empty() {
+            return Err(RedactError::InvalidField {
+                field: "fills".to_string(),
+                expected: "at least one #rrggbb color",
+            });
+        }
+        for &fill in &self.fills {
+            check_contrast(fill, self.page, MIN_FILL_CONTRAST)?;
+            check_contrast(self.ink, fill, MIN_INK_CONTRAST)?;
+        }
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+impl Palette {
+    /// Palette from `#rrggbb` colors; fails unless every fill has enough
+    /// contrast with `page` and `ink`
+    #[wasm_bindgen(constructor)]
+    pub fn new(fills: Vec<String>, ink: &str, page: &str) -> Result<Palette, JsError> {
+        let color = |field: &str, text: &str| {
+            Color::from_hex(text).ok_or_else(|| RedactError::InvalidField {
+                field: field.to_string(),
+                expected: "a #rrggbb color",
+            })
+        };
+        let palette = Palette {
+            fills: fills
+                .iter()
+                .map(|fill| color("fills", fill))
+                .collect::<Result<_, _>>()?,
+            ink: color("ink", ink)?,
+            page: color("page", page)?,
+        };
+        palette.validate()?;
+        Ok(palette)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.fills.len()
+    }
+
+    /// Fill `index`, wrapping around so any index picks a color
+    pub fn fill(&self, index: usize) -> Color {
+        self.fills[index % self.fills.len()]
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn ink(&self) -> Color {
+        self.ink
+    }
+
+    /// Page color the fills were checked against
+    #[wasm_bindgen(getter)]
+    pub fn page(&self) -> Color {
+        self.page
+    }
+
+    /// The palette color (ink or a fill) most legible on `background`
+    pub fn ink_for(&self, background: &Color) -> Color {
+        let mut candidates = vec![self.ink];
+        candidates.extend_from_slice(&self.fills);
+        most_legible(relative_luminance(*background), &candidates)
+    }
+}
+
+fn builtin(name: &str) -> Option<Palette> {
+    let hex = |text: &str| Color::from_hex(text).unwrap();
+    let (fills, ink, page): (&[&str], _, _) = match name {
+        // Black and navy bars on white paper
+        "high_contrast" => (&["#000000", "#000080"], "#ffffff", "#ffffff"),
+        // Dark hues that stay distinct under red-green and blue-yellow
+        // color blindness
+        "colorblind_safe" => (
+            &["#332288", "#117733", "#882255", "#0072b2"],
+            "#ffffff",
+            "#ffffff",
+        ),
+        // Light bars with black labels on dark-theme screenshots
+        "dark_mode" => (
+            &["#e0e0e0", "#9ecaff", "#ffb4ab", "#d0bcff"],
+            "#000000",
+            "#121212",
+        ),
+        _ => return None,
+    };
+    Some(Palette {
+        fills: fills.iter().map(|f| hex(f)).collect(),
+        ink: hex(ink),
+        page: hex(page),
+    })
+}
+
+/// Look up a built-in or registered palette
+pub fn resolve_palette(name: &str) -> Result<Palette, RedactError> {
+    if let Some(palette) = builtin(name) {
+        return Ok(palette);
+    }
+    USER_PALETTES
+        .with(|palettes| palettes.borrow().get(name).cloned())
+        .ok_or_else(|| RedactError::UnknownPalette {
+            name: name.to_string(),
+        })
+}
+
+pub(crate) fn register(name: &str, palette: &Palette) -> Result<(), RedactError> {
+    if builtin(name).is_some() {
+        return Err(RedactError::ReservedPalette {
+            name: name.to_string(),
+        });
+    }
+    palette.validate()?;
+    USER_PALETTES.with(|palettes| {
+        palettes
+            .borrow_mut()
+            .insert(name.to_string(), palette.clone())
+    });
+    Ok(())
+}
+
+/// Register (or replace) a brand palette under `name`
+#[wasm_bindgen]
+pub fn register_palette(name: &str, palette: &Palette) -> Result<(), JsError> {
+    Ok(register(name, palette)?)
+}
+
+/// Remove a brand palette; returns whether one was registered
+#[wasm_bindgen]
+pub fn unregister_palette(name: &str) -> bool {
+    USER_PALETTES.with(|palettes| palettes.borrow_mut().remove(name).is_some())
+}
+
+/// The palette a name resolves to
+#[wasm_bindgen]
+pub fn palette(name: &str) -> Result<Palette, JsError> {
+    Ok(resolve_palette(name)?)
+}
+
+/// Built-in palette names followed by registered ones (sorted)
+#[wasm_bindgen]
+pub fn palette_names() -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN_PALETTES.iter().map(|n| n.to_string()).collect();
+    USER_PALETTES.with(|palettes| names.extend(palettes.borrow().keys().cloned()));
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtins_pass_their_own_checks() {
+        for name in BUILTIN_PALETTES {
+            resolve_palette(name).unwrap().validate().unwrap();
+        }
+        let black = Color::new(0, 0, 0);
+        let white = Color::new(255, 255, 255);
+        assert!((contrast_ratio(&black, &white) - 21.0).abs() < 1e-4);
+        assert_eq!(contrast_ratio(&white, &white), 1.0);
+    }
+
+    #[test]
+    fn test_ink_contrasts_with_what_is_underneath() {
+        let white = Color::new(255, 255, 255);
+        let black = Color::new(0, 0, 0);
+        assert_eq!(legible_ink(Color::new(250, 210, 0)), black);
+        assert_eq!(legible_ink(Color::new(0, 0, 128)), white);
+
+        let dark = resolve_palette("dark_mode").unwrap();
+        assert_eq!(dark.ink_for(&Color::new(0x12, 0x12, 0x12)), dark.fill(0));
+        assert_eq!(dark.ink_for(&Color::new(0xe0, 0xe0, 0xe0)), black);
+
+        // Mostly dark photo region with a bright corner
+        let data: Vec<u8> = (0..16)
+            .flat_map(|i| if i == 0 { [255; 4] } else { [20, 20, 20, 255] })
+            .collect();
+        let luminance = region_luminance(&data, 4, 4, Rect::new(0, 0, 4, 4)).unwrap();
+        assert_eq!(most_legible(luminance, &[]), white);
+        assert!(region_luminance(&data, 4, 4, Rect::new(4, 0, 1, 1)).is_err());
+    }
+
+    #[test]
+    fn test_brand_palettes_are_checked_and_resolve() {
+        let brand = Palette {
+            fills: vec![Color::new(0x00, 0x2b, 0x5c)],
+            ink: Color::new(255, 255, 255),
+            page: Color::new(255, 255, 255),
+        };
+        register("acme", &brand).unwrap();
+        assert_eq!(resolve_palette("acme").unwrap().fill(3), brand.fills[0]);
+        assert!(palette_names().contains(&"acme".to_string()));
+        assert!(unregister_palette("acme"));
+        assert!(resolve_palette("acme").is_err());
+
+        // Pale yellow disappears on white paper
+        let pale = Palette {
+            fills: vec![Color::new(0xff, 0xee, 0x88)],
+            ..brand
+        };
+        assert_eq!(
+            register("pale", &pale).unwrap_err().to_string(),
+            "#ffee88 on #ffffff has contrast 1.18:1, below the required 3:1"
+        );
+        assert!(register("dark_mode", &brand).is_err());
+    }
+}
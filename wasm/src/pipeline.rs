@@ -0,0 +1,624 @@
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+
+use crate::audit::{op_params, AuditLog};
+use crate::blend::apply_blended;
+use crate::callbacks::{report_complete, report_progress};
+use crate::catalog::check_param;
+use crate::classify::{preset_for, PiiClass};
+use crate::crop::crop_and_apply;
+use crate::dryrun::{dry_run, DryRunReport};
+use crate::effect::Effect;
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::image::RedactrImage;
+use crate::json::Json;
+use crate::palettes::resolve_palette;
+use crate::policy::{active_policy, Policy};
+use crate::presets::resolve_preset;
+use crate::review::render_review;
+use crate::stack::{apply_stack, EffectStack};
+use crate::types::{Channels, Color, Rect};
+
+/// One effect applied to one rectangle
+#[derive(Debug, Clone, PartialEq)]
+pub struct Op {
+    pub region: Rect,
+    pub effect: Effect,
+    /// Channels the effect may write; RGB unless changed with `channels()`
+    pub channels: Channels,
+    /// Mix with the original pixels, 0..=1; 1 unless changed with `strength()`
+    pub strength: f32,
+    /// PII class of the redacted content, if known
+    pub class: Option<PiiClass>,
+}
+
+impl Op {
+    /// Full-strength RGB operation
+    pub fn new(region: Rect, effect: Effect) -> Op {
+        Op {
+            region,
+            effect,
+            channels: Channels::RGB,
+            strength: 1.0,
+            class: None,
+        }
+    }
+
+    /// Full strength on the default channels
+    fn is_plain(&self) -> bool {
+        self.channels == Channels::RGB && self.strength == 1.0
+    }
+
+    /// Check the region against a `width` x `height` image and the
+    /// effect parameters and strength against the catalog
+    pub(crate) fn validate(&self, width: u32, height: u32) -> Result<(), RedactError> {
+        check_region(self.region, width, height)?;
+        self.effect.validate()?;
+        check_param(self.effect.name(), "strength", self.strength as f64)
+    }
+
+    /// Whether the output depends on the pixels already in the region
+    fn reads_pixels(&self) -> bool {
+        self.effect.reads_pixels() || self.strength < 1.0
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        Json::object()
+            .with("effect", self.effect.name())
+            .with("params", op_params(self))
+            .with("class", self.class.as_ref().map(PiiClass::name))
+            .with("region", self.region.json())
+    }
+}
+
+/// Chainable list of redactions executed as a batch.
+///
+/// ```js
+/// new Pipeline().blur(faceRect, 12).fill(nameRect, black).run(data, w, h);
+/// ```
+///
+/// Every step is validated before any pixel is touched, and steps whose
+/// output would be completely painted over by a later solid fill are skipped.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    ops: Vec<Op>,
+}
+
+impl Pipeline {
+    pub fn push(mut self, region: Rect, effect: Effect) -> Self {
+        self.ops.push(Op::new(region, effect));
+        self
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// Tag the most recently added step with `class`
+    pub fn tagged(mut self, class: PiiClass) -> Self {
+        if let Some(op) = self.ops.last_mut() {
+            op.class = Some(class);
+        }
+        self
+    }
+
+    /// The steps as a JSON array, the serialized form of a plan
+    pub(crate) fn json(&self) -> Json {
+        Json::Array(self.ops.iter().map(Op::json).collect())
+    }
+
+    /// Apply `policy` to every step, failing on the first one it rejects
+    pub fn enforce_policy(&mut self, policy: &Policy) -> Result<(), RedactError> {
+        for (index, op) in self.ops.iter_mut().enumerate() {
+            policy.enforce(op).map_err(|error| RedactError::InvalidOp {
+                index,
+                error: Box::new(error),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// A copy with the active policy enforced on every step, if one is set.
+    /// Checking every step up front means a rejected step never leaves the
+    /// steps before it applied.
+    fn policed(&self) -> Result<Option<Pipeline>, RedactError> {
+        let Some(policy) = active_policy() else {
+            return Ok(None);
+        };
+        let mut pipeline = self.clone();
+        pipeline.enforce_policy(&policy)?;
+        Ok(Some(pipeline))
+    }
+
+    /// Validate every step against an image of the given size
+    pub fn validate(&self, width: u32, height: u32) -> Result<(), RedactError> {
+        for (index, op) in self.ops.iter().enumerate() {
+            op.validate(width, height)
+                .map_err(|error| RedactError::InvalidOp {
+                    index,
+                    error: Box::new(error),
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Validate, then apply every step in order
+    pub fn apply(&self, data: &mut [u8], width: u32, height: u32) -> Result<(), RedactError> {
+        self.apply_with_progress(data, width, height, |_, _| {})
+    }
+
+    /// Like `apply`, calling `progress(done, total)` after each executed step
+    pub fn apply_with_progress(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<(), RedactError> {
+        check_buffer(data.len(), width, height)?;
+        self.validate(width, height)?;
+        let policed = self.policed()?;
+        let this = policed.as_ref().unwrap_or(self);
+        let live = this.live_ops();
+        let mut done = 0;
+        while done < live.len() {
+            let op = &this.ops[live[done]];
+            // Consecutive plain steps on the same region share one scratch copy
+            let run = live[done..]
+                .iter()
+                .take_while(|&&i| {
+                    op.is_plain() && this.ops[i].is_plain() && this.ops[i].region == op.region
+                })
+                .count();
+            if run > 1 {
+                let effects: Vec<Effect> = live[done..done + run]
+                    .iter()
+                    .map(|&i| this.ops[i].effect)
+                    .collect();
+                apply_stack(&effects, data, width, height, op.region)?;
+                done += run;
+            } else {
+                apply_blended(
+                    &op.effect,
+                    data,
+                    width,
+                    height,
+                    op.region,
+                    op.channels,
+                    op.strength,
+                )?;
+                done += 1;
+            }
+            progress(done, live.len());
+        }
+        Ok(())
+    }
+
+    /// Like `apply`, recording every executed step in `log`. Steps run one at
+    /// a time so each entry has its own before/after hashes.
+    pub fn apply_audited(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        log: &mut AuditLog,
+    ) -> Result<(), RedactError> {
+        check_buffer(data.len(), width, height)?;
+        self.validate(width, height)?;
+        let policed = self.policed()?;
+        let this = policed.as_ref().unwrap_or(self);
+        for i in this.live_ops() {
+            log.apply_op(data, width, height, &this.ops[i])?;
+        }
+        Ok(())
+    }
+
+    /// The steps that touch `crop`, moved into its coordinates and clipped
+    /// to it
+    pub(crate) fn cropped(&self, crop: Rect) -> Pipeline {
+        let ops = self
+            .ops
+            .iter()
+            .filter(|op| op.region.intersects(&crop))
+            .map(|op| {
+                let (x, y) = (op.region.x.max(crop.x), op.region.y.max(crop.y));
+                let right = op.region.right().min(crop.right());
+                let bottom = op.region.bottom().min(crop.bottom());
+                Op {
+                    region: Rect::new(x - crop.x, y - crop.y, right - x, bottom - y),
+                    ..op.clone()
+                }
+            })
+            .collect();
+        Pipeline { ops }
+    }
+
+    /// Indices of the steps that affect the final image.
+    ///
+    /// A step is dead when a later solid fill covers its whole region and no
+    /// step in between reads any of the pixels it wrote.
+    pub(crate) fn live_ops(&self) -> Vec<usize> {
+        (0..self.ops.len())
+            .filter(|&i| {
+                let Op {
+                    region, channels, ..
+                } = self.ops[i];
+                for later in &self.ops[i + 1..] {
+                    if !later.region.intersects(&region) {
+                        continue;
+                    }
+                    if !later.reads_pixels()
+                        && later.region.contains(&region)
+                        && later.channels.contains(channels)
+                    {
+                        return false;
+                    }
+                    if later.reads_pixels() {
+                        return true;
+                    }
+                }
+                true
+            })
+            .collect()
+    }
+}
+
+#[wasm_bindgen]
+impl Pipeline {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    pub fn fill(self, region: &Rect, color: &Color) -> Pipeline {
+        self.push(*region, Effect::SolidFill { color: *color })
+    }
+
+    /// Fill `region` with color `index` of the named palette
+    pub fn fill_palette(
+        self,
+        region: &Rect,
+        name: &str,
+        index: usize,
+    ) -> Result<Pipeline, JsError> {
+        let color = resolve_palette(name)?.fill(index);
+        Ok(self.fill(region, &color))
+    }
+
+    pub fn pixelate(self, region: &Rect, block_size: u32) -> Pipeline {
+        self.push(*region, Effect::pixelate(block_size))
+    }
+
+    pub fn blur(self, region: &Rect, radius: u32) -> Pipeline {
+        self.push(*region, Effect::GaussianBlur { radius })
+    }
+
+    /// Apply every effect of `stack` to `region`, in order
+    pub fn stack(self, region: &Rect, stack: &EffectStack) -> Pipeline {
+        stack
+            .effects()
+            .iter()
+            .fold(self, |pipeline, &effect| pipeline.push(*region, effect))
+    }
+
+    /// Apply the named built-in or registered preset to `region`
+    pub fn preset(self, region: &Rect, name: &str) -> Result<Pipeline, JsError> {
+        Ok(self.stack(region, &resolve_preset(name)?))
+    }
+
+    /// Restrict the most recently added step to the given `Channel` bits
+    pub fn channels(mut self, mask: u8) -> Pipeline {
+        if let Some(op) = self.ops.last_mut() {
+            op.channels = Channels(mask);
+        }
+        self
+    }
+
+    /// Redact `region` with the default preset for the class labelled
+    /// `class` (`"FACE"`, `"SSN"`, ... or a custom label), tagging every
+    /// resulting step with the class
+    pub fn redact(self, region: &Rect, class: &str) -> Result<Pipeline, JsError> {
+        let class = PiiClass::from_name(class)?;
+        let start = self.ops.len();
+        let mut pipeline = self.stack(region, &resolve_preset(&preset_for(&class))?);
+        for op in &mut pipeline.ops[start..] {
+            op.class = Some(class.clone());
+        }
+        Ok(pipeline)
+    }
+
+    /// Tag the most recently added step with the PII class labelled `class`
+    pub fn classify(self, class: &str) -> Result<Pipeline, JsError> {
+        Ok(self.tagged(PiiClass::from_name(class)?))
+    }
+
+    /// Set how strongly the most recently added step replaces the original
+    pub fn strength(mut self, strength: f32) -> Pipeline {
+        if let Some(op) = self.ops.last_mut() {
+            op.strength = strength;
+        }
+        self
+    }
+
+    /// Enforce a minimum-strength policy on every step added so far,
+    /// rejecting the pipeline or upgrading weak steps per `auto_upgrade`
+    pub fn with_policy(mut self, policy: &Policy) -> Result<Pipeline, JsError> {
+        self.enforce_policy(policy)?;
+        Ok(self)
+    }
+
+    /// Number of steps in the pipeline
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Report what `run` would change on a `width` x `height` image
+    /// without touching any pixels
+    pub fn dry_run(&self, width: u32, height: u32) -> DryRunReport {
+        dry_run(self, width, height, None)
+    }
+
+    /// Like `dry_run`, also reporting what `policy` would reject or upgrade
+    pub fn dry_run_with_policy(&self, width: u32, height: u32, policy: &Policy) -> DryRunReport {
+        dry_run(self, width, height, Some(policy))
+    }
+
+    /// Copy of `data` with every step's region drawn as a labeled,
+    /// translucent highlight instead of redacted. `data` is left untouched;
+    /// once the reviewer approves, `run` the same pipeline.
+    pub fn review(&self, data: &[u8], width: u32, height: u32) -> Result<Vec<u8>, JsError> {
+        Ok(render_review(self, data, width, height)?)
+    }
+
+    /// Run the pipeline over a raw RGBA buffer in place
+    pub fn run(&self, data: &mut [u8], width: u32, height: u32) -> Result<(), JsError> {
+        Ok(self.apply(data, width, height)?)
+    }
+
+    /// Run the pipeline, calling `on_progress(fraction)` after each step and
+    /// `on_complete()` once every step has been applied
+    pub fn run_with_callbacks(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        on_progress: Option<Function>,
+        on_complete: Option<Function>,
+    ) -> Result<(), JsError> {
+        self.apply_with_progress(data, width, height, |done, total| {
+            report_progress(&on_progress, done as f32 / total as f32)
+        })?;
+        report_complete(&on_complete);
+        Ok(())
+    }
+
+    /// Run the pipeline, appending an entry to `log` for every executed step
+    pub fn run_audited(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        log: &mut AuditLog,
+    ) -> Result<(), JsError> {
+        Ok(self.apply_audited(data, width, height, log)?)
+    }
+
+    /// Crop to (`x`, `y`, `cw`, `ch`), clipped to the image, and run the
+    /// pipeline over the crop in one step. Regions are in the coordinates of
+    /// the full image; the result carries the new dimensions.
+    pub fn run_cropped(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        x: u32,
+        y: u32,
+        cw: u32,
+        ch: u32,
+    ) -> Result<RedactrImage, JsError> {
+        let rect = Rect::new(x, y, cw, ch);
+        Ok(crop_and_apply(self, data, width, height, rect)?)
+    }
+
+    /// Run the pipeline over a `RedactrImage` in place
+    pub fn run_on(&self, image: &mut RedactrImage) -> Result<(), JsError> {
+        let (width, height) = (image.width(), image.height());
+        Ok(self.apply(image.pixels_mut(), width, height)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern;
+
+    #[test]
+    fn test_pipeline_matches_sequential_calls() {
+        let mut expected = pattern(20, 20);
+        let blur = Effect::GaussianBlur { radius: 2 };
+        let fill = Effect::SolidFill {
+            color: Color::new(0, 0, 0),
+        };
+        blur.apply_rect(&mut expected, 20, 20, Rect::new(0, 0, 10, 10));
+        fill.apply_rect(&mut expected, 20, 20, Rect::new(5, 5, 10, 10));
+
+        let mut data = pattern(20, 20);
+        Pipeline::new()
+            .blur(&Rect::new(0, 0, 10, 10), 2)
+            .fill(&Rect::new(5, 5, 10, 10), &Color::new(0, 0, 0))
+            .apply(&mut data, 20, 20)
+            .unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_validation_happens_before_any_write() {
+        let original = pattern(10, 10);
+        let mut data = original.clone();
+        let err = Pipeline::new()
+            .fill(&Rect::new(0, 0, 5, 5), &Color::new(255, 0, 0))
+            .blur(&Rect::new(0, 0, 5, 5), 0)
+            .apply(&mut data, 10, 10)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "operation 1: invalid radius 0: expected 1 or more"
+        );
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn test_rejects_out_of_bounds_region() {
+        let mut data = pattern(10, 10);
+        let err = Pipeline::new()
+            .pixelate(&Rect::new(12, 0, 5, 5), 2)
+            .apply(&mut data, 10, 10)
+            .unwrap_err();
+        assert!(matches!(err, RedactError::InvalidOp { index: 0, .. }));
+    }
+
+    #[test]
+    fn test_covered_steps_are_skipped() {
+        let pipeline = Pipeline::new()
+            .blur(&Rect::new(2, 2, 4, 4), 3)
+            .pixelate(&Rect::new(20, 20, 4, 4), 2)
+            .fill(&Rect::new(0, 0, 10, 10), &Color::new(0, 0, 0));
+        assert_eq!(pipeline.live_ops(), vec![1, 2]);
+
+        // A later read of the blurred pixels keeps the blur alive
+        let pipeline = Pipeline::new()
+            .blur(&Rect::new(2, 2, 4, 4), 3)
+            .pixelate(&Rect::new(4, 4, 8, 8), 2)
+            .fill(&Rect::new(0, 0, 10, 10), &Color::new(0, 0, 0));
+        assert_eq!(pipeline.live_ops(), vec![0, 1, 2]);
+
+        // An alpha-only fill doesn't hide the blurred color
+        let pipeline = Pipeline::new()
+            .blur(&Rect::new(2, 2, 4, 4), 3)
+            .fill(&Rect::new(0, 0, 10, 10), &Color::new(0, 0, 0))
+            .channels(Channels::A.0);
+        assert_eq!(pipeline.live_ops(), vec![0, 1]);
+
+        // So does a partial-strength fill
+        let pipeline = Pipeline::new()
+            .blur(&Rect::new(2, 2, 4, 4), 3)
+            .fill(&Rect::new(0, 0, 10, 10), &Color::new(0, 0, 0))
+            .strength(0.5);
+        assert_eq!(pipeline.live_ops(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_rejects_strength_out_of_range() {
+        let mut data = pattern(10, 10);
+        let err = Pipeline::new()
+            .blur(&Rect::new(0, 0, 5, 5), 2)
+            .strength(1.5)
+            .apply(&mut data, 10, 10)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "operation 0: invalid strength 1.5: expected a value between 0 and 1"
+        );
+    }
+
+    #[test]
+    fn test_policy_rejects_or_upgrades_steps() {
+        let mut pipeline = Pipeline::new()
+            .pixelate(&Rect::new(0, 0, 5, 5), 16)
+            .blur(&Rect::new(5, 5, 5, 5), 2);
+        let err = pipeline.clone().enforce_policy(&Policy::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "operation 1: radius 2 is below the policy minimum of 16"
+        );
+
+        let mut policy = Policy::new();
+        policy.auto_upgrade = true;
+        pipeline.enforce_policy(&policy).unwrap();
+        assert_eq!(
+            pipeline.ops()[1].effect,
+            Effect::GaussianBlur { radius: 16 }
+        );
+    }
+
+    #[test]
+    fn test_audited_run_matches_apply() {
+        let pipeline = Pipeline::new()
+            .blur(&Rect::new(0, 0, 6, 6), 2)
+            .pixelate(&Rect::new(0, 0, 6, 6), 3)
+            .fill(&Rect::new(8, 8, 2, 2), &Color::new(0, 0, 0));
+        let mut expected = pattern(10, 10);
+        pipeline.apply(&mut expected, 10, 10).unwrap();
+
+        let mut data = pattern(10, 10);
+        let mut log = AuditLog::new();
+        pipeline.apply_audited(&mut data, 10, 10, &mut log).unwrap();
+        assert_eq!(data, expected);
+        assert_eq!(log.length(), 3);
+        assert_eq!(log.entries()[1].operation, "pixelate");
+    }
+
+    #[test]
+    fn test_redact_uses_class_defaults_and_tags_steps() {
+        let pipeline = Pipeline::new()
+            .redact(&Rect::new(0, 0, 8, 8), "FACE")
+            .unwrap()
+            .fill(&Rect::new(8, 8, 2, 2), &Color::new(0, 0, 0))
+            .classify("SSN")
+            .unwrap()
+            .fill(&Rect::new(0, 8, 2, 2), &Color::new(0, 0, 0))
+            .classify("PASSPORT")
+            .unwrap();
+        let classes: Vec<_> = pipeline.ops().iter().map(|op| op.class.clone()).collect();
+        assert_eq!(
+            classes,
+            [
+                Some(PiiClass::Face),
+                Some(PiiClass::Face),
+                Some(PiiClass::Ssn),
+                Some(PiiClass::Custom("PASSPORT".to_string()))
+            ]
+        );
+        assert_eq!(
+            pipeline.ops()[2].json().to_string(),
+            r##"{"effect":"solid_fill","params":{"color":"#000000","channels":7,"strength":1},"class":"SSN","region":{"x":8,"y":8,"w":2,"h":2}}"##
+        );
+        assert_eq!(
+            pipeline.ops()[3].json().get("class").and_then(Json::as_str),
+            Some("PASSPORT")
+        );
+    }
+
+    #[test]
+    fn test_progress_reports_each_executed_step() {
+        let mut data = pattern(10, 10);
+        let mut seen = Vec::new();
+        Pipeline::new()
+            .blur(&Rect::new(0, 0, 4, 4), 2)
+            .fill(&Rect::new(0, 0, 5, 5), &Color::new(0, 0, 0))
+            .pixelate(&Rect::new(5, 5, 5, 5), 2)
+            .apply_with_progress(&mut data, 10, 10, |done, total| seen.push((done, total)))
+            .unwrap();
+        // The covered blur is skipped, so only two steps run
+        assert_eq!(seen, vec![(1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn test_stacked_steps_match_sequential_calls() {
+        let region = Rect::new(1, 1, 12, 12);
+        let mut expected = pattern(16, 16);
+        Effect::pixelate(4).apply_rect(&mut expected, 16, 16, region);
+        Effect::GaussianBlur { radius: 2 }.apply_rect(&mut expected, 16, 16, region);
+
+        let mut data = pattern(16, 16);
+        let mut steps = Vec::new();
+        Pipeline::new()
+            .stack(&region, &EffectStack::new().pixelate(4).blur(2))
+            .apply_with_progress(&mut data, 16, 16, |done, total| steps.push((done, total)))
+            .unwrap();
+        assert_eq!(data, expected);
+        assert_eq!(steps, vec![(2, 2)]);
+    }
+}
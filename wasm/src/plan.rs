@@ -0,0 +1,276 @@
+//! Stored redaction recipes.
+//!
+//! A `RedactionPlan` is an ordered list of regions (any shape, effect,
+//! parameters and PII class, as in `apply_regions`) together with the size
+//! of the image they were drawn on:
+//!
+//! ```json
+//! {"version": 1, "width": 1280, "height": 720,
+//!  "regions": [{"rect": {"x": 10, "y": 10, "w": 80, "h": 20},
+//!               "effect": "solid_fill", "params": {"color": "#000000"}}]}
+//! ```
+//!
+//! The app draws on a downscaled preview and keeps the plan, then replays
+//! it on the full-resolution original at export time; a server can replay
+//! the same JSON, or deserialize it with serde. Replayed on an image of
+//! another size, coordinates scale with the image and pixel-sized
+//! parameters (block size, blur radius, feather) scale by the mean of the
+//! two factors.
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, RedactError};
+use crate::json::Json;
+use crate::region::{check_policy, regions_from_json, Region};
+
+/// Format version written by `to_json`; other versions are refused
+pub const PLAN_VERSION: u32 = 1;
+
+/// Serde goes through the JSON form in the module docs, so a plan deserialized by a
+/// server is validated exactly like one parsed here
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Json", into = "Json")]
+pub struct RedactionPlan {
+    width: u32,
+    height: u32,
+    regions: Vec<Region>,
+}
+
+impl TryFrom<Json> for RedactionPlan {
+    type Error = RedactError;
+
+    fn try_from(value: Json) -> Result<RedactionPlan, RedactError> {
+        RedactionPlan::from_json_value(&value)
+    }
+}
+
+impl From<RedactionPlan> for Json {
+    fn from(plan: RedactionPlan) -> Json {
+        plan.json()
+    }
+}
+
+impl RedactionPlan {
+    /// Parse a plan from its JSON text
+    pub fn parse(text: &str) -> Result<RedactionPlan, RedactError> {
+        RedactionPlan::from_json_value(&Json::parse(text)?)
+    }
+
+    pub(crate) fn from_json_value(value: &Json) -> Result<RedactionPlan, RedactError> {
+        let field = |key: &str, expected| RedactError::InvalidField {
+            field: format!("plan.{}", key),
+            expected,
+        };
+        if value.get("version").and_then(Json::as_f64) != Some(PLAN_VERSION as f64) {
+            return Err(field("version", "1"));
+        }
+        let size = |key: &str| {
+            value
+                .get(key)
+                .and_then(Json::as_f64)
+                .filter(|n| n.fract() == 0.0 && (1.0..=u32::MAX as f64).contains(n))
+                .map(|n| n as u32)
+                .ok_or_else(|| field(key, "a positive integer"))
+        };
+        let (width, height) = (size("width")?, size("height")?);
+        let regions = value
+            .get("regions")
+            .ok_or_else(|| field("regions", "an array of regions"))?;
+        Ok(RedactionPlan {
+            width,
+            height,
+            regions: regions_from_json(regions)?,
+        })
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        Json::object()
+            .with("version", PLAN_VERSION)
+            .with("width", self.width)
+            .with("height", self.height)
+            .with(
+                "regions",
+                Json::Array(self.regions.iter().map(Region::json).collect()),
+            )
+    }
+
+    /// The regions as they apply to a `width` x `height` image
+    pub(crate) fn regions_for(&self, width: u32, height: u32) -> Vec<Region> {
+        if (width, height) == (self.width, self.height) {
+            return self.regions.clone();
+        }
+        let sx = width as f32 / self.width as f32;
+        let sy = height as f32 / self.height as f32;
+        self.regions.iter().map(|r| r.scaled(sx, sy)).collect()
+    }
+
+    /// `regions_for`, checking every region is valid at that scale
+    pub(crate) fn checked_regions(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<Region>, RedactError> {
+        let regions = self.regions_for(width, height);
+        for (index, region) in regions.iter().enumerate() {
+            region.validate().map_err(|error| RedactError::InvalidOp {
+                index,
+                error: Box::new(error),
+            })?;
+        }
+        Ok(regions)
+    }
+
+    /// Apply every region, scaled to the image; nothing is written unless
+    /// all of them are valid at that scale
+    pub fn apply_to(&self, data: &mut [u8], width: u32, height: u32) -> Result<(), RedactError> {
+        check_buffer(data.len(), width, height)?;
+        let regions = self.checked_regions(width, height)?;
+        check_policy(&regions, width, height)?;
+        for region in &regions {
+            region.apply_to(data, width, height)?;
+        }
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+impl RedactionPlan {
+    /// Empty plan for an image of `width` x `height`
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: u32, height: u32) -> RedactionPlan {
+        RedactionPlan {
+            width,
+            height,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Append a region; regions are applied in the order added
+    pub fn push(mut self, region: &Region) -> RedactionPlan {
+        self.regions.push(region.clone());
+        self
+    }
+
+    /// Parse a plan (see the module docs for the format)
+    pub fn from_json(text: &str) -> Result<RedactionPlan, JsError> {
+        Ok(Self::parse(text)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Replay the plan on a raw RGBA buffer of any size
+    pub fn apply(&self, data: &mut [u8], width: u32, height: u32) -> Result<(), JsError> {
+        Ok(self.apply_to(data, width, height)?)
+    }
+}
+
+/// Replay a plan given as JSON on a raw RGBA buffer
+#[wasm_bindgen]
+pub fn apply_plan(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    plan_json: &str,
+) -> Result<(), JsError> {
+    let plan = RedactionPlan::parse(plan_json)?;
+    Ok(plan.apply_to(data, width, height)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern;
+
+    const PLAN: &str = r##"{"version": 1, "width": 20, "height": 10, "regions": [
+        {"rect": {"x": 2, "y": 2, "w": 4, "h": 4}, "effect": "solid_fill",
+         "params": {"color": "#000000"}, "class": "SSN"},
+        {"ellipse": {"cx": 14, "cy": 5, "rx": 3, "ry": 2}, "effect": "pixelate",
+         "params": {"block_size": 2}, "feather": 1}]}"##;
+
+    #[test]
+    fn test_round_trip_and_replay() {
+        let plan = RedactionPlan::from_json_value(&Json::parse(PLAN).unwrap()).unwrap();
+        let again = RedactionPlan::from_json_value(&plan.json()).unwrap();
+        assert_eq!(again, plan);
+        assert!(plan.to_json().contains(r#""class":"SSN""#));
+        assert_eq!(plan.length(), 2);
+
+        let mut data = pattern(20, 10);
+        plan.apply_to(&mut data, 20, 10).unwrap();
+        let mut expected = pattern(20, 10);
+        for region in &plan.regions {
+            region.apply_to(&mut expected, 20, 10).unwrap();
+        }
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_serde_uses_the_plan_format() {
+        let plan = RedactionPlan::parse(PLAN).unwrap();
+        assert_eq!(serde_json::to_string(&plan).unwrap(), plan.to_json());
+        let again: RedactionPlan = serde_json::from_str(PLAN).unwrap();
+        assert_eq!(again, plan);
+        let err = serde_json::from_str::<RedactionPlan>(r#"{"version": 2}"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid field \"plan.version\": expected 1"
+        );
+    }
+
+    #[test]
+    fn test_scales_to_the_original() {
+        let plan = RedactionPlan::from_json_value(&Json::parse(PLAN).unwrap()).unwrap();
+        let scaled = plan.regions_for(60, 30);
+        let expected = r##"{"rect": {"x": 6, "y": 6, "w": 12, "h": 12}, "effect": "solid_fill",
+                            "params": {"color": "#000000"}, "class": "SSN"}"##;
+        assert_eq!(
+            scaled[0],
+            Region::from_json_value(&Json::parse(expected).unwrap(), "region").unwrap()
+        );
+        assert_eq!(
+            scaled[1].json().get("params").unwrap().get("block_size"),
+            Some(&Json::from(6u32))
+        );
+
+        let mut data = pattern(60, 30);
+        plan.apply_to(&mut data, 60, 30).unwrap();
+        assert_eq!(&data[(8 * 60 + 8) * 4..][..3], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn test_rejects_other_versions_and_bad_plans() {
+        let err = |text: &str| {
+            RedactionPlan::from_json_value(&Json::parse(text).unwrap())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            err(&PLAN.replace("\"version\": 1", "\"version\": 2")),
+            "invalid field \"plan.version\": expected 1"
+        );
+        assert_eq!(
+            err(&PLAN.replace("\"width\": 20", "\"width\": 0")),
+            "invalid field \"plan.width\": expected a positive integer"
+        );
+        assert!(err(&PLAN.replace("\"rx\": 3", "\"rx\": -3")).contains("rx"));
+    }
+}
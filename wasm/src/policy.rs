@@ -0,0 +1,866 @@
+//! Host- or organization-configured limits on redactions.
+//!
+//! A policy guarantees a weak effect (a 2px blur, a half-strength mix) is
+//! never shipped as "redacted": operations below a minimum are either
+//! rejected or, with `auto_upgrade`, raised to the minimum, and effects that
+//! only restyle pixels (`dim`, `desaturate`) are refused unless the policy
+//! lists them in `allowed_effects`. Organization policies loaded from JSON
+//! can also restrict which effects are allowed, bound any effect parameter,
+//! and require metadata stripping and verification at export.
+//!
+//! `set_active_policy` makes a policy apply everywhere: every entry point
+//! that applies an effect enforces it (functions without a `Result` throw
+//! on a rejection), and `check_active_export` runs its export checks.
+//!
+//! ```json
+//! {"allowed_effects": ["solid_fill", "pixelate"],
+//!  "min_block_size": 16,
+//!  "bounds": {"pixelate": {"block_size": {"max": 64}}},
+//!  "require_metadata_strip": true, "require_verification": true}
+//! ```
+
+use std::cell::RefCell;
+
+use wasm_bindgen::prelude::*;
+
+use crate::catalog::{effect_info, ParamKind};
+use crate::effect::Effect;
+use crate::error::{check_buffer, RedactError};
+use crate::json::Json;
+use crate::metadata::scan_metadata;
+use crate::pipeline::Op;
+use crate::sprite::SPRITE;
+use crate::types::Rect;
+use crate::verify::{verify_region, DEFAULT_MAX_CORRELATION, DEFAULT_MAX_SSIM};
+
+/// Range an organization allows for one effect parameter
+#[derive(Debug, Clone, PartialEq)]
+struct ParamBound {
+    effect: String,
+    param: String,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl ParamBound {
+    fn describe(&self) -> String {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => format!("a value between {} and {}", min, max),
+            (Some(min), None) => format!("{} or more", min),
+            (None, Some(max)) => format!("{} or less", max),
+            (None, None) => "any value".to_string(),
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    /// Smallest accepted gaussian blur radius
+    pub min_blur_radius: u32,
+    /// Smallest accepted pixelation block size (plain and hardened)
+    pub min_block_size: u32,
+    /// Smallest accepted mix strength, 0..=1
+    pub min_strength: f32,
+    /// Raise weak operations to the minimum instead of rejecting them
+    pub auto_upgrade: bool,
+    /// Exports must carry no leftover metadata (see `check_export`)
+    pub require_metadata_strip: bool,
+    /// Exported regions must pass `verify_redaction` (see `check_export`)
+    pub require_verification: bool,
+    /// Effect names that may be used; `None` allows all
+    allowed_effects: Option<Vec<String>>,
+    bounds: Vec<ParamBound>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            min_blur_radius: 16,
+            min_block_size: 12,
+            min_strength: 1.0,
+            auto_upgrade: false,
+            require_metadata_strip: false,
+            require_verification: false,
+            allowed_effects: None,
+            bounds: Vec::new(),
+        }
+    }
+}
+
+/// Effects that restyle pixels without hiding them; a policy only accepts
+/// them when its `allowed_effects` lists them
+const NON_HIDING: [&str; 2] = ["desaturate", "dim"];
+
+fn not_finite(name: &'static str, value: f64) -> RedactError {
+    RedactError::InvalidParameter {
+        name,
+        value,
+        expected: "a finite number".to_string(),
+    }
+}
+
+fn violation(rule: &'static str, value: f64, minimum: f64) -> RedactError {
+    RedactError::PolicyViolation {
+        rule,
+        value,
+        minimum,
+    }
+}
+
+fn field_error(field: &str, expected: &'static str) -> RedactError {
+    RedactError::InvalidField {
+        field: field.to_string(),
+        expected,
+    }
+}
+
+fn uint(value: &Json, field: &str) -> Result<u32, RedactError> {
+    value
+        .as_f64()
+        .filter(|n| n.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(n))
+        .map(|n| n as u32)
+        .ok_or_else(|| field_error(field, "a non-negative integer"))
+}
+
+fn boolean(value: &Json, field: &str) -> Result<bool, RedactError> {
+    value
+        .as_bool()
+        .ok_or_else(|| field_error(field, "true or false"))
+}
+
+fn parse_bounds(value: &Json) -> Result<Vec<ParamBound>, RedactError> {
+    let mut bounds = Vec::new();
+    let effects = value
+        .as_object()
+        .ok_or_else(|| field_error("bounds", "an object keyed by effect name"))?;
+    for (effect, params) in effects {
+        let info = effect_info(effect)
+            .ok_or_else(|| field_error(&format!("bounds.{}", effect), "a known effect name"))?;
+        let params = params
+            .as_object()
+            .ok_or_else(|| field_error(effect, "an object keyed by parameter name"))?;
+        for (param, range) in params {
+            let field = format!("bounds.{}.{}", effect, param);
+            let numeric = info.params.iter().any(|p| {
+                p.name == param && matches!(p.kind, ParamKind::Integer | ParamKind::Number)
+            });
+            if !numeric {
+                return Err(field_error(&field, "a numeric parameter of the effect"));
+            }
+            let limit = |key| match range.get(key) {
+                None | Some(Json::Null) => Ok(None),
+                Some(v) => v
+                    .as_f64()
+                    .map(Some)
+                    .ok_or_else(|| field_error(&field, "numeric min/max")),
+            };
+            if range.as_object().is_none() {
+                return Err(field_error(&field, "an object with min and/or max"));
+            }
+            bounds.push(ParamBound {
+                effect: effect.clone(),
+                param: param.clone(),
+                min: limit("min")?,
+                max: limit("max")?,
+            });
+        }
+    }
+    Ok(bounds)
+}
+
+impl Policy {
+    /// Parse a policy (as written by `to_json` or by hand); absent fields
+    /// keep their defaults, unknown ones are rejected so a misspelled
+    /// restriction can't silently do nothing
+    pub fn parse(text: &str) -> Result<Policy, RedactError> {
+        let json = Json::parse(text)?;
+        let fields = json
+            .as_object()
+            .ok_or_else(|| field_error("policy", "an object"))?;
+        let mut policy = Policy::default();
+        for (key, value) in fields {
+            match key.as_str() {
+                "min_blur_radius" => policy.min_blur_radius = uint(value, key)?,
+                "min_block_size" => policy.min_block_size = uint(value, key)?,
+                "min_strength" => {
+                    policy.min_strength = value
+                        .as_f64()
+                        .filter(|s| (0.0..=1.0).contains(s))
+                        .ok_or_else(|| field_error(key, "a number between 0 and 1"))?
+                        as f32
+                }
+                "auto_upgrade" => policy.auto_upgrade = boolean(value, key)?,
+                "require_metadata_strip" => policy.require_metadata_strip = boolean(value, key)?,
+                "require_verification" => policy.require_verification = boolean(value, key)?,
+                "allowed_effects" if *value == Json::Null => policy.allowed_effects = None,
+                "allowed_effects" => {
+                    let names = value
+                        .as_array()
+                        .and_then(|items| {
+                            items
+                                .iter()
+                                .map(|item| item.as_str().map(str::to_string))
+                                .collect::<Option<Vec<_>>>()
+                        })
+                        .ok_or_else(|| field_error(key, "an array of effect names"))?;
+                    if let Some(unknown) = names
+                        .iter()
+                        .find(|name| effect_info(name).is_none() && *name != SPRITE)
+                    {
+                        return Err(field_error(
+                            &format!("allowed_effects.{}", unknown),
+                            "a known effect name",
+                        ));
+                    }
+                    policy.allowed_effects = Some(names);
+                }
+                "bounds" => policy.bounds = parse_bounds(value)?,
+                _ => return Err(field_error(key, "a known policy field")),
+            }
+        }
+        Ok(policy)
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        let mut bounds = Json::object();
+        for bound in &self.bounds {
+            let range = Json::object().with("min", bound.min).with("max", bound.max);
+            let effect = match bounds.get(&bound.effect) {
+                Some(existing) => existing.clone().with(&bound.param, range),
+                None => Json::object().with(&bound.param, range),
+            };
+            if let Json::Object(fields) = &mut bounds {
+                fields.retain(|(name, _)| name != &bound.effect);
+                fields.push((bound.effect.clone(), effect));
+            }
+        }
+        Json::object()
+            .with("min_blur_radius", self.min_blur_radius)
+            .with("min_block_size", self.min_block_size)
+            .with("min_strength", self.min_strength)
+            .with("auto_upgrade", self.auto_upgrade)
+            .with("require_metadata_strip", self.require_metadata_strip)
+            .with("require_verification", self.require_verification)
+            .with("allowed_effects", self.allowed_effects.clone())
+            .with("bounds", bounds)
+    }
+
+    /// Check `op` against the policy, upgrading weak parameters in place
+    /// when `auto_upgrade` is set. Disallowed effects, effects that don't
+    /// hide anything (unless explicitly allowed), non-finite values and
+    /// parameters outside organization bounds are always rejected.
+    pub fn enforce(&self, op: &mut Op) -> Result<(), RedactError> {
+        let name = op.effect.name();
+        let allowed = match &self.allowed_effects {
+            Some(allowed) => allowed.iter().any(|a| a == name),
+            None => !NON_HIDING.contains(&name),
+        };
+        if !allowed {
+            return Err(RedactError::EffectNotAllowed { effect: name });
+        }
+
+        // NaN compares false against every minimum, so it would pass them
+        if !op.strength.is_finite() {
+            return Err(not_finite("strength", op.strength as f64));
+        }
+        let params = op.effect.params_json();
+        let info = effect_info(name).expect("every effect is in the catalog");
+        for param in info.params {
+            let value = params.get(param.name).and_then(Json::as_f64);
+            if let Some(value) = value.filter(|v| !v.is_finite()) {
+                return Err(not_finite(param.name, value));
+            }
+        }
+
+        let upgrade = self.auto_upgrade;
+        match &mut op.effect {
+            Effect::GaussianBlur { radius } if *radius < self.min_blur_radius => {
+                if !upgrade {
+                    return Err(violation(
+                        "radius",
+                        *radius as f64,
+                        self.min_blur_radius as f64,
+                    ));
+                }
+                *radius = self.min_blur_radius;
+            }
+            Effect::Pixelate { block_size, .. }
+            | Effect::HardenedPixelate { block_size, .. }
+            | Effect::Mosaic {
+                cell_size: block_size,
+                ..
+            } if *block_size < self.min_block_size => {
+                if !upgrade {
+                    return Err(violation(
+                        "block_size",
+                        *block_size as f64,
+                        self.min_block_size as f64,
+                    ));
+                }
+                *block_size = self.min_block_size;
+            }
+            _ => {}
+        }
+        // A minimum block count must not shrink blocks below the floor
+        if let Effect::Pixelate {
+            block_size,
+            min_blocks,
+            ..
+        } = &mut op.effect
+        {
+            let Rect { w, h, .. } = op.region;
+            let applied = redactr_core::block_size_for_min_blocks(w, h, *block_size, *min_blocks);
+            if applied < self.min_block_size {
+                if !upgrade {
+                    return Err(violation(
+                        "block_size",
+                        applied as f64,
+                        self.min_block_size as f64,
+                    ));
+                }
+                *min_blocks = 0;
+            }
+        }
+        if op.strength < self.min_strength {
+            if !upgrade {
+                return Err(violation(
+                    "strength",
+                    op.strength as f64,
+                    self.min_strength as f64,
+                ));
+            }
+            op.strength = self.min_strength;
+        }
+
+        let params = op.effect.params_json();
+        for bound in self.bounds.iter().filter(|b| b.effect == name) {
+            let Some(value) = params.get(&bound.param).and_then(Json::as_f64) else {
+                continue;
+            };
+            if bound.min.is_some_and(|min| value < min) || bound.max.is_some_and(|max| value > max)
+            {
+                return Err(RedactError::OutsidePolicyBounds {
+                    param: bound.param.clone(),
+                    value,
+                    expected: bound.describe(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a sprite overlay whose least opaque pixel has alpha `alpha`.
+    /// Sprites are their own effect kind for `allowed_effects`, and their
+    /// opacity counts as the strength: with the default minimum only fully
+    /// opaque sprites pass. Returns the alpha to raise every sprite pixel
+    /// to, nonzero only when `auto_upgrade` had to step in.
+    pub(crate) fn enforce_sprite(&self, alpha: u8) -> Result<u8, RedactError> {
+        if let Some(allowed) = &self.allowed_effects {
+            if !allowed.iter().any(|a| a == SPRITE) {
+                return Err(RedactError::EffectNotAllowed { effect: SPRITE });
+            }
+        }
+        let strength = alpha as f32 / 255.0;
+        if strength >= self.min_strength {
+            return Ok(0);
+        }
+        if !self.auto_upgrade {
+            return Err(violation(
+                "strength",
+                strength as f64,
+                self.min_strength as f64,
+            ));
+        }
+        Ok((self.min_strength * 255.0).ceil() as u8)
+    }
+
+    /// Export-time checks: `encoded` must carry no metadata when
+    /// `require_metadata_strip` is set, and every region of `redacted` must
+    /// pass verification against `original` when `require_verification` is
+    pub fn check_export(
+        &self,
+        encoded: &[u8],
+        original: &[u8],
+        redacted: &[u8],
+        width: u32,
+        height: u32,
+        regions: &[Rect],
+    ) -> Result<(), RedactError> {
+        if self.require_metadata_strip {
+            let report = scan_metadata(encoded)?;
+            if !report.clean() {
+                return Err(RedactError::MetadataRemaining {
+                    blocks: report.unexpected(),
+                });
+            }
+        }
+        if self.require_verification {
+            check_buffer(original.len(), width, height)?;
+            for &region in regions {
+                let report = verify_region(
+                    original,
+                    redacted,
+                    width,
+                    height,
+                    region,
+                    DEFAULT_MAX_SSIM,
+                    DEFAULT_MAX_CORRELATION,
+                )?;
+                if report.likely_recoverable {
+                    return Err(RedactError::Recoverable { region });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    // Per thread rather than global: each wasm instance is single-threaded,
+    // and tests running in parallel don't see each other's policy
+    static ACTIVE: RefCell<Option<Policy>> = const { RefCell::new(None) };
+}
+
+/// A copy of the active policy, if one is set
+pub(crate) fn active_policy() -> Option<Policy> {
+    ACTIVE.with_borrow(Clone::clone)
+}
+
+/// Enforce the active policy, if any, on `op`
+pub(crate) fn enforce_active(op: &mut Op) -> Result<(), RedactError> {
+    ACTIVE.with_borrow(|active| active.as_ref().map_or(Ok(()), |policy| policy.enforce(op)))
+}
+
+/// `effect` over `region` at full strength, as the active policy accepts or
+/// upgrades it
+pub(crate) fn policed(effect: Effect, region: Rect) -> Result<Effect, RedactError> {
+    let mut op = Op::new(region, effect);
+    enforce_active(&mut op)?;
+    Ok(op.effect)
+}
+
+/// `policed` for a sized effect (blur, pixelate, hardened pixelate or
+/// mosaic): its radius, block or cell size as the active policy allows it.
+/// That size is the only parameter a policy raises, so callers that already
+/// hold the rest of the effect need nothing else back.
+pub(crate) fn policed_size(effect: Effect, region: Rect) -> Result<u32, RedactError> {
+    match policed(effect, region)? {
+        Effect::GaussianBlur { radius } => Ok(radius),
+        Effect::Pixelate { block_size, .. } | Effect::HardenedPixelate { block_size, .. } => {
+            Ok(block_size)
+        }
+        Effect::Mosaic { cell_size, .. } => Ok(cell_size),
+        other => unreachable!("{} has no size for a policy to raise", other.name()),
+    }
+}
+
+/// For entry points that return nothing: a policy rejection becomes a
+/// thrown JS error (a panic natively)
+pub(crate) fn or_throw<T>(result: Result<T, RedactError>) -> T {
+    match result {
+        Ok(value) => value,
+        #[cfg(target_arch = "wasm32")]
+        Err(error) => wasm_bindgen::throw_str(&error.to_string()),
+        #[cfg(not(target_arch = "wasm32"))]
+        Err(error) => panic!("{}", error),
+    }
+}
+
+/// Enforce `policy` on every redaction from now on, in place of any policy
+/// set before
+#[wasm_bindgen]
+pub fn set_active_policy(policy: &Policy) {
+    ACTIVE.set(Some(policy.clone()));
+}
+
+/// Stop enforcing the active policy
+#[wasm_bindgen]
+pub fn clear_active_policy() {
+    ACTIVE.set(None);
+}
+
+/// `Policy.check_export` for the active policy; passes when none is set.
+/// Call it on every exported file before it leaves the app.
+#[wasm_bindgen]
+pub fn check_active_export(
+    encoded: &[u8],
+    original: &[u8],
+    redacted: &[u8],
+    width: u32,
+    height: u32,
+    regions: &[u32],
+) -> Result<(), JsError> {
+    let regions = Rect::from_flat(regions)?;
+    match active_policy() {
+        Some(policy) => {
+            Ok(policy.check_export(encoded, original, redacted, width, height, &regions)?)
+        }
+        None => Ok(()),
+    }
+}
+
+#[wasm_bindgen]
+impl Policy {
+    /// Policy with the default minimums: blur radius 16, block size 12 and
+    /// full strength, rejecting anything weaker
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Policy {
+        Policy::default()
+    }
+
+    /// Load an organization policy from JSON
+    pub fn from_json(text: &str) -> Result<Policy, JsError> {
+        Ok(Policy::parse(text)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+
+    /// Run the export requirements over the encoded file and the redacted
+    /// pixels; `regions` is flat `[x, y, w, h, ...]`
+    #[wasm_bindgen(js_name = check_export)]
+    pub fn check_export_js(
+        &self,
+        encoded: &[u8],
+        original: &[u8],
+        redacted: &[u8],
+        width: u32,
+        height: u32,
+        regions: &[u32],
+    ) -> Result<(), JsError> {
+        let regions = Rect::from_flat(regions)?;
+        Ok(self.check_export(encoded, original, redacted, width, height, &regions)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mosaic::CellShape;
+    use crate::types::Color;
+
+    fn op(effect: Effect) -> Op {
+        Op::new(Rect::new(0, 0, 4, 4), effect)
+    }
+
+    #[test]
+    fn test_rejects_weak_operations() {
+        let policy = Policy::new();
+        let err = policy
+            .enforce(&mut op(Effect::GaussianBlur { radius: 2 }))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "radius 2 is below the policy minimum of 16"
+        );
+
+        let mut weak_fill = op(Effect::SolidFill {
+            color: Color::new(0, 0, 0),
+        });
+        weak_fill.strength = 0.8;
+        assert!(matches!(
+            policy.enforce(&mut weak_fill),
+            Err(RedactError::PolicyViolation {
+                rule: "strength",
+                ..
+            })
+        ));
+        assert!(policy.enforce(&mut op(Effect::pixelate(12))).is_ok());
+    }
+
+    #[test]
+    fn test_non_hiding_effects_need_explicit_allowing() {
+        let policy = Policy::new();
+        for effect in [
+            Effect::Dim { factor: 1.0 },
+            Effect::Desaturate { contrast: 1.0 },
+        ] {
+            assert!(matches!(
+                policy.enforce(&mut op(effect)),
+                Err(RedactError::EffectNotAllowed { .. })
+            ));
+        }
+        let policy = Policy::parse(r#"{"allowed_effects": ["dim", "pixelate"]}"#).unwrap();
+        assert!(policy.enforce(&mut op(Effect::Dim { factor: 0.5 })).is_ok());
+        assert!(policy
+            .enforce(&mut op(Effect::Desaturate { contrast: 1.0 }))
+            .is_err());
+    }
+
+    #[test]
+    fn test_non_finite_values_are_rejected() {
+        let policy = Policy {
+            auto_upgrade: true,
+            ..Policy::default()
+        };
+        let mut nan_strength = op(Effect::pixelate(16));
+        nan_strength.strength = f32::NAN;
+        assert!(matches!(
+            policy.enforce(&mut nan_strength),
+            Err(RedactError::InvalidParameter {
+                name: "strength",
+                ..
+            })
+        ));
+        let policy = Policy::parse(r#"{"allowed_effects": ["dim"]}"#).unwrap();
+        for factor in [f32::NAN, f32::INFINITY] {
+            assert!(matches!(
+                policy.enforce(&mut op(Effect::Dim { factor })),
+                Err(RedactError::InvalidParameter { name: "factor", .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_auto_upgrade_raises_to_minimum() {
+        let policy = Policy {
+            auto_upgrade: true,
+            ..Policy::default()
+        };
+        let mut pixelate = op(Effect::HardenedPixelate {
+            block_size: 4,
+            seed: 1,
+            glyph_height: 0,
+        });
+        pixelate.strength = 0.3;
+        policy.enforce(&mut pixelate).unwrap();
+        assert_eq!(
+            pixelate.effect,
+            Effect::HardenedPixelate {
+                block_size: 12,
+                seed: 1,
+                glyph_height: 0,
+            }
+        );
+        assert_eq!(pixelate.strength, 1.0);
+
+        // A block count that would shrink the blocks is dropped instead
+        let mut counted = op(Effect::Pixelate {
+            block_size: 16,
+            grid_aligned: false,
+            min_blocks: 2,
+        });
+        assert!(Policy::new().enforce(&mut counted.clone()).is_err());
+        policy.enforce(&mut counted).unwrap();
+        assert!(matches!(
+            counted.effect,
+            Effect::Pixelate {
+                block_size: 16,
+                min_blocks: 0,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_json_policy_restricts_effects_and_bounds() {
+        let policy = Policy::parse(
+            r#"{"allowed_effects":["solid_fill","pixelate"],"min_block_size":8,
+                "bounds":{"pixelate":{"block_size":{"max":32}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy
+                .enforce(&mut op(Effect::GaussianBlur { radius: 40 }))
+                .unwrap_err()
+                .to_string(),
+            "effect gaussian_blur is not allowed by the policy"
+        );
+        assert!(policy.enforce(&mut op(Effect::pixelate(16))).is_ok());
+        assert_eq!(
+            policy
+                .enforce(&mut op(Effect::pixelate(48)))
+                .unwrap_err()
+                .to_string(),
+            "block_size 48 is outside the policy bounds: expected 32 or less"
+        );
+        assert_eq!(Policy::parse(&policy.to_json()).unwrap(), policy);
+    }
+
+    #[test]
+    fn test_json_policy_rejects_unknown_fields() {
+        assert_eq!(
+            Policy::parse(r#"{"min_blur":4}"#).unwrap_err().to_string(),
+            "invalid field \"min_blur\": expected a known policy field"
+        );
+        assert!(Policy::parse(r#"{"min_strength":2}"#).is_err());
+
+        // Misspelled names would otherwise bound or allow nothing
+        let error = |json: &str| Policy::parse(json).unwrap_err().to_string();
+        assert_eq!(
+            error(r#"{"allowed_effects":["solid_fill","pixelat"]}"#),
+            "invalid field \"allowed_effects.pixelat\": expected a known effect name"
+        );
+        assert_eq!(
+            error(r#"{"bounds":{"gausian_blur":{"radius":{"min":8}}}}"#),
+            "invalid field \"bounds.gausian_blur\": expected a known effect name"
+        );
+        assert_eq!(
+            error(r#"{"bounds":{"pixelate":{"block_sise":{"min":8}}}}"#),
+            "invalid field \"bounds.pixelate.block_sise\": \
+             expected a numeric parameter of the effect"
+        );
+        assert!(Policy::parse(r#"{"bounds":{"solid_fill":{"color":{"min":0}}}}"#).is_err());
+    }
+
+    /// Clears the active policy when a test ends, pass or fail
+    struct Active;
+
+    impl Active {
+        fn set(policy: &Policy) -> Active {
+            set_active_policy(policy);
+            Active
+        }
+    }
+
+    impl Drop for Active {
+        fn drop(&mut self) {
+            clear_active_policy();
+        }
+    }
+
+    #[test]
+    fn test_active_policy_guards_entry_points() {
+        let mut data = vec![100u8; 16 * 16 * 4];
+        let weak = Effect::GaussianBlur { radius: 2 };
+        let regions = crate::region::parse_regions(
+            r#"[{"rect":{"x":0,"y":0,"w":8,"h":8},"effect":"gaussian_blur","params":{"radius":2}}]"#,
+        )
+        .unwrap();
+        let active = Active::set(&Policy::new());
+
+        assert!(matches!(
+            crate::stack::apply_stack(&[weak], &mut data, 16, 16, Rect::new(0, 0, 8, 8)),
+            Err(RedactError::PolicyViolation { rule: "radius", .. })
+        ));
+        assert!(crate::region::apply_all(&mut data, 16, 16, &regions).is_err());
+        let mut image = crate::RedactrImage::from_rgba(16, 16, data.clone()).unwrap();
+        assert!(image.apply_op(op(weak)).is_err());
+        let thrown = std::panic::catch_unwind(move || {
+            crate::gaussian_blur(&mut data, 16, 16, 0, 0, 8, 8, 2);
+        });
+        assert!(thrown.is_err());
+        assert!(image.to_rgba().iter().all(|&b| b == 100));
+
+        // Upgrades apply to what runs, not just to what is checked
+        drop(active);
+        let _active = Active::set(&Policy {
+            auto_upgrade: true,
+            ..Policy::default()
+        });
+        let pipeline = crate::Pipeline::new().blur(&Rect::new(0, 0, 8, 8), 2);
+        let mut upgraded = image.to_rgba();
+        pipeline.apply(&mut upgraded, 16, 16).unwrap();
+        let mut expected = image.to_rgba();
+        Effect::GaussianBlur { radius: 16 }.apply_rect(
+            &mut expected,
+            16,
+            16,
+            Rect::new(0, 0, 8, 8),
+        );
+        assert_eq!(upgraded, expected);
+    }
+
+    #[test]
+    fn test_policed_size_is_the_upgraded_size() {
+        let rect = Rect::new(0, 0, 8, 8);
+        let mosaic = Effect::Mosaic {
+            cell_size: 4,
+            shape: CellShape::Hexagon,
+            grout: Color::new(0, 0, 0),
+            grout_width: 0,
+        };
+        assert_eq!(policed_size(mosaic, rect), Ok(4));
+        let _active = Active::set(&Policy {
+            auto_upgrade: true,
+            ..Policy::default()
+        });
+        assert_eq!(
+            policed_size(Effect::GaussianBlur { radius: 2 }, rect),
+            Ok(16)
+        );
+        assert_eq!(policed_size(Effect::pixelate(40), rect), Ok(40));
+        assert_eq!(policed_size(mosaic, rect), Ok(12));
+    }
+
+    #[test]
+    fn test_active_policy_polices_sprites() {
+        let mut data = vec![100u8; 4 * 4 * 4];
+        let mut sprite = vec![255u8; 2 * 2 * 4];
+        sprite[3] = 128;
+        let overlay = |data: &mut [u8], sprite: &[u8]| {
+            crate::sprite::overlay(data, 4, 4, sprite, 2, 2, 0, 0, 1.0)
+        };
+        let active = Active::set(&Policy::new());
+        assert!(matches!(
+            overlay(&mut data, &sprite),
+            Err(RedactError::PolicyViolation {
+                rule: "strength",
+                ..
+            })
+        ));
+        assert!(data.iter().all(|&b| b == 100));
+        drop(active);
+
+        let active = Active::set(&Policy::parse(r#"{"allowed_effects": ["pixelate"]}"#).unwrap());
+        assert!(matches!(
+            overlay(&mut data, &[255; 16]),
+            Err(RedactError::EffectNotAllowed { effect: "sprite" })
+        ));
+        drop(active);
+
+        let _active = Active::set(&Policy {
+            auto_upgrade: true,
+            ..Policy::parse(r#"{"allowed_effects": ["sprite"]}"#).unwrap()
+        });
+        overlay(&mut data, &sprite).unwrap();
+        assert_eq!(data[..4], [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_active_policy_polices_noise() {
+        let seed = [7u8; 16];
+        let mut data = vec![100u8; 8 * 8 * 4];
+        let _active = Active::set(&Policy::parse(r#"{"allowed_effects":["solid_fill"]}"#).unwrap());
+        let rect = Rect::new(0, 0, 4, 4);
+        assert_eq!(
+            crate::noise::noise_rect(&mut data, 8, 8, rect, &seed),
+            Err(RedactError::EffectNotAllowed {
+                effect: "noise_fill"
+            })
+        );
+        assert!(crate::noise::noise_brush(&mut data, 8, 8, &[2.0, 2.0], 2, &seed).is_err());
+        assert!(data.iter().all(|&b| b == 100));
+    }
+
+    #[test]
+    fn test_export_requirements() {
+        let policy =
+            Policy::parse(r#"{"require_metadata_strip":true,"require_verification":true}"#)
+                .unwrap();
+        let original: Vec<u8> = (0..16 * 16 * 4).map(|i| (i * 31 % 256) as u8).collect();
+        let mut redacted = original.clone();
+        crate::solid_fill(&mut redacted, 16, 16, 0, 0, 8, 8, 0, 0, 0);
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(&[0, 0, 0, 0]);
+        png.extend_from_slice(b"IEND");
+        png.extend_from_slice(&[0; 4]);
+
+        let region = Rect::new(0, 0, 8, 8);
+        assert!(policy
+            .check_export(&png, &original, &redacted, 16, 16, &[region])
+            .is_ok());
+        let missed = Rect::new(8, 8, 8, 8);
+        assert_eq!(
+            policy.check_export(&png, &original, &redacted, 16, 16, &[region, missed]),
+            Err(RedactError::Recoverable { region: missed })
+        );
+        png.extend_from_slice(b"extra");
+        assert!(matches!(
+            policy.check_export(&png, &original, &redacted, 16, 16, &[region]),
+            Err(RedactError::MetadataRemaining { .. })
+        ));
+    }
+}
@@ -0,0 +1,162 @@
+//! Named redaction presets.
+//!
+//! Built-ins cover the looks product teams ask for by name; hosts can
+//! register their own approved stacks at runtime. Names resolve to an
+//! `EffectStack` wherever a preset is accepted.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::effect::Effect;
+use crate::error::RedactError;
+use crate::stack::EffectStack;
+use crate::types::Color;
+
+/// Built-in preset names, in the order `preset_names` lists them
+pub const BUILTIN_PRESETS: [&str; 3] = ["document_strict", "social_blur", "face_anonymize"];
+
+thread_local! {
+    static USER_PRESETS: RefCell<BTreeMap<String, EffectStack>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+fn builtin(name: &str) -> Option<EffectStack> {
+    let effects = match name {
+        // Opaque black bar, the only look accepted for legal disclosure
+        "document_strict" => vec![Effect::SolidFill {
+            color: Color::new(0, 0, 0),
+        }],
+        // Soft blur that reads as intentional in shared photos
+        "social_blur" => vec![Effect::GaussianBlur { radius: 12 }],
+        // Coarse blocks smoothed over so block edges don't hint at features
+        "face_anonymize" => vec![Effect::pixelate(16), Effect::GaussianBlur { radius: 4 }],
+        _ => return None,
+    };
+    Some(EffectStack::from(effects))
+}
+
+/// Look up a built-in or registered preset
+pub fn resolve_preset(name: &str) -> Result<EffectStack, RedactError> {
+    if let Some(stack) = builtin(name) {
+        return Ok(stack);
+    }
+    USER_PRESETS
+        .with(|presets| presets.borrow().get(name).cloned())
+        .ok_or_else(|| RedactError::UnknownPreset {
+            name: name.to_string(),
+        })
+}
+
+pub(crate) fn register(name: &str, stack: &EffectStack) -> Result<(), RedactError> {
+    if builtin(name).is_some() {
+        return Err(RedactError::ReservedPreset {
+            name: name.to_string(),
+        });
+    }
+    stack.validate()?;
+    USER_PRESETS.with(|presets| presets.borrow_mut().insert(name.to_string(), stack.clone()));
+    Ok(())
+}
+
+/// Register (or replace) a user preset under `name`
+#[wasm_bindgen]
+pub fn register_preset(name: &str, stack: &EffectStack) -> Result<(), JsError> {
+    Ok(register(name, stack)?)
+}
+
+/// Remove a user preset; returns whether one was registered
+#[wasm_bindgen]
+pub fn unregister_preset(name: &str) -> bool {
+    USER_PRESETS.with(|presets| presets.borrow_mut().remove(name).is_some())
+}
+
+/// The effect stack a preset name resolves to
+#[wasm_bindgen]
+pub fn preset(name: &str) -> Result<EffectStack, JsError> {
+    Ok(resolve_preset(name)?)
+}
+
+/// Built-in preset names followed by registered ones (sorted)
+#[wasm_bindgen]
+pub fn preset_names() -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN_PRESETS.iter().map(|n| n.to_string()).collect();
+    USER_PRESETS.with(|presets| names.extend(presets.borrow().keys().cloned()));
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Rect;
+
+    #[test]
+    fn test_builtins_resolve() {
+        for name in BUILTIN_PRESETS {
+            let stack = resolve_preset(name).unwrap();
+            assert!(stack.length() > 0, "{} is empty", name);
+            stack.validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_register_and_resolve_user_preset() {
+        let stack = EffectStack::new().pixelate(20);
+        register("team_approved", &stack).unwrap();
+        assert_eq!(resolve_preset("team_approved").unwrap(), stack);
+        assert!(preset_names().contains(&"team_approved".to_string()));
+
+        assert!(unregister_preset("team_approved"));
+        assert_eq!(
+            resolve_preset("team_approved").unwrap_err(),
+            RedactError::UnknownPreset {
+                name: "team_approved".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_noise_in_a_user_preset() {
+        // Pixelate, overwrite with noise, then darken the noise
+        let seed = [3u8; 32];
+        let stack = EffectStack::new()
+            .pixelate(8)
+            .noise(&seed)
+            .unwrap()
+            .dim(0.5);
+        register("noise_dark", &stack).unwrap();
+        let mut data = vec![200u8; 8 * 8 * 4];
+        let rect = Rect::new(0, 0, 8, 8);
+        resolve_preset("noise_dark")
+            .unwrap()
+            .apply(&mut data, 8, 8, &rect)
+            .unwrap();
+        let mut noise = vec![200u8; 8 * 8 * 4];
+        crate::noise::noise_rect(&mut noise, 8, 8, rect, &seed).unwrap();
+        Effect::Dim { factor: 0.5 }.apply_rect(&mut noise, 8, 8, rect);
+        assert_eq!(data, noise);
+        assert!(unregister_preset("noise_dark"));
+
+        let effect = Effect::from_json(
+            "noise_fill",
+            Some(&Effect::NoiseFill { seed }.params_json()),
+        );
+        assert_eq!(effect.unwrap(), Effect::NoiseFill { seed });
+        assert!(Effect::from_json("noise_fill", None).is_err());
+    }
+
+    #[test]
+    fn test_builtins_are_reserved() {
+        let err = register("social_blur", &EffectStack::new().blur(1)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "preset \"social_blur\" is built in and can't be changed"
+        );
+    }
+
+    #[test]
+    fn test_invalid_stack_is_rejected() {
+        assert!(register("broken", &EffectStack::new().blur(0)).is_err());
+        assert!(resolve_preset("broken").is_err());
+    }
+}
@@ -0,0 +1,708 @@
+//! A small backtracking regular expression engine for OCR text.
+//!
+//! Supports the syntax people reach for when describing identifiers:
+//! literals, `.`, classes (`[A-Z0-9-]`, `[^...]`), `\d \w \s` and their
+//! negations, `\b \B ^ $`, groups (`(...)`, `(?:...)`), alternation and the
+//! `* + ? {n} {n,} {n,m}` quantifiers (lazy with a trailing `?`). A leading
+//! `(?i)` makes the whole pattern case-insensitive. Backreferences and
+//! lookaround aren't supported.
+//!
+//! Matching is meant for short inputs such as a line of text. Patterns
+//! compile to a flat program that is run with an explicit backtrack stack,
+//! so long words and deep nesting can't overflow the call stack. Every
+//! search has a step budget, so a pathological pattern fails with an error
+//! instead of hanging the page.
+
+use crate::error::RedactError;
+
+/// Backtracking steps allowed per search
+const STEP_LIMIT: usize = 200_000;
+/// Largest counted repetition, `{n}` or `{n,m}`
+const MAX_REPEAT: u32 = 1000;
+/// Deepest nesting of groups
+const MAX_DEPTH: usize = 64;
+/// Instructions a compiled pattern may hold once counted repetitions are
+/// expanded
+const MAX_PROGRAM: usize = 50_000;
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Range(lo, hi) => (lo..=hi).contains(&c),
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => is_word(c) != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class {
+        items: Vec<ClassItem>,
+        negated: bool,
+    },
+    Start,
+    End,
+    /// `\b` (true) or `\B` (false)
+    Boundary(bool),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Regex {
+    source: String,
+    program: Vec<Inst>,
+    /// Loop slots the program marks
+    slots: usize,
+    case_insensitive: bool,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Groups open at `pos`
+    depth: usize,
+}
+
+impl Parser {
+    fn error(&self, detail: &'static str) -> RedactError {
+        RedactError::InvalidPattern {
+            offset: self.pos,
+            detail,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        self.pos += found as usize;
+        found
+    }
+
+    fn alternation(&mut self) -> Result<Node, RedactError> {
+        let mut branches = vec![self.concat()?];
+        while self.eat('|') {
+            branches.push(self.concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alt(branches)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, RedactError> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().ok()
+    }
+
+    fn quantifier(&mut self, atom: Node) -> Result<Node, RedactError> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.pos += 1;
+                let min = self
+                    .number()
+                    .ok_or_else(|| self.error("expected a count"))?;
+                let max = if self.eat(',') {
+                    if self.peek() == Some('}') {
+                        None
+                    } else {
+                        Some(
+                            self.number()
+                                .ok_or_else(|| self.error("expected a count"))?,
+                        )
+                    }
+                } else {
+                    Some(min)
+                };
+                if self.peek() != Some('}') {
+                    return Err(self.error("expected '}'"));
+                }
+                if max.is_some_and(|max| max < min) || max.unwrap_or(min) > MAX_REPEAT {
+                    return Err(self.error("invalid repetition count"));
+                }
+                (min, max)
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if matches!(atom, Node::Start | Node::End | Node::Boundary(_)) {
+            return Err(self.error("nothing to repeat"));
+        }
+        let greedy = !self.eat('?');
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    /// Escape after a backslash, as a class item or a literal
+    fn escape(&mut self) -> Result<Result<ClassItem, char>, RedactError> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("trailing backslash"))?;
+        self.pos += 1;
+        Ok(match c {
+            'd' | 'D' => Ok(ClassItem::Digit(c == 'D')),
+            'w' | 'W' => Ok(ClassItem::Word(c == 'W')),
+            's' | 'S' => Ok(ClassItem::Space(c == 'S')),
+            'n' => Err('\n'),
+            't' => Err('\t'),
+            c if c.is_alphanumeric() => {
+                self.pos -= 1;
+                return Err(self.error("unknown escape"));
+            }
+            c => Err(c),
+        })
+    }
+
+    fn atom(&mut self) -> Result<Node, RedactError> {
+        let c = self.peek().ok_or_else(|| self.error("unexpected end"))?;
+        self.pos += 1;
+        Ok(match c {
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err(self.error("unsupported group"));
+                }
+                self.depth += 1;
+                if self.depth > MAX_DEPTH {
+                    return Err(RedactError::InvalidParameter {
+                        name: "group depth",
+                        value: self.depth as f64,
+                        expected: format!("at most {} nested groups", MAX_DEPTH),
+                    });
+                }
+                let inner = self.alternation()?;
+                self.depth -= 1;
+                if !self.eat(')') {
+                    return Err(self.error("unclosed group"));
+                }
+                inner
+            }
+            '[' => self.class()?,
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '\\' => match self.peek() {
+                Some('b') | Some('B') => {
+                    self.pos += 1;
+                    Node::Boundary(self.chars[self.pos - 1] == 'b')
+                }
+                _ => match self.escape()? {
+                    Ok(item) => Node::Class {
+                        items: vec![item],
+                        negated: false,
+                    },
+                    Err(c) => Node::Char(c),
+                },
+            },
+            '*' | '+' | '?' | '{' => {
+                self.pos -= 1;
+                return Err(self.error("nothing to repeat"));
+            }
+            c => Node::Char(c),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, RedactError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or_else(|| self.error("unclosed class"))?;
+            self.pos += 1;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let lo = if c == '\\' {
+                match self.escape()? {
+                    Ok(item) => {
+                        items.push(item);
+                        continue;
+                    }
+                    Err(c) => c,
+                }
+            } else {
+                c
+            };
+            let is_range = self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']');
+            if !is_range {
+                items.push(ClassItem::Range(lo, lo));
+                continue;
+            }
+            self.pos += 1;
+            let hi = match self.peek() {
+                Some('\\') => {
+                    self.pos += 1;
+                    self.escape()?
+                        .err()
+                        .ok_or_else(|| self.error("invalid range"))?
+                }
+                Some(c) => {
+                    self.pos += 1;
+                    c
+                }
+                None => return Err(self.error("unclosed class")),
+            };
+            if hi < lo {
+                return Err(self.error("invalid range"));
+            }
+            items.push(ClassItem::Range(lo, hi));
+        }
+        Ok(Node::Class { items, negated })
+    }
+}
+
+/// One step of a compiled pattern. Matching walks the program with an
+/// explicit backtrack stack, so neither input length nor nesting grows the
+/// call stack.
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class {
+        items: Vec<ClassItem>,
+        negated: bool,
+    },
+    Start,
+    End,
+    Boundary(bool),
+    /// Continue at the first target, falling back to the second
+    Split(usize, usize),
+    Jump(usize),
+    /// Remember where an iteration of an unbounded loop started
+    Mark(usize),
+    /// Fail an iteration that consumed nothing since its `Mark`, which
+    /// couldn't make progress
+    Progress(usize),
+    Match,
+}
+
+#[derive(Default)]
+struct Compiler {
+    program: Vec<Inst>,
+    slots: usize,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, RedactError> {
+        if self.program.len() >= MAX_PROGRAM {
+            return Err(RedactError::InvalidParameter {
+                name: "pattern size",
+                value: self.program.len() as f64,
+                expected: format!("at most {} steps once repetitions expand", MAX_PROGRAM),
+            });
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    /// A split preferring `body` when greedy and `skip` otherwise
+    fn split(greedy: bool, body: usize, skip: usize) -> Inst {
+        match greedy {
+            true => Inst::Split(body, skip),
+            false => Inst::Split(skip, body),
+        }
+    }
+
+    fn emit(&mut self, node: &Node) -> Result<(), RedactError> {
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                self.push(Inst::Char(*c))?;
+            }
+            Node::Any => {
+                self.push(Inst::Any)?;
+            }
+            Node::Class { items, negated } => {
+                self.push(Inst::Class {
+                    items: items.clone(),
+                    negated: *negated,
+                })?;
+            }
+            Node::Start => {
+                self.push(Inst::Start)?;
+            }
+            Node::End => {
+                self.push(Inst::End)?;
+            }
+            Node::Boundary(want) => {
+                self.push(Inst::Boundary(*want))?;
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.emit(node)?;
+                }
+            }
+            Node::Alt(branches) => {
+                let (last, rest) = branches.split_last().expect("alternation has branches");
+                let mut jumps = Vec::new();
+                for branch in rest {
+                    let split = self.push(Inst::Split(0, 0))?;
+                    self.emit(branch)?;
+                    jumps.push(self.push(Inst::Jump(0))?);
+                    self.program[split] = Inst::Split(split + 1, self.program.len());
+                }
+                self.emit(last)?;
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.emit(node)?;
+                }
+                match *max {
+                    None => {
+                        let slot = self.slots;
+                        self.slots += 1;
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.push(Inst::Mark(slot))?;
+                        self.emit(node)?;
+                        self.push(Inst::Progress(slot))?;
+                        self.push(Inst::Jump(split))?;
+                        self.program[split] =
+                            Compiler::split(*greedy, split + 1, self.program.len());
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..max {
+                            splits.push(self.push(Inst::Split(0, 0))?);
+                            self.emit(node)?;
+                        }
+                        let end = self.program.len();
+                        for split in splits {
+                            self.program[split] = Compiler::split(*greedy, split + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A saved alternative, or a loop slot to put back when backtracking past
+/// the `Mark` that changed it
+enum Frame {
+    Try { pc: usize, pos: usize },
+    Restore { slot: usize, pos: usize },
+}
+
+struct Matcher<'t> {
+    program: &'t [Inst],
+    text: &'t [char],
+    case_insensitive: bool,
+    steps: usize,
+    slots: Vec<usize>,
+    stack: Vec<Frame>,
+}
+
+impl Matcher<'_> {
+    fn char_matches(&self, c: char, test: impl Fn(char) -> bool) -> bool {
+        test(c)
+            || (self.case_insensitive
+                && (c.to_lowercase().any(&test) || c.to_uppercase().any(&test)))
+    }
+
+    /// End of the first match starting at `start`, in backtracking order
+    fn run(&mut self, start: usize) -> Option<usize> {
+        let program = self.program;
+        self.stack.clear();
+        self.stack.push(Frame::Try { pc: 0, pos: start });
+        while let Some(frame) = self.stack.pop() {
+            let (mut pc, mut pos) = match frame {
+                Frame::Try { pc, pos } => (pc, pos),
+                Frame::Restore { slot, pos } => {
+                    self.slots[slot] = pos;
+                    continue;
+                }
+            };
+            loop {
+                self.steps += 1;
+                if self.steps > STEP_LIMIT {
+                    return None;
+                }
+                let next = self.text.get(pos).copied();
+                match &program[pc] {
+                    Inst::Char(want) => {
+                        if !next.is_some_and(|c| self.char_matches(c, |c| c == *want)) {
+                            break;
+                        }
+                        pos += 1;
+                    }
+                    Inst::Any => {
+                        if next.is_none() {
+                            break;
+                        }
+                        pos += 1;
+                    }
+                    Inst::Class { items, negated } => {
+                        let hit = next.is_some_and(|c| {
+                            self.char_matches(c, |c| items.iter().any(|i| i.matches(c)))
+                        });
+                        if next.is_none() || hit == *negated {
+                            break;
+                        }
+                        pos += 1;
+                    }
+                    Inst::Start => {
+                        if pos != 0 {
+                            break;
+                        }
+                    }
+                    Inst::End => {
+                        if next.is_some() {
+                            break;
+                        }
+                    }
+                    Inst::Boundary(want) => {
+                        let before = pos > 0 && is_word(self.text[pos - 1]);
+                        let after = next.is_some_and(is_word);
+                        if (before != after) != *want {
+                            break;
+                        }
+                    }
+                    Inst::Split(first, second) => {
+                        self.stack.push(Frame::Try { pc: *second, pos });
+                        pc = *first;
+                        continue;
+                    }
+                    Inst::Jump(target) => {
+                        pc = *target;
+                        continue;
+                    }
+                    Inst::Mark(slot) => {
+                        self.stack.push(Frame::Restore {
+                            slot: *slot,
+                            pos: self.slots[*slot],
+                        });
+                        self.slots[*slot] = pos;
+                    }
+                    Inst::Progress(slot) => {
+                        if self.slots[*slot] == pos {
+                            break;
+                        }
+                    }
+                    Inst::Match => return Some(pos),
+                }
+                pc += 1;
+            }
+        }
+        None
+    }
+}
+
+impl Regex {
+    pub(crate) fn new(pattern: &str) -> Result<Regex, RedactError> {
+        let (case_insensitive, body) = match pattern.strip_prefix("(?i)") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let mut parser = Parser {
+            chars: body.chars().collect(),
+            pos: 0,
+            depth: 0,
+        };
+        let node = parser.alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("unmatched ')'"));
+        }
+        let mut compiler = Compiler::default();
+        compiler.emit(&node)?;
+        compiler.push(Inst::Match)?;
+        Ok(Regex {
+            source: pattern.to_string(),
+            program: compiler.program,
+            slots: compiler.slots,
+            case_insensitive,
+        })
+    }
+
+    /// Non-overlapping `[start, end)` char ranges of every non-empty match,
+    /// leftmost first
+    pub(crate) fn find_all(&self, text: &[char]) -> Result<Vec<(usize, usize)>, RedactError> {
+        let mut matcher = Matcher {
+            program: &self.program,
+            text,
+            case_insensitive: self.case_insensitive,
+            steps: 0,
+            slots: vec![0; self.slots],
+            stack: Vec::new(),
+        };
+        let mut found = Vec::new();
+        let mut start = 0;
+        while start <= text.len() {
+            let end = matcher.run(start);
+            if matcher.steps > STEP_LIMIT {
+                return Err(RedactError::PatternTooComplex {
+                    pattern: self.source.clone(),
+                });
+            }
+            match end {
+                Some(end) if end > start => {
+                    found.push((start, end));
+                    start = end;
+                }
+                _ => start += 1,
+            }
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        Regex::new(pattern)
+            .unwrap()
+            .find_all(&chars)
+            .unwrap()
+            .into_iter()
+            .map(|(s, e)| chars[s..e].iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_account_numbers_and_ticket_ids() {
+        assert_eq!(
+            find(r"\b\d{10}\b", "acct 0123456789, not 01234567890 or 12345"),
+            ["0123456789"]
+        );
+        assert_eq!(
+            find(
+                r"(?i)\b(?:ops|sec)-\d+\b",
+                "see OPS-12 and sec-7, not dops-3"
+            ),
+            ["OPS-12", "sec-7"]
+        );
+        assert_eq!(
+            find(r"[A-Z]{2}[0-9]{2}( ?\d{4}){2}", "GB82 1234 5678"),
+            ["GB82 1234 5678"]
+        );
+        assert_eq!(find("a.*?b", "aXbaYb"), ["aXb", "aYb"]);
+        assert_eq!(find("^x|y$", "xzy"), ["x", "y"]);
+    }
+
+    #[test]
+    fn test_invalid_patterns_report_offsets() {
+        for (pattern, offset) in [
+            ("(ab", 3),
+            ("a{3,1}", 5),
+            ("*a", 0),
+            ("[z-a]", 4),
+            (r"\q", 1),
+        ] {
+            match Regex::new(pattern) {
+                Err(RedactError::InvalidPattern { offset: at, .. }) => {
+                    assert_eq!(at, offset, "{}", pattern)
+                }
+                other => panic!("{}: {:?}", pattern, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_long_words_and_deep_nesting_dont_overflow_the_stack() {
+        let digits = "7".repeat(30_000);
+        assert_eq!(find(r"\d+", &digits), [digits.as_str()]);
+        let short = &digits[..5_000];
+        assert_eq!(find(r"(?:\d|x)*?$", short), [short]);
+        assert_eq!(find(r"\b\d{4}\b", &digits), Vec::<String>::new());
+
+        let nested = format!("{}a{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert_eq!(find(&nested, "bab"), ["a"]);
+        let deep = format!("{}a{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert!(matches!(
+            Regex::new(&deep),
+            Err(RedactError::InvalidParameter {
+                name: "group depth",
+                ..
+            })
+        ));
+        assert!(matches!(
+            Regex::new("(a{1000}){1000}"),
+            Err(RedactError::InvalidParameter {
+                name: "pattern size",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_empty_iterations_stop_loops() {
+        assert_eq!(find("(a*)*b", "aab"), ["aab"]);
+        assert_eq!(find("(?:x?)+y|z", "xxyz"), ["xxy", "z"]);
+        assert_eq!(find("a{2,3}?", "aaaaa"), ["aa", "aa"]);
+    }
+
+    #[test]
+    fn test_catastrophic_backtracking_is_an_error() {
+        let text: Vec<char> = "a".repeat(40).chars().collect();
+        assert!(matches!(
+            Regex::new("(a+)+b").unwrap().find_all(&text),
+            Err(RedactError::PatternTooComplex { .. })
+        ));
+    }
+}
@@ -0,0 +1,392 @@
+//! Mixed batches of redactions described as JSON.
+//!
+//! One call redacts any mix of shapes and effects, instead of one exported
+//! function per combination. A batch is an array of regions:
+//!
+//! ```json
+//! [{"rect": {"x": 10, "y": 10, "w": 80, "h": 20}, "effect": "solid_fill",
+//!   "params": {"color": "#000000"}},
+//!  {"ellipse": {"cx": 200, "cy": 120, "rx": 40, "ry": 52}, "effect": "pixelate"},
+//!  {"polygon": [0, 0, 50, 8, 46, 40, 0, 30], "effect": "gaussian_blur",
+//!   "params": {"radius": 8}}]
+//! ```
+//!
+//! Each region names exactly one shape; a rect may also carry an `angle`,
+//! in degrees clockwise about its center, for text photographed askew.
+//! Any region may set `feather` to fade the effect out over that many
+//! pixels past its edge, and `class` to label the PII it covers (`"FACE"`,
+//! `"SSN"`, ... or a label of the host's own). Missing effect parameters
+//! take the catalog defaults. The whole batch is parsed and validated before any pixel is
+//! written.
+
+use wasm_bindgen::prelude::*;
+
+use crate::classify::PiiClass;
+use crate::effect::Effect;
+use crate::error::{check_buffer, RedactError};
+use crate::json::Json;
+use crate::policy::policed;
+use crate::shape::{apply_feathered, Shape};
+use crate::types::Rect;
+
+/// One effect over one shape
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    shape: Shape,
+    effect: Effect,
+    /// Width in pixels of the fade past the edge, 0 for a hard edge
+    feather: u32,
+    /// PII class of the covered content, if known
+    class: Option<PiiClass>,
+}
+
+impl Region {
+    /// Parse one region; `at` names it in errors (e.g. `regions[2]`)
+    pub(crate) fn from_json_value(value: &Json, at: &str) -> Result<Region, RedactError> {
+        let field = |key: &str, expected| RedactError::InvalidField {
+            field: format!("{}.{}", at, key),
+            expected,
+        };
+        let number = |value: &Json, key: &str| {
+            value
+                .get(key)
+                .and_then(Json::as_f64)
+                .map(|n| n as f32)
+                .ok_or_else(|| field(key, "a number"))
+        };
+        let shapes: Vec<&str> = ["rect", "ellipse", "polygon"]
+            .into_iter()
+            .filter(|key| value.get(key).is_some())
+            .collect();
+        let shape = match shapes[..] {
+            ["rect"] => {
+                let json = value.get("rect").unwrap();
+                let rect = Rect::from_json(json)
+                    .ok_or_else(|| field("rect", "{x, y, w, h} with non-negative integers"))?;
+                match json.get("angle") {
+                    Some(_) => Shape::RotatedRect {
+                        rect,
+                        angle: number(json, "angle")?,
+                    },
+                    None => Shape::Rect(rect),
+                }
+            }
+            ["ellipse"] => {
+                let ellipse = value.get("ellipse").unwrap();
+                Shape::Ellipse {
+                    cx: number(ellipse, "cx")?,
+                    cy: number(ellipse, "cy")?,
+                    rx: number(ellipse, "rx")?,
+                    ry: number(ellipse, "ry")?,
+                }
+            }
+            ["polygon"] => {
+                let points = value
+                    .get("polygon")
+                    .and_then(Json::as_array)
+                    .and_then(|points| {
+                        points
+                            .iter()
+                            .map(|p| p.as_f64().map(|n| n as f32))
+                            .collect::<Option<Vec<f32>>>()
+                    })
+                    .ok_or_else(|| field("polygon", "a flat array of vertex coordinates"))?;
+                Shape::polygon(&points)?
+            }
+            _ => return Err(field("shape", "exactly one of rect, ellipse or polygon")),
+        };
+        let name = value
+            .get("effect")
+            .and_then(Json::as_str)
+            .ok_or_else(|| field("effect", "an effect name"))?;
+        let effect = Effect::from_json(name, value.get("params"))?;
+        let feather = match value.get("feather") {
+            None => 0,
+            Some(json) => json
+                .as_f64()
+                .filter(|n| n.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(n))
+                .ok_or_else(|| field("feather", "a non-negative integer"))?
+                as u32,
+        };
+        let class = match value.get("class") {
+            None | Some(Json::Null) => None,
+            Some(json) => Some(
+                json.as_str()
+                    .and_then(|name| PiiClass::from_name(name).ok())
+                    .ok_or_else(|| field("class", "a PII class label"))?,
+            ),
+        };
+        shape.validate()?;
+        Ok(Region {
+            shape,
+            effect,
+            feather,
+            class,
+        })
+    }
+
+    pub(crate) fn effect(&self) -> &Effect {
+        &self.effect
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        let json = match &self.shape {
+            Shape::Rect(rect) => Json::object().with("rect", rect.json()),
+            Shape::RotatedRect { rect, angle } => {
+                Json::object().with("rect", rect.json().with("angle", *angle))
+            }
+            Shape::Ellipse { cx, cy, rx, ry } => Json::object().with(
+                "ellipse",
+                Json::object()
+                    .with("cx", *cx)
+                    .with("cy", *cy)
+                    .with("rx", *rx)
+                    .with("ry", *ry),
+            ),
+            Shape::Polygon(vertices) => {
+                let points = vertices
+                    .iter()
+                    .flat_map(|&(x, y)| [Json::from(x), Json::from(y)])
+                    .collect();
+                Json::object().with("polygon", Json::Array(points))
+            }
+        };
+        let json = json
+            .with("effect", self.effect.name())
+            .with("params", self.effect.params_json());
+        let json = match self.feather {
+            0 => json,
+            feather => json.with("feather", feather),
+        };
+        match &self.class {
+            None => json,
+            Some(class) => json.with("class", class.name()),
+        }
+    }
+
+    /// The region on an image scaled by `sx` and `sy`; effect parameters
+    /// and the feather scale by their mean
+    pub(crate) fn scaled(&self, sx: f32, sy: f32) -> Region {
+        let factor = (sx + sy) / 2.0;
+        Region {
+            shape: self.shape.scaled(sx, sy),
+            effect: self.effect.scaled(factor),
+            feather: (self.feather as f32 * factor).round() as u32,
+            class: self.class.clone(),
+        }
+    }
+
+    /// The region on an image cropped to start `rows` further down
+    pub(crate) fn moved_up(&self, rows: u32) -> Region {
+        Region {
+            shape: self.shape.moved_up(rows),
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), RedactError> {
+        self.shape.validate()?;
+        self.effect.validate()
+    }
+
+    /// Pixels the region can change, clipped to the image
+    pub(crate) fn bounds(&self, width: u32, height: u32) -> Option<Rect> {
+        self.shape.grown_bounds(self.feather, width, height)
+    }
+
+    pub(crate) fn apply_to(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+    ) -> Result<(), RedactError> {
+        apply_feathered(
+            std::slice::from_ref(&self.effect),
+            data,
+            width,
+            height,
+            &self.shape,
+            self.feather,
+        )
+    }
+}
+
+#[wasm_bindgen]
+impl Region {
+    /// Parse one region object (see the module docs for the format)
+    pub fn from_json(text: &str) -> Result<Region, JsError> {
+        Ok(Region::from_json_value(&Json::parse(text)?, "region")?)
+    }
+
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+
+    /// Redact this region of a raw RGBA buffer in place
+    pub fn apply(&self, data: &mut [u8], width: u32, height: u32) -> Result<(), JsError> {
+        Ok(self.apply_to(data, width, height)?)
+    }
+}
+
+/// Parse a JSON array of regions
+pub(crate) fn parse_regions(text: &str) -> Result<Vec<Region>, RedactError> {
+    regions_from_json(&Json::parse(text)?)
+}
+
+/// Regions from an already parsed JSON array
+pub(crate) fn regions_from_json(json: &Json) -> Result<Vec<Region>, RedactError> {
+    let items = json.as_array().ok_or(RedactError::InvalidField {
+        field: "regions".to_string(),
+        expected: "an array of regions",
+    })?;
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| Region::from_json_value(item, &format!("regions[{}]", i)))
+        .collect()
+}
+
+/// Apply every region in order, after checking the buffer and parsing and
+/// validating all of them
+pub(crate) fn apply_all(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    regions: &[Region],
+) -> Result<(), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    check_policy(regions, width, height)?;
+    for region in regions {
+        region.apply_to(data, width, height)?;
+    }
+    Ok(())
+}
+
+/// Fail if the active policy rejects any of `regions`, before one is applied
+pub(crate) fn check_policy(regions: &[Region], width: u32, height: u32) -> Result<(), RedactError> {
+    for region in regions {
+        if let Some(bounds) = region.bounds(width, height) {
+            policed(region.effect, bounds)?;
+        }
+    }
+    Ok(())
+}
+
+/// Redact a mixed batch of regions in one call; `regions_json` is an array
+/// of regions as described in the module docs. Nothing is written unless
+/// the whole batch is valid.
+#[wasm_bindgen]
+pub fn apply_regions(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    regions_json: &str,
+) -> Result<(), JsError> {
+    let regions = parse_regions(regions_json)?;
+    Ok(apply_all(data, width, height, &regions)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern;
+    use crate::types::Color;
+
+    #[test]
+    fn test_batch_matches_individual_calls() {
+        let text = r##"[
+            {"rect": {"x": 1, "y": 1, "w": 6, "h": 4}, "effect": "solid_fill",
+             "params": {"color": "#102030"}},
+            {"ellipse": {"cx": 14, "cy": 10, "rx": 5, "ry": 4}, "effect": "pixelate"},
+            {"polygon": [2, 10, 10, 12, 8, 18, 1, 17], "effect": "gaussian_blur",
+             "params": {"radius": 3}},
+            {"rect": {"x": 10, "y": 2, "w": 8, "h": 3, "angle": 20}, "effect": "pixelate"},
+            {"ellipse": {"cx": 6, "cy": 6, "rx": 3, "ry": 2}, "effect": "gaussian_blur",
+             "feather": 2}
+        ]"##;
+        let regions = parse_regions(text).unwrap();
+        let mut data = pattern(20, 20);
+        apply_all(&mut data, 20, 20, &regions).unwrap();
+
+        let mut expected = pattern(20, 20);
+        let fill = Effect::SolidFill {
+            color: Color::new(0x10, 0x20, 0x30),
+        };
+        fill.apply_rect(&mut expected, 20, 20, Rect::new(1, 1, 6, 4));
+        for region in &regions[1..] {
+            let effects = [region.effect];
+            apply_feathered(
+                &effects,
+                &mut expected,
+                20,
+                20,
+                &region.shape,
+                region.feather,
+            )
+            .unwrap();
+        }
+        assert_eq!(data, expected);
+
+        // Round trip through the serialized form
+        for region in &regions[2..] {
+            let again = Region::from_json_value(&region.json(), "region").unwrap();
+            assert_eq!(&again, region);
+        }
+    }
+
+    #[test]
+    fn test_bad_regions_name_their_position() {
+        let err = |text: &str| parse_regions(text).unwrap_err().to_string();
+        assert_eq!(
+            err(r#"[{"rect": {"x": 0, "y": 0, "w": 2, "h": 2}, "effect": "blur"}]"#),
+            "invalid field \"blur\": expected a known effect name"
+        );
+        assert_eq!(
+            err(r#"[{"effect": "pixelate"}, {}]"#),
+            "invalid field \"regions[0].shape\": expected exactly one of rect, ellipse or polygon"
+        );
+        assert_eq!(
+            err(r#"[{"ellipse": {"cx": 1, "cy": 1, "rx": 2}, "effect": "pixelate"}]"#),
+            "invalid field \"regions[0].ry\": expected a number"
+        );
+        assert_eq!(
+            err(
+                r#"[{"ellipse": {"cx": 1, "cy": 1, "rx": 2, "ry": 2}, "effect": "pixelate",
+                     "feather": -3}]"#
+            ),
+            "invalid field \"regions[0].feather\": expected a non-negative integer"
+        );
+        assert_eq!(
+            err(
+                r#"[{"rect": {"x": 0, "y": 0, "w": 2, "h": 2}, "effect": "pixelate", "class": ""}]"#
+            ),
+            "invalid field \"regions[0].class\": expected a PII class label"
+        );
+        assert!(parse_regions(r#"{"rect": {}}"#).is_err());
+    }
+
+    #[test]
+    fn test_class_survives_the_round_trip() {
+        let regions = parse_regions(
+            r#"[{"rect": {"x": 0, "y": 0, "w": 4, "h": 4}, "effect": "pixelate", "class": "SSN"},
+                {"ellipse": {"cx": 6, "cy": 6, "rx": 3, "ry": 2}, "effect": "pixelate",
+                 "class": "PASSPORT"},
+                {"rect": {"x": 4, "y": 4, "w": 2, "h": 2}, "effect": "pixelate"}]"#,
+        )
+        .unwrap();
+        let passport = PiiClass::Custom("PASSPORT".to_string());
+        assert_eq!(regions[0].class.as_ref(), Some(&PiiClass::Ssn));
+        assert_eq!(regions[1].class.as_ref(), Some(&passport));
+        assert_eq!(regions[2].class.as_ref(), None);
+        assert_eq!(regions[1].scaled(2.0, 2.0).class.as_ref(), Some(&passport));
+        for region in &regions {
+            let json = region.json();
+            assert_eq!(
+                json.get("class").and_then(Json::as_str),
+                region.class.as_ref().map(PiiClass::name)
+            );
+            assert_eq!(&Region::from_json_value(&json, "region").unwrap(), region);
+        }
+    }
+}
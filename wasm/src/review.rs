@@ -0,0 +1,168 @@
+//! Review mode: a plan's regions drawn as labeled, translucent highlights.
+//!
+//! Redaction is two-phase in practice: a reviewer checks every proposed
+//! region on a marked-up copy, then the approved plan is applied for real.
+//! The review render tints each region in its PII class's color, outlines it
+//! and tags it with the class (or the effect, when unclassified). It writes
+//! into a copy, so the original pixels and the plan are untouched and
+//! approving is just running the same `Pipeline`.
+
+use crate::classify::PiiClass;
+use crate::error::{check_buffer, RedactError};
+use crate::font::{draw_text, text_width};
+use crate::palettes::legible_ink;
+use crate::pipeline::{Op, Pipeline};
+use crate::types::{Color, Rect};
+
+/// Opacity of the tint over a proposed region
+const TINT: f32 = 0.35;
+/// Outline width in px
+const OUTLINE: u32 = 2;
+/// Height of the label tag in px
+const LABEL_H: u32 = 11;
+
+fn highlight_color(class: Option<&PiiClass>) -> Color {
+    match class {
+        Some(PiiClass::Face) => Color::new(255, 140, 0),
+        Some(PiiClass::Name) => Color::new(30, 120, 255),
+        Some(PiiClass::Ssn) => Color::new(230, 30, 60),
+        Some(PiiClass::Financial) => Color::new(20, 170, 80),
+        Some(PiiClass::Custom(_)) => Color::new(160, 60, 220),
+        None => Color::new(250, 210, 0),
+    }
+}
+
+/// One highlighted region; consecutive steps on the same rectangle (an
+/// effect stack) are one proposal
+fn proposals(plan: &Pipeline) -> Vec<(Rect, Option<PiiClass>, String)> {
+    let mut out: Vec<(Rect, Option<PiiClass>, String)> = Vec::new();
+    for op in plan.ops() {
+        match out.last_mut() {
+            Some((region, class, _)) if *region == op.region => {
+                *class = class.take().or_else(|| op.class.clone());
+            }
+            _ => out.push((op.region, op.class.clone(), label(op))),
+        }
+    }
+    for (_, class, text) in &mut out {
+        if let Some(class) = class {
+            *text = class.name().to_string();
+        }
+    }
+    out
+}
+
+fn label(op: &Op) -> String {
+    op.effect.name().replace('_', " ").to_uppercase()
+}
+
+fn tint(data: &mut [u8], width: u32, rect: Rect, color: Color, alpha: f32) {
+    for y in rect.y..rect.bottom() {
+        for x in rect.x..rect.right() {
+            let i = ((y * width + x) * 4) as usize;
+            for (c, value) in [color.r, color.g, color.b].into_iter().enumerate() {
+                let old = data[i + c] as f32;
+                data[i + c] = (old + (value as f32 - old) * alpha).round() as u8;
+            }
+        }
+    }
+}
+
+/// Draw `region` as a tinted, outlined box with its label tag on top,
+/// inside the region when there is no room above it
+fn highlight(data: &mut [u8], width: u32, height: u32, region: Rect, color: Color, text: &str) {
+    let Some(region) = region.clip(width, height) else {
+        return;
+    };
+    tint(data, width, region, color, TINT);
+    let t = OUTLINE.min(region.w / 2).min(region.h / 2).max(1);
+    let edges = [
+        Rect::new(region.x, region.y, region.w, t),
+        Rect::new(region.x, region.bottom() - t, region.w, t),
+        Rect::new(region.x, region.y, t, region.h),
+        Rect::new(region.right() - t, region.y, t, region.h),
+    ];
+    for edge in edges {
+        tint(data, width, edge, color, 1.0);
+    }
+
+    let tag_h = LABEL_H.min(height);
+    let tag_w = (text_width(text, tag_h) + 4).min(width);
+    let x = region.x.min(width - tag_w);
+    let y = if region.y >= tag_h {
+        region.y - tag_h
+    } else {
+        region.y.min(height - tag_h)
+    };
+    let tag = Rect::new(x, y, tag_w, tag_h);
+    tint(data, width, tag, color, 1.0);
+    draw_text(data, width, height, tag, text, legible_ink(color));
+}
+
+/// Copy of `data` with every region of `plan` highlighted for review
+pub(crate) fn render_review(
+    plan: &Pipeline,
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    plan.validate(width, height)?;
+    let mut marked = data.to_vec();
+    for (region, class, text) in proposals(plan) {
+        highlight(
+            &mut marked,
+            width,
+            height,
+            region,
+            highlight_color(class.as_ref()),
+            &text,
+        );
+    }
+    Ok(marked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_review_tints_without_covering() {
+        let (w, h) = (40, 40);
+        let data: Vec<u8> = (0..w * h * 4).map(|i| (i * 29 % 251) as u8).collect();
+        let plan = Pipeline::new().fill(&Rect::new(10, 20, 20, 10), &Color::new(0, 0, 0));
+        let marked = render_review(&plan, &data, w, h).unwrap();
+
+        let at = |buf: &[u8], x: u32, y: u32| buf[((y * w + x) * 4) as usize];
+        // Inside: a blend, so the original still shows through
+        let (old, new) = (at(&data, 20, 25), at(&marked, 20, 25));
+        let expected = (old as f32 + (250.0 - old as f32) * TINT).round() as u8;
+        assert_eq!(new, expected);
+        // Outline is solid, the rest of the image is untouched
+        assert_eq!(&marked[((29 * w + 20) * 4) as usize..][..3], &[250, 210, 0]);
+        assert_eq!(at(&marked, 5, 35), at(&data, 5, 35));
+        // The label tag sits above the region
+        assert_eq!(&marked[((9 * w + 10) * 4) as usize..][..3], &[250, 210, 0]);
+
+        // And the plan still redacts for real
+        let mut applied = data.clone();
+        plan.apply(&mut applied, w, h).unwrap();
+        assert_eq!(at(&applied, 20, 25), 0);
+    }
+
+    #[test]
+    fn test_stacked_steps_are_one_labeled_proposal() {
+        let region = Rect::new(0, 0, 8, 8);
+        let plan = Pipeline::new()
+            .blur(&region, 4)
+            .fill(&region, &Color::new(0, 0, 0))
+            .classify("SSN")
+            .unwrap()
+            .pixelate(&Rect::new(8, 8, 8, 8), 4);
+        let found = proposals(&plan);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0], (region, Some(PiiClass::Ssn), "SSN".to_string()));
+        assert_eq!(found[1].2, "PIXELATE");
+        assert!(render_review(&plan, &[0; 16], 2, 2).is_err());
+    }
+}
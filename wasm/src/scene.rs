@@ -0,0 +1,65 @@
+//! Hard-cut detection between consecutive video frames.
+//!
+//! Frames are reduced to a coarse luma histogram; a cut is a jump in the
+//! histogram that ordinary motion and lighting drift don't produce. It's
+//! cheap enough to run on every frame and doesn't need a decoder's help.
+
+/// Histogram distance (0..=1) above which two frames are a cut
+pub(crate) const DEFAULT_CUT_THRESHOLD: f32 = 0.5;
+
+const BINS: usize = 32;
+/// Only every `STRIDE`th luma sample is counted
+const STRIDE: usize = 7;
+
+/// Normalized luma histogram of one frame
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Signature([f32; BINS]);
+
+impl Signature {
+    pub(crate) fn from_luma(luma: impl Iterator<Item = u8>) -> Signature {
+        let mut bins = [0f32; BINS];
+        let mut count = 0usize;
+        for l in luma.step_by(STRIDE) {
+            bins[l as usize * BINS / 256] += 1.0;
+            count += 1;
+        }
+        if count > 0 {
+            bins.iter_mut().for_each(|b| *b /= count as f32);
+        }
+        Signature(bins)
+    }
+
+    pub(crate) fn from_rgba(data: &[u8]) -> Signature {
+        Signature::from_luma(
+            data.chunks_exact(4).map(|px| {
+                ((px[0] as u32 * 77 + px[1] as u32 * 150 + px[2] as u32 * 29) >> 8) as u8
+            }),
+        )
+    }
+
+    /// Half the L1 distance: 0 for identical histograms, 1 for disjoint ones
+    pub(crate) fn distance(&self, other: &Signature) -> f32 {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| (a - b).abs())
+            .sum::<f32>()
+            / 2.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_separates_cuts_from_drift() {
+        let dark: Vec<u8> = (0..4096).map(|i| (i % 64) as u8).collect();
+        let drifted: Vec<u8> = dark.iter().map(|l| l + 4).collect();
+        let bright: Vec<u8> = dark.iter().map(|l| l + 160).collect();
+        let a = Signature::from_luma(dark.into_iter());
+        assert_eq!(a.distance(&a), 0.0);
+        assert!(a.distance(&Signature::from_luma(drifted.into_iter())) < DEFAULT_CUT_THRESHOLD);
+        assert!((a.distance(&Signature::from_luma(bright.into_iter())) - 1.0).abs() < 1e-5);
+    }
+}
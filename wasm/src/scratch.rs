@@ -0,0 +1,39 @@
+//! Scratch buffers that may hold unredacted pixels.
+//!
+//! Region copies, blur passes and re-render snapshots outlive the pixels
+//! they were taken from, and freed wasm memory isn't cleared. With
+//! `set_zeroize_scratch(true)` every such buffer is overwritten with zeros
+//! when it is dropped; `RedactrImage::wipe_scratch` clears a retained
+//! snapshot on demand. The buffer type and the setting live in
+//! `redactr-core`, whose blur passes use them too.
+
+use wasm_bindgen::prelude::*;
+
+pub(crate) use redactr_core::scratch::Scratch;
+
+/// Zeroize scratch buffers when they're dropped (off by default)
+#[wasm_bindgen]
+pub fn set_zeroize_scratch(enabled: bool) {
+    redactr_core::scratch::set_zeroize_scratch(enabled);
+}
+
+#[wasm_bindgen]
+pub fn zeroize_scratch_enabled() -> bool {
+    redactr_core::scratch::zeroize_scratch_enabled()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setting_is_off_by_default_and_toggles() {
+        assert!(!zeroize_scratch_enabled());
+        set_zeroize_scratch(true);
+        assert!(zeroize_scratch_enabled());
+        // The core crate's blur passes read the same setting
+        assert!(redactr_core::scratch::zeroize_scratch_enabled());
+        drop(Scratch::from(vec![9; 16]));
+        set_zeroize_scratch(false);
+    }
+}
@@ -0,0 +1,146 @@
+//! Reversible redaction: lift a region's original pixels out for the host
+//! to encrypt, fill the visible region, and put them back on recovery.
+//!
+//! Encryption itself happens on the host (WebCrypto AES-GCM with the
+//! caller's key, see `sealed.ts`) so the key never enters the module.
+
+use wasm_bindgen::prelude::*;
+
+use crate::buffer::{read_region, write_region};
+use crate::effect::Effect;
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::policy::policed;
+use crate::scratch::Scratch;
+use crate::types::{Color, Rect};
+
+/// Original pixels of a sealed region, clipped to the image
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionPixels {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+    pixels: Scratch,
+}
+
+#[wasm_bindgen]
+impl RegionPixels {
+    /// Tightly packed RGBA of the region, `w * h * 4` bytes
+    pub fn pixels(&self) -> Vec<u8> {
+        self.pixels.to_vec()
+    }
+}
+
+/// Copy out the pixels of `rect`, then fill it with `color`
+pub fn seal(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+    color: Color,
+) -> Result<RegionPixels, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    check_region(rect, width, height)?;
+    let rect = rect.clip(width, height).unwrap_or(rect);
+    let fill = policed(Effect::SolidFill { color }, rect)?;
+    let pixels = read_region(data, width, rect);
+    fill.apply_rect(data, width, height, rect);
+    let Rect { x, y, w, h } = rect;
+    Ok(RegionPixels { x, y, w, h, pixels })
+}
+
+/// Write previously sealed pixels back; `rect` must be the clipped rect
+/// `seal` returned
+pub fn unseal(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+    pixels: &[u8],
+) -> Result<(), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    check_region(rect, width, height)?;
+    if rect.clip(width, height) != Some(rect) {
+        return Err(RedactError::RegionOutOfBounds {
+            region: rect,
+            width,
+            height,
+        });
+    }
+    check_buffer(pixels.len(), rect.w, rect.h)?;
+    write_region(data, width, rect, pixels);
+    Ok(())
+}
+
+/// Fill a region after copying out its original pixels for encryption
+#[wasm_bindgen]
+pub fn seal_region(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+) -> Result<RegionPixels, JsError> {
+    Ok(seal(
+        data,
+        width,
+        height,
+        Rect::new(x, y, w, h),
+        Color::new(r, g, b),
+    )?)
+}
+
+/// Restore decrypted pixels into a sealed region
+#[wasm_bindgen]
+pub fn unseal_region(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    pixels: &[u8],
+) -> Result<(), JsError> {
+    Ok(unseal(data, width, height, Rect::new(x, y, w, h), pixels)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern;
+
+    #[test]
+    fn test_seal_then_unseal_round_trips() {
+        let original = pattern(10, 10);
+        let mut data = original.clone();
+        let sealed = seal(
+            &mut data,
+            10,
+            10,
+            Rect::new(6, 6, 8, 8),
+            Color::new(0, 0, 0),
+        )
+        .unwrap();
+        assert_eq!((sealed.w, sealed.h), (4, 4));
+        assert_eq!(data[((6 * 10 + 6) * 4) as usize], 0);
+
+        let rect = Rect::new(sealed.x, sealed.y, sealed.w, sealed.h);
+        unseal(&mut data, 10, 10, rect, &sealed.pixels()).unwrap();
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn test_unseal_rejects_mismatched_pixels() {
+        let mut data = pattern(10, 10);
+        let err = unseal(&mut data, 10, 10, Rect::new(0, 0, 2, 2), &[0; 12]).unwrap_err();
+        assert_eq!(err.to_string(), "data length 12 does not match 2x2x4");
+        assert!(unseal(&mut data, 10, 10, Rect::new(8, 8, 4, 4), &[0; 64]).is_err());
+    }
+}
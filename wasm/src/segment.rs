@@ -0,0 +1,202 @@
+//! Person segmentation, for blurring whole people rather than just faces.
+//!
+//! Like the face cascade, the model doesn't ship with the module: the host
+//! fetches an ONNX person-segmentation model (a MODNet or selfie-segmenter
+//! export, a few megabytes) and hands its bytes to `PersonSegmenter`, which
+//! runs it with tract. The model must take one `[1, 3, H, W]` float input,
+//! RGB scaled to 0..1, and produce a person probability map: `[1, 1, h, w]`,
+//! `[1, h, w]`, or `[1, C, h, w]` with the person class last.
+//!
+//! `mask` upsamples the probabilities to the image as a 0-255 coverage
+//! buffer for `apply_with_mask`. `blur_people` does both in one call,
+//! treating probabilities of one half or more as full coverage so no part
+//! of a person is blurred only partway.
+//!
+//! Built with the `segment` feature, which is off by default.
+
+use tract_onnx::prelude::*;
+use wasm_bindgen::prelude::*;
+
+use crate::effect::Effect;
+use crate::error::{check_buffer, RedactError};
+use crate::mask::apply_masked;
+use crate::resize::{resample, ResizeFilter};
+
+fn model_error(error: impl std::fmt::Display) -> RedactError {
+    RedactError::InvalidModel {
+        detail: error.to_string(),
+    }
+}
+
+/// A loaded segmentation model and the input size it runs at
+#[wasm_bindgen]
+pub struct PersonSegmenter {
+    plan: TypedRunnableModel<TypedModel>,
+    input_width: u32,
+    input_height: u32,
+}
+
+impl PersonSegmenter {
+    pub(crate) fn load(
+        model: &[u8],
+        input_width: u32,
+        input_height: u32,
+    ) -> Result<PersonSegmenter, RedactError> {
+        for (name, side) in [("input_width", input_width), ("input_height", input_height)] {
+            if side == 0 {
+                return Err(RedactError::InvalidParameter {
+                    name,
+                    value: 0.0,
+                    expected: "a positive size".to_string(),
+                });
+            }
+        }
+        let shape = [1, 3, input_height as usize, input_width as usize];
+        let plan = tract_onnx::onnx()
+            .model_for_read(&mut std::io::Cursor::new(model))
+            .and_then(|m| m.with_input_fact(0, f32::fact(shape).into()))
+            .and_then(|m| m.into_optimized())
+            .and_then(|m| m.into_runnable())
+            .map_err(model_error)?;
+        Ok(PersonSegmenter {
+            plan,
+            input_width,
+            input_height,
+        })
+    }
+
+    /// Person probability per model output pixel, with the output's size
+    fn probabilities(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<(Vec<f32>, u32, u32), RedactError> {
+        let (iw, ih) = (self.input_width, self.input_height);
+        let small = resample(data, width, height, iw, ih, ResizeFilter::CatmullRom)?;
+        let input = tract_ndarray::Array4::from_shape_fn(
+            (1, 3, ih as usize, iw as usize),
+            |(_, c, y, x)| small[(y * iw as usize + x) * 4 + c] as f32 / 255.0,
+        );
+        let outputs = self
+            .plan
+            .run(tvec!(Tensor::from(input).into()))
+            .map_err(model_error)?;
+        let output = outputs[0].to_array_view::<f32>().map_err(model_error)?;
+        let shape = output.shape();
+        let (oh, ow) = match *shape {
+            [1, h, w] | [1, _, h, w] => (h, w),
+            _ => return Err(model_error(format!("unexpected output shape {:?}", shape))),
+        };
+        // The person class is the last channel
+        let values: Vec<f32> = output.iter().copied().collect();
+        let last = values.len() - oh * ow;
+        Ok((values[last..].to_vec(), ow as u32, oh as u32))
+    }
+
+    pub(crate) fn coverage(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, RedactError> {
+        check_buffer(data.len(), width, height)?;
+        let (probs, pw, ph) = self.probabilities(data, width, height)?;
+        Ok(upsample(&probs, pw, ph, width, height))
+    }
+}
+
+/// Bilinear upsampling of a `pw` x `ph` probability map to 0-255 coverage
+/// at `width` x `height`, sampling at pixel centers
+fn upsample(probs: &[f32], pw: u32, ph: u32, width: u32, height: u32) -> Vec<u8> {
+    let at = |x: usize, y: usize| probs[y * pw as usize + x].clamp(0.0, 1.0);
+    let axis = |p: u32, n: u32, size: u32| {
+        let f = ((p as f32 + 0.5) * n as f32 / size as f32 - 0.5).clamp(0.0, (n - 1) as f32);
+        let i = (f as usize).min(n as usize - 1);
+        (i, (i + 1).min(n as usize - 1), f - i as f32)
+    };
+    let mut out = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        let (y0, y1, ty) = axis(y, ph, height);
+        for x in 0..width {
+            let (x0, x1, tx) = axis(x, pw, width);
+            let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * tx;
+            let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * tx;
+            let p = top + (bottom - top) * ty;
+            out.push((p * 255.0).round() as u8);
+        }
+    }
+    out
+}
+
+/// Coverage of one half or more becomes full; the rest still fades out
+fn harden(mask: &mut [u8]) {
+    for m in mask {
+        *m = if *m >= 128 { 255 } else { *m * 2 };
+    }
+}
+
+#[wasm_bindgen]
+impl PersonSegmenter {
+    /// Load an ONNX model from its bytes, to be run at `input_width` x
+    /// `input_height`
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        model: &[u8],
+        input_width: u32,
+        input_height: u32,
+    ) -> Result<PersonSegmenter, JsError> {
+        Ok(PersonSegmenter::load(model, input_width, input_height)?)
+    }
+
+    /// Per-pixel person coverage (0..=255), ready for `apply_with_mask`
+    pub fn mask(&self, data: &[u8], width: u32, height: u32) -> Result<Vec<u8>, JsError> {
+        Ok(self.coverage(data, width, height)?)
+    }
+
+    /// Gaussian-blur every person in the image
+    pub fn blur_people(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        radius: u32,
+    ) -> Result<(), JsError> {
+        let mut mask = self.coverage(data, width, height)?;
+        harden(&mut mask);
+        let blur = Effect::GaussianBlur { radius };
+        Ok(apply_masked(&blur, data, width, height, &mask)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upsampling_and_hardening() {
+        // Left half person, right half background
+        let probs = [1.0, 0.0, 1.0, 0.0];
+        let mut mask = upsample(&probs, 2, 2, 8, 4);
+        let row: Vec<u8> = mask[..8].to_vec();
+        assert_eq!(row[..2], [255, 255]);
+        assert_eq!(row[6..], [0, 0]);
+        assert!(row[3] > row[4] && row[3] < 255);
+        assert!(mask.chunks(8).all(|r| r == row));
+
+        harden(&mut mask);
+        assert_eq!(mask[3], 255);
+        assert_eq!(mask[5], row[5] * 2);
+    }
+
+    #[test]
+    fn test_rejects_bad_models() {
+        let err = PersonSegmenter::load(b"not a model", 64, 64).err().unwrap();
+        assert!(matches!(err, RedactError::InvalidModel { .. }));
+        let err = PersonSegmenter::load(b"", 0, 64).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid input_width 0: expected a positive size"
+        );
+    }
+}
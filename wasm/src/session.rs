@@ -0,0 +1,403 @@
+//! Undoable editing of an image held on the wasm side.
+//!
+//! Each edit keeps a copy of just the rectangle it changed, taken before
+//! the change; undoing swaps that copy back in and keeps the pixels it
+//! replaced for redo. History costs memory proportional to the edited
+//! area, not to the image, and the host never holds image copies at all.
+//!
+//! Every edit, undo and redo is also appended to an `AuditLog`, with
+//! SHA-256 hashes of the edited rectangle before and after, so the trail
+//! can be attached to the exported file as evidence of what was done.
+
+use wasm_bindgen::prelude::*;
+
+use crate::audit::AuditLog;
+use crate::buffer::{read_region, write_region};
+use crate::error::{check_buffer, RedactError};
+use crate::hash::sha256;
+use crate::json::Json;
+use crate::region::Region;
+use crate::scratch::Scratch;
+use crate::types::{Color, Rect};
+use crate::{policed_brush_gaussian_blur, policed_brush_pixelate, policed_brush_solid_fill};
+
+/// Edits kept for undo unless `set_history_limit` says otherwise
+pub const DEFAULT_HISTORY: usize = 100;
+
+/// The pixels of `rect` on one side of an edit
+struct Snapshot {
+    rect: Rect,
+    pixels: Scratch,
+}
+
+#[wasm_bindgen]
+pub struct RedactionSession {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    limit: usize,
+    audit: AuditLog,
+}
+
+/// Bounding box of a brush stroke, clipped to the image
+fn stroke_bounds(points: &[f32], brush_size: u32, width: u32, height: u32) -> Option<Rect> {
+    let r = (brush_size / 2) as f32;
+    let (x0, y0, x1, y1) = points.chunks_exact(2).fold(
+        (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+        |(x0, y0, x1, y1), p| {
+            let (x, y) = ((p[0] as i32) as f32, (p[1] as i32) as f32);
+            (x0.min(x - r), y0.min(y - r), x1.max(x + r), y1.max(y + r))
+        },
+    );
+    if x0 > x1 || x1 < 0.0 || y1 < 0.0 {
+        return None;
+    }
+    // Float to int casts saturate, so far-off points clamp to the image
+    let (x, y) = (x0.max(0.0) as u32, y0.max(0.0) as u32);
+    if x >= width || y >= height {
+        return None;
+    }
+    let right = (x1 as u32).saturating_add(1).min(width);
+    let bottom = (y1 as u32).saturating_add(1).min(height);
+    Some(Rect::new(x, y, right - x, bottom - y))
+}
+
+/// Audit parameters shared by the brush strokes
+fn stroke_params(points: &[f32], brush_size: u32) -> Json {
+    let points = points.iter().map(|&p| Json::from(p)).collect();
+    Json::object()
+        .with("points", Json::Array(points))
+        .with("brush_size", brush_size)
+}
+
+impl RedactionSession {
+    pub(crate) fn from_rgba(width: u32, height: u32, pixels: Vec<u8>) -> Result<Self, RedactError> {
+        check_buffer(pixels.len(), width, height)?;
+        Ok(RedactionSession {
+            width,
+            height,
+            pixels,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            limit: DEFAULT_HISTORY,
+            audit: AuditLog::default(),
+        })
+    }
+
+    /// Run `apply`, which may only change pixels inside `rect`, as one
+    /// undoable edit logged as `operation`; clears the redo history
+    pub(crate) fn edit(
+        &mut self,
+        rect: Option<Rect>,
+        operation: &str,
+        params: Json,
+        apply: impl FnOnce(&mut [u8], u32, u32) -> Result<(), RedactError>,
+    ) -> Result<(), RedactError> {
+        let Some(rect) = rect.and_then(|r| r.clip(self.width, self.height)) else {
+            return Ok(());
+        };
+        let pixels = read_region(&self.pixels, self.width, rect);
+        apply(&mut self.pixels, self.width, self.height)?;
+        let after = sha256(&read_region(&self.pixels, self.width, rect));
+        self.audit
+            .push(operation, params, Some(rect), None, sha256(&pixels), after);
+        self.redo.clear();
+        self.undo.push(Snapshot { rect, pixels });
+        self.trim();
+        Ok(())
+    }
+
+    fn trim(&mut self) {
+        if self.undo.len() > self.limit {
+            self.undo.drain(..self.undo.len() - self.limit);
+        }
+    }
+
+    /// Swap the top snapshot of `from` into the image, pushing what it
+    /// covered onto `to`
+    fn swap(&mut self, undo: bool) -> bool {
+        let (from, to) = if undo {
+            (&mut self.undo, &mut self.redo)
+        } else {
+            (&mut self.redo, &mut self.undo)
+        };
+        let Some(snapshot) = from.pop() else {
+            return false;
+        };
+        let current = read_region(&self.pixels, self.width, snapshot.rect);
+        write_region(
+            &mut self.pixels,
+            self.width,
+            snapshot.rect,
+            &snapshot.pixels,
+        );
+        self.audit.push(
+            if undo { "undo" } else { "redo" },
+            Json::object(),
+            Some(snapshot.rect),
+            None,
+            sha256(&current),
+            sha256(&snapshot.pixels),
+        );
+        to.push(Snapshot {
+            rect: snapshot.rect,
+            pixels: current,
+        });
+        true
+    }
+}
+
+#[wasm_bindgen]
+impl RedactionSession {
+    /// Start a session on a copy of `pixels` (`width * height * 4` bytes)
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Result<RedactionSession, JsError> {
+        Ok(Self::from_rgba(width, height, pixels)?)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Apply one region (any shape and effect) as an undoable edit
+    pub fn apply(&mut self, region: &Region) -> Result<(), JsError> {
+        let bounds = region.bounds(self.width, self.height);
+        Ok(self.edit(
+            bounds,
+            region.effect().name(),
+            region.json(),
+            |data, w, h| region.apply_to(data, w, h),
+        )?)
+    }
+
+    /// `apply` for a region given as JSON (see `apply_regions`)
+    pub fn apply_json(&mut self, region_json: &str) -> Result<(), JsError> {
+        let region = Region::from_json_value(&Json::parse(region_json)?, "region")?;
+        self.apply(&region)
+    }
+
+    /// Paint a stroke; fails, leaving the image and history untouched, if
+    /// the active policy rejects the effect
+    pub fn brush_solid_fill(
+        &mut self,
+        points: &[f32],
+        brush_size: u32,
+        color: &Color,
+    ) -> Result<(), JsError> {
+        let bounds = stroke_bounds(points, brush_size, self.width, self.height);
+        let params = stroke_params(points, brush_size).with("color", color.hex());
+        Ok(self.edit(bounds, "brush_solid_fill", params, |data, w, h| {
+            policed_brush_solid_fill(data, w, h, points, brush_size, *color)
+        })?)
+    }
+
+    pub fn brush_pixelate(
+        &mut self,
+        points: &[f32],
+        brush_size: u32,
+        block_size: u32,
+    ) -> Result<(), JsError> {
+        let bounds = stroke_bounds(points, brush_size, self.width, self.height);
+        let params = stroke_params(points, brush_size).with("block_size", block_size);
+        Ok(self.edit(bounds, "brush_pixelate", params, |data, w, h| {
+            policed_brush_pixelate(data, w, h, points, brush_size, block_size)
+        })?)
+    }
+
+    pub fn brush_gaussian_blur(
+        &mut self,
+        points: &[f32],
+        brush_size: u32,
+        radius: u32,
+    ) -> Result<(), JsError> {
+        let bounds = stroke_bounds(points, brush_size, self.width, self.height);
+        let params = stroke_params(points, brush_size).with("radius", radius);
+        Ok(
+            self.edit(bounds, "brush_gaussian_blur", params, |data, w, h| {
+                policed_brush_gaussian_blur(data, w, h, points, brush_size, radius)
+            })?,
+        )
+    }
+
+    /// Revert the most recent edit; false when there is nothing to undo
+    pub fn undo(&mut self) -> bool {
+        self.swap(true)
+    }
+
+    /// Re-apply the most recently undone edit; false when there is none
+    pub fn redo(&mut self) -> bool {
+        self.swap(false)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Keep at most `limit` edits for undo, dropping the oldest
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    /// Bytes held by undo and redo snapshots
+    #[wasm_bindgen(getter)]
+    pub fn history_bytes(&self) -> usize {
+        self.undo
+            .iter()
+            .chain(&self.redo)
+            .map(|s| s.pixels.len())
+            .sum()
+    }
+
+    /// Every edit, undo and redo so far, oldest first. Entry hashes cover
+    /// the edited rectangle's pixels, not the whole image.
+    pub fn audit_log(&self) -> AuditLog {
+        self.audit.clone()
+    }
+
+    /// `audit_log` as JSON (see `AuditLog.to_json`)
+    pub fn audit_json(&self) -> String {
+        self.audit.to_json()
+    }
+
+    /// Copy of the current pixels as tightly packed RGBA
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> (Vec<u8>, RedactionSession) {
+        let original: Vec<u8> = (0..32 * 32 * 4).map(|i| (i * 7 % 256) as u8).collect();
+        let session = RedactionSession::from_rgba(32, 32, original.clone()).unwrap();
+        (original, session)
+    }
+
+    fn region(text: &str) -> Region {
+        Region::from_json_value(&Json::parse(text).unwrap(), "region").unwrap()
+    }
+
+    #[test]
+    fn test_undo_and_redo_restore_exact_pixels() {
+        let (original, mut session) = session();
+        let fill = region(r#"{"rect": {"x": 2, "y": 2, "w": 8, "h": 4}, "effect": "solid_fill"}"#);
+        let blur = region(
+            r#"{"ellipse": {"cx": 16, "cy": 16, "rx": 6, "ry": 4}, "effect": "gaussian_blur"}"#,
+        );
+        session.apply(&fill).unwrap();
+        let filled = session.to_rgba();
+        session.apply(&blur).unwrap();
+        let both = session.to_rgba();
+        // Only the edited rectangles are kept
+        assert_eq!(session.history_bytes(), (8 * 4 + 12 * 8) * 4);
+
+        assert!(session.undo());
+        assert_eq!(session.to_rgba(), filled);
+        assert!(session.undo());
+        assert_eq!(session.to_rgba(), original);
+        assert!(!session.undo());
+        assert!(session.redo() && session.redo());
+        assert_eq!(session.to_rgba(), both);
+        assert!(!session.can_redo());
+    }
+
+    #[test]
+    fn test_far_off_strokes_clamp_to_the_image() {
+        let across = [-1.0e12, 5.0, 1.0e12, 5.0];
+        assert_eq!(
+            stroke_bounds(&across, 4, 32, 32),
+            Some(Rect::new(0, 3, 32, 5))
+        );
+        assert_eq!(
+            stroke_bounds(&[f32::MAX, f32::MAX], u32::MAX, 32, 32),
+            Some(Rect::new(0, 0, 32, 32))
+        );
+        assert_eq!(stroke_bounds(&[1.0e12, 1.0e12], 4, 32, 32), None);
+        assert_eq!(stroke_bounds(&[-1.0e12, -1.0e12], 4, 32, 32), None);
+
+        let (original, mut session) = session();
+        session
+            .brush_solid_fill(&across, 4, &Color::new(0, 0, 0))
+            .unwrap();
+        assert!(session.undo());
+        assert_eq!(session.to_rgba(), original);
+    }
+
+    #[test]
+    fn test_new_edits_clear_redo_and_history_is_bounded() {
+        let (original, mut session) = session();
+        session
+            .brush_solid_fill(&[4.0, 4.0, 20.0, 4.0], 4, &Color::new(0, 0, 0))
+            .unwrap();
+        assert!(session.undo());
+        assert_eq!(session.to_rgba(), original);
+        session.brush_pixelate(&[10.0, 10.0], 6, 3).unwrap();
+        assert!(!session.can_redo());
+
+        session.set_history_limit(2);
+        for x in [0.0, 8.0, 16.0] {
+            session
+                .brush_solid_fill(&[x, 24.0], 4, &Color::new(255, 0, 0))
+                .unwrap();
+        }
+        assert!(session.undo() && session.undo());
+        assert!(!session.undo());
+        // Strokes entirely off the image aren't edits
+        session
+            .brush_solid_fill(&[-50.0, -50.0], 4, &Color::new(0, 0, 0))
+            .unwrap();
+        assert!(!session.can_undo());
+    }
+
+    #[test]
+    fn test_audit_log_records_edits_with_region_hashes() {
+        let (original, mut session) = session();
+        let rect = Rect::new(2, 2, 8, 4);
+        session
+            .apply(&region(
+                r##"{"rect": {"x": 2, "y": 2, "w": 8, "h": 4}, "effect": "solid_fill",
+                     "params": {"color": "#ff0000"}}"##,
+            ))
+            .unwrap();
+        let filled = session.to_rgba();
+        session.brush_pixelate(&[20.0, 20.0], 4, 2).unwrap();
+        assert!(session.undo());
+
+        let log = session.audit_log();
+        let [fill, brush, undo] = log.entries() else {
+            panic!("expected three entries");
+        };
+        assert_eq!(fill.operation, "solid_fill");
+        assert_eq!(fill.region, Some(rect));
+        assert_eq!(fill.input_hash, sha256(&read_region(&original, 32, rect)));
+        assert_eq!(fill.output_hash, sha256(&read_region(&filled, 32, rect)));
+        assert_eq!(brush.operation, "brush_pixelate");
+        // Undoing swaps a stroke's before and after
+        assert_eq!(undo.operation, "undo");
+        assert_eq!(
+            (undo.input_hash, undo.output_hash),
+            (brush.output_hash, brush.input_hash)
+        );
+
+        let json = session.audit_json();
+        assert!(json.contains(r##""params":{"rect":{"x":2,"y":2,"w":8,"h":4},"effect":"solid_fill","params":{"color":"#ff0000"}}"##));
+        assert!(json.contains(r#""params":{"points":[20,20],"brush_size":4,"block_size":2}"#));
+    }
+}
@@ -0,0 +1,768 @@
+//! Effects confined to non-rectangular shapes: ellipses, polygons and
+//! rotated rectangles.
+//!
+//! The effect runs over the shape's bounding box as usual (so a blur still
+//! samples the real surroundings), then every pixel whose center falls
+//! outside the shape is put back. The mask is hard-edged: a pixel is either
+//! fully redacted or untouched, never partly mixed with the original.
+//!
+//! A `feather` softens the edge with a band where the effect fades into the
+//! original. The band lies outside the shape, so everything inside is
+//! still fully redacted; the effect runs over the box grown by the band.
+
+use wasm_bindgen::prelude::*;
+
+use crate::buffer::read_region;
+use crate::effect::Effect;
+use crate::error::{check_buffer, RedactError};
+use crate::stack::{apply_stack, EffectStack};
+use crate::types::{Color, Rect};
+
+/// A region to redact, in image pixel coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    /// Axis-aligned rectangle, redacted without a mask
+    Rect(Rect),
+    /// Axis-aligned ellipse around (`cx`, `cy`)
+    Ellipse { cx: f32, cy: f32, rx: f32, ry: f32 },
+    /// Closed polygon through the vertices in order, filled even-odd
+    Polygon(Vec<(f32, f32)>),
+    /// `rect` turned `angle` degrees clockwise (as on screen) about its
+    /// center
+    RotatedRect { rect: Rect, angle: f32 },
+}
+
+impl Shape {
+    /// Polygon from a flat `[x1, y1, x2, y2, ...]` vertex list
+    pub(crate) fn polygon(points: &[f32]) -> Result<Shape, RedactError> {
+        if !points.len().is_multiple_of(2) || points.len() < 6 {
+            return Err(RedactError::InvalidParameter {
+                name: "points",
+                value: points.len() as f64,
+                expected: "an even count of at least 6 (three vertices)".to_string(),
+            });
+        }
+        Ok(Shape::Polygon(
+            points.chunks_exact(2).map(|p| (p[0], p[1])).collect(),
+        ))
+    }
+
+    /// Check that every coordinate is finite and the shape has an area
+    pub(crate) fn validate(&self) -> Result<(), RedactError> {
+        match *self {
+            Shape::Rect(region) if region.w == 0 || region.h == 0 => {
+                return Err(RedactError::EmptyRegion { region });
+            }
+            Shape::Rect(_) => {}
+            Shape::RotatedRect { rect: region, .. } if region.w == 0 || region.h == 0 => {
+                return Err(RedactError::EmptyRegion { region });
+            }
+            Shape::RotatedRect { angle, .. } if !angle.is_finite() => {
+                return Err(RedactError::InvalidParameter {
+                    name: "angle",
+                    value: angle as f64,
+                    expected: "a finite angle in degrees".to_string(),
+                });
+            }
+            Shape::RotatedRect { .. } => {}
+            Shape::Polygon(ref vertices) => {
+                if let Some(&(x, y)) = vertices
+                    .iter()
+                    .find(|(x, y)| !x.is_finite() || !y.is_finite())
+                {
+                    return Err(RedactError::InvalidParameter {
+                        name: "points",
+                        value: if x.is_finite() { y } else { x } as f64,
+                        expected: "finite coordinates".to_string(),
+                    });
+                }
+                if vertices.len() < 3 {
+                    return Err(RedactError::InvalidParameter {
+                        name: "points",
+                        value: vertices.len() as f64 * 2.0,
+                        expected: "at least three vertices".to_string(),
+                    });
+                }
+            }
+            Shape::Ellipse { cx, cy, rx, ry } => {
+                for (name, value) in [("cx", cx), ("cy", cy)] {
+                    if !value.is_finite() {
+                        return Err(RedactError::InvalidParameter {
+                            name,
+                            value: value as f64,
+                            expected: "a finite coordinate".to_string(),
+                        });
+                    }
+                }
+                for (name, value) in [("rx", rx), ("ry", ry)] {
+                    if !(value.is_finite() && value > 0.0) {
+                        return Err(RedactError::InvalidParameter {
+                            name,
+                            value: value as f64,
+                            expected: "a positive radius".to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the point (`x`, `y`) is inside
+    pub(crate) fn contains(&self, x: f32, y: f32) -> bool {
+        match *self {
+            Shape::Rect(r) => {
+                x >= r.x as f32 && y >= r.y as f32 && x < r.right() as f32 && y < r.bottom() as f32
+            }
+            Shape::Ellipse { cx, cy, rx, ry } => {
+                let (u, v) = ((x - cx) / rx, (y - cy) / ry);
+                u * u + v * v <= 1.0
+            }
+            // Turn the point back by the angle and test against the rect
+            Shape::RotatedRect { rect, angle } => {
+                let (cx, cy, hw, hh) = rotated_frame(rect);
+                let (sin, cos) = angle.to_radians().sin_cos();
+                let (dx, dy) = (x - cx, y - cy);
+                let (u, v) = (dx * cos + dy * sin, dy * cos - dx * sin);
+                u.abs() <= hw && v.abs() <= hh
+            }
+            // Count edge crossings of a ray to the right
+            Shape::Polygon(ref vertices) => {
+                let mut inside = false;
+                let mut prev = vertices[vertices.len() - 1];
+                for &(vx, vy) in vertices {
+                    let (px, py) = prev;
+                    if (vy > y) != (py > y) && x < px + (y - py) * (vx - px) / (vy - py) {
+                        inside = !inside;
+                    }
+                    prev = (vx, vy);
+                }
+                inside
+            }
+        }
+    }
+
+    /// The shape on an image scaled by `sx` horizontally and `sy`
+    /// vertically. Rects grow outwards to whole pixels; a rotated rect keeps
+    /// its angle, which is only exact when `sx == sy`.
+    pub(crate) fn scaled(&self, sx: f32, sy: f32) -> Shape {
+        let rect = |r: Rect| {
+            let (x, y) = ((r.x as f32 * sx).floor(), (r.y as f32 * sy).floor());
+            let right = (r.right() as f32 * sx).ceil();
+            let bottom = (r.bottom() as f32 * sy).ceil();
+            Rect::new(x as u32, y as u32, (right - x) as u32, (bottom - y) as u32)
+        };
+        match *self {
+            Shape::Rect(r) => Shape::Rect(rect(r)),
+            Shape::RotatedRect { rect: r, angle } => Shape::RotatedRect {
+                rect: rect(r),
+                angle,
+            },
+            Shape::Ellipse { cx, cy, rx, ry } => Shape::Ellipse {
+                cx: cx * sx,
+                cy: cy * sy,
+                rx: rx * sx,
+                ry: ry * sy,
+            },
+            Shape::Polygon(ref vertices) => {
+                Shape::Polygon(vertices.iter().map(|&(x, y)| (x * sx, y * sy)).collect())
+            }
+        }
+    }
+
+    /// The shape `rows` pixels higher up, for an image cropped to start
+    /// that far down. A rect must not start above the cut.
+    pub(crate) fn moved_up(&self, rows: u32) -> Shape {
+        let dy = rows as f32;
+        match *self {
+            Shape::Rect(r) => Shape::Rect(Rect::new(r.x, r.y - rows, r.w, r.h)),
+            Shape::RotatedRect { rect: r, angle } => Shape::RotatedRect {
+                rect: Rect::new(r.x, r.y - rows, r.w, r.h),
+                angle,
+            },
+            Shape::Ellipse { cx, cy, rx, ry } => Shape::Ellipse {
+                cx,
+                cy: cy - dy,
+                rx,
+                ry,
+            },
+            Shape::Polygon(ref vertices) => {
+                Shape::Polygon(vertices.iter().map(|&(x, y)| (x, y - dy)).collect())
+            }
+        }
+    }
+
+    /// Distance from a point outside to the shape's edge, 0 inside
+    pub(crate) fn distance(&self, x: f32, y: f32) -> f32 {
+        if self.contains(x, y) {
+            return 0.0;
+        }
+        // Distance from (x, y) to the box of half extents (hw, hh) at the origin
+        let to_box = |x: f32, y: f32, hw: f32, hh: f32| {
+            (x.abs() - hw).max(0.0).hypot((y.abs() - hh).max(0.0))
+        };
+        match *self {
+            Shape::Rect(rect) => {
+                let (cx, cy, hw, hh) = rotated_frame(rect);
+                to_box(x - cx, y - cy, hw, hh)
+            }
+            Shape::RotatedRect { rect, angle } => {
+                let (cx, cy, hw, hh) = rotated_frame(rect);
+                let (sin, cos) = angle.to_radians().sin_cos();
+                let (dx, dy) = (x - cx, y - cy);
+                to_box(dx * cos + dy * sin, dy * cos - dx * sin, hw, hh)
+            }
+            // Along the ray from the center, close to the true distance
+            // unless the ellipse is very elongated
+            Shape::Ellipse { cx, cy, rx, ry } => {
+                let (dx, dy) = (x - cx, y - cy);
+                let r = (dx / rx).hypot(dy / ry);
+                dx.hypot(dy) * (1.0 - 1.0 / r)
+            }
+            Shape::Polygon(ref vertices) => {
+                let mut prev = vertices[vertices.len() - 1];
+                let mut nearest = f32::MAX;
+                for &(vx, vy) in vertices {
+                    let (px, py) = prev;
+                    let (ex, ey) = (vx - px, vy - py);
+                    let len = ex * ex + ey * ey;
+                    let t = if len > 0.0 {
+                        (((x - px) * ex + (y - py) * ey) / len).clamp(0.0, 1.0)
+                    } else {
+                        0.0
+                    };
+                    nearest = nearest.min((x - px - t * ex).hypot(y - py - t * ey));
+                    prev = (vx, vy);
+                }
+                nearest
+            }
+        }
+    }
+
+    /// Bounding box grown by `margin` pixels on every side and clipped to a
+    /// `width` x `height` image, `None` when that lies outside it
+    pub(crate) fn grown_bounds(&self, margin: u32, width: u32, height: u32) -> Option<Rect> {
+        let (x0, y0, x1, y1) = match *self {
+            Shape::Rect(rect) if margin == 0 => return rect.clip(width, height),
+            Shape::Rect(rect) => (
+                rect.x as f32,
+                rect.y as f32,
+                rect.right() as f32,
+                rect.bottom() as f32,
+            ),
+            Shape::Ellipse { cx, cy, rx, ry } => (cx - rx, cy - ry, cx + rx, cy + ry),
+            Shape::Polygon(ref vertices) => vertices.iter().fold(
+                (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+                |(x0, y0, x1, y1), &(x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+            ),
+            Shape::RotatedRect { rect, angle } => {
+                let (cx, cy, hw, hh) = rotated_frame(rect);
+                let (sin, cos) = angle.to_radians().sin_cos();
+                let (ex, ey) = (
+                    hw * cos.abs() + hh * sin.abs(),
+                    hw * sin.abs() + hh * cos.abs(),
+                );
+                (cx - ex, cy - ey, cx + ex, cy + ey)
+            }
+        };
+        let m = margin as f32;
+        let (x0, y0, x1, y1) = (x0 - m, y0 - m, x1 + m, y1 + m);
+        if x1 <= 0.0 || y1 <= 0.0 {
+            return None;
+        }
+        let (x0, y0) = (x0.max(0.0).floor() as u32, y0.max(0.0).floor() as u32);
+        let (x1, y1) = (x1.ceil() as u32, y1.ceil() as u32);
+        Rect::new(x0, y0, x1.saturating_sub(x0), y1.saturating_sub(y0)).clip(width, height)
+    }
+}
+
+/// Center and half extents of a rect, before rotation
+fn rotated_frame(rect: Rect) -> (f32, f32, f32, f32) {
+    let (hw, hh) = (rect.w as f32 / 2.0, rect.h as f32 / 2.0);
+    (rect.x as f32 + hw, rect.y as f32 + hh, hw, hh)
+}
+
+/// Apply `effect` inside `shape` only
+pub(crate) fn apply_in_shape(
+    effect: &Effect,
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    shape: &Shape,
+) -> Result<(), RedactError> {
+    apply_feathered(std::slice::from_ref(effect), data, width, height, shape, 0)
+}
+
+/// Apply `effects` in order inside `shape`, fading them out over `feather`
+/// pixels past its edge
+pub(crate) fn apply_feathered(
+    effects: &[Effect],
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    shape: &Shape,
+    feather: u32,
+) -> Result<(), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    shape.validate()?;
+    for effect in effects {
+        effect.validate()?;
+    }
+    let Some(rect) = shape.grown_bounds(feather, width, height) else {
+        return Ok(());
+    };
+    if let (Shape::Rect(_), 0) = (shape, feather) {
+        return apply_stack(effects, data, width, height, rect);
+    }
+    let original = read_region(data, width, rect);
+    apply_stack(effects, data, width, height, rect)?;
+    for y in 0..rect.h {
+        for x in 0..rect.w {
+            let (px, py) = ((rect.x + x) as f32 + 0.5, (rect.y + y) as f32 + 0.5);
+            let distance = shape.distance(px, py);
+            if distance == 0.0 {
+                continue;
+            }
+            let from = ((y * rect.w + x) * 4) as usize;
+            let to = (((rect.y + y) * width + rect.x + x) * 4) as usize;
+            // Share of the effect kept, falling linearly to 0 across the band
+            let keep = match feather {
+                0 => 0.0,
+                _ => 1.0 - distance / feather as f32,
+            };
+            if keep <= 0.0 {
+                data[to..to + 4].copy_from_slice(&original[from..from + 4]);
+                continue;
+            }
+            for c in 0..4 {
+                let (was, now) = (original[from + c] as f32, data[to + c] as f32);
+                data[to + c] = (was + (now - was) * keep).round() as u8;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Solid fill inside the ellipse centered on (`cx`, `cy`) with radii
+/// `rx`, `ry`
+#[wasm_bindgen]
+pub fn solid_fill_ellipse(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    cx: f32,
+    cy: f32,
+    rx: f32,
+    ry: f32,
+    r: u8,
+    g: u8,
+    b: u8,
+) -> Result<(), JsError> {
+    let effect = Effect::SolidFill {
+        color: Color::new(r, g, b),
+    };
+    let shape = Shape::Ellipse { cx, cy, rx, ry };
+    Ok(apply_in_shape(&effect, data, width, height, &shape)?)
+}
+
+/// Pixelate inside an ellipse; blocks are aligned to its bounding box
+#[wasm_bindgen]
+pub fn pixelate_ellipse(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    cx: f32,
+    cy: f32,
+    rx: f32,
+    ry: f32,
+    block_size: u32,
+) -> Result<(), JsError> {
+    let effect = Effect::pixelate(block_size);
+    let shape = Shape::Ellipse { cx, cy, rx, ry };
+    Ok(apply_in_shape(&effect, data, width, height, &shape)?)
+}
+
+/// Gaussian blur inside an ellipse
+#[wasm_bindgen]
+pub fn gaussian_blur_ellipse(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    cx: f32,
+    cy: f32,
+    rx: f32,
+    ry: f32,
+    radius: u32,
+) -> Result<(), JsError> {
+    let effect = Effect::GaussianBlur { radius };
+    let shape = Shape::Ellipse { cx, cy, rx, ry };
+    Ok(apply_in_shape(&effect, data, width, height, &shape)?)
+}
+
+/// Solid fill inside the polygon with flat vertices `[x1, y1, x2, y2, ...]`
+#[wasm_bindgen]
+pub fn solid_fill_polygon(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    r: u8,
+    g: u8,
+    b: u8,
+) -> Result<(), JsError> {
+    let effect = Effect::SolidFill {
+        color: Color::new(r, g, b),
+    };
+    let shape = Shape::polygon(points)?;
+    Ok(apply_in_shape(&effect, data, width, height, &shape)?)
+}
+
+/// Pixelate inside a polygon; blocks are aligned to its bounding box
+#[wasm_bindgen]
+pub fn pixelate_polygon(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    block_size: u32,
+) -> Result<(), JsError> {
+    let effect = Effect::pixelate(block_size);
+    let shape = Shape::polygon(points)?;
+    Ok(apply_in_shape(&effect, data, width, height, &shape)?)
+}
+
+/// Gaussian blur inside a polygon
+#[wasm_bindgen]
+pub fn gaussian_blur_polygon(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    radius: u32,
+) -> Result<(), JsError> {
+    let effect = Effect::GaussianBlur { radius };
+    let shape = Shape::polygon(points)?;
+    Ok(apply_in_shape(&effect, data, width, height, &shape)?)
+}
+
+/// Solid fill inside the rect `x, y, w, h` turned `angle` degrees
+/// clockwise about its center
+#[wasm_bindgen]
+pub fn solid_fill_rotated_rect(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    angle: f32,
+    r: u8,
+    g: u8,
+    b: u8,
+) -> Result<(), JsError> {
+    let effect = Effect::SolidFill {
+        color: Color::new(r, g, b),
+    };
+    let rect = Rect::new(x, y, w, h);
+    let shape = Shape::RotatedRect { rect, angle };
+    Ok(apply_in_shape(&effect, data, width, height, &shape)?)
+}
+
+/// Pixelate inside a rotated rect; blocks are aligned to the image axes
+#[wasm_bindgen]
+pub fn pixelate_rotated_rect(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    angle: f32,
+    block_size: u32,
+) -> Result<(), JsError> {
+    let effect = Effect::pixelate(block_size);
+    let rect = Rect::new(x, y, w, h);
+    let shape = Shape::RotatedRect { rect, angle };
+    Ok(apply_in_shape(&effect, data, width, height, &shape)?)
+}
+
+/// Gaussian blur inside a rotated rect
+#[wasm_bindgen]
+pub fn gaussian_blur_rotated_rect(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    angle: f32,
+    radius: u32,
+) -> Result<(), JsError> {
+    let effect = Effect::GaussianBlur { radius };
+    let rect = Rect::new(x, y, w, h);
+    let shape = Shape::RotatedRect { rect, angle };
+    Ok(apply_in_shape(&effect, data, width, height, &shape)?)
+}
+
+/// Apply `stack` over the rect `x, y, w, h`, fading out over `feather`
+/// pixels outside it instead of stopping at a hard edge
+#[wasm_bindgen]
+pub fn feathered_rect(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    feather: u32,
+    stack: &EffectStack,
+) -> Result<(), JsError> {
+    let shape = Shape::Rect(Rect::new(x, y, w, h));
+    Ok(apply_feathered(
+        stack.effects(),
+        data,
+        width,
+        height,
+        &shape,
+        feather,
+    )?)
+}
+
+/// Apply `stack` inside an ellipse, fading out over `feather` pixels
+/// outside it
+#[wasm_bindgen]
+pub fn feathered_ellipse(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    cx: f32,
+    cy: f32,
+    rx: f32,
+    ry: f32,
+    feather: u32,
+    stack: &EffectStack,
+) -> Result<(), JsError> {
+    let shape = Shape::Ellipse { cx, cy, rx, ry };
+    Ok(apply_feathered(
+        stack.effects(),
+        data,
+        width,
+        height,
+        &shape,
+        feather,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern;
+
+    #[test]
+    fn test_ellipse_fill_leaves_the_corners() {
+        let (w, h) = (20, 20);
+        let original = pattern(w, h);
+        let mut data = original.clone();
+        let shape = Shape::Ellipse {
+            cx: 10.0,
+            cy: 10.0,
+            rx: 8.0,
+            ry: 5.0,
+        };
+        let black = Effect::SolidFill {
+            color: Color::new(0, 0, 0),
+        };
+        apply_in_shape(&black, &mut data, w, h, &shape).unwrap();
+        let px = |buf: &[u8], x: u32, y: u32| buf[((y * w + x) * 4) as usize..][..3].to_vec();
+        assert_eq!(px(&data, 10, 10), [0, 0, 0]);
+        assert_eq!(px(&data, 3, 10), [0, 0, 0]);
+        // Bounding box corners and everything outside are untouched
+        assert_eq!(px(&data, 2, 5), px(&original, 2, 5));
+        assert_eq!(px(&data, 17, 14), px(&original, 17, 14));
+        assert_eq!(px(&data, 10, 16), px(&original, 10, 16));
+        let changed = (0..w * h)
+            .filter(|i| data[(*i * 4) as usize..][..3] != original[(*i * 4) as usize..][..3])
+            .count() as f32;
+        // Close to the ellipse's area, far from its 16x10 box
+        assert!(
+            (changed - std::f32::consts::PI * 40.0).abs() < 8.0,
+            "{}",
+            changed
+        );
+    }
+
+    #[test]
+    fn test_stacked_shape_keeps_the_image_grid() {
+        let (w, h) = (20, 20);
+        let shape = Shape::Ellipse {
+            cx: 10.0,
+            cy: 9.0,
+            rx: 7.0,
+            ry: 6.0,
+        };
+        let effects = [
+            Effect::Pixelate {
+                block_size: 4,
+                grid_aligned: true,
+                min_blocks: 0,
+            },
+            Effect::Dim { factor: 0.5 },
+        ];
+        // Inside the shape, the same pixels as the stack over its whole box
+        let bounds = shape.grown_bounds(0, w, h).unwrap();
+        let mut boxed = pattern(w, h);
+        for effect in &effects {
+            effect.apply_rect(&mut boxed, w, h, bounds);
+        }
+        let mut data = pattern(w, h);
+        apply_feathered(&effects, &mut data, w, h, &shape, 0).unwrap();
+        for y in 0..h {
+            for x in 0..w {
+                if shape.contains(x as f32 + 0.5, y as f32 + 0.5) {
+                    let i = ((y * w + x) * 4) as usize;
+                    assert_eq!(data[i..i + 4], boxed[i..i + 4], "({}, {})", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_skewed_quadrilateral() {
+        // Parallelogram leaning right: (4,2) (14,2) (10,10) (0,10)
+        let shape = Shape::polygon(&[4.0, 2.0, 14.0, 2.0, 10.0, 10.0, 0.0, 10.0]).unwrap();
+        assert!(shape.contains(9.0, 6.0));
+        assert!(!shape.contains(1.0, 3.0));
+        assert!(!shape.contains(13.0, 9.0));
+        assert_eq!(shape.grown_bounds(0, 12, 12), Some(Rect::new(0, 2, 12, 8)));
+
+        let (w, h) = (16, 12);
+        let original = pattern(w, h);
+        let mut data = original.clone();
+        let fill = Effect::SolidFill {
+            color: Color::new(0, 0, 0),
+        };
+        apply_in_shape(&fill, &mut data, w, h, &shape).unwrap();
+        let changed = (0..w * h)
+            .filter(|i| data[(*i * 4) as usize..][..3] != original[(*i * 4) as usize..][..3])
+            .count();
+        // Area 10 x 8
+        assert_eq!(changed, 80);
+
+        assert!(Shape::polygon(&[0.0, 0.0, 4.0, 0.0]).is_err());
+        assert!(Shape::polygon(&[0.0, 0.0, 4.0, 0.0, 4.0]).is_err());
+        let nan = Shape::polygon(&[0.0, 0.0, 4.0, f32::NAN, 4.0, 4.0]).unwrap();
+        assert!(apply_in_shape(&fill, &mut data, w, h, &nan).is_err());
+    }
+
+    #[test]
+    fn test_rotated_rect() {
+        let rect = Rect::new(4, 8, 16, 4);
+        let quarter = Shape::RotatedRect { rect, angle: 90.0 };
+        // A quarter turn stands the 16x4 box upright about (12, 10)
+        assert_eq!(
+            quarter.grown_bounds(0, 24, 24),
+            Some(Rect::new(10, 2, 4, 16))
+        );
+        assert!(quarter.contains(12.5, 3.0) && !quarter.contains(5.0, 10.0));
+
+        let (w, h) = (24, 24);
+        let original = pattern(w, h);
+        let fill = Effect::SolidFill {
+            color: Color::new(0, 0, 0),
+        };
+        for (angle, blur) in [(30.0, false), (-45.0, true)] {
+            let shape = Shape::RotatedRect { rect, angle };
+            let mut data = original.clone();
+            let effect = if blur {
+                Effect::GaussianBlur { radius: 3 }
+            } else {
+                fill
+            };
+            apply_in_shape(&effect, &mut data, w, h, &shape).unwrap();
+            let changed = (0..w * h)
+                .filter(|i| data[(*i * 4) as usize..][..3] != original[(*i * 4) as usize..][..3])
+                .count() as f32;
+            // Close to the rect's area of 64 at any angle
+            assert!((changed - 64.0).abs() <= 10.0, "{}: {}", angle, changed);
+            // The corners of the unrotated box are outside the turned one
+            assert_eq!(
+                data[((8 * w + 4) * 4) as usize],
+                original[((8 * w + 4) * 4) as usize]
+            );
+        }
+
+        let mut data = original.clone();
+        let nan = Shape::RotatedRect {
+            rect,
+            angle: f32::NAN,
+        };
+        assert!(apply_in_shape(&fill, &mut data, w, h, &nan).is_err());
+    }
+
+    #[test]
+    fn test_feather_fades_outside_the_shape() {
+        let (w, h) = (40, 20);
+        let original = vec![200u8; (w * h * 4) as usize];
+        let black = [Effect::SolidFill {
+            color: Color::new(0, 0, 0),
+        }];
+        let px = |buf: &[u8], x: u32, y: u32| buf[((y * w + x) * 4) as usize];
+        let rect = Shape::Rect(Rect::new(10, 5, 10, 10));
+        let mut data = original.clone();
+        apply_feathered(&black, &mut data, w, h, &rect, 4).unwrap();
+        // Fully redacted inside, then fading across four pixels
+        assert_eq!(px(&data, 10, 5), 0);
+        assert_eq!(px(&data, 19, 14), 0);
+        let fade: Vec<u8> = (20..25).map(|x| px(&data, x, 10)).collect();
+        assert_eq!(fade, [25, 75, 125, 175, 200]);
+        assert_eq!(px(&data, 9, 10), 25);
+
+        let ellipse = Shape::Ellipse {
+            cx: 20.0,
+            cy: 10.0,
+            rx: 6.0,
+            ry: 4.0,
+        };
+        let mut feathered = original.clone();
+        apply_feathered(&black, &mut feathered, w, h, &ellipse, 3).unwrap();
+        let mut hard = original.clone();
+        apply_in_shape(&black[0], &mut hard, w, h, &ellipse).unwrap();
+        // Everything the hard edge redacts is still fully redacted
+        for i in (0..feathered.len()).step_by(4) {
+            if hard[i] == 0 {
+                assert_eq!(feathered[i], 0);
+            }
+        }
+        assert!(px(&feathered, 27, 10) > 0 && px(&feathered, 27, 10) < 200);
+        assert_eq!(px(&feathered, 30, 10), 200);
+    }
+
+    #[test]
+    fn test_rejects_degenerate_ellipses() {
+        let mut data = pattern(8, 8);
+        let blur = Effect::GaussianBlur { radius: 2 };
+        for (rx, ry) in [(0.0, 2.0), (2.0, -1.0), (f32::NAN, 2.0)] {
+            let shape = Shape::Ellipse {
+                cx: 4.0,
+                cy: 4.0,
+                rx,
+                ry,
+            };
+            assert!(apply_in_shape(&blur, &mut data, 8, 8, &shape).is_err());
+        }
+        // Entirely off the image is a no-op
+        let away = Shape::Ellipse {
+            cx: -10.0,
+            cy: 4.0,
+            rx: 3.0,
+            ry: 3.0,
+        };
+        let before = data.clone();
+        apply_in_shape(&blur, &mut data, 8, 8, &away).unwrap();
+        assert_eq!(data, before);
+    }
+}
@@ -0,0 +1,67 @@
+//! Digest that a detached signature over a redacted image covers.
+//!
+//! The wasm side only produces the digest; signing happens on the host with
+//! the caller's key (WebCrypto Ed25519 in the browser, see `signing.ts`), so
+//! private keys never enter the module.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, RedactError};
+use crate::hash::sha256;
+use crate::scratch::Scratch;
+
+/// Domain separator, so the digest can't be confused with other hashes of
+/// the same bytes
+const CONTEXT: &[u8] = b"redactr-signature-v1\0";
+
+/// SHA-256 over the context, dimensions, metadata and pixels. Lengths are
+/// included so moving bytes between metadata and pixels changes the digest.
+pub(crate) fn digest(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    metadata: &str,
+) -> Result<[u8; 32], RedactError> {
+    check_buffer(data.len(), width, height)?;
+    let mut message = Scratch::from(Vec::with_capacity(
+        CONTEXT.len() + 16 + metadata.len() + data.len(),
+    ));
+    message.extend_from_slice(CONTEXT);
+    message.extend_from_slice(&width.to_be_bytes());
+    message.extend_from_slice(&height.to_be_bytes());
+    message.extend_from_slice(&(metadata.len() as u64).to_be_bytes());
+    message.extend_from_slice(metadata.as_bytes());
+    message.extend_from_slice(data);
+    Ok(sha256(&message))
+}
+
+/// 32-byte digest to sign (or verify) for a redacted RGBA image and its
+/// metadata string (e.g. a redaction certificate)
+#[wasm_bindgen]
+pub fn signing_digest(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    metadata: &str,
+) -> Result<Vec<u8>, JsError> {
+    Ok(digest(data, width, height, metadata)?.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest_covers_pixels_metadata_and_size() {
+        let data = vec![7u8; 4 * 4 * 4];
+        let base = digest(&data, 4, 4, "{}").unwrap();
+        assert_eq!(base, digest(&data, 4, 4, "{}").unwrap());
+
+        let mut altered = data.clone();
+        altered[5] ^= 1;
+        assert_ne!(base, digest(&altered, 4, 4, "{}").unwrap());
+        assert_ne!(base, digest(&data, 4, 4, "{ }").unwrap());
+        assert_ne!(base, digest(&data, 2, 8, "{}").unwrap());
+        assert!(digest(&data, 5, 5, "").is_err());
+    }
+}
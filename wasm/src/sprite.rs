@@ -0,0 +1,157 @@
+//! Covering regions with RGBA stickers (emoji over faces and the like).
+//!
+//! The sprite is resampled to the requested scale with the Catmull-Rom
+//! filter from `resize` and composited source-over, so its antialiased
+//! edges blend into the image. Only fully opaque sprite pixels hide what's
+//! under them: anything the sprite leaves translucent stays partly
+//! visible, so stickers meant to redact should be opaque over the content.
+//! An active policy treats a sprite as the `sprite` effect and its least
+//! opaque pixel as its strength, so by default only opaque sprites pass.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, RedactError};
+use crate::policy::active_policy;
+use crate::resize::{resample, ResizeFilter};
+
+/// Effect name of sprite overlays in policies
+pub(crate) const SPRITE: &str = "sprite";
+
+/// `over` composited onto `under`, both straight (not premultiplied) RGBA
+fn source_over(under: &mut [u8], over: &[u8]) {
+    let sa = over[3] as f32 / 255.0;
+    if sa == 0.0 {
+        return;
+    }
+    let da = under[3] as f32 / 255.0;
+    let out_a = sa + da * (1.0 - sa);
+    for c in 0..3 {
+        let v = (over[c] as f32 * sa + under[c] as f32 * da * (1.0 - sa)) / out_a;
+        under[c] = v.round().clamp(0.0, 255.0) as u8;
+    }
+    under[3] = (out_a * 255.0).round() as u8;
+}
+
+pub(crate) fn overlay(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    sprite: &[u8],
+    sprite_w: u32,
+    sprite_h: u32,
+    x: i32,
+    y: i32,
+    scale: f32,
+) -> Result<(), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    check_buffer(sprite.len(), sprite_w, sprite_h)?;
+    if !(scale.is_finite() && scale > 0.0) {
+        return Err(RedactError::InvalidParameter {
+            name: "scale",
+            value: scale as f64,
+            expected: "a positive number".to_string(),
+        });
+    }
+    let floor = match active_policy() {
+        Some(policy) => {
+            let alpha = sprite.chunks_exact(4).map(|px| px[3]).min().unwrap_or(255);
+            policy.enforce_sprite(alpha)?
+        }
+        None => 0,
+    };
+    let side = |n: u32| ((n as f32 * scale).round() as u32).max(1);
+    let (w, h) = (side(sprite_w), side(sprite_h));
+    let scaled = resample(sprite, sprite_w, sprite_h, w, h, ResizeFilter::CatmullRom)?;
+
+    for sy in 0..h as i64 {
+        let py = y as i64 + sy;
+        if !(0..height as i64).contains(&py) {
+            continue;
+        }
+        for sx in 0..w as i64 {
+            let px = x as i64 + sx;
+            if !(0..width as i64).contains(&px) {
+                continue;
+            }
+            let i = ((py * width as i64 + px) * 4) as usize;
+            let s = ((sy * w as i64 + sx) * 4) as usize;
+            let mut over = [0; 4];
+            over.copy_from_slice(&scaled[s..s + 4]);
+            over[3] = over[3].max(floor);
+            source_over(&mut data[i..i + 4], &over);
+        }
+    }
+    Ok(())
+}
+
+/// Composite the RGBA `sprite` (`sprite_w` x `sprite_h`) onto the image
+/// with its top-left corner at `x, y`, resized by `scale`. The position
+/// may be negative or run past the image, as when a sticker is dragged
+/// partly off the edge; only the overlap is drawn. The active policy, if
+/// any, is enforced first (see the module docs).
+#[wasm_bindgen]
+pub fn overlay_sprite(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    sprite: &[u8],
+    sprite_w: u32,
+    sprite_h: u32,
+    x: i32,
+    y: i32,
+    scale: f32,
+) -> Result<(), JsError> {
+    Ok(overlay(
+        data, width, height, sprite, sprite_w, sprite_h, x, y, scale,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, px: [u8; 4]) -> Vec<u8> {
+        (0..width * height).flat_map(|_| px).collect()
+    }
+
+    #[test]
+    fn test_opaque_sprite_covers_and_clips() {
+        let mut data = solid(10, 10, [200, 200, 200, 255]);
+        let sprite = solid(2, 2, [255, 200, 0, 255]);
+        overlay(&mut data, 10, 10, &sprite, 2, 2, -2, 6, 3.0).unwrap();
+        let at = |x: u32, y: u32| &data[((y * 10 + x) * 4) as usize..][..4];
+        // 6x6 at (-2, 6): columns 0..4, rows 6..10 are covered
+        assert_eq!(at(0, 6), [255, 200, 0, 255]);
+        assert_eq!(at(3, 9), [255, 200, 0, 255]);
+        assert_eq!(at(4, 9), [200, 200, 200, 255]);
+        assert_eq!(at(0, 5), [200, 200, 200, 255]);
+    }
+
+    #[test]
+    fn test_alpha_compositing() {
+        let mut data = solid(4, 4, [0, 0, 0, 255]);
+        let mut sprite = solid(4, 4, [255, 255, 255, 128]);
+        // A fully transparent pixel leaves the image alone
+        sprite[..4].copy_from_slice(&[255, 0, 0, 0]);
+        overlay(&mut data, 4, 4, &sprite, 4, 4, 0, 0, 1.0).unwrap();
+        assert_eq!(&data[..4], [0, 0, 0, 255]);
+        assert_eq!(&data[4..8], [128, 128, 128, 255]);
+
+        // Over a transparent image the sprite's own alpha shows through
+        let mut clear = solid(4, 4, [0, 0, 0, 0]);
+        overlay(&mut clear, 4, 4, &sprite, 4, 4, 0, 0, 1.0).unwrap();
+        assert_eq!(&clear[4..8], [255, 255, 255, 128]);
+    }
+
+    #[test]
+    fn test_rejects_bad_sprites() {
+        let mut data = solid(4, 4, [0, 0, 0, 255]);
+        let sprite = solid(2, 2, [255, 255, 255, 255]);
+        assert!(overlay(&mut data, 4, 4, &sprite, 3, 2, 0, 0, 1.0).is_err());
+        let err = overlay(&mut data, 4, 4, &sprite, 2, 2, 0, 0, 0.0).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid scale 0: expected a positive number"
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use wasm_bindgen::prelude::*;
+
+use crate::buffer::{read_region, write_region};
+use crate::effect::Effect;
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::noise::noise_key;
+use crate::policy::policed;
+use crate::types::{Color, Rect};
+
+/// Ordered list of effects applied to the same region.
+///
+/// The region is copied out once, every effect runs over that compact copy,
+/// and the result is written back once, instead of each effect making its
+/// own pass over the full image.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EffectStack {
+    effects: Vec<Effect>,
+}
+
+impl EffectStack {
+    pub fn push(mut self, effect: Effect) -> Self {
+        self.effects.push(effect);
+        self
+    }
+
+    pub fn effects(&self) -> &[Effect] {
+        &self.effects
+    }
+
+    pub fn validate(&self) -> Result<(), RedactError> {
+        for (index, effect) in self.effects.iter().enumerate() {
+            effect.validate().map_err(|error| RedactError::InvalidOp {
+                index,
+                error: Box::new(error),
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Vec<Effect>> for EffectStack {
+    fn from(effects: Vec<Effect>) -> Self {
+        Self { effects }
+    }
+}
+
+/// Run `effects` in order over `rect` using a single region-sized scratch
+/// copy, after the active policy has accepted (or upgraded) every one
+pub(crate) fn apply_stack(
+    effects: &[Effect],
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+) -> Result<(), RedactError> {
+    let effects = effects
+        .iter()
+        .map(|&effect| policed(effect, rect))
+        .collect::<Result<Vec<_>, _>>()?;
+    if let [effect] = effects[..] {
+        effect.apply_rect(data, width, height, rect);
+        return Ok(());
+    }
+    let Some(rect) = rect.clip(width, height) else {
+        return Ok(());
+    };
+    // Grid-aligned pixelation reads whole cells, which may reach past `rect`
+    let outer = effects.iter().fold(rect, |outer, effect| {
+        outer.union(&effect.footprint(rect, width, height))
+    });
+    let local = Rect::new(rect.x - outer.x, rect.y - outer.y, rect.w, rect.h);
+    let mut scratch = read_region(data, width, outer);
+    for effect in &effects {
+        effect.apply_rect_at(&mut scratch, outer.w, outer.h, local, (outer.x, outer.y));
+    }
+    let region = read_region(&scratch, outer.w, local);
+    write_region(data, width, rect, &region);
+    Ok(())
+}
+
+/// Run `effects` over the whole image except the `keep` rects (clipped to
+/// the image; off-image ones are ignored), which come out untouched
+pub(crate) fn apply_inverse(
+    effects: &[Effect],
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    keep: &[Rect],
+) -> Result<(), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    let kept: Vec<_> = keep
+        .iter()
+        .filter_map(|r| r.clip(width, height))
+        .map(|r| (r, read_region(data, width, r)))
+        .collect();
+    apply_stack(effects, data, width, height, Rect::new(0, 0, width, height))?;
+    for (rect, pixels) in &kept {
+        write_region(data, width, *rect, pixels);
+    }
+    Ok(())
+}
+
+#[wasm_bindgen]
+impl EffectStack {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> EffectStack {
+        EffectStack::default()
+    }
+
+    pub fn fill(self, color: &Color) -> EffectStack {
+        self.push(Effect::SolidFill { color: *color })
+    }
+
+    pub fn pixelate(self, block_size: u32) -> EffectStack {
+        self.push(Effect::pixelate(block_size))
+    }
+
+    pub fn blur(self, radius: u32) -> EffectStack {
+        self.push(Effect::GaussianBlur { radius })
+    }
+
+    pub fn dim(self, factor: f32) -> EffectStack {
+        self.push(Effect::Dim { factor })
+    }
+
+    /// Random noise keyed with `seed`, at least 16 bytes from
+    /// `crypto.getRandomValues`
+    pub fn noise(self, seed: &[u8]) -> Result<EffectStack, JsError> {
+        Ok(self.push(Effect::NoiseFill {
+            seed: noise_key(seed)?,
+        }))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.effects.len()
+    }
+
+    /// Apply the whole stack to one region of a raw RGBA buffer
+    pub fn apply(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        region: &Rect,
+    ) -> Result<(), JsError> {
+        check_buffer(data.len(), width, height)?;
+        check_region(*region, width, height)?;
+        self.validate()?;
+        Ok(apply_stack(&self.effects, data, width, height, *region)?)
+    }
+
+    /// Apply the stack to everything except the flat `[x, y, w, h, ...]`
+    /// `keep` regions, for "highlight this, blur the rest" screenshots
+    pub fn apply_inverse(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        keep: &[u32],
+    ) -> Result<(), JsError> {
+        self.validate()?;
+        let keep = Rect::from_flat(keep)?;
+        Ok(apply_inverse(&self.effects, data, width, height, &keep)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern;
+
+    #[test]
+    fn test_stack_matches_sequential_effects() {
+        let effects = [Effect::pixelate(3), Effect::GaussianBlur { radius: 2 }];
+        let rect = Rect::new(2, 3, 11, 9);
+
+        let mut expected = pattern(16, 16);
+        for effect in &effects {
+            effect.apply_rect(&mut expected, 16, 16, rect);
+        }
+
+        let mut data = pattern(16, 16);
+        apply_stack(&effects, &mut data, 16, 16, rect).unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_stack_keeps_the_image_grid() {
+        // Aligned blocks at the region's edge average their whole cell
+        let effects = [
+            Effect::Pixelate {
+                block_size: 4,
+                grid_aligned: true,
+                min_blocks: 0,
+            },
+            Effect::Dim { factor: 0.5 },
+        ];
+        let rect = Rect::new(3, 5, 10, 9);
+
+        let mut expected = pattern(16, 16);
+        for effect in &effects {
+            effect.apply_rect(&mut expected, 16, 16, rect);
+        }
+
+        let mut data = pattern(16, 16);
+        apply_stack(&effects, &mut data, 16, 16, rect).unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_stack_clips_to_image() {
+        let mut expected = pattern(8, 8);
+        let rect = Rect::new(4, 4, 10, 10);
+        Effect::pixelate(2).apply_rect(&mut expected, 8, 8, rect);
+        Effect::GaussianBlur { radius: 1 }.apply_rect(&mut expected, 8, 8, rect);
+
+        let mut data = pattern(8, 8);
+        EffectStack::new()
+            .pixelate(2)
+            .blur(1)
+            .apply(&mut data, 8, 8, &rect)
+            .unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_inverse_keeps_only_the_listed_regions() {
+        let original = pattern(12, 10);
+        let mut data = original.clone();
+        let keep = [Rect::new(2, 2, 4, 3), Rect::new(9, 7, 10, 10)];
+        let effects = [Effect::GaussianBlur { radius: 2 }];
+        apply_inverse(&effects, &mut data, 12, 10, &keep).unwrap();
+
+        let mut everywhere = original.clone();
+        apply_stack(&effects, &mut everywhere, 12, 10, Rect::new(0, 0, 12, 10)).unwrap();
+        for y in 0..10 {
+            for x in 0..12 {
+                let i = ((y * 12 + x) * 4) as usize;
+                let kept = keep.iter().any(|r| r.contains(&Rect::new(x, y, 1, 1)));
+                let expected = if kept { &original } else { &everywhere };
+                assert_eq!(data[i..i + 4], expected[i..i + 4], "({}, {})", x, y);
+            }
+        }
+        assert!(apply_inverse(&effects, &mut data[..8], 12, 10, &keep).is_err());
+    }
+
+    #[test]
+    fn test_dim_everything_but_the_highlight() {
+        let original = vec![200u8; 6 * 6 * 4];
+        let mut data = original.clone();
+        let stack = EffectStack::new().dim(0.5);
+        let keep = [Rect::new(1, 1, 2, 2)];
+        apply_inverse(stack.effects(), &mut data, 6, 6, &keep).unwrap();
+        assert_eq!(&data[(6 + 1) * 4..][..4], [200, 200, 200, 200]);
+        assert_eq!(&data[..4], [100, 100, 100, 200]);
+    }
+
+    #[test]
+    fn test_stack_validation_reports_index() {
+        let err = EffectStack::new()
+            .blur(3)
+            .pixelate(0)
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "operation 1: invalid block_size 0: expected 1 or more"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! Per-channel statistics of a region: histograms, mean, variance, range.
+//!
+//! Hosts use these to pick effect parameters from the content, e.g. a
+//! stronger blur for a high-contrast region, without walking the pixels in
+//! JS. One pass over the region fills all four channels.
+//!
+//! `background_color` answers the common case directly: the dominant color
+//! around a region, so a fill matches the page (white paper, a dark-mode
+//! screenshot) instead of a hardcoded black.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, check_region, RedactError};
+use crate::json::Json;
+use crate::types::{Channel, Color, Rect};
+
+/// Width of the ring sampled around a region, at least this many px
+const MIN_MARGIN: u32 = 4;
+/// Bits kept per channel when bucketing colors for the mode
+const BUCKET_BITS: u32 = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+struct ChannelStats {
+    histogram: [u32; 256],
+    mean: f64,
+    variance: f64,
+    min: u8,
+    max: u8,
+}
+
+impl ChannelStats {
+    fn from_histogram(histogram: [u32; 256]) -> Self {
+        let n: f64 = histogram.iter().map(|&c| c as f64).sum();
+        let level = |v: usize| v as f64;
+        let mean = histogram
+            .iter()
+            .enumerate()
+            .map(|(v, &c)| level(v) * c as f64)
+            .sum::<f64>()
+            / n;
+        let variance = histogram
+            .iter()
+            .enumerate()
+            .map(|(v, &c)| (level(v) - mean).powi(2) * c as f64)
+            .sum::<f64>()
+            / n;
+        let min = histogram.iter().position(|&c| c > 0).unwrap_or(0) as u8;
+        let max = histogram.iter().rposition(|&c| c > 0).unwrap_or(0) as u8;
+        ChannelStats {
+            histogram,
+            mean,
+            variance,
+            min,
+            max,
+        }
+    }
+
+    fn json(&self) -> Json {
+        Json::object()
+            .with("mean", self.mean)
+            .with("variance", self.variance)
+            .with("min", self.min as u32)
+            .with("max", self.max as u32)
+            .with(
+                "histogram",
+                Json::Array(self.histogram.iter().map(|&c| Json::from(c)).collect()),
+            )
+    }
+}
+
+/// Statistics of one region, per RGBA channel
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionStats {
+    region: Rect,
+    channels: Vec<ChannelStats>,
+}
+
+impl RegionStats {
+    fn channel(&self, channel: Channel) -> &ChannelStats {
+        &self.channels[(channel as u8).trailing_zeros() as usize]
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        let names = ["r", "g", "b", "a"];
+        self.channels.iter().zip(names).fold(
+            Json::object()
+                .with("region", self.region.json())
+                .with("pixels", self.pixels()),
+            |json, (stats, name)| json.with(name, stats.json()),
+        )
+    }
+}
+
+#[wasm_bindgen]
+impl RegionStats {
+    /// Number of pixels measured
+    #[wasm_bindgen(getter)]
+    pub fn pixels(&self) -> u32 {
+        self.region.w * self.region.h
+    }
+
+    /// 256 counts, one per channel value
+    pub fn histogram(&self, channel: Channel) -> Vec<u32> {
+        self.channel(channel).histogram.to_vec()
+    }
+
+    pub fn mean(&self, channel: Channel) -> f64 {
+        self.channel(channel).mean
+    }
+
+    /// Population variance of the channel values
+    pub fn variance(&self, channel: Channel) -> f64 {
+        self.channel(channel).variance
+    }
+
+    pub fn min(&self, channel: Channel) -> u8 {
+        self.channel(channel).min
+    }
+
+    pub fn max(&self, channel: Channel) -> u8 {
+        self.channel(channel).max
+    }
+
+    /// `{"region", "pixels", "r": {mean, variance, min, max, histogram},
+    /// "g", "b", "a"}`
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+}
+
+/// Measure `rect`, clipped to the image like the effects clip it
+pub fn measure_region(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+) -> Result<RegionStats, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    check_region(rect, width, height)?;
+    let rect = rect.clip(width, height).unwrap_or(rect);
+    let mut histograms = [[0u32; 256]; 4];
+    for y in rect.y..rect.bottom() {
+        let start = ((y * width + rect.x) * 4) as usize;
+        for px in data[start..start + rect.w as usize * 4].chunks_exact(4) {
+            for (histogram, &value) in histograms.iter_mut().zip(px) {
+                histogram[value as usize] += 1;
+            }
+        }
+    }
+    Ok(RegionStats {
+        region: rect,
+        channels: histograms
+            .into_iter()
+            .map(ChannelStats::from_histogram)
+            .collect(),
+    })
+}
+
+/// Per-channel histograms, mean, variance and min/max of a region
+#[wasm_bindgen]
+pub fn region_stats(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+) -> Result<RegionStats, JsError> {
+    Ok(measure_region(data, width, height, Rect::new(x, y, w, h))?)
+}
+
+/// Dominant color of the ring of pixels around `rect` (a quarter of its
+/// shorter side wide), or of `rect` itself when it covers the whole image.
+/// Colors are bucketed so noise and antialiasing fall together, and the
+/// result is the mean of the busiest bucket.
+pub(crate) fn dominant_color(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    rect: Rect,
+) -> Result<Color, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    check_region(rect, width, height)?;
+    let inner = rect.clip(width, height).unwrap_or(rect);
+    let margin = (inner.w.min(inner.h) / 4).max(MIN_MARGIN);
+    let (x0, y0) = (
+        inner.x.saturating_sub(margin),
+        inner.y.saturating_sub(margin),
+    );
+    let outer = Rect::new(
+        x0,
+        y0,
+        inner.right() + margin - x0,
+        inner.bottom() + margin - y0,
+    )
+    .clip(width, height)
+    .unwrap_or(inner);
+    let ring_empty = outer == inner;
+
+    let shift = 8 - BUCKET_BITS;
+    let mut buckets = vec![(0u32, [0u64; 3]); 1 << (3 * BUCKET_BITS)];
+    for y in outer.y..outer.bottom() {
+        for x in outer.x..outer.right() {
+            let inside = x >= inner.x && x < inner.right() && y >= inner.y && y < inner.bottom();
+            if inside && !ring_empty {
+                continue;
+            }
+            let px = &data[((y * width + x) * 4) as usize..][..3];
+            let key = px.iter().fold(0usize, |key, &c| {
+                (key << BUCKET_BITS) | (c >> shift) as usize
+            });
+            let (count, sums) = &mut buckets[key];
+            *count += 1;
+            for (sum, &c) in sums.iter_mut().zip(px) {
+                *sum += c as u64;
+            }
+        }
+    }
+    let (count, sums) = buckets
+        .iter()
+        .max_by_key(|(count, _)| *count)
+        .copied()
+        .unwrap_or_default();
+    let mean = |i: usize| ((sums[i] as f64) / count.max(1) as f64).round() as u8;
+    Ok(Color::new(mean(0), mean(1), mean(2)))
+}
+
+/// Dominant color around a region, for a fill that blends with the page
+#[wasm_bindgen]
+pub fn background_color(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+) -> Result<Color, JsError> {
+    Ok(dominant_color(data, width, height, Rect::new(x, y, w, h))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_of_a_two_tone_region() {
+        // Left half black, right half white, alpha opaque
+        let (w, h) = (8, 4);
+        let data: Vec<u8> = (0..w * h)
+            .flat_map(|i| {
+                let v = if i % w < 4 { 0 } else { 200 };
+                [v, v / 2, v, 255]
+            })
+            .collect();
+        let stats = measure_region(&data, w, h, Rect::new(2, 1, 4, 2)).unwrap();
+        assert_eq!(stats.pixels(), 8);
+        assert_eq!(stats.mean(Channel::R), 100.0);
+        assert_eq!(stats.variance(Channel::R), 10000.0);
+        assert_eq!((stats.min(Channel::G), stats.max(Channel::G)), (0, 100));
+        assert_eq!(stats.variance(Channel::A), 0.0);
+        let histogram = stats.histogram(Channel::B);
+        assert_eq!((histogram[0], histogram[200]), (4, 4));
+        assert_eq!(histogram.iter().sum::<u32>(), 8);
+    }
+
+    #[test]
+    fn test_background_ignores_the_region_and_sparse_ink() {
+        // Dark-mode page with slight noise, a bright region and a few
+        // bright text pixels in the ring
+        let (w, h) = (32, 32);
+        let mut data: Vec<u8> = (0..w * h)
+            .flat_map(|i| {
+                let n = (i * 7 % 3) as u8;
+                [30 + n, 32 + n, 36 + n, 255]
+            })
+            .collect();
+        for y in 8..24 {
+            for x in 8..24 {
+                data[((y * w + x) * 4) as usize..][..3].copy_from_slice(&[250, 250, 250]);
+            }
+        }
+        for x in 4..8 {
+            data[((5 * w + x) * 4) as usize..][..3].copy_from_slice(&[240, 240, 240]);
+        }
+        let color = dominant_color(&data, w, h, Rect::new(8, 8, 16, 16)).unwrap();
+        assert_eq!((color.r, color.g, color.b), (31, 33, 37));
+
+        // Covering the whole image, the region itself decides
+        let whole = dominant_color(&data, w, h, Rect::new(0, 0, w, h)).unwrap();
+        assert_eq!((whole.r, whole.g, whole.b), (31, 33, 37));
+    }
+
+    #[test]
+    fn test_region_is_clipped() {
+        let data = vec![0u8; 4 * 4 * 4];
+        let clipped = measure_region(&data, 4, 4, Rect::new(2, 2, 4, 1)).unwrap();
+        assert_eq!(clipped.pixels(), 2);
+        assert!(measure_region(&data, 4, 4, Rect::new(4, 0, 1, 1)).is_err());
+        assert!(measure_region(&data, 4, 4, Rect::new(0, 0, 0, 0)).is_err());
+        let json = measure_region(&data, 4, 4, Rect::new(0, 0, 2, 2))
+            .unwrap()
+            .json()
+            .to_string();
+        assert!(json.contains(r#""pixels":4,"r":{"mean":0,"variance":0,"min":0,"max":0"#));
+    }
+}
@@ -0,0 +1,333 @@
+//! Table structure in document scans, for redacting by cell address.
+//!
+//! Ruled tables are found from their lines: rows and columns of the image
+//! holding a long run of dark pixels. Borderless tables can be built from
+//! OCR words instead, with rows from text lines and columns from the
+//! vertical gutters no word crosses. Either way the grid is a list of
+//! separators per axis, and cells are the space between them, so redacting
+//! a cell leaves the ruling and the neighbouring cells untouched.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, RedactError};
+use crate::stack::{apply_stack, EffectStack};
+use crate::text::{flatten, normalize, same_line, words_from, OcrWord};
+use crate::types::Rect;
+use crate::verify::luma;
+
+/// Luma below which a pixel counts as ink
+const DARK: f64 = 110.0;
+/// Light pixels a ruling may skip, for broken scan lines
+const MAX_BREAK: u32 = 2;
+/// Shortest ruling, as a fraction of the image side it runs along
+const MIN_RULING: f32 = 0.25;
+
+/// Row and column separators of a table
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableGrid {
+    /// Inclusive `(start, end)` extents of the column separators, left to right
+    columns: Vec<(u32, u32)>,
+    /// Same for row separators, top to bottom
+    rows: Vec<(u32, u32)>,
+}
+
+/// Longest run of `dark(i)` over `0..len`, bridging short breaks
+fn longest_run(len: u32, dark: impl Fn(u32) -> bool) -> u32 {
+    let (mut best, mut start, mut last) = (0, None, 0);
+    for i in 0..len {
+        if !dark(i) {
+            continue;
+        }
+        match start {
+            Some(s) if i - last <= MAX_BREAK + 1 => best = best.max(i - s + 1),
+            _ => {
+                start = Some(i);
+                best = best.max(1);
+            }
+        }
+        last = i;
+    }
+    best
+}
+
+/// Merge adjacent positions into `(start, end)` separators
+fn cluster(positions: impl Iterator<Item = u32>) -> Vec<(u32, u32)> {
+    let mut spans: Vec<(u32, u32)> = Vec::new();
+    for p in positions {
+        match spans.last_mut() {
+            Some(span) if p == span.1 + 1 => span.1 = p,
+            _ => spans.push((p, p)),
+        }
+    }
+    spans
+}
+
+impl TableGrid {
+    /// Grid from the ruling lines of an RGBA scan, or `None` without at
+    /// least two lines on each axis
+    pub fn detect(data: &[u8], width: u32, height: u32) -> Result<Option<TableGrid>, RedactError> {
+        check_buffer(data.len(), width, height)?;
+        let dark = |x: u32, y: u32| {
+            let i = ((y * width + x) * 4) as usize;
+            luma(&data[i..i + 4]) < DARK
+        };
+        let min_h = ((width as f32 * MIN_RULING) as u32).max(8);
+        let min_v = ((height as f32 * MIN_RULING) as u32).max(8);
+        let rows = cluster((0..height).filter(|&y| longest_run(width, |x| dark(x, y)) >= min_h));
+        let columns = cluster((0..width).filter(|&x| longest_run(height, |y| dark(x, y)) >= min_v));
+        Ok((rows.len() >= 2 && columns.len() >= 2).then_some(TableGrid { columns, rows }))
+    }
+
+    /// Grid of a borderless table from its OCR words, or `None` unless
+    /// there are at least two lines and two columns
+    pub fn from_words(words: &[OcrWord]) -> Option<TableGrid> {
+        let words: Vec<&OcrWord> = words.iter().filter(|w| !w.text.trim().is_empty()).collect();
+        let (first, rest) = words.split_first()?;
+
+        // Text lines, as vertical extents
+        let mut lines = vec![(first.rect.y, first.rect.bottom())];
+        let mut prev = first.rect;
+        for word in rest {
+            let line = lines.last_mut().unwrap();
+            if same_line(&prev, &word.rect) {
+                *line = (line.0.min(word.rect.y), line.1.max(word.rect.bottom()));
+            } else {
+                lines.push((word.rect.y, word.rect.bottom()));
+            }
+            prev = word.rect;
+        }
+        lines.sort_unstable();
+
+        // Gutters: gaps in the horizontal projection of every word, wider
+        // than the spaces between words of one cell
+        let mut spans: Vec<(u32, u32)> = words.iter().map(|w| (w.rect.x, w.rect.right())).collect();
+        spans.sort_unstable();
+        let mut heights: Vec<u32> = words.iter().map(|w| w.rect.h).collect();
+        heights.sort_unstable();
+        let min_gutter = heights[heights.len() / 2];
+        let mut blocks = vec![spans[0]];
+        for (x0, x1) in spans.into_iter().skip(1) {
+            let block = blocks.last_mut().unwrap();
+            if x0 < block.1 + min_gutter {
+                block.1 = block.1.max(x1);
+            } else {
+                blocks.push((x0, x1));
+            }
+        }
+        if lines.len() < 2 || blocks.len() < 2 {
+            return None;
+        }
+
+        let separators = |extents: &[(u32, u32)]| {
+            let mut seps = vec![(
+                extents[0].0.saturating_sub(1),
+                extents[0].0.saturating_sub(1),
+            )];
+            seps.extend(extents.windows(2).map(|p| {
+                let mid = (p[0].1 + p[1].0) / 2;
+                (mid, mid)
+            }));
+            let end = extents[extents.len() - 1].1;
+            seps.push((end, end));
+            seps
+        };
+        Some(TableGrid {
+            columns: separators(&blocks),
+            rows: separators(&lines),
+        })
+    }
+
+    /// Interior of the cell at `row`, `column`
+    pub fn cell(&self, row: u32, column: u32) -> Option<Rect> {
+        let (row, column) = (row as usize, column as usize);
+        let (top, bottom) = (self.rows.get(row)?, self.rows.get(row + 1)?);
+        let (left, right) = (self.columns.get(column)?, self.columns.get(column + 1)?);
+        let (x, y) = (left.1 + 1, top.1 + 1);
+        (right.0 > x && bottom.0 > y).then(|| Rect::new(x, y, right.0 - x, bottom.0 - y))
+    }
+
+    /// Cells of `column`, starting at `first_row`
+    pub fn column_cells(&self, column: u32, first_row: u32) -> Vec<Rect> {
+        (first_row..self.row_count())
+            .filter_map(|row| self.cell(row, column))
+            .collect()
+    }
+
+    pub fn row_cells(&self, row: u32) -> Vec<Rect> {
+        (0..self.column_count())
+            .filter_map(|column| self.cell(row, column))
+            .collect()
+    }
+
+    /// Column whose header cell (the first row) contains `title`, compared
+    /// word by word ignoring case and punctuation
+    pub fn find_column(&self, title: &str, words: &[OcrWord]) -> Option<u32> {
+        let wanted: Vec<String> = title.split_whitespace().map(normalize).collect();
+        (0..self.column_count()).find(|&column| {
+            let Some(header) = self.cell(0, column) else {
+                return false;
+            };
+            let text: Vec<String> = words
+                .iter()
+                // OCR boxes can overhang the cell; go by their centers
+                .filter(|w| {
+                    let (cx, cy) = (w.rect.x + w.rect.w / 2, w.rect.y + w.rect.h / 2);
+                    header.contains(&Rect::new(cx, cy, 1, 1))
+                })
+                .flat_map(|w| w.text.split_whitespace().map(normalize))
+                .collect();
+            !wanted.is_empty()
+                && text
+                    .windows(wanted.len())
+                    .any(|win| win == wanted.as_slice())
+        })
+    }
+}
+
+fn redact_cells(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    cells: &[Rect],
+    stack: &EffectStack,
+) -> Result<Vec<u32>, JsError> {
+    check_buffer(data.len(), width, height)?;
+    stack.validate()?;
+    for &cell in cells {
+        apply_stack(stack.effects(), data, width, height, cell)?;
+    }
+    Ok(flatten(cells))
+}
+
+#[wasm_bindgen]
+impl TableGrid {
+    /// Grid of a borderless table from OCR `words` and their flat boxes
+    pub fn from_ocr(words: Vec<String>, boxes: &[u32]) -> Result<Option<TableGrid>, JsError> {
+        Ok(TableGrid::from_words(&words_from(&words, boxes)?))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn row_count(&self) -> u32 {
+        self.rows.len().saturating_sub(1) as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn column_count(&self) -> u32 {
+        self.columns.len().saturating_sub(1) as u32
+    }
+
+    /// Cell interior, or `undefined` outside the grid
+    pub fn cell_rect(&self, row: u32, column: u32) -> Option<Rect> {
+        self.cell(row, column)
+    }
+
+    /// Index of the column titled `title` in the header row, per OCR
+    pub fn column_titled(
+        &self,
+        title: &str,
+        words: Vec<String>,
+        boxes: &[u32],
+    ) -> Result<Option<u32>, JsError> {
+        Ok(self.find_column(title, &words_from(&words, boxes)?))
+    }
+
+    /// Redact one cell; returns its box, flat
+    pub fn redact_cell(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        row: u32,
+        column: u32,
+        stack: &EffectStack,
+    ) -> Result<Vec<u32>, JsError> {
+        let cells: Vec<Rect> = self.cell(row, column).into_iter().collect();
+        redact_cells(data, width, height, &cells, stack)
+    }
+
+    /// Redact every cell of a row
+    pub fn redact_row(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        row: u32,
+        stack: &EffectStack,
+    ) -> Result<Vec<u32>, JsError> {
+        redact_cells(data, width, height, &self.row_cells(row), stack)
+    }
+
+    /// Redact a column, keeping its header cell when `keep_header` is set
+    pub fn redact_column(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        column: u32,
+        keep_header: bool,
+        stack: &EffectStack,
+    ) -> Result<Vec<u32>, JsError> {
+        let cells = self.column_cells(column, keep_header as u32);
+        redact_cells(data, width, height, &cells, stack)
+    }
+}
+
+/// Ruled table in an RGBA scan, or `undefined` if none is found
+#[wasm_bindgen]
+pub fn detect_table(data: &[u8], width: u32, height: u32) -> Result<Option<TableGrid>, JsError> {
+    Ok(TableGrid::detect(data, width, height)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// White 3x3 table, 2 px rulings at x = 0, 30, 60, 88 and y = 0, 20, 40, 58
+    fn ruled() -> Vec<u8> {
+        let (w, h) = (90, 60);
+        (0..w * h)
+            .flat_map(|i| {
+                let (x, y) = (i % w, i / w);
+                let on = |p: u32, lines: [u32; 4]| lines.iter().any(|&l| (l..l + 2).contains(&p));
+                let line = on(x, [0, 30, 60, 88]) || on(y, [0, 20, 40, 58]);
+                // Some "text" in the cells, too short to be a ruling
+                let text = (x % 30 > 8 && x % 30 < 20) && y % 20 == 10;
+                let v = if line || text { 0 } else { 255 };
+                [v, v, v, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detects_ruled_grid() {
+        let grid = TableGrid::detect(&ruled(), 90, 60).unwrap().unwrap();
+        assert_eq!((grid.row_count(), grid.column_count()), (3, 3));
+        assert_eq!(grid.cell(1, 1), Some(Rect::new(32, 22, 28, 18)));
+        assert_eq!(grid.cell(3, 0), None);
+        let blank = vec![255u8; 90 * 60 * 4];
+        assert_eq!(TableGrid::detect(&blank, 90, 60).unwrap(), None);
+    }
+
+    #[test]
+    fn test_borderless_grid_and_titled_column() {
+        let word = |text: &str, x, y| OcrWord::new(text, Rect::new(x, y, 30, 10));
+        let words = [
+            word("Name", 10, 0),
+            word("SSN:", 100, 0),
+            word("Jane", 10, 20),
+            word("Doe", 44, 20),
+            word("078-05-1120", 100, 20),
+            word("John", 10, 40),
+            word("219-09-9999", 100, 40),
+        ];
+        let grid = TableGrid::from_words(&words).unwrap();
+        assert_eq!((grid.row_count(), grid.column_count()), (3, 2));
+        assert_eq!(grid.find_column("ssn", &words), Some(1));
+        assert_eq!(grid.find_column("phone", &words), None);
+        let cells = grid.column_cells(1, 1);
+        assert_eq!(cells.len(), 2);
+        assert!(cells[0].contains(&words[4].rect) && cells[1].contains(&words[6].rect));
+        assert!(!cells.iter().any(|c| c.intersects(&words[1].rect)));
+    }
+}
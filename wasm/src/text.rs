@@ -0,0 +1,639 @@
+//! Redaction of text found by OCR.
+//!
+//! OCR runs on the host (Tesseract in the browser); this module matches its
+//! word boxes against search strings and maps each hit back to pixels.
+//! Matching is on whole words, case-insensitive, and ignores punctuation
+//! at word edges, so "Jane Doe" finds "JANE DOE," and a name broken
+//! across a line wrap. A hit spanning several lines yields one rect per
+//! line rather than a box over everything in between.
+//!
+//! A `Wordlist` holds the terms a team maintains per case (names, project
+//! codenames, client identifiers) and matches them the same way, optionally
+//! tolerating a few OCR misreads per word.
+//!
+//! A `TextPattern` matches a regular expression instead, for identifiers
+//! that follow a format rather than a spelling. Each OCR line (split at
+//! column gutters) is matched as its words joined by single spaces; a hit covering part of a word is
+//! mapped to the matching share of that word's box, assuming roughly even
+//! glyph widths, or to its character boxes when OCR reported them.
+//!
+//! Patterns can also redact partially, leaving the last few characters of
+//! each match readable ("show only the last 4 digits"), for recipients who
+//! need to check the tail of a card or account number.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, RedactError};
+use crate::regex::Regex;
+use crate::stack::{apply_stack, EffectStack};
+use crate::types::Rect;
+
+/// One recognized word and its bounding box
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrWord {
+    pub text: String,
+    pub rect: Rect,
+    /// Box of each character of the trimmed text, if OCR reported them;
+    /// empty otherwise
+    pub chars: Vec<Rect>,
+}
+
+impl OcrWord {
+    pub fn new(text: &str, rect: Rect) -> OcrWord {
+        OcrWord {
+            text: text.to_string(),
+            rect,
+            chars: Vec::new(),
+        }
+    }
+
+    /// Box of trimmed characters `start..end`, from the character boxes or
+    /// else an even share of the word box
+    fn span(&self, start: usize, end: usize) -> Rect {
+        if let Some(chars) = self.chars.get(start..end).filter(|c| !c.is_empty()) {
+            return chars.iter().skip(1).fold(chars[0], |a, b| a.union(b));
+        }
+        let n = self.text.trim().chars().count().max(1) as u64;
+        let w = self.rect.w as u64;
+        let x0 = (w * start as u64 / n) as u32;
+        let x1 = (w * end as u64).div_ceil(n) as u32;
+        Rect::new(self.rect.x + x0, self.rect.y, x1 - x0, self.rect.h)
+    }
+}
+
+/// Comparison form of a word: lowercase, without leading or trailing
+/// punctuation or a possessive `'s`, so "Doe's" matches a query for "Doe"
+pub(crate) fn normalize(word: &str) -> String {
+    let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+    let word = ["'s", "'S", "\u{2019}s", "\u{2019}S"]
+        .iter()
+        .find_map(|suffix| word.strip_suffix(suffix))
+        .unwrap_or(word);
+    word.to_lowercase()
+}
+
+/// Words from parallel text and flat `[x, y, w, h, ...]` box lists
+pub(crate) fn words_from(text: &[String], boxes: &[u32]) -> Result<Vec<OcrWord>, RedactError> {
+    let rects = Rect::from_flat(boxes)?;
+    if rects.len() != text.len() {
+        return Err(RedactError::InvalidParameter {
+            name: "boxes",
+            value: boxes.len() as f64,
+            expected: format!("4 values per word ({})", text.len() * 4),
+        });
+    }
+    Ok(text
+        .iter()
+        .zip(rects)
+        .map(|(text, rect)| OcrWord::new(text, rect))
+        .collect())
+}
+
+/// `words_from` with flat character boxes for every word's trimmed text,
+/// concatenated in word order; none at all falls back to even shares
+pub(crate) fn words_with_chars(
+    text: &[String],
+    boxes: &[u32],
+    char_boxes: &[u32],
+) -> Result<Vec<OcrWord>, RedactError> {
+    let mut words = words_from(text, boxes)?;
+    if char_boxes.is_empty() {
+        return Ok(words);
+    }
+    let mut chars = Rect::from_flat(char_boxes)?.into_iter();
+    let total: usize = words.iter().map(|w| w.text.trim().chars().count()).sum();
+    if chars.len() != total {
+        return Err(RedactError::InvalidParameter {
+            name: "char_boxes",
+            value: char_boxes.len() as f64,
+            expected: format!("4 values per character ({})", total * 4),
+        });
+    }
+    for word in &mut words {
+        let n = word.text.trim().chars().count();
+        word.chars = chars.by_ref().take(n).collect();
+    }
+    Ok(words)
+}
+
+/// Words shorter than this always match exactly, even when fuzzy: one edit
+/// turns too many short words into each other
+const FUZZY_MIN_LEN: usize = 5;
+/// Most edits per word a fuzzy `Wordlist` accepts
+const MAX_EDITS: u32 = 2;
+
+/// Levenshtein distance, or `None` once it exceeds `limit`
+fn edit_distance(a: &str, b: &str, limit: u32) -> Option<u32> {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    if a.len().abs_diff(b.len()) > limit as usize {
+        return None;
+    }
+    let mut row: Vec<u32> = (0..=b.len() as u32).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i as u32 + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = diagonal + (ca != cb) as u32;
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+        if row.iter().all(|&d| d > limit) {
+            return None;
+        }
+    }
+    Some(row[b.len()]).filter(|&d| d <= limit)
+}
+
+/// Boxes covering every occurrence of any of `queries`, one per line of
+/// each occurrence
+pub fn find_matches(words: &[OcrWord], queries: &[String]) -> Vec<Rect> {
+    find_terms(words, queries, 0)
+}
+
+/// `find_matches`, letting each word of a query differ from the OCR text
+/// by up to `max_edits` edits
+fn find_terms(words: &[OcrWord], queries: &[String], max_edits: u32) -> Vec<Rect> {
+    let same = |token: &str, wanted: &str| {
+        token == wanted
+            || (max_edits > 0
+                && wanted.chars().count() >= FUZZY_MIN_LEN
+                && edit_distance(token, wanted, max_edits).is_some())
+    };
+    // OCR words occasionally contain spaces; match on single tokens
+    let tokens: Vec<(String, Rect)> = words
+        .iter()
+        .flat_map(|word| {
+            word.text
+                .split_whitespace()
+                .map(normalize)
+                .filter(|t| !t.is_empty())
+                .map(move |t| (t, word.rect))
+        })
+        .collect();
+
+    let mut matches = Vec::new();
+    for query in queries {
+        let wanted: Vec<String> = query
+            .split_whitespace()
+            .map(normalize)
+            .filter(|t| !t.is_empty())
+            .collect();
+        if wanted.is_empty() || wanted.len() > tokens.len() {
+            continue;
+        }
+        for start in 0..=tokens.len() - wanted.len() {
+            let hit = &tokens[start..start + wanted.len()];
+            if hit.iter().zip(&wanted).all(|((t, _), w)| same(t, w)) {
+                matches.extend(line_boxes(hit.iter().map(|(_, r)| *r)));
+            }
+        }
+    }
+    matches.dedup();
+    matches
+}
+
+/// Merge consecutive word boxes on the same line
+fn line_boxes(rects: impl Iterator<Item = Rect>) -> Vec<Rect> {
+    let mut lines: Vec<Rect> = Vec::new();
+    for rect in rects {
+        match lines.last_mut() {
+            Some(line) if same_column_line(line, &rect) => {
+                *line = line.union(&rect);
+            }
+            _ => lines.push(rect),
+        }
+    }
+    lines
+}
+
+/// Whether `next` continues the line ending in `prev`: vertically
+/// overlapping and further right
+pub(crate) fn same_line(prev: &Rect, next: &Rect) -> bool {
+    next.x >= prev.x && next.y < prev.bottom() && prev.y < next.bottom()
+}
+
+/// Word gap, in line heights, past which two words on one line are in
+/// different columns
+const COLUMN_GAP: u32 = 2;
+
+/// `same_line`, and not across a column gutter
+pub(crate) fn same_column_line(prev: &Rect, next: &Rect) -> bool {
+    same_line(prev, next) && next.x <= prev.right() + COLUMN_GAP * prev.h.max(next.h)
+}
+
+/// Find every occurrence and apply `effects` over it; returns the boxes
+pub fn redact_matches(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    words: &[OcrWord],
+    queries: &[String],
+    stack: &EffectStack,
+) -> Result<Vec<Rect>, RedactError> {
+    let matches = find_matches(words, queries);
+    redact_boxes(data, width, height, &matches, stack)?;
+    Ok(matches)
+}
+
+/// Apply `stack` over each OCR box, padded
+pub(crate) fn redact_boxes(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    boxes: &[Rect],
+    stack: &EffectStack,
+) -> Result<(), RedactError> {
+    check_buffer(data.len(), width, height)?;
+    stack.validate()?;
+    for &rect in boxes {
+        // Pad past OCR's tight boxes so ascenders and descenders are covered
+        let pad = (rect.h / 8).max(1);
+        let padded = Rect::new(
+            rect.x.saturating_sub(pad),
+            rect.y.saturating_sub(pad),
+            rect.w + 2 * pad,
+            rect.h + 2 * pad,
+        );
+        apply_stack(stack.effects(), data, width, height, padded)?;
+    }
+    Ok(())
+}
+
+/// Terms to redact wherever OCR finds them, case-insensitively
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Wordlist {
+    terms: Vec<String>,
+    max_edits: u32,
+}
+
+impl Wordlist {
+    pub fn matches(&self, words: &[OcrWord]) -> Vec<Rect> {
+        find_terms(words, &self.terms, self.max_edits)
+    }
+
+    pub(crate) fn set_max_edits(&mut self, max_edits: u32) -> Result<(), RedactError> {
+        if max_edits > MAX_EDITS {
+            return Err(RedactError::InvalidParameter {
+                name: "max_edits",
+                value: max_edits as f64,
+                expected: format!("0 to {}", MAX_EDITS),
+            });
+        }
+        self.max_edits = max_edits;
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+impl Wordlist {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Wordlist {
+        Wordlist::default()
+    }
+
+    /// Wordlist from one term per line; blank lines and lines starting
+    /// with `#` are skipped
+    pub fn from_text(text: &str) -> Wordlist {
+        let mut list = Wordlist::new();
+        for line in text.lines().map(str::trim) {
+            if !line.is_empty() && !line.starts_with('#') {
+                list.add(line);
+            }
+        }
+        list
+    }
+
+    /// Add a term; multi-word terms match the words in sequence
+    pub fn add(&mut self, term: &str) {
+        if !self.terms.iter().any(|t| t == term) {
+            self.terms.push(term.to_string());
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.terms.len()
+    }
+
+    /// Accept up to `max_edits` (0..=2) misread characters per word of five
+    /// or more letters; 0, the default, matches exactly
+    pub fn set_fuzzy(&mut self, max_edits: u32) -> Result<(), JsError> {
+        Ok(self.set_max_edits(max_edits)?)
+    }
+
+    /// Boxes (flat `[x, y, w, h, ...]`) of every term among OCR `words`
+    pub fn find(&self, words: Vec<String>, boxes: &[u32]) -> Result<Vec<u32>, JsError> {
+        Ok(flatten(&self.matches(&words_from(&words, boxes)?)))
+    }
+
+    /// Redact every term with `stack`; returns the matched boxes, flat
+    pub fn redact(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        words: Vec<String>,
+        boxes: &[u32],
+        stack: &EffectStack,
+    ) -> Result<Vec<u32>, JsError> {
+        let matches = self.matches(&words_from(&words, boxes)?);
+        redact_boxes(data, width, height, &matches, stack)?;
+        Ok(flatten(&matches))
+    }
+}
+
+/// Compiled regular expression matched against OCR lines
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextPattern {
+    regex: Regex,
+}
+
+impl TextPattern {
+    /// Boxes of every match, one per match
+    pub fn matches(&self, words: &[OcrWord]) -> Result<Vec<Rect>, RedactError> {
+        self.matches_keeping(words, 0)
+    }
+
+    /// Boxes of every match except its last `keep` letters or digits,
+    /// which stay readable; matches with no more than `keep` are skipped
+    pub fn matches_keeping(
+        &self,
+        words: &[OcrWord],
+        keep: usize,
+    ) -> Result<Vec<Rect>, RedactError> {
+        let mut lines: Vec<Vec<&OcrWord>> = Vec::new();
+        for word in words.iter().filter(|w| !w.text.trim().is_empty()) {
+            match lines.last_mut() {
+                Some(line) if same_column_line(&line[line.len() - 1].rect, &word.rect) => {
+                    line.push(word)
+                }
+                _ => lines.push(vec![word]),
+            }
+        }
+
+        let mut matches = Vec::new();
+        for line in lines {
+            // Char range of each word in the joined line
+            let mut text = Vec::new();
+            let mut spans = Vec::new();
+            for word in &line {
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                let start = text.len();
+                text.extend(word.text.trim().chars());
+                spans.push((start, text.len()));
+            }
+            for (start, mut end) in self.regex.find_all(&text)? {
+                let mut kept = 0;
+                while kept < keep && end > start {
+                    end -= 1;
+                    kept += text[end].is_alphanumeric() as usize;
+                }
+                let parts = line.iter().zip(&spans).filter_map(|(word, &(s, e))| {
+                    let (a, b) = (start.max(s), end.min(e));
+                    (a < b).then(|| word.span(a - s, b - s))
+                });
+                matches.extend(parts.reduce(|a, b| a.union(&b)));
+            }
+        }
+        Ok(matches)
+    }
+}
+
+#[wasm_bindgen]
+impl TextPattern {
+    /// Compile `pattern`, e.g. `\b\d{10}\b` for account numbers. Compile once
+    /// and reuse it across every page of a document set.
+    #[wasm_bindgen(constructor)]
+    pub fn new(pattern: &str) -> Result<TextPattern, JsError> {
+        Ok(TextPattern {
+            regex: Regex::new(pattern)?,
+        })
+    }
+
+    /// Boxes (flat `[x, y, w, h, ...]`) of every match among OCR `words`
+    pub fn find(&self, words: Vec<String>, boxes: &[u32]) -> Result<Vec<u32>, JsError> {
+        Ok(flatten(&self.matches(&words_from(&words, boxes)?)?))
+    }
+
+    /// Redact every match except its last `keep` letters or digits, e.g.
+    /// `keep = 4` leaves "**** 1234" of a card number. `char_boxes` are the
+    /// OCR character boxes of every word, flat and concatenated; pass an
+    /// empty array to estimate them from the word boxes.
+    pub fn redact_partial(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        words: Vec<String>,
+        boxes: &[u32],
+        char_boxes: &[u32],
+        keep: usize,
+        stack: &EffectStack,
+    ) -> Result<Vec<u32>, JsError> {
+        let words = words_with_chars(&words, boxes, char_boxes)?;
+        let matches = self.matches_keeping(&words, keep)?;
+        redact_boxes(data, width, height, &matches, stack)?;
+        Ok(flatten(&matches))
+    }
+
+    /// Redact every match with `stack`; returns the matched boxes, flat
+    pub fn redact(
+        &self,
+        data: &mut [u8],
+        width: u32,
+        height: u32,
+        words: Vec<String>,
+        boxes: &[u32],
+        stack: &EffectStack,
+    ) -> Result<Vec<u32>, JsError> {
+        let matches = self.matches(&words_from(&words, boxes)?)?;
+        redact_boxes(data, width, height, &matches, stack)?;
+        Ok(flatten(&matches))
+    }
+}
+
+pub(crate) fn flatten(rects: &[Rect]) -> Vec<u32> {
+    rects.iter().flat_map(|r| [r.x, r.y, r.w, r.h]).collect()
+}
+
+/// Boxes (flat `[x, y, w, h, ...]`) of every occurrence of `queries` among
+/// OCR `words`, whose boxes are given flat in the same order
+#[wasm_bindgen]
+pub fn find_text_matches(
+    words: Vec<String>,
+    boxes: &[u32],
+    queries: Vec<String>,
+) -> Result<Vec<u32>, JsError> {
+    Ok(flatten(&find_matches(
+        &words_from(&words, boxes)?,
+        &queries,
+    )))
+}
+
+/// Apply `stack` over OCR word boxes (flat), padded to cover ascenders and
+/// descenders
+#[wasm_bindgen]
+pub fn redact_text_boxes(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    boxes: &[u32],
+    stack: &EffectStack,
+) -> Result<(), JsError> {
+    Ok(redact_boxes(
+        data,
+        width,
+        height,
+        &Rect::from_flat(boxes)?,
+        stack,
+    )?)
+}
+
+/// Redact every occurrence of `queries` among OCR `words` with `stack`.
+/// Returns the matched boxes, flat, so the host can list what was hidden.
+#[wasm_bindgen]
+pub fn redact_text_matches(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    words: Vec<String>,
+    boxes: &[u32],
+    queries: Vec<String>,
+    stack: &EffectStack,
+) -> Result<Vec<u32>, JsError> {
+    let words = words_from(&words, boxes)?;
+    let matches = redact_matches(data, width, height, &words, &queries, stack)?;
+    Ok(flatten(&matches))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Color;
+
+    fn line(words: &[&str], y: u32) -> Vec<OcrWord> {
+        words
+            .iter()
+            .enumerate()
+            .map(|(i, text)| OcrWord::new(text, Rect::new(i as u32 * 20, y, 18, 10)))
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_ignore_case_and_punctuation() {
+        let words = line(&["Signed:", "JANE", "DOE,", "and", "Jane", "Doe."], 0);
+        let matches = find_matches(&words, &["jane doe".to_string()]);
+        assert_eq!(
+            matches,
+            [Rect::new(20, 0, 38, 10), Rect::new(80, 0, 38, 10)]
+        );
+        assert!(find_matches(&words, &["Jane Smith".to_string()]).is_empty());
+
+        let words = line(&["Jane", "Doe's", "file,", "DOE\u{2019}S", "car"], 0);
+        let matches = find_matches(&words, &["Doe".to_string()]);
+        assert_eq!(
+            matches,
+            [Rect::new(20, 0, 18, 10), Rect::new(60, 0, 18, 10)]
+        );
+    }
+
+    #[test]
+    fn test_match_across_lines_gives_a_box_per_line() {
+        let mut words = line(&["to", "Jane"], 0);
+        words.extend(line(&["Doe", "today"], 14));
+        let matches = find_matches(&words, &["Jane Doe".to_string()]);
+        assert_eq!(
+            matches,
+            [Rect::new(20, 0, 18, 10), Rect::new(0, 14, 18, 10)]
+        );
+    }
+
+    #[test]
+    fn test_wordlist_fuzzy_matching() {
+        let words = line(&["Project", "Bluebrid", "and", "Acme", "Corp"], 0);
+        let mut list = Wordlist::from_text("# case 1142\nBluebird\n\nacme corp\nAcne\n");
+        assert_eq!(list.length(), 3);
+        assert_eq!(list.matches(&words), [Rect::new(60, 0, 38, 10)]);
+
+        list.set_max_edits(2).unwrap();
+        // "Bluebrid" is two edits away; "Acne" is too short to match fuzzily
+        assert_eq!(
+            list.matches(&words),
+            [Rect::new(20, 0, 18, 10), Rect::new(60, 0, 38, 10)]
+        );
+        assert!(list.set_max_edits(3).is_err());
+        assert_eq!(edit_distance("kitten", "sitting", 3), Some(3));
+        assert_eq!(edit_distance("kitten", "sitting", 2), None);
+    }
+
+    #[test]
+    fn test_pattern_maps_partial_words() {
+        let mut words = line(&["Acct:1234567890", "ref", "OPS-77"], 0);
+        words.extend(line(&["0987654321"], 14));
+        let accounts = TextPattern {
+            regex: Regex::new(r"\b\d{10}\b").unwrap(),
+        };
+        // 10 of the first word's 15 chars, right-aligned in its 18px box
+        assert_eq!(
+            accounts.matches(&words).unwrap(),
+            [Rect::new(6, 0, 12, 10), Rect::new(0, 14, 18, 10)]
+        );
+        let ticket = TextPattern {
+            regex: Regex::new(r"(?i)ref ops-\d+").unwrap(),
+        };
+        assert_eq!(ticket.matches(&words).unwrap(), [Rect::new(20, 0, 38, 10)]);
+    }
+
+    #[test]
+    fn test_partial_match_keeps_the_tail() {
+        let cards = TextPattern {
+            regex: Regex::new(r"\d{4}(-\d{4}){3}").unwrap(),
+        };
+        // 19 chars in a 95 px box: 5 px each
+        let words = vec![OcrWord::new("4111-1111-1111-1234", Rect::new(0, 0, 95, 10))];
+        assert_eq!(
+            cards.matches_keeping(&words, 4).unwrap(),
+            [Rect::new(0, 0, 75, 10)]
+        );
+
+        // Character boxes win over even shares
+        let chars = words_with_chars(
+            &["12-3456".to_string()],
+            &[0, 0, 60, 10],
+            &[
+                0, 0, 6, 10, 6, 0, 6, 10, 12, 0, 4, 10, 16, 0, 11, 10, 27, 0, 11, 10, 38, 0, 11,
+                10, 49, 0, 11, 10,
+            ],
+        )
+        .unwrap();
+        let any = TextPattern {
+            regex: Regex::new(r"[\d-]+").unwrap(),
+        };
+        assert_eq!(
+            any.matches_keeping(&chars, 4).unwrap(),
+            [Rect::new(0, 0, 16, 10)]
+        );
+        assert!(any.matches_keeping(&chars, 6).unwrap().is_empty());
+        assert!(words_with_chars(&["ab".to_string()], &[0, 0, 4, 4], &[0, 0, 1, 1]).is_err());
+    }
+
+    #[test]
+    fn test_redacts_padded_match() {
+        let mut data = vec![200u8; 64 * 32 * 4];
+        let words = words_from(
+            &["Case".to_string(), "4411".to_string()],
+            &[0, 10, 16, 8, 20, 10, 16, 8],
+        )
+        .unwrap();
+        let stack = EffectStack::new().fill(&Color::new(0, 0, 0));
+        let found = redact_matches(&mut data, 64, 32, &words, &["4411".to_string()], &stack);
+        assert_eq!(found.unwrap(), [Rect::new(20, 10, 16, 8)]);
+        assert_eq!(data[((9 * 64 + 19) * 4) as usize], 0);
+        assert_eq!(data[((12 * 64 + 5) * 4) as usize], 200);
+        assert!(words_from(&["one".to_string()], &[0, 0, 1]).is_err());
+    }
+}
@@ -0,0 +1,326 @@
+//! Detection of text in the pixels themselves, for "redact all text".
+//!
+//! Unlike `text`, which maps OCR results back to pixels, this needs no
+//! OCR and recognizes nothing: it finds regions that look like lines of
+//! text, whatever they say. Pixels darker (or lighter) than
+//! their neighbourhood by a clear margin are ink; each connected blob of
+//! ink is a glyph candidate if its strokes are thin relative to its height,
+//! which rules out solid boxes, icons and photo texture; and candidates of
+//! similar height sitting side by side on one baseline are merged into
+//! lines. The heuristics are tuned for screenshots (rendered text on flat
+//! backgrounds) and favour covering too much over missing text.
+//!
+//! Built with the `text-detect` feature, which is on by default.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, RedactError};
+use crate::stack::EffectStack;
+use crate::text::{flatten, redact_boxes};
+use crate::types::Rect;
+use crate::verify::luma;
+
+/// Half-width of the neighbourhood a pixel is compared against
+const WINDOW: usize = 12;
+/// Luma margin from the neighbourhood mean that makes a pixel ink
+const CONTRAST: f64 = 40.0;
+/// Glyph heights considered, in pixels
+const MIN_GLYPH: u32 = 5;
+const MAX_GLYPH: u32 = 160;
+/// Widest glyph candidate, in glyph heights (touching letters included)
+const MAX_ASPECT: u32 = 6;
+/// Stroke widths per glyph height at most; solid shapes are around 1
+const MAX_STROKE: f64 = 0.35;
+/// Gap between neighbours on a line, in glyph heights (spans word spaces)
+const MAX_GAP: f64 = 1.2;
+/// Glyphs a line needs; lone blobs are more often icons than text
+const MIN_GLYPHS: usize = 2;
+
+/// Bounds and run statistics of one connected blob of ink
+#[derive(Debug, Clone, Copy)]
+struct Blob {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    count: u32,
+    /// Horizontal and vertical runs of ink the blob is made of
+    h_runs: u32,
+    v_runs: u32,
+}
+
+impl Blob {
+    fn rect(&self) -> Rect {
+        Rect::new(
+            self.x0,
+            self.y0,
+            self.x1 - self.x0 + 1,
+            self.y1 - self.y0 + 1,
+        )
+    }
+
+    /// Mean stroke width: the shorter of the mean horizontal and vertical
+    /// run lengths, so a horizontal bar isn't taken for a thick stroke
+    fn stroke(&self) -> f64 {
+        let runs = self.h_runs.max(self.v_runs).max(1);
+        self.count as f64 / runs as f64
+    }
+
+    fn is_glyph(&self) -> bool {
+        let rect = self.rect();
+        (MIN_GLYPH..=MAX_GLYPH).contains(&rect.h)
+            && rect.w <= rect.h * MAX_ASPECT
+            && self.stroke() <= rect.h as f64 * MAX_STROKE
+    }
+}
+
+/// Ink masks (dark-on-light, light-on-dark) by contrast with the mean luma
+/// of each pixel's neighbourhood, read from a summed-area table
+fn ink_masks(data: &[u8], width: usize, height: usize) -> [Vec<bool>; 2] {
+    let lumas: Vec<f64> = data[..width * height * 4]
+        .chunks_exact(4)
+        .map(luma)
+        .collect();
+    let stride = width + 1;
+    let mut sums = vec![0.0; stride * (height + 1)];
+    for y in 0..height {
+        let mut row = 0.0;
+        for x in 0..width {
+            row += lumas[y * width + x];
+            sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row;
+        }
+    }
+    let mut dark = vec![false; width * height];
+    let mut light = vec![false; width * height];
+    for y in 0..height {
+        let (y0, y1) = (y.saturating_sub(WINDOW), (y + WINDOW + 1).min(height));
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(WINDOW), (x + WINDOW + 1).min(width));
+            let total = sums[y1 * stride + x1] - sums[y0 * stride + x1] - sums[y1 * stride + x0]
+                + sums[y0 * stride + x0];
+            let mean = total / ((y1 - y0) * (x1 - x0)) as f64;
+            let l = lumas[y * width + x];
+            dark[y * width + x] = l < mean - CONTRAST;
+            light[y * width + x] = l > mean + CONTRAST;
+        }
+    }
+    [dark, light]
+}
+
+/// 8-connected blobs of `mask`
+fn blobs(mask: &[bool], width: usize, height: usize) -> Vec<Blob> {
+    let at = |x: usize, y: usize| mask[y * width + x];
+    let mut seen = vec![false; mask.len()];
+    let mut found = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..mask.len() {
+        if !mask[start] || seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        stack.push(start);
+        let (sx, sy) = ((start % width) as u32, (start / width) as u32);
+        let mut blob = Blob {
+            x0: sx,
+            y0: sy,
+            x1: sx,
+            y1: sy,
+            count: 0,
+            h_runs: 0,
+            v_runs: 0,
+        };
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % width, i / width);
+            blob.x0 = blob.x0.min(x as u32);
+            blob.x1 = blob.x1.max(x as u32);
+            blob.y0 = blob.y0.min(y as u32);
+            blob.y1 = blob.y1.max(y as u32);
+            blob.count += 1;
+            blob.h_runs += (x == 0 || !at(x - 1, y)) as u32;
+            blob.v_runs += (y == 0 || !at(x, y - 1)) as u32;
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    let j = ny * width + nx;
+                    if mask[j] && !seen[j] {
+                        seen[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+        }
+        found.push(blob);
+    }
+    found
+}
+
+/// Whether `glyph` continues `line`: overlapping it vertically by half the
+/// shorter height, of comparable height, and close enough on the right
+fn continues(line: &Rect, line_h: u32, glyph: &Rect) -> bool {
+    let top = line.y.max(glyph.y);
+    let bottom = line.bottom().min(glyph.bottom());
+    let overlap = bottom.saturating_sub(top);
+    let tall = line_h.max(glyph.h);
+    overlap * 2 >= line_h.min(glyph.h)
+        && glyph.h * 3 >= line_h
+        && line_h * 3 >= glyph.h
+        && glyph.x as f64 <= line.right() as f64 + tall as f64 * MAX_GAP
+}
+
+/// Group glyph boxes into lines of at least `MIN_GLYPHS`
+fn lines(mut glyphs: Vec<Rect>) -> Vec<Rect> {
+    glyphs.sort_by_key(|r| (r.x, r.y));
+    // (bounds, tallest glyph, glyph count)
+    let mut lines: Vec<(Rect, u32, usize)> = Vec::new();
+    for glyph in glyphs {
+        match lines
+            .iter_mut()
+            .rev()
+            .find(|(line, h, _)| continues(line, *h, &glyph))
+        {
+            Some((line, h, n)) => {
+                *line = line.union(&glyph);
+                *h = (*h).max(glyph.h);
+                *n += 1;
+            }
+            None => lines.push((glyph, glyph.h, 1)),
+        }
+    }
+    lines
+        .into_iter()
+        .filter(|&(_, _, n)| n >= MIN_GLYPHS)
+        .map(|(line, _, _)| line)
+        .collect()
+}
+
+/// Merge overlapping boxes until none overlap
+fn merge_overlapping(mut rects: Vec<Rect>) -> Vec<Rect> {
+    let mut merged = true;
+    while merged {
+        merged = false;
+        let mut out: Vec<Rect> = Vec::with_capacity(rects.len());
+        for rect in rects {
+            match out.iter_mut().find(|r| r.intersects(&rect)) {
+                Some(r) => {
+                    *r = r.union(&rect);
+                    merged = true;
+                }
+                None => out.push(rect),
+            }
+        }
+        rects = out;
+    }
+    rects.sort_by_key(|r| (r.y, r.x));
+    rects
+}
+
+/// Boxes of the lines of text found in an RGBA image, top to bottom
+pub(crate) fn detect(data: &[u8], width: u32, height: u32) -> Result<Vec<Rect>, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    let (w, h) = (width as usize, height as usize);
+    let mut found = Vec::new();
+    for mask in ink_masks(data, w, h) {
+        let glyphs = blobs(&mask, w, h)
+            .iter()
+            .filter(|b| b.is_glyph())
+            .map(Blob::rect)
+            .collect();
+        found.extend(lines(glyphs));
+    }
+    Ok(merge_overlapping(found))
+}
+
+/// Boxes (flat `[x, y, w, h, ...]`) of regions that look like lines of
+/// text, found from the pixels alone
+#[wasm_bindgen]
+pub fn detect_text_regions(data: &[u8], width: u32, height: u32) -> Result<Vec<u32>, JsError> {
+    Ok(flatten(&detect(data, width, height)?))
+}
+
+/// Find text with `detect_text_regions` and apply `stack` (typically a
+/// blur or fill) over each region, padded like OCR boxes. Returns the
+/// regions, flat, so the host can show what was hidden.
+#[wasm_bindgen]
+pub fn auto_redact_text(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    stack: &EffectStack,
+) -> Result<Vec<u32>, JsError> {
+    let regions = detect(data, width, height)?;
+    redact_boxes(data, width, height, &regions, stack)?;
+    Ok(flatten(&regions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::{draw_text, text_width};
+    use crate::types::Color;
+
+    fn canvas(width: u32, height: u32, v: u8) -> Vec<u8> {
+        (0..width * height).flat_map(|_| [v, v, v, 255]).collect()
+    }
+
+    fn write(data: &mut [u8], (w, h): (u32, u32), at: (u32, u32), text: &str, ink: u8) -> Rect {
+        let rect = Rect::new(at.0, at.1, text_width(text, 14), 14);
+        draw_text(data, w, h, rect, text, Color::new(ink, ink, ink));
+        rect
+    }
+
+    #[test]
+    fn test_finds_lines_of_either_polarity() {
+        let (w, h) = (240, 120);
+        let mut data = canvas(w, h, 250);
+        let dark = write(&mut data, (w, h), (10, 10), "ACCOUNT 4417 1234", 20);
+        // A light-on-dark banner lower down
+        for y in 60..100 {
+            for x in 0..w {
+                let i = ((y * w + x) * 4) as usize;
+                data[i..i + 3].copy_from_slice(&[30, 30, 30]);
+            }
+        }
+        let light = write(&mut data, (w, h), (20, 72), "JANE DOE", 240);
+
+        let found = detect(&data, w, h).unwrap();
+        assert_eq!(found.len(), 2, "{:?}", found);
+        for (text, line) in [dark, light].iter().zip(&found) {
+            // The line covers the drawn glyphs without spilling far past them
+            assert!(line.union(text).w <= text.w + 2, "{:?} vs {:?}", line, text);
+            assert!(line.intersects(text) && line.w * 10 >= text.w * 8);
+            assert!(line.h <= text.h + 2);
+        }
+    }
+
+    #[test]
+    fn test_ignores_shapes_and_gradients() {
+        let (w, h) = (160, 80);
+        // A smooth gradient and two solid boxes (an avatar, a button)
+        let mut data: Vec<u8> = (0..w * h)
+            .flat_map(|i| {
+                let v = (i % w * 255 / w) as u8;
+                [v, v, v, 255]
+            })
+            .collect();
+        for (x0, y0, side) in [(10, 10, 30), (60, 20, 24)] {
+            for y in y0..y0 + side {
+                for x in x0..x0 + side {
+                    let i = ((y * w + x) * 4) as usize;
+                    data[i..i + 3].copy_from_slice(&[200, 30, 30]);
+                }
+            }
+        }
+        assert!(detect(&data, w, h).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_auto_redact_fills_the_text() {
+        let (w, h) = (200, 40);
+        let mut data = canvas(w, h, 255);
+        let text = write(&mut data, (w, h), (8, 12), "SSN 078 05 1120", 0);
+        let stack = EffectStack::new().fill(&Color::new(255, 255, 255));
+        let regions = auto_redact_text(&mut data, w, h, &stack).unwrap();
+        assert_eq!(regions.len(), 4);
+        assert!(Rect::from_flat(&regions).unwrap()[0].intersects(&text));
+        assert!(data.chunks_exact(4).all(|px| px[0] == 255));
+    }
+}
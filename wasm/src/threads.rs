@@ -0,0 +1,36 @@
+//! Gaussian blur and pixelation of large regions across a thread pool.
+//!
+//! With the `threads` feature, `redactr-core` splits regions of at least
+//! 256x256 pixels by rows and hands them to rayon. In the browser the pool
+//! is Web Workers from `wasm-bindgen-rayon`, started once with
+//! `set_thread_pool_size` before the first effect; until then, and for
+//! smaller regions, the effects run on the calling thread. Every row is
+//! computed exactly as the single-threaded code does, so the output is
+//! identical.
+//!
+//! Threads need shared memory, so the wasm build is a nightly one with
+//! atomics, and the page must be cross-origin isolated:
+//!
+//! ```text
+//! RUSTFLAGS="-C target-feature=+atomics,+bulk-memory,+mutable-globals" \
+//!   rustup run nightly wasm-pack build --target web -- --features threads -Z build-std=panic_abort,std
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+/// Start a pool of `threads` Web Workers for large effects. Resolves once
+/// the workers are ready; call it once, before redacting.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn set_thread_pool_size(threads: usize) -> js_sys::Promise {
+    wasm_bindgen_rayon::init_thread_pool(threads)
+}
+
+/// Size the global pool; only the first call has any effect
+#[cfg(not(target_arch = "wasm32"))]
+#[wasm_bindgen]
+pub fn set_thread_pool_size(threads: usize) {
+    let _ = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global();
+}
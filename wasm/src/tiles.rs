@@ -0,0 +1,215 @@
+//! Strip-by-strip redaction of images too big to hold whole.
+//!
+//! A 12000x8000 scan is 384 MB of RGBA before any effect allocates its
+//! scratch copy. A `TiledRedactor` takes a plan and the full image size,
+//! then is fed horizontal strips top to bottom with `process_tile`. Each
+//! call returns the rows that are finished, which may lag behind the rows
+//! fed: a region is applied once every row it covers has arrived, so a
+//! blur or pixelate straddling a seam reads the same pixels it would in a
+//! one-shot run, and the output is identical to `RedactionPlan::apply`.
+//!
+//! What's held between calls is the rows from the top of the highest
+//! pending region down, so memory is bounded by the tallest region plus a
+//! strip rather than by the image. Rows no region touches pass straight
+//! through.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::RedactError;
+use crate::plan::RedactionPlan;
+use crate::region::Region;
+use crate::types::Rect;
+
+#[wasm_bindgen]
+pub struct TiledRedactor {
+    width: u32,
+    height: u32,
+    /// Regions not yet applied, with their bounds on the full image, in
+    /// plan order; regions off the image are never added
+    pending: Vec<(Region, Rect)>,
+    /// Rows from `top` to `received`, still waiting on a region
+    held: Vec<u8>,
+    top: u32,
+    received: u32,
+}
+
+impl TiledRedactor {
+    pub(crate) fn with_plan(
+        width: u32,
+        height: u32,
+        plan: &RedactionPlan,
+    ) -> Result<TiledRedactor, RedactError> {
+        let pending = plan
+            .checked_regions(width, height)?
+            .into_iter()
+            .filter_map(|region| {
+                let bounds = region.bounds(width, height)?;
+                Some((region, bounds))
+            })
+            .collect();
+        Ok(TiledRedactor {
+            width,
+            height,
+            pending,
+            held: Vec::new(),
+            top: 0,
+            received: 0,
+        })
+    }
+
+    /// Add a strip of whole rows; returns the rows now finished
+    pub(crate) fn push_rows(&mut self, strip: &[u8]) -> Result<Vec<u8>, RedactError> {
+        let row_bytes = self.width as usize * 4;
+        let remaining = (self.height - self.received) as usize;
+        if !strip.len().is_multiple_of(row_bytes) || strip.len() > remaining * row_bytes {
+            return Err(RedactError::InvalidParameter {
+                name: "tile",
+                value: strip.len() as f64,
+                expected: format!(
+                    "whole rows of {} bytes, at most {} more",
+                    row_bytes, remaining
+                ),
+            });
+        }
+        self.held.extend_from_slice(strip);
+        self.received += (strip.len() / row_bytes) as u32;
+        self.apply_ready()?;
+
+        // Rows above every pending region are final
+        let done = self
+            .pending
+            .iter()
+            .map(|(_, bounds)| bounds.y)
+            .min()
+            .unwrap_or(self.received)
+            .min(self.received);
+        let split = (done - self.top) as usize * row_bytes;
+        let rest = self.held.split_off(split);
+        self.top = done;
+        Ok(std::mem::replace(&mut self.held, rest))
+    }
+
+    /// Apply, in plan order, each region whose rows have all arrived and
+    /// which no earlier pending region overlaps
+    fn apply_ready(&mut self) -> Result<(), RedactError> {
+        let rows = self.received - self.top;
+        let mut i = 0;
+        while i < self.pending.len() {
+            let (region, bounds) = &self.pending[i];
+            let blocked = self.pending[..i]
+                .iter()
+                .any(|(_, earlier)| earlier.intersects(bounds));
+            if bounds.bottom() > self.received || blocked {
+                i += 1;
+                continue;
+            }
+            // Held rows start `top` rows down; at the image's last row the
+            // held rows end where the image does, so clipping matches
+            region
+                .moved_up(self.top)
+                .apply_to(&mut self.held, self.width, rows)?;
+            self.pending.remove(i);
+        }
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+impl TiledRedactor {
+    /// Redactor for a `width` x `height` image, replaying `plan` scaled to
+    /// that size. Fails if any region is invalid at that scale.
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: u32, height: u32, plan: &RedactionPlan) -> Result<TiledRedactor, JsError> {
+        Ok(Self::with_plan(width, height, plan)?)
+    }
+
+    /// Feed the next horizontal strip (whole rows of RGBA, top to bottom)
+    /// and take back the rows that are finished, in order. Every row comes
+    /// back exactly once; the last strip returns whatever is left.
+    pub fn process_tile(&mut self, strip: &[u8]) -> Result<Vec<u8>, JsError> {
+        Ok(self.push_rows(strip)?)
+    }
+
+    /// Rows fed so far
+    #[wasm_bindgen(getter)]
+    pub fn rows_in(&self) -> u32 {
+        self.received
+    }
+
+    /// Rows returned so far
+    #[wasm_bindgen(getter)]
+    pub fn rows_out(&self) -> u32 {
+        self.top
+    }
+
+    /// Whether every row has been fed and returned
+    #[wasm_bindgen(getter)]
+    pub fn finished(&self) -> bool {
+        self.top == self.height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::Json;
+    use crate::pattern;
+
+    const PLAN: &str = r##"{"version": 1, "width": 30, "height": 40, "regions": [
+        {"rect": {"x": 2, "y": 6, "w": 20, "h": 18}, "effect": "gaussian_blur",
+         "params": {"radius": 4}},
+        {"rect": {"x": 10, "y": 10, "w": 6, "h": 6}, "effect": "solid_fill",
+         "params": {"color": "#ff0000"}},
+        {"ellipse": {"cx": 20, "cy": 30, "rx": 6, "ry": 5}, "effect": "pixelate",
+         "params": {"block_size": 3}, "feather": 2},
+        {"rect": {"x": 0, "y": 36, "w": 30, "h": 10}, "effect": "gaussian_blur",
+         "params": {"radius": 2}}]}"##;
+
+    fn plan() -> RedactionPlan {
+        RedactionPlan::from_json_value(&Json::parse(PLAN).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_tiles_match_one_shot() {
+        let (w, h) = (30, 40);
+        let mut expected = pattern(w, h);
+        plan().apply_to(&mut expected, w, h).unwrap();
+        let source = pattern(w, h);
+        for rows in [1, 7, 16, 40] {
+            let mut tiled = TiledRedactor::with_plan(w, h, &plan()).unwrap();
+            let mut out = Vec::new();
+            for strip in source.chunks((w * 4 * rows) as usize) {
+                out.extend(tiled.push_rows(strip).unwrap());
+                assert_eq!(out.len(), (tiled.rows_out() * w * 4) as usize);
+            }
+            assert!(tiled.finished());
+            assert_eq!(out, expected, "strips of {} rows", rows);
+        }
+    }
+
+    #[test]
+    fn test_rows_wait_for_regions_across_seams() {
+        let (w, h) = (30, 40);
+        let source = pattern(w, h);
+        let mut tiled = TiledRedactor::with_plan(w, h, &plan()).unwrap();
+        // The first blur starts at row 6, so only rows above it are final
+        let out = tiled.push_rows(&source[..(w * 4 * 10) as usize]).unwrap();
+        assert_eq!(out, source[..(w * 4 * 6) as usize]);
+        assert_eq!((tiled.rows_in(), tiled.rows_out()), (10, 6));
+        // Once it and the fill inside it are complete, rows through the
+        // start of the feathered ellipse are released
+        tiled
+            .push_rows(&source[(w * 4 * 10) as usize..(w * 4 * 26) as usize])
+            .unwrap();
+        assert_eq!(tiled.rows_out(), 23);
+    }
+
+    #[test]
+    fn test_rejects_partial_rows_and_overruns() {
+        let mut tiled = TiledRedactor::with_plan(30, 40, &plan()).unwrap();
+        let err = tiled.push_rows(&[0; 30 * 4 + 1]).unwrap_err().to_string();
+        assert!(err.contains("whole rows of 120 bytes"), "{}", err);
+        assert!(tiled.push_rows(&[0; 30 * 4 * 41]).is_err());
+        assert_eq!(tiled.rows_in(), 0);
+    }
+}
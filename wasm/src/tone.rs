@@ -0,0 +1,183 @@
+//! Per-pixel tone effects that de-emphasize content instead of hiding it.
+//!
+//! `desaturate` turns a region gray (luma, as `verify` measures it) and can
+//! flatten it towards mid-gray with `contrast` below 1. It leaves content
+//! readable, so it's for focus and emphasis, not for redacting secrets.
+//!
+//! `dim` scales a region's RGB by a factor: below 1 darkens, above 1
+//! brightens. With `EffectStack.apply_inverse` it gives the tutorial
+//! screenshot look, everything darkened except the highlighted area.
+
+use wasm_bindgen::prelude::*;
+
+use crate::effect::Effect;
+use crate::for_each_brush_pixel;
+use crate::policy::{or_throw, policed};
+use crate::shape::{apply_in_shape, Shape};
+use crate::types::Rect;
+use crate::verify::luma;
+
+/// Gray every pixel of `rect` (clamped to the image), with `contrast` 1
+/// keeping the full luma range and 0 leaving flat mid-gray
+pub(crate) fn desaturate_rect(data: &mut [u8], width: u32, height: u32, rect: Rect, contrast: f32) {
+    let Some(rect) = rect.clip(width, height) else {
+        return;
+    };
+    for py in rect.y..rect.bottom() {
+        for px in rect.x..rect.right() {
+            let idx = ((py * width + px) * 4) as usize;
+            if let Some(pixel) = data.get_mut(idx..idx + 4) {
+                desaturate_pixel(pixel, contrast);
+            }
+        }
+    }
+}
+
+fn desaturate_pixel(pixel: &mut [u8], contrast: f32) {
+    let gray = 128.0 + (luma(pixel) as f32 - 128.0) * contrast;
+    let gray = gray.round().clamp(0.0, 255.0) as u8;
+    pixel[..3].fill(gray);
+}
+
+/// Multiply the RGB of every pixel of `rect` (clamped to the image) by
+/// `factor`, saturating at white
+pub(crate) fn dim_rect(data: &mut [u8], width: u32, height: u32, rect: Rect, factor: f32) {
+    let Some(rect) = rect.clip(width, height) else {
+        return;
+    };
+    for py in rect.y..rect.bottom() {
+        let start = ((py * width + rect.x) * 4) as usize;
+        let end = (start + rect.w as usize * 4).min(data.len());
+        for pixel in data[start.min(end)..end].chunks_exact_mut(4) {
+            for c in &mut pixel[..3] {
+                *c = (*c as f32 * factor).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+}
+
+/// Darken (`factor` below 1) or brighten (above 1) a region
+#[wasm_bindgen]
+pub fn dim(data: &mut [u8], width: u32, height: u32, x: u32, y: u32, w: u32, h: u32, factor: f32) {
+    let rect = Rect::new(x, y, w, h);
+    or_throw(policed(Effect::Dim { factor }, rect));
+    dim_rect(data, width, height, rect, factor);
+}
+
+/// Convert a region to grayscale; `contrast` (0..=1) below 1 also pulls it
+/// towards mid-gray
+#[wasm_bindgen]
+pub fn desaturate(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    contrast: f32,
+) {
+    let rect = Rect::new(x, y, w, h);
+    or_throw(policed(Effect::Desaturate { contrast }, rect));
+    desaturate_rect(data, width, height, rect, contrast);
+}
+
+/// `desaturate` inside the ellipse centered on (`cx`, `cy`) with radii
+/// `rx`, `ry`
+#[wasm_bindgen]
+pub fn desaturate_ellipse(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    cx: f32,
+    cy: f32,
+    rx: f32,
+    ry: f32,
+    contrast: f32,
+) -> Result<(), JsError> {
+    let effect = Effect::Desaturate { contrast };
+    let shape = Shape::Ellipse { cx, cy, rx, ry };
+    Ok(apply_in_shape(&effect, data, width, height, &shape)?)
+}
+
+/// `desaturate` under a brush stroke; pixels where segments overlap are
+/// only converted once, so the stroke is even
+#[wasm_bindgen]
+pub fn brush_desaturate(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    points: &[f32],
+    brush_size: u32,
+    contrast: f32,
+) {
+    let whole = Rect::new(0, 0, width, height);
+    or_throw(policed(Effect::Desaturate { contrast }, whole));
+    let mut done = vec![false; (width * height) as usize];
+    for_each_brush_pixel(width, height, points, brush_size, |px, py| {
+        let i = (py * width + px) as usize;
+        if std::mem::replace(&mut done[i], true) {
+            return;
+        }
+        if let Some(pixel) = data.get_mut(i * 4..i * 4 + 4) {
+            desaturate_pixel(pixel, contrast);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colorful(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| [(i * 40 % 256) as u8, 200, (i * 7 % 256) as u8, 255])
+            .collect()
+    }
+
+    fn is_gray(px: &[u8]) -> bool {
+        px[0] == px[1] && px[1] == px[2]
+    }
+
+    #[test]
+    fn test_desaturate_grays_and_flattens() {
+        let mut data = colorful(8, 8);
+        desaturate(&mut data, 8, 8, 2, 2, 4, 4, 1.0);
+        let at = |data: &[u8], x: usize, y: usize| data[(y * 8 + x) * 4..][..4].to_vec();
+        assert!(is_gray(&at(&data, 3, 3)));
+        assert!(!is_gray(&at(&data, 1, 1)));
+        assert_eq!(at(&data, 3, 3)[3], 255);
+
+        let mut flat = colorful(8, 8);
+        desaturate(&mut flat, 8, 8, 0, 0, 8, 8, 0.0);
+        assert!(flat.chunks_exact(4).all(|px| px == [128, 128, 128, 255]));
+    }
+
+    #[test]
+    fn test_dim_scales_rgb() {
+        let mut data: Vec<u8> = [[100, 200, 40, 90]; 4].concat();
+        dim(&mut data, 2, 2, 0, 0, 1, 2, 0.5);
+        assert_eq!(&data[..4], [50, 100, 20, 90]);
+        assert_eq!(&data[4..8], [100, 200, 40, 90]);
+        dim(&mut data, 2, 2, 1, 0, 1, 1, 2.0);
+        assert_eq!(&data[4..8], [200, 255, 80, 90]);
+    }
+
+    #[test]
+    fn test_ellipse_and_brush_shapes() {
+        let mut data = colorful(20, 20);
+        desaturate_ellipse(&mut data, 20, 20, 10.0, 10.0, 5.0, 3.0, 0.5).unwrap();
+        let at = |data: &[u8], x: usize, y: usize| data[(y * 20 + x) * 4..][..4].to_vec();
+        assert!(is_gray(&at(&data, 10, 10)));
+        assert!(!is_gray(&at(&data, 10, 15)));
+
+        // Overlapping segments don't compound the contrast reduction
+        let mut once = colorful(20, 20);
+        brush_desaturate(&mut once, 20, 20, &[5.0, 5.0, 12.0, 5.0], 4, 0.5);
+        let mut twice = colorful(20, 20);
+        let there_and_back = [5.0, 5.0, 12.0, 5.0, 5.0, 5.0];
+        brush_desaturate(&mut twice, 20, 20, &there_and_back, 4, 0.5);
+        assert_eq!(once, twice);
+        assert!(is_gray(&at(&once, 8, 5)) && !is_gray(&at(&once, 8, 12)));
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::RedactError;
+use crate::json::Json;
+
+/// Axis-aligned rectangle in image pixel coordinates
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[wasm_bindgen]
+impl Rect {
+    #[wasm_bindgen(constructor)]
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> Rect {
+        Rect { x, y, w, h }
+    }
+}
+
+/// Color used by the fill effects.
+///
+/// Fills only write `a` when the alpha channel is explicitly targeted, so
+/// it defaults to opaque.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+#[wasm_bindgen]
+impl Color {
+    #[wasm_bindgen(constructor)]
+    pub fn new(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+
+    pub fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+}
+
+impl Color {
+    /// `#rrggbb`, the color format used in JSON
+    pub(crate) fn hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    /// Parse `#rrggbb`
+    pub(crate) fn from_hex(text: &str) -> Option<Color> {
+        let digits = text.strip_prefix('#').filter(|d| d.len() == 6)?;
+        let channel = |i: usize| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok();
+        Some(Color::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+/// Bit flags for `Channels`, usable from JS as `Channel.R | Channel.B`
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    R = 1,
+    G = 2,
+    B = 4,
+    A = 8,
+}
+
+/// Set of RGBA channels an effect is allowed to write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channels(pub u8);
+
+impl Channels {
+    pub const R: Channels = Channels(Channel::R as u8);
+    pub const G: Channels = Channels(Channel::G as u8);
+    pub const B: Channels = Channels(Channel::B as u8);
+    pub const A: Channels = Channels(Channel::A as u8);
+    /// What every effect writes by default: color, but never alpha
+    pub const RGB: Channels = Channels(0b0111);
+    pub const ALL: Channels = Channels(0b1111);
+
+    /// Whether channel `index` (0 = R .. 3 = A) is in the set
+    pub fn has(self, index: usize) -> bool {
+        self.0 & (1 << index) != 0
+    }
+
+    pub fn contains(self, other: Channels) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Channels::RGB
+    }
+}
+
+impl std::ops::BitOr for Channels {
+    type Output = Channels;
+
+    fn bitor(self, rhs: Channels) -> Channels {
+        Channels(self.0 | rhs.0)
+    }
+}
+
+impl Rect {
+    /// Intersection with a `width` x `height` image, or `None` if empty
+    pub(crate) fn clip(&self, width: u32, height: u32) -> Option<Rect> {
+        let right = self.right().min(width);
+        let bottom = self.bottom().min(height);
+        if self.x >= right || self.y >= bottom {
+            return None;
+        }
+        Some(Rect::new(self.x, self.y, right - self.x, bottom - self.y))
+    }
+
+    /// Exclusive right edge, saturating instead of overflowing
+    pub(crate) fn right(&self) -> u32 {
+        self.x.saturating_add(self.w)
+    }
+
+    /// Exclusive bottom edge, saturating instead of overflowing
+    pub(crate) fn bottom(&self) -> u32 {
+        self.y.saturating_add(self.h)
+    }
+
+    pub(crate) fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.right() <= self.right()
+            && other.bottom() <= self.bottom()
+    }
+
+    /// Smallest rect covering both
+    pub(crate) fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect::new(
+            x,
+            y,
+            self.right().max(other.right()) - x,
+            self.bottom().max(other.bottom()) - y,
+        )
+    }
+
+    pub(crate) fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+}
+
+impl Rect {
+    /// Rects from a flat `[x, y, w, h, ...]` list, as passed from JS
+    pub(crate) fn from_flat(values: &[u32]) -> Result<Vec<Rect>, RedactError> {
+        if !values.len().is_multiple_of(4) {
+            return Err(RedactError::InvalidParameter {
+                name: "regions",
+                value: values.len() as f64,
+                expected: "a multiple of 4 values".to_string(),
+            });
+        }
+        Ok(values
+            .chunks_exact(4)
+            .map(|r| Rect::new(r[0], r[1], r[2], r[3]))
+            .collect())
+    }
+
+    /// Parse `{"x", "y", "w", "h"}` with non-negative integer fields
+    pub(crate) fn from_json(value: &Json) -> Option<Rect> {
+        let field = |key| {
+            value
+                .get(key)?
+                .as_f64()
+                .filter(|n| n.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(n))
+                .map(|n| n as u32)
+        };
+        Some(Rect::new(
+            field("x")?,
+            field("y")?,
+            field("w")?,
+            field("h")?,
+        ))
+    }
+
+    pub(crate) fn json(&self) -> Json {
+        Json::object()
+            .with("x", self.x)
+            .with("y", self.y)
+            .with("w", self.w)
+            .with("h", self.h)
+    }
+}
+
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{} {}x{}", self.x, self.y, self.w, self.h)
+    }
+}
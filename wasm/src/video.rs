@@ -0,0 +1,1010 @@
+//! Frame-by-frame redaction for video.
+//!
+//! A `VideoRedactor` is created once per stream and fed frames in order.
+//! Each track pairs an effect with region keyframes; the region at a frame's
+//! timestamp is interpolated between the surrounding keyframes, and a track
+//! only applies between its first and last keyframe. Timestamps are in
+//! whatever unit the caller uses (milliseconds, frame indices), as long as
+//! keyframes and frames agree.
+//!
+//! The redactor keeps its conversion buffer between frames, so steady-state
+//! frames of the same size don't allocate for YUV input.
+//!
+//! Redactions that jump around from frame to frame draw the eye, so two
+//! opt-in stabilizers are available. Temporal smoothing eases each track's
+//! region and calibrated parameters toward their targets. It only ever lets
+//! coverage and strength trail behind, never fall short: a region grows and
+//! a block size rises immediately. Grid locking anchors pixelation blocks to
+//! the frame instead of the moving region, so blocks don't shimmer as the
+//! region slides a pixel at a time.
+//!
+//! Every frame is also checked for a hard cut. At a cut, smoothing restarts
+//! and no track interpolates across it: a track jumps straight to its next
+//! keyframe rather than sliding through content of the new shot. The host
+//! is notified so it can re-run detection.
+//!
+//! Burned-in captions can be redacted too: with caption redaction on, each
+//! frame's lower third is scanned for a subtitle band, which is filled or
+//! blurred for as long as it stays on screen. The detected spans are written
+//! to the plan as ordinary tracks, so a replay redacts them without
+//! detecting again.
+//!
+//! A redactor's tracks serialize to a timeline plan, so one JSON document
+//! describes a whole video's redaction:
+//!
+//! ```json
+//! {"version": 1, "width": 1280, "height": 720, "time_unit": "ms",
+//!  "tracks": [
+//!   {"effect": "pixelate", "params": {"block_size": 16}, "class": "FACE",
+//!    "keyframes": [{"t": 0, "region": {"x": 10, "y": 10, "w": 64, "h": 64}},
+//!                  {"t": 400, "region": {"x": 90, "y": 12, "w": 64, "h": 64}}]},
+//!   {"effect": "solid_fill", "params": {"color": "#000000"},
+//!    "start": 1200, "end": 3400, "region": {"x": 0, "y": 600, "w": 1280, "h": 120}}]}
+//! ```
+//!
+//! `start`/`end` with a single `region` is shorthand for two keyframes.
+//! Face tracks are `"calibrated": true` pixelate tracks. An optional
+//! `"audio": [{"start", "end", "mode": "beep"}]` list covers spoken names in
+//! the same plan; audio times are always milliseconds.
+
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+
+use crate::audio::{apply_ranges, AudioMode, AudioRange, Ducking};
+use crate::calibrate::face_block_size;
+use crate::callbacks::report_scene_cut;
+use crate::captions::{detect_band, CaptionTracker};
+use crate::classify::PiiClass;
+use crate::effect::Effect;
+use crate::error::{check_buffer, RedactError};
+use crate::json::Json;
+use crate::policy::policed;
+use crate::scene::{Signature, DEFAULT_CUT_THRESHOLD};
+use crate::scratch::Scratch;
+use crate::types::{Color, Rect};
+use crate::verify::luma;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Track {
+    id: u32,
+    effect: Effect,
+    /// Sorted by timestamp
+    keyframes: Vec<(f64, Rect)>,
+    /// Pixelate with a block size calibrated to the region each frame
+    face: bool,
+    class: Option<PiiClass>,
+    smoothed: Option<Smoothed>,
+}
+
+/// A track's eased geometry and parameter as of the last processed frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Smoothed {
+    timestamp: f64,
+    /// Left, top, right, bottom
+    edges: [f32; 4],
+    block_size: f32,
+}
+
+fn lerp(a: u32, b: u32, t: f64) -> u32 {
+    (a as f64 + (b as f64 - a as f64) * t).round() as u32
+}
+
+impl Track {
+    /// Region at `timestamp`, or `None` outside the keyframed span. Past a
+    /// scene cut at `cut`, keyframes from before the cut aren't
+    /// interpolated from.
+    fn region_at(&self, timestamp: f64, cut: Option<f64>) -> Option<Rect> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+        if timestamp < first.0 || timestamp > last.0 {
+            return None;
+        }
+        let next = self
+            .keyframes
+            .iter()
+            .position(|&(t, _)| t >= timestamp)
+            .unwrap_or(self.keyframes.len() - 1);
+        let (t1, b) = self.keyframes[next];
+        if next == 0 || t1 == timestamp {
+            return Some(b);
+        }
+        let (t0, a) = self.keyframes[next - 1];
+        if cut.is_some_and(|cut| cut > t0 && cut <= timestamp) {
+            return Some(b);
+        }
+        let t = (timestamp - t0) / (t1 - t0);
+        Some(Rect::new(
+            lerp(a.x, b.x, t),
+            lerp(a.y, b.y, t),
+            lerp(a.w, b.w, t),
+            lerp(a.h, b.h, t),
+        ))
+    }
+}
+
+/// Stateful redactor for a stream of same-sized frames
+#[wasm_bindgen]
+pub struct VideoRedactor {
+    width: u32,
+    height: u32,
+    tracks: Vec<Track>,
+    next_id: u32,
+    /// RGBA copy of the region being redacted in a YUV frame
+    rgba: Scratch,
+    /// Weight of the previous frame when easing, 0 (off) ..= 0.95
+    smoothing: f32,
+    lock_grid: bool,
+    /// Unit of keyframe and frame timestamps, recorded in plans
+    time_unit: String,
+    audio: Vec<AudioRange>,
+    ducking: Option<Ducking>,
+    /// Histogram distance that counts as a cut; 0 disables detection
+    cut_threshold: f32,
+    previous: Option<Signature>,
+    last_cut: Option<f64>,
+    cut_count: usize,
+    on_scene_cut: Option<Function>,
+    captions: Option<CaptionTracker>,
+}
+
+/// Default caption hold: half a second in milliseconds
+const CAPTION_HOLD: f64 = 500.0;
+
+fn plane_error(name: &'static str, len: usize, expected: usize) -> RedactError {
+    RedactError::InvalidParameter {
+        name,
+        value: len as f64,
+        expected: format!("{} bytes", expected),
+    }
+}
+
+/// This frame's effects as the active policy accepts or upgrades them
+fn policed_all(active: Vec<(Effect, Rect)>) -> Result<Vec<(Effect, Rect)>, RedactError> {
+    active
+        .into_iter()
+        .map(|(effect, region)| Ok((policed(effect, region)?, region)))
+        .collect()
+}
+
+fn clamp_u8(v: f32) -> u8 {
+    v.round().clamp(0.0, 255.0) as u8
+}
+
+// BT.601 limited-range conversions, the usual encoding of decoded video
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = 1.164 * (y as f32 - 16.0);
+    let (d, e) = (u as f32 - 128.0, v as f32 - 128.0);
+    [
+        clamp_u8(c + 1.596 * e),
+        clamp_u8(c - 0.391 * d - 0.813 * e),
+        clamp_u8(c + 2.018 * d),
+    ]
+}
+
+fn rgb_to_yuv(px: &[u8]) -> (f32, f32, f32) {
+    let (r, g, b) = (px[0] as f32, px[1] as f32, px[2] as f32);
+    (
+        16.0 + 0.257 * r + 0.504 * g + 0.098 * b,
+        128.0 - 0.148 * r - 0.291 * g + 0.439 * b,
+        128.0 + 0.439 * r - 0.368 * g - 0.071 * b,
+    )
+}
+
+impl VideoRedactor {
+    /// Add a track; `face` tracks recompute their block size every frame,
+    /// so their effect's own block size is ignored
+    pub(crate) fn add_track(&mut self, effect: Effect, face: bool) -> Result<u32, RedactError> {
+        if !face {
+            effect.validate()?;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tracks.push(Track {
+            id,
+            effect,
+            keyframes: Vec::new(),
+            face,
+            class: None,
+            smoothed: None,
+        });
+        Ok(id)
+    }
+
+    pub(crate) fn set_smoothing(&mut self, amount: f32) -> Result<(), RedactError> {
+        if !(0.0..=0.95).contains(&amount) {
+            return Err(RedactError::InvalidParameter {
+                name: "smoothing",
+                value: amount as f64,
+                expected: "between 0 and 0.95".to_string(),
+            });
+        }
+        self.smoothing = amount;
+        Ok(())
+    }
+
+    fn track_mut(&mut self, id: u32) -> Result<&mut Track, RedactError> {
+        self.tracks
+            .iter_mut()
+            .find(|t| t.id == id)
+            .ok_or(RedactError::InvalidParameter {
+                name: "track",
+                value: id as f64,
+                expected: "a track id returned by add_*_track".to_string(),
+            })
+    }
+
+    pub(crate) fn keyframe(
+        &mut self,
+        id: u32,
+        timestamp: f64,
+        region: Rect,
+    ) -> Result<(), RedactError> {
+        let track = self.track_mut(id)?;
+        let at = track.keyframes.partition_point(|&(t, _)| t < timestamp);
+        if track
+            .keyframes
+            .get(at)
+            .is_some_and(|&(t, _)| t == timestamp)
+        {
+            track.keyframes[at].1 = region;
+        } else {
+            track.keyframes.insert(at, (timestamp, region));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn plan_json(&self) -> Json {
+        let tracks = self
+            .tracks
+            .iter()
+            .map(|track| {
+                let keyframes = track
+                    .keyframes
+                    .iter()
+                    .map(|(t, region)| Json::object().with("t", *t).with("region", region.json()))
+                    .collect();
+                let json = Json::object()
+                    .with("effect", track.effect.name())
+                    .with("params", track.effect.params_json())
+                    .with("class", track.class.as_ref().map(PiiClass::name))
+                    .with("keyframes", Json::Array(keyframes));
+                if track.face {
+                    json.with("calibrated", true)
+                } else {
+                    json
+                }
+            })
+            .collect::<Vec<_>>();
+        let captions = self.captions.iter().flat_map(|captions| {
+            captions.spans().map(|span| {
+                Json::object()
+                    .with("effect", captions.effect.name())
+                    .with("params", captions.effect.params_json())
+                    .with("class", Json::Null)
+                    .with("start", span.start)
+                    .with("end", span.end + captions.hold)
+                    .with("region", span.region.json())
+            })
+        });
+        let tracks = tracks.into_iter().chain(captions).collect();
+        Json::object()
+            .with("version", 1u32)
+            .with("width", self.width)
+            .with("height", self.height)
+            .with("time_unit", self.time_unit.as_str())
+            .with("tracks", Json::Array(tracks))
+            .with(
+                "audio",
+                Json::Array(self.audio.iter().map(AudioRange::json).collect()),
+            )
+    }
+
+    /// Build a redactor from a timeline plan (see the module docs)
+    pub(crate) fn parse_plan(text: &str) -> Result<VideoRedactor, RedactError> {
+        let field = |field: &str, expected| RedactError::InvalidField {
+            field: field.to_string(),
+            expected,
+        };
+        let json = Json::parse(text)?;
+        if json.get("version").and_then(Json::as_f64) != Some(1.0) {
+            return Err(field("version", "1"));
+        }
+        let dimension = |key| {
+            json.get(key)
+                .and_then(Json::as_f64)
+                .filter(|n| n.fract() == 0.0 && *n >= 1.0 && *n <= u32::MAX as f64)
+                .map(|n| n as u32)
+                .ok_or_else(|| field(key, "a positive integer"))
+        };
+        let mut video = VideoRedactor::new(dimension("width")?, dimension("height")?);
+        if let Some(unit) = json.get("time_unit") {
+            video.time_unit = unit
+                .as_str()
+                .ok_or_else(|| field("time_unit", "a string such as \"ms\" or \"frame\""))?
+                .to_string();
+        }
+        let tracks = json
+            .get("tracks")
+            .and_then(Json::as_array)
+            .ok_or_else(|| field("tracks", "an array"))?;
+        for (i, track) in tracks.iter().enumerate() {
+            let name = track
+                .get("effect")
+                .and_then(Json::as_str)
+                .ok_or_else(|| field(&format!("tracks[{}].effect", i), "an effect name"))?;
+            let face = track.get("calibrated").and_then(Json::as_bool) == Some(true);
+            let id = if face {
+                video.add_track(Effect::pixelate(0), true)?
+            } else {
+                video.add_track(Effect::from_json(name, track.get("params"))?, false)?
+            };
+            match track.get("class") {
+                None | Some(Json::Null) => {}
+                Some(class) => {
+                    let class = class
+                        .as_str()
+                        .and_then(|name| PiiClass::from_name(name).ok())
+                        .ok_or_else(|| {
+                            field(&format!("tracks[{}].class", i), "a PII class label")
+                        })?;
+                    video.track_mut(id)?.class = Some(class);
+                }
+            }
+
+            let bad_time = || {
+                field(
+                    &format!("tracks[{}]", i),
+                    "keyframes or start, end and region",
+                )
+            };
+            let time = |value: Option<&Json>| value.and_then(Json::as_f64).ok_or_else(bad_time);
+            let region =
+                |value: Option<&Json>| value.and_then(Rect::from_json).ok_or_else(bad_time);
+            if let Some(keyframes) = track.get("keyframes") {
+                for keyframe in keyframes.as_array().ok_or_else(bad_time)? {
+                    let t = time(keyframe.get("t"))?;
+                    video.keyframe(id, t, region(keyframe.get("region"))?)?;
+                }
+            } else {
+                let (start, end) = (time(track.get("start"))?, time(track.get("end"))?);
+                let region = region(track.get("region"))?;
+                video.keyframe(id, start, region)?;
+                video.keyframe(id, end, region)?;
+            }
+        }
+        if let Some(audio) = json.get("audio") {
+            let bad = || field("audio", "an array of {start, end, mode}");
+            for range in audio.as_array().ok_or_else(bad)? {
+                let time = |key| range.get(key).and_then(Json::as_f64).ok_or_else(bad);
+                let mode = match range.get("mode") {
+                    None => AudioMode::Beep,
+                    Some(mode) => mode
+                        .as_str()
+                        .and_then(AudioMode::from_name)
+                        .ok_or_else(bad)?,
+                };
+                video.audio.push(AudioRange {
+                    start: time("start")?,
+                    end: time("end")?,
+                    mode,
+                });
+            }
+        }
+        Ok(video)
+    }
+
+    pub(crate) fn process_pcm(
+        &self,
+        samples: &mut [f32],
+        sample_rate: u32,
+        channels: u32,
+        start_ms: f64,
+    ) -> Result<(), RedactError> {
+        apply_ranges(
+            samples,
+            sample_rate,
+            channels,
+            start_ms,
+            &self.audio,
+            self.ducking,
+        )
+    }
+
+    /// Regions active at `timestamp`, clipped to the frame, with their
+    /// effect; advances each track's smoothing state
+    fn active(&mut self, timestamp: f64) -> Vec<(Effect, Rect)> {
+        let (smoothing, lock_grid, cut) = (self.smoothing, self.lock_grid, self.last_cut);
+        let (width, height) = (self.width, self.height);
+        self.tracks
+            .iter_mut()
+            .filter_map(|track| {
+                let Some(target) = track.region_at(timestamp, cut) else {
+                    track.smoothed = None;
+                    return None;
+                };
+                let target_edges = [
+                    target.x as f32,
+                    target.y as f32,
+                    target.right() as f32,
+                    target.bottom() as f32,
+                ];
+                let target_block = match track.effect {
+                    _ if track.face => face_block_size(target.w, target.h, 0.0) as f32,
+                    Effect::Pixelate { block_size, .. } => block_size as f32,
+                    _ => 0.0,
+                };
+
+                // Seeking backwards restarts the easing
+                let previous = track.smoothed.filter(|s| {
+                    smoothing > 0.0
+                        && s.timestamp <= timestamp
+                        && cut.is_none_or(|cut| s.timestamp >= cut)
+                });
+                let state = match previous {
+                    Some(prev) => {
+                        let ease = |from: f32, to: f32| from + (to - from) * (1.0 - smoothing);
+                        let mut edges = [0.0; 4];
+                        for i in 0..4 {
+                            let eased = ease(prev.edges[i], target_edges[i]);
+                            // Never uncover any of the target
+                            edges[i] = if i < 2 {
+                                eased.min(target_edges[i])
+                            } else {
+                                eased.max(target_edges[i])
+                            };
+                        }
+                        Smoothed {
+                            timestamp,
+                            edges,
+                            block_size: ease(prev.block_size, target_block).max(target_block),
+                        }
+                    }
+                    None => Smoothed {
+                        timestamp,
+                        edges: target_edges,
+                        block_size: target_block,
+                    },
+                };
+                track.smoothed = Some(state);
+
+                let mut effect = track.effect;
+                if let Effect::Pixelate { block_size, .. } = &mut effect {
+                    *block_size = (state.block_size.ceil() as u32).max(1);
+                }
+                let [mut x0, mut y0, mut x1, mut y1] = state.edges.map(|e| e.round() as u32);
+                if let (true, Effect::Pixelate { block_size, .. }) = (lock_grid, effect) {
+                    x0 -= x0 % block_size;
+                    y0 -= y0 % block_size;
+                    x1 = x1.div_ceil(block_size) * block_size;
+                    y1 = y1.div_ceil(block_size) * block_size;
+                }
+                let region = Rect::new(x0, y0, x1 - x0, y1 - y0).clip(width, height)?;
+                Some((effect, region))
+            })
+            .collect()
+    }
+
+    /// Compare a frame with the previous one; returns whether it starts a
+    /// new shot
+    fn detect_cut(&mut self, signature: Signature, timestamp: f64) -> bool {
+        if self.last_cut.is_some_and(|cut| timestamp < cut) {
+            // Seeked back before the cut
+            self.last_cut = None;
+        }
+        let previous = self.previous.replace(signature);
+        let (Some(previous), Some(current)) = (previous, &self.previous) else {
+            return false;
+        };
+        let score = previous.distance(current);
+        if self.cut_threshold <= 0.0 || score <= self.cut_threshold {
+            return false;
+        }
+        self.last_cut = Some(timestamp);
+        self.cut_count += 1;
+        report_scene_cut(&self.on_scene_cut, timestamp, score);
+        true
+    }
+
+    pub(crate) fn process_rgba(
+        &mut self,
+        data: &mut [u8],
+        timestamp: f64,
+    ) -> Result<usize, RedactError> {
+        check_buffer(data.len(), self.width, self.height)?;
+        self.detect_cut(Signature::from_rgba(data), timestamp);
+        let mut active = self.active(timestamp);
+        if let Some(captions) = &mut self.captions {
+            let band = detect_band(self.width, self.height, |x, y| {
+                let i = ((y * self.width + x) * 4) as usize;
+                luma(&data[i..i + 4]) as f32
+            });
+            active.extend(
+                captions
+                    .update(band, timestamp)
+                    .map(|r| (captions.effect, r)),
+            );
+        }
+        let active = policed_all(active)?;
+        for (effect, region) in &active {
+            effect.apply_rect(data, self.width, self.height, *region);
+        }
+        Ok(active.len())
+    }
+
+    pub(crate) fn process_i420(
+        &mut self,
+        y_plane: &mut [u8],
+        u_plane: &mut [u8],
+        v_plane: &mut [u8],
+        timestamp: f64,
+    ) -> Result<usize, RedactError> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (cw, ch) = (width.div_ceil(2), height.div_ceil(2));
+        if y_plane.len() != width * height {
+            return Err(plane_error("y", y_plane.len(), width * height));
+        }
+        for (name, plane) in [("u", &u_plane), ("v", &v_plane)] {
+            if plane.len() != cw * ch {
+                return Err(plane_error(name, plane.len(), cw * ch));
+            }
+        }
+
+        self.detect_cut(Signature::from_luma(y_plane.iter().copied()), timestamp);
+        let mut active = self.active(timestamp);
+        if let Some(captions) = &mut self.captions {
+            let band = detect_band(self.width, self.height, |x, y| {
+                y_plane[(y * self.width + x) as usize] as f32
+            });
+            active.extend(
+                captions
+                    .update(band, timestamp)
+                    .map(|r| (captions.effect, r)),
+            );
+        }
+        let active = policed_all(active)?;
+        for (effect, region) in &active {
+            // Work on whole chroma blocks so subsampled samples round-trip
+            let x0 = region.x & !1;
+            let y0 = region.y & !1;
+            let x1 = (region.right() + 1).min(self.width) & !1;
+            let x1 = if x1 < region.right() { self.width } else { x1 };
+            let y1 = (region.bottom() + 1).min(self.height) & !1;
+            let y1 = if y1 < region.bottom() {
+                self.height
+            } else {
+                y1
+            };
+            let (bw, bh) = ((x1 - x0) as usize, (y1 - y0) as usize);
+
+            self.rgba.resize(bw * bh * 4, 0);
+            for row in 0..bh {
+                for col in 0..bw {
+                    let (x, y) = (x0 as usize + col, y0 as usize + row);
+                    let c = (y / 2) * cw + x / 2;
+                    let rgb = yuv_to_rgb(y_plane[y * width + x], u_plane[c], v_plane[c]);
+                    let i = (row * bw + col) * 4;
+                    self.rgba[i..i + 3].copy_from_slice(&rgb);
+                    self.rgba[i + 3] = 255;
+                }
+            }
+            let local = Rect::new(region.x - x0, region.y - y0, region.w, region.h);
+            effect.apply_rect(&mut self.rgba, bw as u32, bh as u32, local);
+
+            for row in region.y as usize..region.bottom() as usize {
+                for col in region.x as usize..region.right() as usize {
+                    let i = ((row - y0 as usize) * bw + col - x0 as usize) * 4;
+                    y_plane[row * width + col] = clamp_u8(rgb_to_yuv(&self.rgba[i..i + 4]).0);
+                }
+            }
+            for cy in y0 as usize / 2..(y1 as usize).div_ceil(2) {
+                for cx in x0 as usize / 2..(x1 as usize).div_ceil(2) {
+                    let (mut u, mut v, mut n) = (0.0, 0.0, 0.0);
+                    for y in cy * 2..(cy * 2 + 2).min(y1 as usize) {
+                        for x in cx * 2..(cx * 2 + 2).min(x1 as usize) {
+                            let i = ((y - y0 as usize) * bw + x - x0 as usize) * 4;
+                            let (_, pu, pv) = rgb_to_yuv(&self.rgba[i..i + 4]);
+                            u += pu;
+                            v += pv;
+                            n += 1.0;
+                        }
+                    }
+                    u_plane[cy * cw + cx] = clamp_u8(u / n);
+                    v_plane[cy * cw + cx] = clamp_u8(v / n);
+                }
+            }
+        }
+        Ok(active.len())
+    }
+}
+
+#[wasm_bindgen]
+impl VideoRedactor {
+    /// Redactor for frames of `width` x `height`
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: u32, height: u32) -> VideoRedactor {
+        VideoRedactor {
+            width,
+            height,
+            tracks: Vec::new(),
+            next_id: 0,
+            rgba: Scratch::default(),
+            smoothing: 0.0,
+            lock_grid: false,
+            time_unit: "ms".to_string(),
+            audio: Vec::new(),
+            ducking: None,
+            cut_threshold: DEFAULT_CUT_THRESHOLD,
+            previous: None,
+            last_cut: None,
+            cut_count: 0,
+            on_scene_cut: None,
+            captions: None,
+        }
+    }
+
+    /// Redactor replaying a timeline plan written by `to_plan`, by hand or
+    /// by another pipeline
+    pub fn from_plan(json: &str) -> Result<VideoRedactor, JsError> {
+        Ok(Self::parse_plan(json)?)
+    }
+
+    /// Every track and keyframe as a timeline plan
+    pub fn to_plan(&self) -> String {
+        self.plan_json().to_string()
+    }
+
+    /// Unit of timestamps (`"ms"`, `"frame"`, ...), recorded in the plan
+    pub fn set_time_unit(&mut self, unit: &str) {
+        self.time_unit = unit.to_string();
+    }
+
+    /// Silence or bleep `start..end` (milliseconds) of the soundtrack
+    pub fn add_audio_range(&mut self, start: f64, end: f64, mode: AudioMode) {
+        self.audio.push(AudioRange { start, end, mode });
+    }
+
+    /// Lower the audio within `ms` of every range to `gain` at the edge;
+    /// `ms` of 0 turns ducking off
+    pub fn set_audio_ducking(&mut self, ms: f64, gain: f32) {
+        self.ducking = (ms > 0.0).then_some(Ducking {
+            ms,
+            gain: gain.clamp(0.0, 1.0),
+        });
+    }
+
+    /// Apply the audio ranges to a chunk of interleaved PCM that starts
+    /// `start_ms` into the stream (e.g. an `AudioData`'s timestamp / 1000)
+    pub fn process_audio(
+        &self,
+        samples: &mut [f32],
+        sample_rate: u32,
+        channels: u32,
+        start_ms: f64,
+    ) -> Result<(), JsError> {
+        Ok(self.process_pcm(samples, sample_rate, channels, start_ms)?)
+    }
+
+    /// Tag a track with the PII class it redacts, by label (`"FACE"`, ...
+    /// or a custom label)
+    pub fn set_track_class(&mut self, id: u32, class: &str) -> Result<(), JsError> {
+        self.track_mut(id)?.class = Some(PiiClass::from_name(class)?);
+        Ok(())
+    }
+
+    /// Luma histogram distance (0..=1) that counts as a hard cut; 0 turns
+    /// detection off. Defaults to 0.5.
+    pub fn set_scene_cut_threshold(&mut self, threshold: f32) {
+        self.cut_threshold = threshold.clamp(0.0, 1.0);
+    }
+
+    /// Call `callback(timestamp, score)` whenever a frame starts a new shot
+    pub fn set_on_scene_cut(&mut self, callback: Option<Function>) {
+        self.on_scene_cut = callback;
+    }
+
+    /// Number of cuts detected so far
+    #[wasm_bindgen(getter)]
+    pub fn scene_cuts(&self) -> usize {
+        self.cut_count
+    }
+
+    /// Timestamp of the most recent cut, if any
+    #[wasm_bindgen(getter)]
+    pub fn last_scene_cut(&self) -> Option<f64> {
+        self.last_cut
+    }
+
+    /// Track pixelated with a block size calibrated to the region's size
+    /// (see `face_block_size`), e.g. for a tracked face
+    pub fn add_face_track(&mut self) -> Result<u32, JsError> {
+        Ok(self.add_track(Effect::pixelate(0), true)?)
+    }
+
+    /// Ease regions and calibrated block sizes across frames; `amount` is
+    /// the weight kept from the previous frame, 0 (off) to 0.95
+    pub fn set_temporal_smoothing(&mut self, amount: f32) -> Result<(), JsError> {
+        Ok(self.set_smoothing(amount)?)
+    }
+
+    /// Anchor pixelation blocks to the frame grid rather than the region
+    pub fn set_lock_block_grid(&mut self, lock: bool) {
+        self.lock_grid = lock;
+    }
+
+    /// Add a track redacted with a solid fill; returns its id
+    pub fn add_fill_track(&mut self, color: &Color) -> Result<u32, JsError> {
+        Ok(self.add_track(Effect::SolidFill { color: *color }, false)?)
+    }
+
+    pub fn add_pixelate_track(&mut self, block_size: u32) -> Result<u32, JsError> {
+        Ok(self.add_track(Effect::pixelate(block_size), false)?)
+    }
+
+    pub fn add_blur_track(&mut self, radius: u32) -> Result<u32, JsError> {
+        Ok(self.add_track(Effect::GaussianBlur { radius }, false)?)
+    }
+
+    /// Set where track `id` is at `timestamp`, replacing any keyframe at
+    /// the same time
+    pub fn add_keyframe(&mut self, id: u32, timestamp: f64, region: &Rect) -> Result<(), JsError> {
+        Ok(self.keyframe(id, timestamp, *region)?)
+    }
+
+    /// Drop a track; returns whether it existed
+    pub fn remove_track(&mut self, id: u32) -> bool {
+        let before = self.tracks.len();
+        self.tracks.retain(|t| t.id != id);
+        self.tracks.len() != before
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn track_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Detect burned-in captions and cover them with a solid fill
+    pub fn fill_captions(&mut self, color: &Color) {
+        self.captions = Some(CaptionTracker::new(
+            Effect::SolidFill { color: *color },
+            CAPTION_HOLD,
+        ));
+    }
+
+    /// Detect burned-in captions and blur them
+    pub fn blur_captions(&mut self, radius: u32) -> Result<(), JsError> {
+        let effect = Effect::GaussianBlur { radius };
+        effect.validate()?;
+        self.captions = Some(CaptionTracker::new(effect, CAPTION_HOLD));
+        Ok(())
+    }
+
+    /// Stop caption detection and forget the detected spans
+    pub fn disable_captions(&mut self) {
+        self.captions = None;
+    }
+
+    /// How long a caption stays redacted after it was last detected, in
+    /// timestamp units (default 500, half a second in milliseconds)
+    pub fn set_caption_hold(&mut self, hold: f64) {
+        if let Some(captions) = &mut self.captions {
+            captions.hold = hold.max(0.0);
+        }
+    }
+
+    /// Detected caption spans as `[{start, end, region}]`
+    pub fn captions_json(&self) -> String {
+        self.captions
+            .as_ref()
+            .map_or(Json::Array(Vec::new()), CaptionTracker::json)
+            .to_string()
+    }
+
+    /// Redact an RGBA frame in place; returns how many regions were applied
+    pub fn process_frame(&mut self, data: &mut [u8], timestamp: f64) -> Result<usize, JsError> {
+        Ok(self.process_rgba(data, timestamp)?)
+    }
+
+    /// Redact an I420 (YUV 4:2:0, BT.601) frame in place, e.g. the planes
+    /// copied out of a WebCodecs `VideoFrame`
+    pub fn process_frame_i420(
+        &mut self,
+        y: &mut [u8],
+        u: &mut [u8],
+        v: &mut [u8],
+        timestamp: f64,
+    ) -> Result<usize, JsError> {
+        Ok(self.process_i420(y, u, v, timestamp)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pattern, pixelate};
+
+    #[test]
+    fn test_regions_interpolate_between_keyframes() {
+        let mut video = VideoRedactor::new(32, 32);
+        let id = video.add_track(Effect::pixelate(4), false).unwrap();
+        video.keyframe(id, 10.0, Rect::new(0, 0, 8, 8)).unwrap();
+        video.keyframe(id, 0.0, Rect::new(20, 20, 4, 4)).unwrap();
+        video.keyframe(id, 20.0, Rect::new(10, 0, 8, 8)).unwrap();
+
+        let track = &video.tracks[0];
+        assert_eq!(track.region_at(5.0, None), Some(Rect::new(10, 10, 6, 6)));
+        assert_eq!(track.region_at(15.0, None), Some(Rect::new(5, 0, 8, 8)));
+        assert_eq!(track.region_at(20.0, None), Some(Rect::new(10, 0, 8, 8)));
+        assert_eq!(track.region_at(20.5, None), None);
+        assert_eq!(
+            track.region_at(15.0, Some(12.0)),
+            Some(Rect::new(10, 0, 8, 8))
+        );
+
+        let mut frame = pattern(32, 32);
+        let mut expected = frame.clone();
+        pixelate(&mut expected, 32, 32, 5, 0, 8, 8, 4);
+        assert_eq!(video.process_rgba(&mut frame, 15.0).unwrap(), 1);
+        assert_eq!(frame, expected);
+        assert_eq!(video.process_rgba(&mut frame, 25.0).unwrap(), 0);
+        assert!(video.keyframe(7, 0.0, Rect::new(0, 0, 1, 1)).is_err());
+    }
+
+    #[test]
+    fn test_smoothing_trails_but_never_uncovers() {
+        let mut video = VideoRedactor::new(64, 64);
+        let id = video.add_face_track().unwrap();
+        video.keyframe(id, 0.0, Rect::new(0, 0, 40, 40)).unwrap();
+        video.keyframe(id, 10.0, Rect::new(20, 0, 20, 20)).unwrap();
+        video.set_smoothing(0.5).unwrap();
+        assert!(video.set_smoothing(1.0).is_err());
+
+        let at = |video: &mut VideoRedactor, t| video.active(t)[0];
+        assert_eq!(
+            at(&mut video, 0.0),
+            (Effect::pixelate(7), Rect::new(0, 0, 40, 40))
+        );
+        // Target at t=10 is 20,0 20x20 with block 4; the left edge trails
+        // and the block size decays instead of dropping
+        let (effect, region) = at(&mut video, 10.0);
+        assert_eq!(region, Rect::new(10, 0, 30, 30));
+        assert_eq!(effect, Effect::pixelate(6));
+
+        video.set_lock_block_grid(true);
+        let (effect, region) = at(&mut video, 10.0);
+        let Effect::Pixelate { block_size, .. } = effect else {
+            panic!("face tracks pixelate");
+        };
+        assert_eq!(region.x % block_size, 0);
+        assert!(region.contains(&Rect::new(20, 0, 20, 20)));
+    }
+
+    #[test]
+    fn test_scene_cut_stops_interpolation() {
+        let mut video = VideoRedactor::new(16, 16);
+        let id = video.add_track(Effect::pixelate(2), false).unwrap();
+        video.keyframe(id, 0.0, Rect::new(0, 0, 4, 4)).unwrap();
+        video.keyframe(id, 10.0, Rect::new(12, 12, 4, 4)).unwrap();
+
+        let dark = vec![10u8; 16 * 16 * 4];
+        let bright = vec![240u8; 16 * 16 * 4];
+        for (t, frame) in [(0.0, &dark), (2.0, &dark), (4.0, &bright)] {
+            video.process_rgba(&mut frame.clone(), t).unwrap();
+        }
+        assert_eq!(video.scene_cuts(), 1);
+        assert_eq!(video.last_scene_cut(), Some(4.0));
+        assert_eq!(
+            video.active(5.0),
+            [(Effect::pixelate(2), Rect::new(12, 12, 4, 4))]
+        );
+
+        // Seeking back before the cut interpolates again
+        video.process_rgba(&mut bright.clone(), 2.0).unwrap();
+        assert_eq!(video.last_scene_cut(), None);
+        assert_eq!(video.active(2.0)[0].1, Rect::new(2, 2, 4, 4));
+    }
+
+    #[test]
+    fn test_captions_are_filled_and_exported_to_the_plan() {
+        let (width, height) = (160, 120);
+        let frame: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let glyph = (84..96).contains(&y) && (30..130).contains(&x) && x % 4 < 2;
+                let v = if glyph { 250 } else { 80 };
+                [v, v, v, 255]
+            })
+            .collect();
+        let mut video = VideoRedactor::new(width, height);
+        video.fill_captions(&Color::new(0, 0, 0));
+        video.set_caption_hold(50.0);
+
+        let mut data = frame.clone();
+        assert_eq!(video.process_rgba(&mut data, 0.0).unwrap(), 1);
+        assert!(data[((90 * width + 31) * 4) as usize..][..3] == [0, 0, 0]);
+        // The upper frame is untouched
+        assert_eq!(
+            data[..(60 * width * 4) as usize],
+            frame[..(60 * width * 4) as usize]
+        );
+
+        let mut blank = vec![80u8; (width * height * 4) as usize];
+        assert_eq!(video.process_rgba(&mut blank, 40.0).unwrap(), 1);
+        assert_eq!(video.process_rgba(&mut blank, 200.0).unwrap(), 0);
+
+        let replay = VideoRedactor::parse_plan(&video.to_plan()).unwrap();
+        assert_eq!(replay.track_count(), 1);
+        assert_eq!(replay.tracks[0].keyframes[1].0, 50.0);
+    }
+
+    #[test]
+    fn test_plan_round_trip() {
+        let plan = r##"{"version": 1, "width": 64, "height": 48, "time_unit": "frame",
+            "tracks": [
+              {"effect": "pixelate", "calibrated": true, "class": "FACE",
+               "keyframes": [{"t": 10, "region": {"x": 0, "y": 0, "w": 20, "h": 20}},
+                             {"t": 0, "region": {"x": 4, "y": 4, "w": 20, "h": 20}}]},
+              {"effect": "solid_fill", "params": {"color": "#102030"},
+               "start": 5, "end": 30, "region": {"x": 0, "y": 40, "w": 64, "h": 8}}],
+            "audio": [{"start": 1000, "end": 1500, "mode": "silence"}]}"##;
+        let mut video = VideoRedactor::parse_plan(plan).unwrap();
+        assert_eq!(
+            (video.width, video.height, video.track_count()),
+            (64, 48, 2)
+        );
+        assert_eq!(video.tracks[0].class, Some(PiiClass::Face));
+        assert_eq!(video.tracks[0].keyframes[0].0, 0.0);
+        assert_eq!(
+            video.active(20.0),
+            [(
+                Effect::SolidFill {
+                    color: Color::new(16, 32, 48)
+                },
+                Rect::new(0, 40, 64, 8)
+            )]
+        );
+
+        let mut pcm = vec![0.5f32; 100];
+        video.process_pcm(&mut pcm, 100, 1, 1000.0).unwrap();
+        assert!(pcm[..50].iter().all(|&s| s == 0.0));
+        assert_eq!(pcm[99], 0.5);
+
+        let reparsed = VideoRedactor::parse_plan(&video.to_plan()).unwrap();
+        assert_eq!(reparsed.to_plan(), video.to_plan());
+
+        let bad = r#"{"version": 1, "width": 8, "height": 8, "tracks": [{"effect": "swirl", "start": 0, "end": 1}]}"#;
+        assert!(matches!(
+            VideoRedactor::parse_plan(bad),
+            Err(RedactError::InvalidField { .. })
+        ));
+    }
+
+    #[test]
+    fn test_i420_fill_touches_only_the_region() {
+        let mut video = VideoRedactor::new(9, 6);
+        let id = video
+            .add_track(
+                Effect::SolidFill {
+                    color: Color::new(0, 0, 0),
+                },
+                false,
+            )
+            .unwrap();
+        video.keyframe(id, 0.0, Rect::new(1, 1, 4, 3)).unwrap();
+
+        let mut y = vec![180u8; 9 * 6];
+        let (mut u, mut v) = (vec![90u8; 5 * 3], vec![160u8; 5 * 3]);
+        assert_eq!(video.process_i420(&mut y, &mut u, &mut v, 0.0).unwrap(), 1);
+        assert_eq!(y[9 + 1], 16);
+        assert_eq!(y[3 * 9 + 4], 16);
+        assert_eq!(y[0], 180);
+        assert_eq!(y[4 * 9 + 1], 180);
+        assert_eq!(y[9 + 5], 180);
+        // Chroma blocks fully inside the region turn neutral
+        assert_eq!((u[5 + 1], v[5 + 1]), (128, 128));
+        assert_eq!((u[2 * 5 + 4], v[2 * 5 + 4]), (90, 160));
+
+        assert!(video
+            .process_i420(&mut y[..10], &mut u, &mut v, 0.0)
+            .is_err());
+    }
+}
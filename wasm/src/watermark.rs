@@ -0,0 +1,278 @@
+//! Invisible, tamper-evident watermark for redacted output.
+//!
+//! A fixed-size frame (magic, document ID, content hash) is written into
+//! the least significant bit of the blue channel, in blocks of `TILE_W` x
+//! `TILE_H` pixels tiled from the image origin. The detector majority-votes
+//! every bit over all copies and tries each offset of the block grid, so
+//! any crop at least one block in size, and light damage, still decode. The
+//! hash covers the pixels with that bit cleared, so any later edit
+//! (cropping included) shows as `intact == false` while the ID still traces
+//! the copy.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{check_buffer, RedactError};
+use crate::hash::{hex, sha256};
+use crate::scratch::Scratch;
+
+const MAGIC: &[u8; 2] = b"RW";
+/// Longest document ID the frame holds, in bytes
+pub const MAX_DOCUMENT_ID: usize = 128;
+const FRAME_BYTES: usize = MAGIC.len() + 1 + MAX_DOCUMENT_ID + 32;
+const FRAME_BITS: usize = FRAME_BYTES * 8;
+/// Block the frame is tiled in: bit `i` at `(i % TILE_W, i / TILE_W)`, with
+/// the cells past the frame's end left 0
+const TILE_W: usize = 37;
+const TILE_H: usize = FRAME_BITS.div_ceil(TILE_W);
+const TILE_CELLS: usize = TILE_W * TILE_H;
+
+/// Decoded watermark
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watermark {
+    document_id: String,
+    hash: [u8; 32],
+    /// Whether the pixels still match the embedded hash
+    pub intact: bool,
+    /// Mean agreement of the votes for each bit, 0.5..=1
+    pub confidence: f32,
+}
+
+#[wasm_bindgen]
+impl Watermark {
+    #[wasm_bindgen(getter)]
+    pub fn document_id(&self) -> String {
+        self.document_id.clone()
+    }
+
+    /// Hex SHA-256 of the pixels (blue LSB cleared) at embedding time
+    #[wasm_bindgen(getter)]
+    pub fn hash(&self) -> String {
+        hex(&self.hash)
+    }
+}
+
+/// Hash of the pixels ignoring the bit the watermark lives in
+fn content_hash(data: &[u8]) -> [u8; 32] {
+    let mut masked = Scratch::from(data.to_vec());
+    for px in masked.chunks_exact_mut(4) {
+        px[2] &= !1;
+    }
+    sha256(&masked)
+}
+
+fn bit(frame: &[u8], i: usize) -> u8 {
+    (frame[i / 8] >> (7 - i % 8)) & 1
+}
+
+/// Block cell of pixel `i` of a `width`-wide image
+fn cell(i: usize, width: usize) -> usize {
+    (i / width % TILE_H) * TILE_W + i % width % TILE_W
+}
+
+fn check_size(width: u32, height: u32) -> Result<(), RedactError> {
+    for (name, side, min) in [("width", width, TILE_W), ("height", height, TILE_H)] {
+        if (side as usize) < min {
+            return Err(RedactError::InvalidParameter {
+                name,
+                value: side as f64,
+                expected: format!("{} or more for a watermark", min),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The frame read with the block grid shifted by `(dx, dy)`, from
+/// per-cell counts of set bits and of votes, and the mean agreement of
+/// its votes; `None` unless the magic and the zero padding both check out
+fn read_frame(
+    ones: &[u32],
+    votes: &[u32],
+    dx: usize,
+    dy: usize,
+) -> Option<([u8; FRAME_BYTES], f32)> {
+    let vote = |i: usize| {
+        let (x, y) = (
+            (i % TILE_W + TILE_W - dx) % TILE_W,
+            (i / TILE_W + TILE_H - dy) % TILE_H,
+        );
+        let at = y * TILE_W + x;
+        (
+            ones[at] * 2 > votes[at],
+            ones[at].max(votes[at] - ones[at]) as f32 / votes[at] as f32,
+        )
+    };
+    let magic = (0..MAGIC.len() * 8).all(|i| vote(i).0 == (bit(MAGIC, i) == 1));
+    if !magic || (FRAME_BITS..TILE_CELLS).any(|i| vote(i).0) {
+        return None;
+    }
+    let mut frame = [0u8; FRAME_BYTES];
+    let mut agreement = 0.0;
+    for i in 0..FRAME_BITS {
+        let (one, share) = vote(i);
+        frame[i / 8] |= (one as u8) << (7 - i % 8);
+        agreement += share;
+    }
+    Some((frame, agreement / FRAME_BITS as f32))
+}
+
+/// Embed `document_id` and the content hash; returns the hash
+pub fn embed(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    document_id: &str,
+) -> Result<[u8; 32], RedactError> {
+    check_buffer(data.len(), width, height)?;
+    if document_id.len() > MAX_DOCUMENT_ID {
+        return Err(RedactError::InvalidParameter {
+            name: "document_id",
+            value: document_id.len() as f64,
+            expected: format!("at most {} bytes", MAX_DOCUMENT_ID),
+        });
+    }
+    check_size(width, height)?;
+
+    let hash = content_hash(data);
+    let mut frame = Vec::with_capacity(FRAME_BYTES);
+    frame.extend_from_slice(MAGIC);
+    frame.push(document_id.len() as u8);
+    frame.extend_from_slice(document_id.as_bytes());
+    frame.resize(MAGIC.len() + 1 + MAX_DOCUMENT_ID, 0);
+    frame.extend_from_slice(&hash);
+
+    for (i, px) in data.chunks_exact_mut(4).enumerate() {
+        let cell = cell(i, width as usize);
+        let value = if cell < FRAME_BITS {
+            bit(&frame, cell)
+        } else {
+            0
+        };
+        px[2] = (px[2] & !1) | value;
+    }
+    Ok(hash)
+}
+
+/// Decode a watermark, or `None` if the image doesn't carry one
+pub fn detect(data: &[u8], width: u32, height: u32) -> Result<Option<Watermark>, RedactError> {
+    check_buffer(data.len(), width, height)?;
+    if check_size(width, height).is_err() {
+        return Ok(None);
+    }
+
+    let mut ones = vec![0u32; TILE_CELLS];
+    let mut votes = vec![0u32; TILE_CELLS];
+    for (i, px) in data.chunks_exact(4).enumerate() {
+        let cell = cell(i, width as usize);
+        ones[cell] += (px[2] & 1) as u32;
+        votes[cell] += 1;
+    }
+    // A crop moves the block grid; keep the offset whose votes agree most
+    let best = (0..TILE_H)
+        .flat_map(|dy| (0..TILE_W).map(move |dx| (dx, dy)))
+        .filter_map(|(dx, dy)| read_frame(&ones, &votes, dx, dy))
+        .max_by(|a, b| a.1.total_cmp(&b.1));
+    let Some((frame, confidence)) = best else {
+        return Ok(None);
+    };
+
+    let id_len = frame[MAGIC.len()] as usize;
+    if id_len > MAX_DOCUMENT_ID {
+        return Ok(None);
+    }
+    let id_start = MAGIC.len() + 1;
+    let Ok(document_id) = String::from_utf8(frame[id_start..id_start + id_len].to_vec()) else {
+        return Ok(None);
+    };
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&frame[FRAME_BYTES - 32..]);
+    Ok(Some(Watermark {
+        document_id,
+        hash,
+        intact: content_hash(data) == hash,
+        confidence,
+    }))
+}
+
+/// Embed an invisible watermark carrying `document_id` and a hash of the
+/// redacted pixels. Call it last: any later change breaks `intact`.
+/// Returns the hex hash.
+#[wasm_bindgen]
+pub fn embed_watermark(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    document_id: &str,
+) -> Result<String, JsError> {
+    Ok(hex(&embed(data, width, height, document_id)?))
+}
+
+/// Read the watermark from an image, or `undefined` if there is none
+#[wasm_bindgen]
+pub fn detect_watermark(
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<Option<Watermark>, JsError> {
+    Ok(detect(data, width, height)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern;
+
+    #[test]
+    fn test_round_trip_and_invisibility() {
+        let original = pattern(64, 64);
+        let mut data = original.clone();
+        let hash = embed(&mut data, 64, 64, "case-2024/0042").unwrap();
+        assert!(data.iter().zip(&original).all(|(a, b)| a.abs_diff(*b) <= 1));
+
+        let mark = detect(&data, 64, 64).unwrap().unwrap();
+        assert_eq!(mark.document_id(), "case-2024/0042");
+        assert_eq!(mark.hash(), hex(&hash));
+        assert!(mark.intact);
+        assert_eq!(mark.confidence, 1.0);
+    }
+
+    #[test]
+    fn test_edits_break_intact_but_keep_id() {
+        let mut data = pattern(64, 64);
+        embed(&mut data, 64, 64, "doc-7").unwrap();
+        // Visible edit to a few pixels
+        for px in data[..64].chunks_exact_mut(4) {
+            px[0] = 255;
+        }
+        let mark = detect(&data, 64, 64).unwrap().unwrap();
+        assert_eq!(mark.document_id(), "doc-7");
+        assert!(!mark.intact);
+    }
+
+    #[test]
+    fn test_id_survives_a_crop() {
+        let mut data = pattern(100, 90);
+        embed(&mut data, 100, 90, "crop-me").unwrap();
+        // 50 x 40 from (13, 29): off the block grid on both axes
+        let cropped: Vec<u8> = (29..69)
+            .flat_map(|y| data[(y * 100 + 13) * 4..(y * 100 + 63) * 4].to_vec())
+            .collect();
+        let mark = detect(&cropped, 50, 40).unwrap().unwrap();
+        assert_eq!(mark.document_id(), "crop-me");
+        assert_eq!(mark.confidence, 1.0);
+        assert!(!mark.intact);
+    }
+
+    #[test]
+    fn test_unmarked_and_invalid_inputs() {
+        assert_eq!(detect(&vec![0; 64 * 64 * 4], 64, 64).unwrap(), None);
+        let mut small = pattern(8, 8);
+        assert!(embed(&mut small, 8, 8, "x").is_err());
+        // Enough pixels for a frame, but narrower than a block
+        let mut thin = pattern(16, 128);
+        assert!(embed(&mut thin, 16, 128, "x").is_err());
+        let mut data = pattern(64, 64);
+        assert!(embed(&mut data, 64, 64, &"x".repeat(200)).is_err());
+    }
+}
@@ -0,0 +1,46 @@
+//! The public API under Node, as a server would call it:
+//! `wasm-pack test --node`. A `Buffer` arrives as a `Uint8Array` and
+//! comes back as a new one.
+
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::Uint8Array;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::*;
+
+use redactr_wasm::{
+    gaussian_blur, gaussian_blur_async, pixelate, redact_encoded, redact_pixels, solid_fill,
+};
+
+const PLAN: &str = r##"{"version": 1, "width": 4, "height": 4, "regions": [
+    {"rect": {"x": 0, "y": 0, "w": 2, "h": 4}, "effect": "solid_fill",
+     "params": {"color": "#ff0000"}}]}"##;
+
+#[wasm_bindgen_test]
+fn test_in_place_effects_run_without_a_canvas() {
+    let mut data = vec![100u8; 4 * 4 * 4];
+    solid_fill(&mut data, 4, 4, 0, 0, 1, 1, 1, 2, 3);
+    assert_eq!(data[..4], [1, 2, 3, 100]);
+    pixelate(&mut data, 4, 4, 0, 0, 4, 4, 2);
+    assert_ne!(data[..4], [1, 2, 3, 100]);
+}
+
+#[wasm_bindgen_test]
+fn test_buffer_entry_points() {
+    let pixels = vec![50u8; 4 * 4 * 4];
+    let out = redact_pixels(&pixels, 4, 4, PLAN).unwrap();
+    assert_eq!(out[..4], [255, 0, 0, 50]);
+    assert_eq!(pixels[..4], [50; 4]);
+    assert!(redact_encoded(b"not a png", "png", PLAN).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn test_async_blur_yields_and_resolves() {
+    // Tall enough to take several bands, each after a setTimeout turn
+    let data: Vec<u8> = (0..64 * 4096 * 4).map(|i| (i % 251) as u8).collect();
+    let mut expected = data.clone();
+    gaussian_blur(&mut expected, 64, 4096, 0, 0, 64, 4096, 3);
+    let promise = gaussian_blur_async(data, 64, 4096, 0, 0, 64, 4096, 3, None);
+    let out = Uint8Array::new(&JsFuture::from(promise).await.unwrap()).to_vec();
+    assert_eq!(out, expected);
+}